@@ -17,13 +17,24 @@ fn main() {
 
     let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_WITH_BOOTLOADER").unwrap());
     
+    // pass a PSF2 console font as the ramdisk if RAMDISK names one (optional)
+    println!("cargo:rerun-if-env-changed=RAMDISK");
+    let ramdisk = std::env::var_os("RAMDISK").map(PathBuf::from);
+    let mut uefi = bootloader::UefiBoot::new(&kernel);
+    let mut bios = bootloader::BiosBoot::new(&kernel);
+    if let Some(ramdisk) = &ramdisk {
+        println!("cargo:rerun-if-changed={}", ramdisk.display());
+        uefi.set_ramdisk(ramdisk);
+        bios.set_ramdisk(ramdisk);
+    }
+
     // create an UEFI disk image (optional)
     let uefi_path = out_dir.join("uefi.img");
-    bootloader::UefiBoot::new(&kernel).create_disk_image(&uefi_path).unwrap();
+    uefi.create_disk_image(&uefi_path).unwrap();
 
     // create a BIOS disk image
     let bios_path = out_dir.join("bios.img");
-    bootloader::BiosBoot::new(&kernel).create_disk_image(&bios_path).unwrap();
+    bios.create_disk_image(&bios_path).unwrap();
 
     // pass the disk image paths as env variables to the main.rs
     println!("cargo:rustc-env=UEFI_PATH={}", uefi_path.display());
@@ -0,0 +1,125 @@
+//! Micro-benchmarks of the writer, measured in time stamp counter cycles. Run with the shell's
+//! `bench` command; the screen is overwritten meanwhile.
+use crate::println;
+use crate::writer::style::{Style, TextEffect};
+use crate::writer::{FrameBufferWriter, WRITER};
+use core::fmt::Write;
+use core::arch::x86_64::{_mm_lfence, _rdtsc};
+
+/// Number of times every benchmark is repeated.
+const ITERATIONS: usize = 9;
+
+/// Number of glyphs printed per iteration by the glyph benchmarks.
+const GLYPHS: usize = 1000;
+
+/// Result of a benchmark, in cycles per operation.
+#[derive(Clone, Copy, Debug)]
+pub struct Measurement {
+    pub name: &'static str,
+    pub min: u64,
+    pub median: u64,
+}
+
+/// Reads the time stamp counter, keeping earlier and later instructions from being reordered
+/// around it.
+fn cycles() -> u64 {
+    // SAFETY: fencing and reading the time stamp counter have no side effects.
+    unsafe {
+        _mm_lfence();
+        let now = _rdtsc();
+        _mm_lfence();
+        now
+    }
+}
+
+/// Runs `f` [ITERATIONS] times and reports the cycles per operation, given that every call
+/// performs `ops` operations.
+fn measure(
+    name: &'static str,
+    ops: u64,
+    writer: &mut FrameBufferWriter,
+    mut f: impl FnMut(&mut FrameBufferWriter),
+) -> Measurement {
+    let mut samples = [0u64; ITERATIONS];
+    for sample in &mut samples {
+        let start = cycles();
+        f(writer);
+        *sample = (cycles() - start) / ops;
+    }
+    samples.sort_unstable();
+    Measurement { name, min: samples[0], median: samples[ITERATIONS / 2] }
+}
+
+/// Prints `count` copies of `c`, in chunks to avoid a large buffer.
+fn print_glyphs(writer: &mut FrameBufferWriter, c: u8, count: usize) {
+    let chunk = [c; 100];
+    let text = core::str::from_utf8(&chunk).unwrap_or_default();
+    for _ in 0..count / chunk.len() {
+        writer.print(text);
+    }
+}
+
+/// Number of lines of the multi-line print benchmark.
+const LINES: usize = 100;
+
+/// Writes [LINES] short lines in a single call, which scrolls once per screenful.
+fn print_lines(writer: &mut FrameBufferWriter) {
+    let mut text = [0u8; LINES * 5];
+    text.chunks_exact_mut(5).for_each(|line| line.copy_from_slice(b"line\n"));
+    let _ = writer.write_str(core::str::from_utf8(&text).unwrap_or_default());
+}
+
+/// Runs all benchmarks on the given writer.
+pub fn run(writer: &mut FrameBufferWriter) -> [Measurement; 6] {
+    let saved = writer.style();
+    let styled = Style { bold: true, underline: true, effect: TextEffect::Shadow, ..saved };
+    let results = [
+        measure("1000 ASCII chars", 1, writer, |writer| print_glyphs(writer, b'x', GLYPHS)),
+        measure("plain glyph", GLYPHS as u64, writer, |writer| {
+            print_glyphs(writer, b'A', GLYPHS)
+        }),
+        measure("styled glyph", GLYPHS as u64, writer, |writer| {
+            writer.set_style(styled);
+            print_glyphs(writer, b'A', GLYPHS);
+            writer.set_style(saved);
+        }),
+        measure("scroll one line", 1, writer, |writer| {
+            // Any newline at the bottom of the screen scrolls
+            writer.print("\n");
+        }),
+        measure("100-line print", 1, writer, print_lines),
+        measure("clear", 1, writer, FrameBufferWriter::clear),
+    ];
+    writer.set_style(saved);
+    writer.clear();
+    results
+}
+
+/// Runs all benchmarks on the global writer and prints a table of the results, which is
+/// mirrored to the serial port.
+pub fn bench() {
+    let Some(results) = WRITER.lock().as_mut().map(|writer| {
+        // Make sure the scroll benchmark starts at the bottom of the screen
+        for _ in 0..writer.rows() {
+            writer.print("\n");
+        }
+        run(writer)
+    }) else {
+        return;
+    };
+    println!("{:<20}{:>12}{:>12}", "benchmark", "min", "median");
+    for result in results {
+        println!("{:<20}{:>12}{:>12}", result.name, result.min, result.median);
+    }
+    println!("(cycles per operation, {} iterations)", ITERATIONS);
+    let Some(stats) = WRITER.lock().as_mut().map(|writer| {
+        let before = writer.stats();
+        print_lines(writer);
+        let after = writer.stats();
+        writer.clear();
+        (after.scrolls - before.scrolls, after.scrolled_bytes - before.scrolled_bytes)
+    }) else {
+        return;
+    };
+    println!("{}-line print: {} scrolls moving {} bytes", LINES, stats.0, stats.1);
+}
@@ -0,0 +1,146 @@
+//! Timing of the named stages of the boot path, recorded by [boot_stage!] and summarized by
+//! [report] at the end of the kernel's initialization.
+//!
+//! The stages are timed with the time stamp counter, so stages that run before [time::init]
+//! calibrated it are converted when the report is printed.
+use crate::units::Micros;
+use crate::{output, time};
+use core::fmt::{self, Write};
+use spin::Mutex;
+
+/// Maximum number of stages recorded; later ones are only counted.
+pub const MAX_STAGES: usize = 16;
+
+/// A stage as recorded by [boot_stage!], in time stamp counter cycles.
+#[derive(Clone, Copy, Debug)]
+struct Stage {
+    name: &'static str,
+    start: u64,
+    /// `None` while the stage is running, or if it never completed.
+    end: Option<u64>,
+}
+
+impl Stage {
+    fn cycles(&self) -> Option<u64> {
+        self.end.map(|end| end.saturating_sub(self.start))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Stages {
+    stages: [Option<Stage>; MAX_STAGES],
+    len: usize,
+    /// Number of stages that didn't fit.
+    dropped: usize,
+}
+
+static STAGES: Mutex<Stages> =
+    Mutex::new(Stages { stages: [None; MAX_STAGES], len: 0, dropped: 0 });
+
+#[doc(hidden)]
+pub fn _stage_enter(name: &'static str) -> Option<usize> {
+    let mut stages = STAGES.lock();
+    if stages.len == MAX_STAGES {
+        stages.dropped += 1;
+        return None;
+    }
+    let index = stages.len;
+    stages.stages[index] = Some(Stage { name, start: time::cycles(), end: None });
+    stages.len += 1;
+    Some(index)
+}
+
+#[doc(hidden)]
+pub fn _stage_exit(index: Option<usize>) {
+    let end = time::cycles();
+    let Some(index) = index else { return };
+    if let Some(stage) = &mut STAGES.lock().stages[index] {
+        stage.end = Some(end);
+    }
+}
+
+/// Runs the block as a named stage of the boot path, which [report] lists with its duration.
+/// Evaluates to the value of the block: `boot_stage!("init IDT", { idt::init() })`.
+#[macro_export]
+macro_rules! boot_stage {
+    ($name:expr, $body:block) => {{
+        let stage = $crate::boot::_stage_enter($name);
+        let value = $body;
+        $crate::boot::_stage_exit(stage);
+        value
+    }};
+}
+
+/// Prints the recorded stages with their duration and share of the total, the slowest first
+/// and in reverse video. Stages that are still running, e.g. the one that [report] is called
+/// from, are listed last as unfinished.
+pub fn report() {
+    let stages = *STAGES.lock();
+    let _ = write_report(&mut output::stdout(), stages);
+}
+
+/// Writes the report of [report] about `stages` to `out`.
+fn write_report(out: &mut impl Write, stages: Stages) -> fmt::Result {
+    let Stages { mut stages, dropped, .. } = stages;
+    // Unfinished stages sort after all finished ones
+    stages.sort_unstable_by_key(|stage| {
+        core::cmp::Reverse(stage.map(|stage| stage.cycles().map_or(0, |cycles| cycles + 1)))
+    });
+    let total: u64 = stages.iter().flatten().filter_map(Stage::cycles).sum();
+    writeln!(out, "{:<24}{:>14}{:>9}", "boot stage", "time", "share")?;
+    for (rank, stage) in stages.iter().flatten().enumerate() {
+        let Some(cycles) = stage.cycles() else {
+            writeln!(out, "{:<24}{:>14}", stage.name, "unfinished")?;
+            continue;
+        };
+        let time = Micros(time::cycles_to_us(cycles));
+        let per_mille = (cycles as u128 * 1000).checked_div(total as u128).unwrap_or(0);
+        let (start, end) = match rank {
+            0 => ("\x1b[7m", "\x1b[27m"),
+            _ => ("", ""),
+        };
+        writeln!(
+            out,
+            "{}{:<24}{:>14}{:>5}.{} %{}",
+            start,
+            stage.name,
+            time,
+            per_mille / 10,
+            per_mille % 10,
+            end
+        )?;
+    }
+    writeln!(out, "{:<24}{:>14}", "total", Micros(time::cycles_to_us(total)))?;
+    if dropped > 0 {
+        writeln!(out, "({} more stages not recorded)", dropped)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::fake;
+
+    #[test]
+    fn stages_are_reported_slowest_first_with_their_share() {
+        fake::set(0, 1_000);
+        crate::boot_stage!("outer", {
+            fake::advance(1_000);
+            crate::boot_stage!("inner", { fake::advance(3_000) });
+            fake::advance(1_000);
+        });
+        let running = _stage_enter("report");
+        let mut report = String::new();
+        write_report(&mut report, *STAGES.lock()).unwrap();
+        _stage_exit(running);
+        let expected = [
+            "boot stage                        time    share",
+            "\x1b[7mouter                         5.000 ms   62.5 %\x1b[27m",
+            "inner                         3.000 ms   37.5 %",
+            "report                      unfinished",
+            "total                         8.000 ms",
+        ];
+        assert_eq!(report.lines().collect::<Vec<_>>(), expected);
+    }
+}
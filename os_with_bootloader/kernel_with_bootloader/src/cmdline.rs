@@ -0,0 +1,365 @@
+//! The kernel command line, for console options that change per boot without rebuilding, e.g.
+//! `console.font=24 console.theme=light console.serial=off console.scale=2 console.rotate=90`,
+//! `console.hidpi=2` or `font=ramdisk:console.psf`.
+//!
+//! bootloader_api doesn't pass a command line, so it is read from the file `opt/cmdline` of
+//! QEMU's firmware configuration device, which the runner fills from the `CMDLINE` environment
+//! variable. Without QEMU, the command line is empty.
+//!
+//! Options are `key=value` pairs separated by whitespace. Double quotes keep whitespace in a
+//! value, as in `key="a b"` or `"key=a b"`; an unterminated quote runs to the end of the line
+//! and is kept. Of a key given several times, the last one wins.
+//! Unknown keys are ignored and malformed values fall back to the default, see [problems].
+use crate::writer::color::Color;
+use crate::writer::error::UnsupportedFontSize;
+use crate::writer::rotation::Rotation;
+use crate::writer::{FrameBufferWriter, MAX_HIDPI_SCALE};
+use core::fmt;
+use x86_64::instructions::port::Port;
+
+/// Longest command line that [read] returns; the rest is cut off.
+pub const MAX_LEN: usize = 1024;
+
+/// I/O port that selects an item of the firmware configuration.
+const FW_CFG_SELECTOR: u16 = 0x510;
+
+/// I/O port that the selected item is read from, byte by byte.
+const FW_CFG_DATA: u16 = 0x511;
+
+/// Item that holds `QEMU` if the device is present.
+const FW_CFG_SIGNATURE: u16 = 0x0000;
+
+/// Item that lists the files, see [FwCfg::find].
+const FW_CFG_FILE_DIR: u16 = 0x0019;
+
+/// Size of an entry of the file directory: size, item, reserved bytes and name.
+const FILE_ENTRY_LEN: usize = 64;
+
+/// Name of the file that holds the command line.
+const FILE_NAME: &[u8] = b"opt/cmdline";
+
+/// The firmware configuration device of QEMU.
+struct FwCfg {
+    selector: Port<u16>,
+    data: Port<u8>,
+}
+
+impl FwCfg {
+    const fn new() -> Self {
+        Self { selector: Port::new(FW_CFG_SELECTOR), data: Port::new(FW_CFG_DATA) }
+    }
+
+    /// Selects an item, which reads start at.
+    fn select(&mut self, item: u16) {
+        // SAFETY: selecting an item has no side effects, and the port is unused on machines
+        // without the device.
+        unsafe { self.selector.write(item) }
+    }
+
+    /// Reads the next bytes of the selected item.
+    fn read(&mut self, buffer: &mut [u8]) {
+        for byte in buffer {
+            // SAFETY: reading only advances within the selected item.
+            *byte = unsafe { self.data.read() };
+        }
+    }
+
+    /// Returns the item and the size of the file with the given name, or `None` if the device
+    /// or the file is missing.
+    fn find(&mut self, name: &[u8]) -> Option<(u16, usize)> {
+        let mut signature = [0; 4];
+        self.select(FW_CFG_SIGNATURE);
+        self.read(&mut signature);
+        if &signature != b"QEMU" {
+            return None;
+        }
+        let mut count = [0; 4];
+        self.select(FW_CFG_FILE_DIR);
+        self.read(&mut count);
+        // All numbers are big-endian
+        for _ in 0..u32::from_be_bytes(count) {
+            let mut entry = [0; FILE_ENTRY_LEN];
+            self.read(&mut entry);
+            let file = &entry[8..];
+            let file = &file[..file.iter().position(|&byte| byte == 0).unwrap_or(file.len())];
+            if file == name {
+                let size = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                return Some((u16::from_be_bytes([entry[4], entry[5]]), size as usize));
+            }
+        }
+        None
+    }
+}
+
+/// Reads the command line into `buffer` and returns it, cut off at [MAX_LEN] and before the
+/// first invalid UTF-8.
+pub fn read(buffer: &mut [u8; MAX_LEN]) -> &str {
+    let mut fw_cfg = FwCfg::new();
+    let Some((item, size)) = fw_cfg.find(FILE_NAME) else { return "" };
+    let text = &mut buffer[..size.min(MAX_LEN)];
+    fw_cfg.select(item);
+    fw_cfg.read(text);
+    let valid = core::str::from_utf8(text).map_or_else(|error| error.valid_up_to(), str::len);
+    core::str::from_utf8(&text[..valid]).unwrap_or_default().trim_end_matches('\0')
+}
+
+/// Removes a pair of double quotes around `text`.
+fn unquote(text: &str) -> &str {
+    text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(text)
+}
+
+/// Splits the command line into its `key=value` pairs, see the module docs. A pair without `=`
+/// has an empty value.
+pub fn pairs(cmdline: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut rest = cmdline;
+    core::iter::from_fn(move || {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let mut quoted = false;
+        let end = rest.char_indices().find(|&(_, c)| {
+            quoted ^= c == '"';
+            c.is_whitespace() && !quoted
+        });
+        let (pair, tail) = rest.split_at(end.map_or(rest.len(), |(index, _)| index));
+        rest = tail;
+        let pair = unquote(pair);
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        Some((unquote(key), unquote(value)))
+    })
+}
+
+/// An option of the command line that was ignored, see [problems].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem<'a> {
+    UnknownKey(&'a str),
+    /// The value can't be parsed, so the option has its default.
+    Malformed { key: &'a str, value: &'a str },
+}
+
+impl fmt::Display for Problem<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownKey(key) => write!(f, "unknown option {} ignored", key),
+            Self::Malformed { key, value } => {
+                write!(f, "malformed value {:?} of {}, using the default", value, key)
+            }
+        }
+    }
+}
+
+/// Returns the options of the command line that [ConsoleOptions::parse] ignored, so that they
+/// can be logged once logging works.
+pub fn problems(cmdline: &str) -> impl Iterator<Item = Problem<'_>> {
+    pairs(cmdline).filter_map(|(key, value)| {
+        let mut options = ConsoleOptions::DEFAULT;
+        options.set(key, value).err()
+    })
+}
+
+/// Colors of the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// White on black.
+    #[default]
+    Dark,
+    /// Black on white.
+    Light,
+}
+
+/// Where the console font comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FontSource {
+    /// The font compiled into the kernel.
+    #[default]
+    BuiltIn,
+    /// A PSF2 file in the ramdisk, see [crate::writer::psf].
+    Ramdisk,
+}
+
+/// The console options of the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsoleOptions {
+    /// `console.font`: the raster height of the text in pixels, or `None` for the default.
+    pub font: Option<usize>,
+    /// `console.theme`: `dark` or `light`.
+    pub theme: Theme,
+    /// `console.serial`: `on` or `off`, whether the output is mirrored to the serial port.
+    pub serial: bool,
+    /// `console.scale`: the text scale, see [FrameBufferWriter::set_text_scale].
+    pub scale: usize,
+    /// `console.rotate`: `0`, `90`, `180` or `270` degrees clockwise.
+    pub rotation: Rotation,
+    /// `console.hidpi`: `auto` or the HiDPI scale, see
+    /// [FrameBufferWriter::set_hidpi_scale]. `None` keeps the one chosen for the resolution.
+    pub hidpi: Option<usize>,
+    /// `font`: `builtin`, or `ramdisk:` and the name of the PSF2 file that the bootloader was
+    /// given as the ramdisk. The ramdisk holds a single file, so the name only documents it.
+    pub font_source: FontSource,
+}
+
+/// Sets `option` to `value`, or to `default` if there is none. Returns whether there was one.
+fn set_or_default<T>(option: &mut T, value: Option<T>, default: T) -> bool {
+    let valid = value.is_some();
+    *option = value.unwrap_or(default);
+    valid
+}
+
+impl ConsoleOptions {
+    /// The options of an empty command line.
+    pub const DEFAULT: Self = Self {
+        font: None,
+        theme: Theme::Dark,
+        serial: true,
+        scale: 1,
+        rotation: Rotation::None,
+        hidpi: None,
+        font_source: FontSource::BuiltIn,
+    };
+
+    /// Returns the options of the command line, see the module docs.
+    pub fn parse(cmdline: &str) -> Self {
+        let mut options = Self::DEFAULT;
+        for (key, value) in pairs(cmdline) {
+            let _ = options.set(key, value);
+        }
+        options
+    }
+
+    /// Sets the option `key` to `value`. Fails for unknown keys, and for malformed values, which
+    /// set the default instead.
+    fn set<'a>(&mut self, key: &'a str, value: &'a str) -> Result<(), Problem<'a>> {
+        let default = Self::DEFAULT;
+        let valid = match key {
+            "console.font" => {
+                let font = value.parse().ok().filter(|&pixels| pixels > 0);
+                set_or_default(&mut self.font, font.map(Some), default.font)
+            }
+            "console.theme" => {
+                let theme = match value {
+                    "dark" => Some(Theme::Dark),
+                    "light" => Some(Theme::Light),
+                    _ => None,
+                };
+                set_or_default(&mut self.theme, theme, default.theme)
+            }
+            "console.serial" => {
+                let serial = match value {
+                    "on" => Some(true),
+                    "off" => Some(false),
+                    _ => None,
+                };
+                set_or_default(&mut self.serial, serial, default.serial)
+            }
+            "console.scale" => {
+                let scale = value.parse().ok().filter(|&scale| scale > 0);
+                set_or_default(&mut self.scale, scale, default.scale)
+            }
+            "console.rotate" => {
+                let rotation = Rotation::from_degrees(value);
+                set_or_default(&mut self.rotation, rotation, default.rotation)
+            }
+            "console.hidpi" => {
+                let scale = value.parse().ok();
+                let scale = scale.filter(|scale| (1..=MAX_HIDPI_SCALE).contains(scale));
+                let hidpi = match value {
+                    "auto" => Some(None),
+                    _ => scale.map(Some),
+                };
+                set_or_default(&mut self.hidpi, hidpi, default.hidpi)
+            }
+            "font" => {
+                let source = match value.split_once(':') {
+                    Some(("ramdisk", name)) if !name.is_empty() => Some(FontSource::Ramdisk),
+                    None if value == "builtin" => Some(FontSource::BuiltIn),
+                    _ => None,
+                };
+                set_or_default(&mut self.font_source, source, default.font_source)
+            }
+            _ => return Err(Problem::UnknownKey(key)),
+        };
+        valid.then_some(()).ok_or(Problem::Malformed { key, value })
+    }
+
+    /// Applies the options of the framebuffer console to `writer`, before anything is printed.
+    /// The serial option is up to the caller. Fails if the font lacks the size of
+    /// `console.font`, which keeps the default size.
+    pub fn apply(&self, writer: &mut FrameBufferWriter) -> Result<(), UnsupportedFontSize> {
+        if let Some(scale) = self.hidpi.filter(|&scale| scale != writer.hidpi_scale()) {
+            writer.set_hidpi_scale(scale);
+        }
+        if self.rotation != writer.rotation() {
+            writer.set_rotation(self.rotation);
+        }
+        let font = self.font.map(|pixels| writer.set_font_size(pixels)).unwrap_or(Ok(()));
+        if self.theme == Theme::Light {
+            writer.recolor_default_colors(Color::BLACK, Color::WHITE);
+        }
+        writer.set_text_scale(self.scale);
+        font
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_selects_the_ramdisk_by_name() {
+        let source = |cmdline| ConsoleOptions::parse(cmdline).font_source;
+        assert_eq!(source(""), FontSource::BuiltIn);
+        assert_eq!(source("font=ramdisk:console.psf"), FontSource::Ramdisk);
+        assert_eq!(source("font=ramdisk:console.psf font=builtin"), FontSource::BuiltIn);
+        for malformed in ["font=ramdisk", "font=ramdisk:", "font=disk:console.psf"] {
+            assert_eq!(source(malformed), FontSource::BuiltIn);
+            let (key, value) = malformed.split_once('=').unwrap();
+            let problems: Vec<_> = problems(malformed).collect();
+            assert_eq!(problems, [Problem::Malformed { key, value }]);
+        }
+    }
+
+    #[test]
+    fn quotes_keep_whitespace_in_pairs() {
+        let pairs = |cmdline| pairs(cmdline).collect::<Vec<_>>();
+        assert_eq!(pairs(""), []);
+        assert_eq!(pairs(" \t\n "), []);
+        assert_eq!(pairs("a=1  b=2\tc"), [("a", "1"), ("b", "2"), ("c", "")]);
+        assert_eq!(pairs("key=\"a b\" next"), [("key", "a b"), ("next", "")]);
+        assert_eq!(pairs("\"key=a b\" next"), [("key", "a b"), ("next", "")]);
+        assert_eq!(pairs("key=\"\""), [("key", "")]);
+        assert_eq!(pairs("a=b=c"), [("a", "b=c")]);
+        // An unterminated quote runs to the end, and isn't removed
+        assert_eq!(pairs("key=\"a b next=1"), [("key", "\"a b next=1")]);
+        assert_eq!(pairs("\"key=a b"), [("\"key", "a b")]);
+    }
+
+    #[test]
+    fn the_last_of_duplicate_keys_wins() {
+        let options = ConsoleOptions::parse("console.scale=2 console.theme=light console.scale=3");
+        assert_eq!(options.scale, 3);
+        assert_eq!(options.theme, Theme::Light);
+        // A malformed value resets the option to its default, even after a valid one
+        assert_eq!(ConsoleOptions::parse("console.scale=2 console.scale=x").scale, 1);
+        assert_eq!(ConsoleOptions::parse("console.theme=\"light\"").theme, Theme::Light);
+    }
+
+    #[test]
+    fn problems_list_unknown_keys_and_malformed_values() {
+        assert_eq!(ConsoleOptions::parse(""), ConsoleOptions::DEFAULT);
+        assert_eq!(problems("").count(), 0);
+        let cmdline = "console.scale=2 quiet console.theme=\"very dark\" console.rotate=45";
+        let problems: Vec<_> = problems(cmdline).collect();
+        assert_eq!(
+            problems,
+            [
+                Problem::UnknownKey("quiet"),
+                Problem::Malformed { key: "console.theme", value: "very dark" },
+                Problem::Malformed { key: "console.rotate", value: "45" },
+            ]
+        );
+        let unterminated: Vec<_> = super::problems("console.theme=\"light").collect();
+        let value = "\"light";
+        assert_eq!(unterminated, [Problem::Malformed { key: "console.theme", value }]);
+    }
+}
@@ -0,0 +1,282 @@
+//! Helpers for printf-style debugging of the kernel.
+use crate::writer::WRITER;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether [kprint!] starts lines with the module they are printed from.
+static MODULE_PREFIXES: AtomicBool = AtomicBool::new(true);
+
+/// Turns the module prefixes of [kprint!] and [kprintln!] on or off for all modules.
+pub fn set_module_prefixes(enabled: bool) {
+    MODULE_PREFIXES.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns the last two segments of a module path, e.g. `memory::paging` for
+/// `kernel::memory::paging`, or the whole path if it is shorter.
+pub fn abbreviate_module_path(path: &str) -> &str {
+    match path.rmatch_indices("::").nth(1) {
+        Some((index, _)) => &path[index + 2..],
+        None => path,
+    }
+}
+
+#[doc(hidden)]
+pub fn _kprint(module_path: &str, args: fmt::Arguments) {
+    let at_line_start = WRITER.lock().as_ref().is_none_or(|writer| writer.is_at_line_start());
+    if at_line_start && MODULE_PREFIXES.load(Ordering::Relaxed) {
+        crate::print!("\x1b[2m[{}]\x1b[22m ", abbreviate_module_path(module_path));
+        if let Some(writer) = WRITER.lock().as_mut() {
+            writer.indent_wrapped_lines();
+        }
+    }
+    crate::writer::_print(args);
+}
+
+/// Like [print!](crate::print!) to the global writer, but a line that starts with this is
+/// prefixed with the calling module in a dim style, abbreviated to its last two segments,
+/// e.g. `[log::filter] `. Lines that the text wraps onto start under the text.
+///
+/// Continuing a line that something else started doesn't add a prefix. The prefixes can be
+/// turned off with [set_module_prefixes].
+#[macro_export]
+macro_rules! kprint {
+    ($($arg:tt)*) => {
+        $crate::debug::_kprint(module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// Like [kprint!], but appends a newline.
+#[macro_export]
+macro_rules! kprintln {
+    () => {
+        $crate::kprint!("\n")
+    };
+    ($fmt:literal $($arg:tt)*) => {
+        $crate::debug::_kprint(module_path!(), format_args!(concat!($fmt, "\n") $($arg)*))
+    };
+}
+
+/// Kernel equivalent of std's `dbg!`: prints `[file:line] expr = value` in a dim style through
+/// the global writer and returns the value, so it can be dropped into the middle of expressions.
+///
+/// Like `dbg!`, it takes ownership of the value and hands it back, and with several arguments it
+/// returns a tuple. With the `quiet-debug` feature it compiles to just the expressions.
+#[cfg(not(feature = "quiet-debug"))]
+#[macro_export]
+macro_rules! kdbg {
+    () => {
+        $crate::writer::_print_styled(
+            |style| style.dim = true,
+            format_args!("[{}:{}]\n", file!(), line!()),
+        )
+    };
+    ($val:expr $(,)?) => {
+        match $val {
+            tmp => {
+                $crate::writer::_print_styled(
+                    |style| style.dim = true,
+                    format_args!(
+                        "[{}:{}] {} = {:#?}\n",
+                        file!(),
+                        line!(),
+                        stringify!($val),
+                        &tmp
+                    ),
+                );
+                tmp
+            }
+        }
+    };
+    ($($val:expr),+ $(,)?) => {
+        ($($crate::kdbg!($val)),+,)
+    };
+}
+
+/// Kernel equivalent of std's `dbg!`. Printing is disabled by the `quiet-debug` feature, so this
+/// only evaluates to the given expressions.
+#[cfg(feature = "quiet-debug")]
+#[macro_export]
+macro_rules! kdbg {
+    () => {
+        ()
+    };
+    ($val:expr $(,)?) => {
+        $val
+    };
+    ($($val:expr),+ $(,)?) => {
+        ($($val),+,)
+    };
+}
+
+/// Prints the report of a failed [kassert!]: the condition in bold red, the location of the
+/// assertion and the message, if any.
+#[track_caller]
+fn report_assertion(condition: fmt::Arguments, message: Option<fmt::Arguments>) {
+    let location = core::panic::Location::caller();
+    crate::print!("\x1b[1;31massertion failed: `{}`\x1b[22;39m", condition);
+    crate::println!(" \x1b[2mat {}\x1b[22m", location);
+    if let Some(message) = message {
+        crate::println!("  {}", message);
+    }
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn _kassert_failed(condition: &str, message: Option<fmt::Arguments>) -> ! {
+    report_assertion(format_args!("{}", condition), message);
+    panic!("assertion failed: {}", condition)
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn _kassert_cmp_failed(
+    op: &str,
+    (left_expr, right_expr): (&str, &str),
+    left: &dyn fmt::Debug,
+    right: &dyn fmt::Debug,
+    message: Option<fmt::Arguments>,
+) -> ! {
+    report_assertion(format_args!("{} {} {}", left_expr, op, right_expr), message);
+    crate::println!("   left: {:?}", left);
+    crate::println!("  right: {:?}", right);
+    panic!("assertion failed: {} {} {}", left_expr, op, right_expr)
+}
+
+/// Like `assert!`, but a failure first prints a report through the print macros, so that it is
+/// readable on the console: the condition in bold red with the location of the assertion, and
+/// the message, if one is given. Then it panics with a short message.
+#[macro_export]
+macro_rules! kassert {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            $crate::debug::_kassert_failed(stringify!($cond), None)
+        }
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::debug::_kassert_failed(stringify!($cond), Some(format_args!($($arg)+)))
+        }
+    };
+}
+
+/// Like [kassert!] for `left == right`. The report shows both values with `Debug` on lines of
+/// their own.
+#[macro_export]
+macro_rules! kassert_eq {
+    (@cmp $op:tt, $left:expr, $right:expr, $message:expr) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if !(*left $op *right) {
+                    let exprs = (stringify!($left), stringify!($right));
+                    let op = stringify!($op);
+                    $crate::debug::_kassert_cmp_failed(op, exprs, left, right, $message)
+                }
+            }
+        }
+    };
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::kassert_eq!(@cmp ==, $left, $right, None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::kassert_eq!(@cmp ==, $left, $right, Some(format_args!($($arg)+)))
+    };
+}
+
+/// Like [kassert_eq!] for `left != right`.
+#[macro_export]
+macro_rules! kassert_ne {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::kassert_eq!(@cmp !=, $left, $right, None)
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        $crate::kassert_eq!(@cmp !=, $left, $right, Some(format_args!($($arg)+)))
+    };
+}
+
+/// Like [kassert!], but only checked in builds with debug assertions, like `debug_assert!`.
+#[macro_export]
+macro_rules! debug_kassert {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            $crate::kassert!($($arg)*)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::color::Color;
+    use crate::writer::rect::Rect;
+    use crate::writer::style::Style;
+    use crate::writer::surface::MemorySurface;
+    use crate::writer::FrameBufferWriter;
+    use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+
+    /// Returns the text of the rows of the global writer.
+    fn console_text() -> Vec<String> {
+        let writer = WRITER.lock();
+        let whole = Rect::new(0, 0, usize::MAX, usize::MAX);
+        writer.as_ref().unwrap().get_text_region(whole).map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn module_paths_keep_their_last_two_segments() {
+        assert_eq!(abbreviate_module_path("kernel::memory::paging"), "memory::paging");
+        assert_eq!(abbreviate_module_path("a::b::c::d"), "c::d");
+        assert_eq!(abbreviate_module_path("memory::paging"), "memory::paging");
+        assert_eq!(abbreviate_module_path("kernel"), "kernel");
+        assert_eq!(abbreviate_module_path(""), "");
+    }
+
+    #[test]
+    fn only_lines_that_kprint_starts_get_the_prefix() {
+        let _console = crate::writer::test_console();
+        kprint!("a");
+        kprintln!("b");
+        crate::print!("x ");
+        kprintln!("y");
+        set_module_prefixes(false);
+        kprintln!("off");
+        set_module_prefixes(true);
+        assert_eq!(console_text(), ["[debug::tests] ab", "x y", "off", "", ""]);
+    }
+
+    #[test]
+    fn a_failed_kassert_eq_reports_the_condition_location_message_and_values() {
+        let _console = crate::writer::test_console();
+        // Wide enough that no line of the report wraps
+        let (width, height, bytes_per_pixel) = (1000, 120, 4);
+        let info = FrameBufferInfo {
+            byte_len: width * height * bytes_per_pixel,
+            width,
+            height,
+            pixel_format: PixelFormat::Rgb,
+            bytes_per_pixel,
+            stride: width,
+        };
+        let writer = FrameBufferWriter::from_surface(MemorySurface::zeroed(info));
+        *WRITER.lock() = Some(writer.unwrap());
+        let line = line!() + 1;
+        let failed = std::panic::catch_unwind(|| kassert_eq!(1 + 1, 3, "math is {}", "hard"));
+        let payload = failed.unwrap_err();
+        assert_eq!(payload.downcast_ref::<String>().unwrap(), "assertion failed: 1 + 1 == 3");
+
+        let text = console_text();
+        let (condition, location) = text[0].split_at("assertion failed: `1 + 1 == 3`".len());
+        assert_eq!(condition, "assertion failed: `1 + 1 == 3`");
+        let location_len = location.chars().count();
+        let prefix = std::format!(" at {}:{}:", file!(), line);
+        assert!(location.starts_with(&prefix), "{:?}", location);
+        assert_eq!(text[1..], ["  math is hard", "   left: 2", "  right: 3", ""]);
+        // The condition is bold red and the location after the space dim
+        let writer = WRITER.lock();
+        let mut rows = writer.as_ref().unwrap().get_text_region(Rect::new(0, 0, usize::MAX, 1));
+        let styles: Vec<_> = rows.next().unwrap().cells().map(|cell| cell.style).collect();
+        let (condition, location) = styles.split_at(condition.len());
+        assert!(condition.iter().all(|style| style.bold && style.foreground == Color::RED));
+        assert_eq!(location[0], Style::default());
+        let at = &location[1..location_len];
+        assert!(at.iter().all(|style| style.dim && !style.bold));
+    }
+}
@@ -0,0 +1,167 @@
+//! Stress test of the writer's escape sequence parser, UTF-8 decoder and line wrapping on the
+//! host: known tricky sequences and pseudo-random bytes are fed in chunks of random length to
+//! writers over memory of the host, and the state is checked with
+//! [FrameBufferWriter::check_consistency] after every chunk.
+//!
+//! A run is reproducible from its seed, which failures report. The environment variables
+//! `FUZZ_SEED` and `FUZZ_CHUNKS` choose the seed and the number of random chunks, e.g. for a run
+//! of minutes with `FUZZ_SEED=$RANDOM FUZZ_CHUNKS=10000000 cargo host-test --release fuzz`.
+use crate::writer::error::Inconsistency;
+use crate::writer::surface::MemorySurface;
+use crate::writer::FrameBufferWriter;
+use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+
+/// Number of chunks of random bytes fed per writer, unless `FUZZ_CHUNKS` says otherwise.
+const DEFAULT_CHUNKS: usize = 1_000;
+
+/// Seed of the random bytes, unless `FUZZ_SEED` says otherwise.
+const DEFAULT_SEED: u64 = 0x5eed;
+
+/// Longest chunk of random bytes.
+const MAX_CHUNK: usize = 64;
+
+/// Sequences that broke or nearly broke the writer, or that target its edge cases: truncated
+/// and unterminated escapes, absurd parameters, split and invalid UTF-8, and wide chars at the
+/// right edge.
+const CORPUS: &[&[u8]] = &[
+    b"\x1b",
+    b"\x1b[",
+    b"\x1b[38;5",
+    b"\x1b[38;2;255;255",
+    b"\x1b[48;5;999m",
+    b"\x1b[99999999999999999999m",
+    b"\x1b[;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;;m",
+    b"\x1b[1;2;3;4;5;6;7;8;9;21;22;23;24;25;26;27;28;29;30;31;38;5;1;48;2;1;2;3m",
+    b"\x1b[999;999H\x1b[999A\x1b[999D\x1b[999B\x1b[999C",
+    b"\x1b[0;0H\x1b[65535@\x1b[65535P\x1b[65535X",
+    b"\x1b[4h\xe6\xbc\xa2\xe6\xbc\xa2\xe6\xbc\xa2\x1b[4l",
+    b"\x1b[999S\x1b[999T\x1b[2J\x1b[3J",
+    b"\x1b[?25l\x1b[?25h\x1b[?1049h\x1b[?1049l",
+    b"\x1b[#{\x1b[#{\x1b[#}\x1b[#}\x1b[#}",
+    b"\x1b]0;title without terminator",
+    b"\x1b]0;\x1b\\\x1b]2;\x07\x1b]",
+    b"\xe2\x82",
+    b"\xac",
+    b"\xff\xfe\xc0\x80\xed\xa0\x80\xf4\x90\x80\x80",
+    b"\x08\x08\x08\r\r\n\r\n\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t\t",
+    b"a\xcc\x81\xcc\x81\xcc\x81\xe2\x80\x8d\xe2\x80\x8d",
+    b"\x1b[6n\x1b[5n\x1b[c\x1b[>c",
+    b"\x1b[1;1H\x1b[K\x1b[1K\x1b[2K\x1b[J\x1b[1J",
+    b"\x1b\x1b\x1b[[[\x1b[\x1b]\x1bP\x1b_\x1bX",
+];
+
+/// Bytes of which the random chunks are mostly made, so that they often form sequences.
+const ALPHABET: &[u8] = b"\x1b\x1b\x1b[[[;;;0123456789?]mmmHJKABCDhlSTPX@nsu\n\r\t\x08\x07 ax";
+
+/// A xorshift pseudo-random number generator.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift gets stuck at 0
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..bound`.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// Fills `chunk` with random bytes: mostly from [ALPHABET], sometimes a long SGR parameter
+/// list, and sometimes any byte, including invalid UTF-8.
+fn random_chunk<'a>(rng: &mut Rng, chunk: &'a mut [u8; MAX_CHUNK]) -> &'a [u8] {
+    if rng.below(32) == 0 {
+        // Room for the introducer and the final byte
+        let len = 3 + rng.below(MAX_CHUNK - 2);
+        chunk[..2].copy_from_slice(b"\x1b[");
+        for byte in &mut chunk[2..len] {
+            *byte = b"0123456789;;"[rng.below(12)];
+        }
+        chunk[len - 1] = b'm';
+        return &chunk[..len];
+    }
+    let len = 1 + rng.below(MAX_CHUNK);
+    for byte in &mut chunk[..len] {
+        *byte = match rng.below(8) {
+            0 => rng.next() as u8,
+            1 => b' ' + rng.below(95) as u8,
+            _ => ALPHABET[rng.below(ALPHABET.len())],
+        };
+    }
+    &chunk[..len]
+}
+
+/// Feeds `bytes` to the writer in chunks of random length, checking its state after every one.
+fn feed(writer: &mut FrameBufferWriter, rng: &mut Rng, bytes: &[u8]) -> Result<(), Inconsistency> {
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (chunk, tail) = rest.split_at(1 + rng.below(rest.len()));
+        writer.write_bytes(chunk);
+        writer.check_consistency()?;
+        rest = tail;
+    }
+    Ok(())
+}
+
+/// Returns the value of the environment variable `name`, or `default` if it isn't a number.
+fn setting<T: core::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+/// Returns writers over a usual framebuffer, and over one that leaves room for a few cells.
+fn writers() -> impl Iterator<Item = FrameBufferWriter> {
+    let surfaces = [(200, 120, PixelFormat::Bgr, 4), (48, 40, PixelFormat::U8, 1)];
+    surfaces.into_iter().map(|(width, height, pixel_format, bytes_per_pixel)| {
+        let byte_len = width * height * bytes_per_pixel;
+        let info = FrameBufferInfo {
+            byte_len,
+            width,
+            height,
+            pixel_format,
+            bytes_per_pixel,
+            stride: width,
+        };
+        FrameBufferWriter::from_surface(MemorySurface::zeroed(info)).unwrap()
+    })
+}
+
+#[test]
+fn corpus_keeps_the_writer_consistent() {
+    for mut writer in writers() {
+        // Every seed splits the entries at other places
+        for seed in 1..=16 {
+            let mut rng = Rng::new(seed);
+            for (index, &bytes) in CORPUS.iter().enumerate() {
+                if let Err(error) = feed(&mut writer, &mut rng, bytes) {
+                    panic!("corpus entry {} failed with seed {}: {}", index, seed, error);
+                }
+            }
+            writer.hard_reset();
+            writer.check_consistency().unwrap();
+        }
+    }
+}
+
+#[test]
+fn random_chunks_keep_the_writer_consistent() {
+    let seed = setting("FUZZ_SEED", DEFAULT_SEED);
+    let chunks = setting("FUZZ_CHUNKS", DEFAULT_CHUNKS);
+    for mut writer in writers() {
+        let mut rng = Rng::new(seed);
+        let mut chunk = [0; MAX_CHUNK];
+        for index in 0..chunks {
+            writer.write_bytes(random_chunk(&mut rng, &mut chunk));
+            if let Err(error) = writer.check_consistency() {
+                panic!("chunk {} failed with seed {}: {}", index, seed, error);
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! The kernel heap: a bump allocator on a static arena, so it works without paging, with
+//! counters for the shell's `heap` command.
+//!
+//! Freed memory is only reclaimed once every allocation has been freed, which makes the peak
+//! usage the number to watch.
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Size of the arena in bytes.
+pub const HEAP_SIZE: usize = 256 * 1024;
+
+/// Memory backing the heap.
+#[repr(C, align(4096))]
+struct Arena(UnsafeCell<[u8; HEAP_SIZE]>);
+
+// SAFETY: the bytes are only handed out through the allocator, one range per allocation.
+unsafe impl Sync for Arena {}
+
+static ARENA: Arena = Arena(UnsafeCell::new([0; HEAP_SIZE]));
+
+/// Counters of the heap, as returned by [alloc_stats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Bytes requested by all allocations so far.
+    pub allocated: usize,
+    /// Bytes released by all deallocations so far.
+    pub freed: usize,
+    /// Bytes currently in use, i.e. `allocated - freed`.
+    pub current: usize,
+    /// Highest value of `current` so far.
+    pub peak: usize,
+    /// Number of allocations so far.
+    pub allocations: usize,
+    /// Number of allocations that failed because the arena was exhausted.
+    pub failures: usize,
+    /// Bytes of the arena that are taken, including the ones lost to alignment and to freed
+    /// allocations that can't be reclaimed yet.
+    pub arena_used: usize,
+}
+
+/// State of the bump allocator proper.
+struct Bump {
+    /// Offset of the first free byte of the arena.
+    next: usize,
+    /// Number of allocations that haven't been freed; the arena is reset when it drops to 0.
+    live: usize,
+}
+
+/// Bump allocator with statistics.
+pub struct BumpAllocator {
+    bump: Mutex<Bump>,
+    /// Copy of [Bump::next] for the statistics, so reading them never waits for the lock.
+    arena_used: AtomicUsize,
+    allocated: AtomicUsize,
+    freed: AtomicUsize,
+    peak: AtomicUsize,
+    allocations: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+impl BumpAllocator {
+    pub const fn new() -> Self {
+        Self {
+            bump: Mutex::new(Bump { next: 0, live: 0 }),
+            arena_used: AtomicUsize::new(0),
+            allocated: AtomicUsize::new(0),
+            freed: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            failures: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a snapshot of the counters. They are updated independently, so a snapshot taken
+    /// during an allocation may be off by that allocation.
+    pub fn stats(&self) -> AllocStats {
+        let allocated = self.allocated.load(Ordering::Relaxed);
+        let freed = self.freed.load(Ordering::Relaxed);
+        AllocStats {
+            allocated,
+            freed,
+            current: allocated.saturating_sub(freed),
+            peak: self.peak.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            arena_used: self.arena_used.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for BumpAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: every allocation gets its own range of the arena, which is only reused once all
+// allocations have been freed.
+unsafe impl GlobalAlloc for BumpAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let base = ARENA.0.get() as usize;
+        let mut bump = self.bump.lock();
+        let aligned = (base + bump.next).checked_next_multiple_of(layout.align());
+        let start = aligned.map(|aligned| aligned - base);
+        let end = start.and_then(|start| start.checked_add(layout.size()));
+        let (Some(start), Some(end @ ..=HEAP_SIZE)) = (start, end) else {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            return ptr::null_mut();
+        };
+        bump.next = end;
+        bump.live += 1;
+        drop(bump);
+        self.arena_used.store(end, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        let allocated = self.allocated.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        let current = allocated.saturating_sub(self.freed.load(Ordering::Relaxed));
+        self.peak.fetch_max(current, Ordering::Relaxed);
+        // SAFETY: the range start..end lies inside the arena.
+        unsafe { ARENA.0.get().cast::<u8>().add(start) }
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, layout: Layout) {
+        self.freed.fetch_add(layout.size(), Ordering::Relaxed);
+        let mut bump = self.bump.lock();
+        bump.live -= 1;
+        if bump.live == 0 {
+            bump.next = 0;
+            self.arena_used.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// Host tests allocate from the host
+#[cfg_attr(not(test), global_allocator)]
+static ALLOCATOR: BumpAllocator = BumpAllocator::new();
+
+/// Returns the addresses of the memory backing the heap.
+pub fn arena() -> core::ops::Range<u64> {
+    let start = ARENA.0.get() as u64;
+    start..start + HEAP_SIZE as u64
+}
+
+/// Returns the counters of the kernel heap.
+pub fn alloc_stats() -> AllocStats {
+    ALLOCATOR.stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_counters_follow_a_script_of_allocations() {
+        // The only allocator of the tests that uses the arena
+        let heap = BumpAllocator::new();
+        let layout = |size, align| Layout::from_size_align(size, align).unwrap();
+        let expect = |allocated, freed, peak, allocations, failures, arena_used| AllocStats {
+            allocated,
+            freed,
+            current: allocated - freed,
+            peak,
+            allocations,
+            failures,
+            arena_used,
+        };
+        // SAFETY: every pointer is freed once, with the layout it was allocated with.
+        unsafe {
+            let first = heap.alloc(layout(100, 8));
+            assert_eq!(first, ARENA.0.get().cast());
+            // Aligned to 64 bytes after the first allocation
+            let second = heap.alloc(layout(30, 64));
+            assert_eq!(second, first.add(128));
+            assert_eq!(heap.stats(), expect(130, 0, 130, 2, 0, 158));
+            assert!(heap.alloc(layout(HEAP_SIZE, 1)).is_null());
+            assert_eq!(heap.stats(), expect(130, 0, 130, 2, 1, 158));
+            // Freed memory isn't reused while other allocations are live
+            heap.dealloc(first, layout(100, 8));
+            let third = heap.alloc(layout(10, 1));
+            assert_eq!(third, first.add(158));
+            assert_eq!(heap.stats(), expect(140, 100, 130, 3, 1, 168));
+            heap.dealloc(second, layout(30, 64));
+            heap.dealloc(third, layout(10, 1));
+            assert_eq!(heap.stats(), expect(140, 140, 130, 3, 1, 0));
+            // Once everything is freed, the arena starts over
+            let fourth = heap.alloc(layout(8, 8));
+            assert_eq!(fourth, first);
+            heap.dealloc(fourth, layout(8, 8));
+            assert_eq!(heap.stats(), expect(148, 148, 130, 4, 1, 0));
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Queue of key events shared by all input sources, so that consumers like the shell don't
+//! need to know whether a key came from the keyboard or the serial port.
+use spin::Mutex;
+
+/// Number of keys that can be queued before further keys are dropped.
+const QUEUE_CAPACITY: usize = 64;
+
+/// A key as seen by the consumers of the input queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// A printable character.
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+}
+
+/// Modifier keys that were held when a key was pressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { shift: false, ctrl: false, alt: false };
+}
+
+/// A pressed key together with the held modifiers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// Creates an event for a key pressed without modifiers.
+    pub const fn new(key: Key) -> Self {
+        Self { key, modifiers: Modifiers::NONE }
+    }
+}
+
+/// Fixed-size ring buffer of key events.
+struct KeyQueue {
+    events: [KeyEvent; QUEUE_CAPACITY],
+    head: usize,
+    len: usize,
+}
+
+impl KeyQueue {
+    const fn new() -> Self {
+        Self { events: [KeyEvent::new(Key::Enter); QUEUE_CAPACITY], head: 0, len: 0 }
+    }
+
+    /// Adds an event, dropping it if the queue is full.
+    fn push(&mut self, event: KeyEvent) {
+        if self.len < QUEUE_CAPACITY {
+            self.events[(self.head + self.len) % QUEUE_CAPACITY] = event;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<KeyEvent> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % QUEUE_CAPACITY;
+        self.len -= 1;
+        Some(event)
+    }
+}
+
+static QUEUE: Mutex<KeyQueue> = Mutex::new(KeyQueue::new());
+
+/// Adds a key event to the input queue.
+pub fn push_key(event: KeyEvent) {
+    QUEUE.lock().push(event);
+}
+
+/// Removes the oldest key event from the input queue.
+pub fn pop_key() -> Option<KeyEvent> {
+    QUEUE.lock().pop()
+}
+
+/// Maximum number of numeric parameters of an escape sequence the [SerialDecoder] keeps.
+const MAX_PARAMS: usize = 2;
+
+/// Translates the bytes a terminal sends over a serial line into [KeyEvent]s: CR, LF and CRLF
+/// become a single [Key::Enter], DEL and BS become [Key::Backspace], the other control
+/// characters of letters become the letter with Ctrl, e.g. 0x15 is Ctrl+U, and the xterm
+/// sequences for the arrow and navigation keys (`ESC [ A`, `ESC [ 5 ~`, `ESC [ 1 ; 2 H`, ...)
+/// become the corresponding keys, including their modifiers. Other control characters and
+/// non-ASCII bytes are dropped.
+pub struct SerialDecoder {
+    state: DecoderState,
+    /// Whether the previous byte was a CR, so that a following LF is swallowed.
+    after_cr: bool,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+}
+
+#[derive(Clone, Copy)]
+enum DecoderState {
+    Ground,
+    Escape,
+    Csi,
+}
+
+impl SerialDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: DecoderState::Ground,
+            after_cr: false,
+            params: [0; MAX_PARAMS],
+            param_count: 0,
+        }
+    }
+
+    /// Feeds one received byte, returning the key event it completes, if any.
+    pub fn decode(&mut self, byte: u8) -> Option<KeyEvent> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        let key = match (self.state, byte) {
+            (DecoderState::Ground, 0x1b) => {
+                self.state = DecoderState::Escape;
+                None
+            }
+            (DecoderState::Ground, b'\n') if after_cr => None,
+            (DecoderState::Ground, b'\r' | b'\n') => Some(Key::Enter),
+            (DecoderState::Ground, 0x08 | 0x7f) => Some(Key::Backspace),
+            (DecoderState::Ground, b' '..=b'~') => Some(Key::Char(byte as char)),
+            (DecoderState::Ground, 0x01..=0x1a) if byte != b'\t' => {
+                let key = Key::Char((b'a' + byte - 1) as char);
+                let modifiers = Modifiers { ctrl: true, ..Modifiers::NONE };
+                return Some(KeyEvent { key, modifiers });
+            }
+            (DecoderState::Ground, _) => None,
+            (DecoderState::Escape, b'[') => {
+                self.state = DecoderState::Csi;
+                (self.params, self.param_count) = ([0; MAX_PARAMS], 1);
+                None
+            }
+            (DecoderState::Escape, _) => {
+                self.state = DecoderState::Ground;
+                None
+            }
+            (DecoderState::Csi, b'0'..=b'9') => {
+                if let Some(param) = self.params.get_mut(self.param_count - 1) {
+                    *param = param.saturating_mul(10).saturating_add(u16::from(byte - b'0'));
+                }
+                None
+            }
+            (DecoderState::Csi, b';') => {
+                self.param_count += 1;
+                None
+            }
+            // Other parameter bytes of sequences that are ignored once complete
+            (DecoderState::Csi, b':'..=b'?') => None,
+            (DecoderState::Csi, _) => {
+                self.state = DecoderState::Ground;
+                return self.decode_csi(byte);
+            }
+        };
+        key.map(KeyEvent::new)
+    }
+
+    /// Maps a complete CSI sequence ending in `final_byte` to a key event.
+    fn decode_csi(&self, final_byte: u8) -> Option<KeyEvent> {
+        let key = match (final_byte, self.params[0]) {
+            (b'A', _) => Key::Up,
+            (b'B', _) => Key::Down,
+            (b'C', _) => Key::Right,
+            (b'D', _) => Key::Left,
+            (b'H', _) | (b'~', 1 | 7) => Key::Home,
+            (b'F', _) | (b'~', 4 | 8) => Key::End,
+            (b'~', 3) => Key::Delete,
+            (b'~', 5) => Key::PageUp,
+            (b'~', 6) => Key::PageDown,
+            _ => return None,
+        };
+        // xterm encodes the modifiers as 1 + a bit mask in the second parameter
+        let mask = self.params[1].saturating_sub(1);
+        let modifiers = Modifiers { shift: mask & 1 != 0, alt: mask & 2 != 0, ctrl: mask & 4 != 0 };
+        Some(KeyEvent { key, modifiers })
+    }
+}
+
+impl Default for SerialDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the key events that `bytes` decode to.
+    fn decode(bytes: &[u8]) -> Vec<KeyEvent> {
+        let mut decoder = SerialDecoder::new();
+        bytes.iter().filter_map(|&byte| decoder.decode(byte)).collect()
+    }
+
+    /// Returns the keys of the events that `bytes` decode to.
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        decode(bytes).into_iter().map(|event| event.key).collect()
+    }
+
+    #[test]
+    fn arrow_keys_are_decoded_from_their_sequences() {
+        let keys = decode(b"\x1b[A\x1b[B\x1b[C\x1b[D");
+        let expected = [Key::Up, Key::Down, Key::Right, Key::Left].map(KeyEvent::new);
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn modifiers_of_arrow_keys_are_decoded_from_the_second_parameter() {
+        let shift = Modifiers { shift: true, ..Modifiers::NONE };
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+        let ctrl_alt = Modifiers { alt: true, ..ctrl };
+        let keys = decode(b"\x1b[1;2A\x1b[1;5D\x1b[1;7C");
+        let expected = [(Key::Up, shift), (Key::Left, ctrl), (Key::Right, ctrl_alt)];
+        assert_eq!(keys, expected.map(|(key, modifiers)| KeyEvent { key, modifiers }));
+    }
+
+    #[test]
+    fn a_sequence_is_only_a_key_once_it_is_complete() {
+        let mut decoder = SerialDecoder::new();
+        let events: Vec<_> = b"\x1b[1;5A".iter().map(|&byte| decoder.decode(byte)).collect();
+        assert!(events[..5].iter().all(Option::is_none));
+        assert_eq!(events[5].map(|event| event.key), Some(Key::Up));
+        // Plain chars around sequences pass through
+        assert_eq!(keys(b"a\x1b[Db"), [Key::Char('a'), Key::Left, Key::Char('b')]);
+    }
+
+    #[test]
+    fn unknown_sequences_and_lone_escapes_are_dropped() {
+        assert_eq!(keys(b"\x1b[Z\x1bOx\x1b[?25hy"), [Key::Char('x'), Key::Char('y')]);
+    }
+}
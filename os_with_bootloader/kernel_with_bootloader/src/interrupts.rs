@@ -0,0 +1,177 @@
+//! The interrupt descriptor table. Only page faults are handled so far; they are reported and
+//! halt the CPU.
+//!
+//! Handlers record the [Context] they run in, so that the log can tell their output apart.
+use crate::output;
+use crate::writer::color::Color;
+use crate::writer::colored::Colored;
+use core::fmt;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use spin::{Lazy, Mutex};
+use x86_64::instructions::hlt;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+
+/// Maximum number of regions recorded with [record_region].
+pub const MAX_REGIONS: usize = 8;
+
+/// A named range of virtual addresses, e.g. the kernel stack.
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    name: &'static str,
+    start: u64,
+    end: u64,
+}
+
+static REGIONS: Mutex<[Option<Region>; MAX_REGIONS]> = Mutex::new([None; MAX_REGIONS]);
+
+/// Records a range of virtual addresses, so that faults inside it are reported with its name.
+/// Further regions beyond [MAX_REGIONS] are ignored.
+pub fn record_region(name: &'static str, range: Range<u64>) {
+    let mut regions = REGIONS.lock();
+    if let Some(free) = regions.iter_mut().find(|region| region.is_none()) {
+        *free = Some(Region { name, start: range.start, end: range.end });
+    }
+}
+
+/// Returns the name of the recorded region that contains `address`, unless the regions are
+/// locked.
+fn region_of(address: u64) -> Option<&'static str> {
+    let regions = REGIONS.try_lock()?;
+    let mut regions = regions.iter().flatten();
+    regions.find(|region| (region.start..region.end).contains(&address)).map(|region| region.name)
+}
+
+/// Maximum nesting depth of interrupt contexts that is tracked. Deeper ones are counted, but
+/// reported as the innermost tracked one.
+const MAX_NESTING: usize = 8;
+
+/// What the CPU is executing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Context {
+    /// The main loop or boot code, i.e. no interrupt handler.
+    Main,
+    /// The page fault handler.
+    PageFault,
+}
+
+impl Context {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Main => 0,
+            Self::PageFault => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            1 => Self::PageFault,
+            _ => Self::Main,
+        }
+    }
+}
+
+impl fmt::Display for Context {
+    /// Formats as the short name used in log prefixes, e.g. `#PF`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Main => f.write_str("main"),
+            Self::PageFault => f.write_str("#PF"),
+        }
+    }
+}
+
+/// The contexts of the running handlers, innermost last, up to [MAX_NESTING].
+static CONTEXTS: [AtomicU8; MAX_NESTING] = [const { AtomicU8::new(0) }; MAX_NESTING];
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Leaves the context entered by [enter] when dropped.
+pub struct ContextGuard(());
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        DEPTH.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Records that a handler for `context` starts running, until the returned guard is dropped.
+/// Handlers call this first, so that everything they log is attributed to them.
+pub fn enter(context: Context) -> ContextGuard {
+    let depth = DEPTH.fetch_add(1, Ordering::Relaxed);
+    if let Some(slot) = CONTEXTS.get(depth) {
+        slot.store(context.to_byte(), Ordering::Relaxed);
+    }
+    ContextGuard(())
+}
+
+/// Returns the context of the innermost running handler, or [Context::Main].
+pub fn context() -> Context {
+    match DEPTH.load(Ordering::Relaxed) {
+        0 => Context::Main,
+        depth => Context::from_byte(CONTEXTS[depth.min(MAX_NESTING) - 1].load(Ordering::Relaxed)),
+    }
+}
+
+static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
+    let mut idt = InterruptDescriptorTable::new();
+    idt.page_fault.set_handler_fn(page_fault);
+    idt
+});
+
+/// Loads the interrupt descriptor table.
+pub fn init() {
+    IDT.load();
+}
+
+/// Displays a flag of the page fault error code, in red if it hints at a bug rather than at a
+/// page that isn't mapped yet.
+struct Flag {
+    set: bool,
+    /// Whether a set flag is suspicious.
+    suspicious: bool,
+    meaning: (&'static str, &'static str),
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (set, clear) = self.meaning;
+        match (self.set, self.suspicious) {
+            (true, true) => write!(f, "{}", Colored(set, Color::RED)),
+            (true, false) => f.write_str(set),
+            (false, _) => f.write_str(clear),
+        }
+    }
+}
+
+extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, code: PageFaultErrorCode) {
+    let _context = enter(Context::PageFault);
+    let address = Cr2::read().as_u64();
+    let flag = |bit, suspicious, meaning| Flag { set: code.contains(bit), suspicious, meaning };
+    // Sinks whose locks are held by the interrupted code are skipped instead of waited for
+    output::try_write_fmt(format_args!("\n=== PAGE FAULT ===\n"));
+    let note = if address < 0x1000 { " (null pointer)" } else { "" };
+    output::try_write_fmt(format_args!(
+        "{:<14}{:#018x}{}\n",
+        "address",
+        address,
+        Colored(note, Color::RED)
+    ));
+    let instruction = frame.instruction_pointer.as_u64();
+    output::try_write_fmt(format_args!("{:<14}{:#018x}\n", "instruction", instruction));
+    output::try_write_fmt(format_args!("{:<14}{:#06x}\n", "error code", code.bits()));
+    for (label, flag) in [
+        ("  present", flag(PageFaultErrorCode::PROTECTION_VIOLATION, false, ("yes", "no"))),
+        ("  access", flag(PageFaultErrorCode::CAUSED_BY_WRITE, false, ("write", "read"))),
+        ("  mode", flag(PageFaultErrorCode::USER_MODE, true, ("user", "kernel"))),
+        ("  reserved", flag(PageFaultErrorCode::MALFORMED_TABLE, true, ("set", "clear"))),
+        ("  fetch", flag(PageFaultErrorCode::INSTRUCTION_FETCH, true, ("yes", "no"))),
+    ] {
+        output::try_write_fmt(format_args!("{:<14}{}\n", label, flag));
+    }
+    let region = region_of(address).unwrap_or("none");
+    output::try_write_fmt(format_args!("{:<14}{}\n", "region", region));
+    loop {
+        hlt();
+    }
+}
@@ -0,0 +1,430 @@
+//! Polled driver for the PS/2 keyboard, translating scancode set 1 with a [layout] into
+//! events for the [input] queue. The state of the modifiers and lock keys is shown in a field
+//! of the header band.
+pub mod layout;
+
+pub use layout::Layout;
+
+use crate::input::{self, Key, KeyEvent, Modifiers};
+use crate::writer::grid::Cell;
+use crate::writer::style::Style;
+use crate::writer::WRITER;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+/// Data port of the PS/2 controller.
+const DATA_PORT: u16 = 0x60;
+
+/// Status port of the PS/2 controller.
+const STATUS_PORT: u16 = 0x64;
+
+/// Status bit that is set when a byte is waiting in the data port.
+const OUTPUT_FULL: u8 = 1 << 0;
+
+/// Status bit that is set while the controller hasn't taken the last byte sent to it yet.
+const INPUT_FULL: u8 = 1 << 1;
+
+/// Status bit that is set when the waiting byte comes from the mouse instead.
+const AUX_DATA: u8 = 1 << 5;
+
+/// Command that sets the keyboard LEDs to the bits of the following byte.
+const SET_LEDS: u8 = 0xed;
+
+/// LED bits of [SET_LEDS].
+const NUM_LOCK_LED: u8 = 1 << 1;
+const CAPS_LOCK_LED: u8 = 1 << 2;
+
+/// Header band field that shows the modifiers, see
+/// [set_status_field](crate::writer::FrameBufferWriter::set_status_field).
+pub const INDICATOR_FIELD: usize = 0;
+
+/// Prefix of the scancodes of the extended keys.
+const EXTENDED: u8 = 0xe0;
+
+/// Bit that distinguishes a release from a press.
+const RELEASED: u8 = 0x80;
+
+/// Modifiers that are held and lock keys that are on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Indicators {
+    pub modifiers: Modifiers,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+impl Indicators {
+    /// Renders the indicators as `[SHIFT CTRL ALT CAPS NUM]` in reverse video for the header
+    /// band, with the active ones bold and the others dim.
+    pub fn cells(&self) -> [Cell; 25] {
+        let base = Style { reverse: true, ..Style::DEFAULT };
+        let mut cells = [Cell::blank(base); 25];
+        cells[0].c = '[';
+        cells[24].c = ']';
+        let mut column = 1;
+        for (label, active) in [
+            ("SHIFT", self.modifiers.shift),
+            ("CTRL", self.modifiers.ctrl),
+            ("ALT", self.modifiers.alt),
+            ("CAPS", self.caps_lock),
+            ("NUM", self.num_lock),
+        ] {
+            let style = Style { bold: active, dim: !active, ..base };
+            for c in label.chars() {
+                cells[column] = Cell::new(c, style);
+                column += 1;
+            }
+            column += 1;
+        }
+        cells
+    }
+}
+
+/// Scancode decoding state.
+struct Keyboard {
+    modifiers: Modifiers,
+    left_shift: bool,
+    right_shift: bool,
+    left_alt: bool,
+    /// The right Alt, which is AltGr for the keys that the layout gives a char with it.
+    right_alt: bool,
+    caps_lock: bool,
+    num_lock: bool,
+    /// Whether the previous byte was [EXTENDED].
+    extended: bool,
+    /// Indicators that the header band shows, or `None` before they were shown first.
+    shown: Option<Indicators>,
+    layout: &'static Layout,
+}
+
+impl Keyboard {
+    const fn new() -> Self {
+        Self {
+            modifiers: Modifiers::NONE,
+            left_shift: false,
+            right_shift: false,
+            left_alt: false,
+            right_alt: false,
+            caps_lock: false,
+            num_lock: false,
+            extended: false,
+            shown: None,
+            layout: &layout::US,
+        }
+    }
+
+    fn indicators(&self) -> Indicators {
+        Indicators { modifiers: self.modifiers, caps_lock: self.caps_lock, num_lock: self.num_lock }
+    }
+
+    /// Feeds one scancode byte, returning the key event it completes, if any.
+    fn decode(&mut self, byte: u8) -> Option<KeyEvent> {
+        if byte == EXTENDED {
+            self.extended = true;
+            return None;
+        }
+        let extended = core::mem::replace(&mut self.extended, false);
+        let (code, pressed) = (byte & !RELEASED, byte & RELEASED == 0);
+        match (extended, code) {
+            (false, 0x2a) => self.left_shift = pressed,
+            (false, 0x36) => self.right_shift = pressed,
+            (_, 0x1d) => self.modifiers.ctrl = pressed,
+            (false, 0x38) => self.left_alt = pressed,
+            (true, 0x38) => self.right_alt = pressed,
+            _ if !pressed => {}
+            (false, 0x3a) => {
+                self.caps_lock = !self.caps_lock;
+                self.update_leds();
+            }
+            (false, 0x45) => {
+                self.num_lock = !self.num_lock;
+                self.update_leds();
+            }
+            (true, code) => return self.event(extended_key(code)?),
+            (false, code) => {
+                if let Some(c) = self.layout.alt_gr(code).filter(|_| self.right_alt) {
+                    // AltGr selects the char instead of acting as Alt
+                    let modifiers = Modifiers { alt: self.left_alt, ..self.modifiers };
+                    return Some(KeyEvent { key: Key::Char(c), modifiers });
+                }
+                let (plain, shifted) = self.layout.chars(code)?;
+                // Caps lock only affects letters, and shift undoes it
+                let letter = plain.is_lowercase() && shifted.is_uppercase();
+                let shift = self.modifiers.shift ^ (self.caps_lock && letter);
+                let key = match if shift { shifted } else { plain } {
+                    '\0' | '\x1b' | '\t' => return None,
+                    '\n' => Key::Enter,
+                    '\x08' => Key::Backspace,
+                    c => Key::Char(c),
+                };
+                return self.event(key);
+            }
+        }
+        self.modifiers.shift = self.left_shift || self.right_shift;
+        self.modifiers.alt = self.left_alt || self.right_alt;
+        None
+    }
+
+    fn event(&self, key: Key) -> Option<KeyEvent> {
+        Some(KeyEvent { key, modifiers: self.modifiers })
+    }
+
+    /// Lights the LEDs of the lock keys that are on. The keyboard acknowledges both bytes with
+    /// `0xfa`, which [Keyboard::decode] ignores like any other release of an unknown key.
+    fn update_leds(&self) {
+        let led = |on: bool, bit: u8| if on { bit } else { 0 };
+        let leds = led(self.num_lock, NUM_LOCK_LED) | led(self.caps_lock, CAPS_LOCK_LED);
+        send(SET_LEDS);
+        send(leds);
+    }
+
+    /// Shows the indicators in the header band if they changed since they were last shown.
+    /// If the writer is busy, they are shown by a later poll.
+    fn show_indicators(&mut self) {
+        let indicators = self.indicators();
+        if self.shown == Some(indicators) {
+            return;
+        }
+        let Some(mut writer) = WRITER.try_lock() else { return };
+        if let Some(writer) = writer.as_mut() {
+            writer.set_status_field(INDICATOR_FIELD, &indicators.cells());
+        }
+        self.shown = Some(indicators);
+    }
+}
+
+/// Sends a byte to the keyboard once the controller is ready for it, or drops it if the
+/// controller doesn't become ready.
+fn send(byte: u8) {
+    // Host tests run in user mode, which may not access I/O ports, so there nothing is sent
+    if cfg!(test) {
+        return;
+    }
+    let mut status_port = Port::<u8>::new(STATUS_PORT);
+    let mut data_port = Port::<u8>::new(DATA_PORT);
+    for _ in 0..100_000 {
+        // SAFETY: reading the status port has no side effects, and writing the data port once
+        // the input buffer is empty sends a byte to the keyboard.
+        unsafe {
+            if status_port.read() & INPUT_FULL == 0 {
+                return data_port.write(byte);
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// Maps the code following an [EXTENDED] prefix to a key.
+fn extended_key(code: u8) -> Option<Key> {
+    Some(match code {
+        0x1c => Key::Enter,
+        0x47 => Key::Home,
+        0x48 => Key::Up,
+        0x49 => Key::PageUp,
+        0x4b => Key::Left,
+        0x4d => Key::Right,
+        0x4f => Key::End,
+        0x50 => Key::Down,
+        0x51 => Key::PageDown,
+        0x53 => Key::Delete,
+        _ => return None,
+    })
+}
+
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard::new());
+
+/// Moves all pending scancodes into the input queue as [KeyEvent]s.
+pub fn poll() {
+    let mut keyboard = KEYBOARD.lock();
+    let mut status_port = Port::<u8>::new(STATUS_PORT);
+    let mut data_port = Port::<u8>::new(DATA_PORT);
+    // Host tests run in user mode, which may not access I/O ports, so there no scancodes arrive
+    while !cfg!(test) {
+        // SAFETY: reading the status port has no side effects, and reading the data port only
+        // consumes the byte the status announced.
+        let status = unsafe { status_port.read() };
+        if status & OUTPUT_FULL == 0 {
+            break;
+        }
+        let byte = unsafe { data_port.read() };
+        if status & AUX_DATA != 0 {
+            continue;
+        }
+        if let Some(event) = keyboard.decode(byte) {
+            input::push_key(event);
+        }
+    }
+    keyboard.show_indicators();
+}
+
+/// Switches the layout that scancodes are translated with, e.g. to one of [layout::LAYOUTS].
+pub fn set_layout(layout: &'static Layout) {
+    KEYBOARD.lock().layout = layout;
+}
+
+/// Returns the layout that scancodes are translated with; [layout::US] unless changed.
+pub fn layout() -> &'static Layout {
+    KEYBOARD.lock().layout
+}
+
+/// Returns the modifiers that are held and the lock keys that are on.
+pub fn indicators() -> Indicators {
+    KEYBOARD.lock().indicators()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::color::Color;
+    use crate::writer::surface::MemorySurface;
+    use crate::writer::FrameBufferWriter;
+    use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+
+    /// Feeds scancode bytes and returns the indicator cells as text, with the labels of the
+    /// active indicators and dots for the others.
+    fn feed(keyboard: &mut Keyboard, bytes: &[u8]) -> String {
+        for &byte in bytes {
+            keyboard.decode(byte);
+        }
+        let cells = keyboard.indicators().cells();
+        for cell in &cells[1..24] {
+            assert!(cell.style.reverse);
+            if cell.c != ' ' {
+                assert_ne!(cell.style.bold, cell.style.dim, "{:?}", cell);
+            }
+        }
+        let shown = |cell: &Cell| match cell.style.dim {
+            true => '.',
+            false => cell.c,
+        };
+        cells.iter().map(shown).collect()
+    }
+
+    #[test]
+    fn the_indicators_follow_the_modifiers_and_lock_keys() {
+        let mut keyboard = Keyboard::new();
+        let steps: [(&[u8], &str); 9] = [
+            (&[], "[..... .... ... .... ...]"),
+            // Left shift, then the right one held while the left one is released
+            (&[0x2a], "[SHIFT .... ... .... ...]"),
+            (&[0x36, 0xaa], "[SHIFT .... ... .... ...]"),
+            (&[0xb6], "[..... .... ... .... ...]"),
+            // Right Ctrl and right Alt are extended
+            (&[EXTENDED, 0x1d, EXTENDED, 0x38], "[..... CTRL ALT .... ...]"),
+            (&[EXTENDED, 0x9d, 0x38, EXTENDED, 0xb8], "[..... .... ALT .... ...]"),
+            // Lock keys toggle on the press and stay on after the release
+            (&[0xb8, 0x3a, 0xba], "[..... .... ... CAPS ...]"),
+            (&[0x45, 0xc5], "[..... .... ... CAPS NUM]"),
+            (&[0x3a, 0xba], "[..... .... ... .... NUM]"),
+        ];
+        for (bytes, expected) in steps {
+            assert_eq!(feed(&mut keyboard, bytes), expected, "after {:02x?}", bytes);
+        }
+    }
+
+    /// Returns what the scancode bytes type in `layout`, with `M-` before chars typed with Alt.
+    fn typed(layout: &'static Layout, bytes: &[u8]) -> String {
+        let mut keyboard = Keyboard { layout, ..Keyboard::new() };
+        let mut text = String::new();
+        for event in bytes.iter().filter_map(|&byte| keyboard.decode(byte)) {
+            let Key::Char(c) = event.key else { panic!("{:?}", event) };
+            if event.modifiers.alt {
+                text.push_str("M-");
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    /// Left shift pressed and released.
+    const SHIFT: u8 = 0x2a;
+    const UNSHIFT: u8 = 0xaa;
+
+    /// AltGr, i.e. the right Alt, pressed and released.
+    const ALT_GR: [u8; 2] = [EXTENDED, 0x38];
+    const ALT_GR_UP: [u8; 2] = [EXTENDED, 0xb8];
+
+    #[test]
+    fn every_layout_maps_the_scancodes_to_its_own_chars() {
+        // Y, Z, 2, shifted 2 and 3, the key left of Enter and the ISO key
+        let keys = [0x15, 0x2c, 0x03, SHIFT, 0x03, 0x04, UNSHIFT, 0x2b, layout::ISO_KEY];
+        assert_eq!(typed(&layout::US, &keys), "yz2@#\\");
+        assert_eq!(typed(&layout::UK, &keys), "yz2\"£#\\");
+        assert_eq!(typed(&layout::DE, &keys), "zy2\"§#<");
+        // The keys right of the digits and the shifted ISO key
+        let keys = [0x0c, 0x0d, 0x1a, SHIFT, 0x0c, 0x1a, layout::ISO_KEY];
+        assert_eq!(typed(&layout::US, &keys), "-=[_{");
+        assert_eq!(typed(&layout::DE, &keys), "ß´ü?Ü>");
+        // Caps lock only affects letters, like the umlauts
+        let keys = [0x3a, 0xba, 0x1a, 0x0c, 0x15];
+        assert_eq!(typed(&layout::DE, &keys), "ÜßZ");
+    }
+
+    #[test]
+    fn alt_gr_types_the_third_layer_or_acts_as_alt() {
+        let keys = [ALT_GR[0], ALT_GR[1], 0x05, 0x10, layout::ISO_KEY, ALT_GR_UP[0], ALT_GR_UP[1]];
+        let keys = [&keys[..], &[0x05]].concat();
+        // The US layout has no ISO key
+        assert_eq!(typed(&layout::US, &keys), "M-4M-q4");
+        assert_eq!(typed(&layout::UK, &keys), "€M-qM-\\4");
+        // The German € is on E instead of 4
+        assert_eq!(typed(&layout::DE, &keys), "M-4@|4");
+        assert_eq!(typed(&layout::DE, &[ALT_GR[0], ALT_GR[1], 0x12]), "€");
+    }
+
+    #[test]
+    fn layouts_are_found_by_name() {
+        for layout in layout::LAYOUTS {
+            assert!(core::ptr::eq(layout::find(layout.name()).unwrap(), layout));
+        }
+        assert!(layout::find("fr").is_none());
+    }
+
+    /// Returns the pixels of the header band of the console.
+    fn header() -> Vec<Option<Color>> {
+        let writer = WRITER.lock();
+        let writer = writer.as_ref().unwrap();
+        let (width, _) = writer.screen_size();
+        (0..20).flat_map(|y| (0..width).map(move |x| writer.get_pixel(x, y))).collect()
+    }
+
+    #[test]
+    fn the_indicators_are_shown_in_the_header_band_once_the_writer_is_free() {
+        let _console = crate::writer::test_console();
+        // The field is wider than the console of the tests
+        let (width, height, bytes_per_pixel) = (400, 40, 4);
+        let info = FrameBufferInfo {
+            byte_len: width * height * bytes_per_pixel,
+            width,
+            height,
+            pixel_format: PixelFormat::Rgb,
+            bytes_per_pixel,
+            stride: width,
+        };
+        let mut writer = FrameBufferWriter::from_surface(MemorySurface::zeroed(info)).unwrap();
+        writer.set_header_band(true);
+        *WRITER.lock() = Some(writer);
+        let mut keyboard = Keyboard::new();
+        let blank = header();
+        keyboard.show_indicators();
+        assert_eq!(keyboard.shown, Some(Indicators::default()));
+        let shown = header();
+        assert_ne!(shown, blank);
+
+        // While the writer is locked, the change waits for a later poll
+        keyboard.decode(0x2a);
+        let writer = WRITER.lock();
+        keyboard.show_indicators();
+        drop(writer);
+        assert_eq!(keyboard.shown, Some(Indicators::default()));
+        assert_eq!(header(), shown);
+        keyboard.show_indicators();
+        assert_eq!(keyboard.shown, Some(keyboard.indicators()));
+        let shifted = header();
+        assert_ne!(shifted, shown);
+
+        // The field holds exactly the cells of the indicators
+        let cells = keyboard.indicators().cells();
+        WRITER.lock().as_mut().unwrap().set_status_field(INDICATOR_FIELD, &cells);
+        assert_eq!(header(), shifted);
+    }
+}
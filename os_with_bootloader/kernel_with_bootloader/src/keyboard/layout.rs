@@ -0,0 +1,124 @@
+//! Keyboard layouts: the chars that the printable keys type, by scancode of set 1. A layout is
+//! a single static of plain data, see [Layout::new].
+//!
+//! Dead keys, like the accents of the German layout, type their accent on their own instead of
+//! combining it with the next letter.
+
+/// Number of scancodes, starting at 0, that the base and shifted layers cover.
+pub const KEYS: usize = 0x3a;
+
+/// Scancode of the extra key between the left shift and Z on ISO keyboards.
+pub const ISO_KEY: u8 = 0x56;
+
+/// Returns the number of chars of `text`, i.e. of bytes that don't continue a UTF-8 sequence.
+const fn char_count(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let (mut count, mut i) = (0, 0);
+    while i < bytes.len() {
+        if bytes[i] & 0xc0 != 0x80 {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// The chars that the keys of a keyboard type, with and without shift and with AltGr.
+#[derive(Debug)]
+pub struct Layout {
+    name: &'static str,
+    base: &'static str,
+    shifted: &'static str,
+    alt_gr: &'static [(u8, char)],
+    iso_key: (char, char),
+}
+
+impl Layout {
+    /// Creates a layout from the chars of the scancodes `0..KEYS` without and with shift, one
+    /// char per scancode with `\0` for keys that type none, the chars typed with AltGr by
+    /// scancode, and the chars of the [ISO_KEY] without and with shift.
+    ///
+    /// # Panics
+    ///
+    /// Panics, at compile time for a static, if a layer doesn't have [KEYS] chars.
+    pub const fn new(
+        name: &'static str,
+        base: &'static str,
+        shifted: &'static str,
+        alt_gr: &'static [(u8, char)],
+        iso_key: (char, char),
+    ) -> Self {
+        assert!(char_count(base) == KEYS && char_count(shifted) == KEYS);
+        Self { name, base, shifted, alt_gr, iso_key }
+    }
+
+    /// Returns the name that the shell's `layout` command selects the layout by.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the chars that the key with the given scancode types without and with shift, or
+    /// `None` if it types none.
+    pub fn chars(&self, code: u8) -> Option<(char, char)> {
+        if code == ISO_KEY {
+            return Some(self.iso_key).filter(|&(base, _)| base != '\0');
+        }
+        let code = usize::from(code);
+        let base = self.base.chars().nth(code)?;
+        let shifted = self.shifted.chars().nth(code)?;
+        (base != '\0').then_some((base, shifted))
+    }
+
+    /// Returns the char that the key with the given scancode types with AltGr, if any.
+    pub fn alt_gr(&self, code: u8) -> Option<char> {
+        self.alt_gr.iter().find(|&&(key, _)| key == code).map(|&(_, c)| c)
+    }
+}
+
+/// US QWERTY, where the right Alt is a plain Alt.
+pub static US: Layout = Layout::new(
+    "us",
+    "\0\x1b1234567890-=\x08\tqwertyuiop[]\n\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ",
+    "\0\x1b!@#$%^&*()_+\x08\tQWERTYUIOP{}\n\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ",
+    &[],
+    ('\0', '\0'),
+);
+
+/// UK QWERTY.
+pub static UK: Layout = Layout::new(
+    "uk",
+    "\0\x1b1234567890-=\x08\tqwertyuiop[]\n\0asdfghjkl;'`\0#zxcvbnm,./\0*\0 ",
+    "\0\x1b!\"£$%^&*()_+\x08\tQWERTYUIOP{}\n\0ASDFGHJKL:@¬\0~ZXCVBNM<>?\0*\0 ",
+    &[(0x05, '€'), (0x29, '¦')],
+    ('\\', '|'),
+);
+
+/// German QWERTZ. `^`, `´` and `` ` `` are dead keys.
+pub static DE: Layout = Layout::new(
+    "de",
+    "\0\x1b1234567890ß´\x08\tqwertzuiopü+\n\0asdfghjklöä^\0#yxcvbnm,.-\0*\0 ",
+    "\0\x1b!\"§$%&/()=?`\x08\tQWERTZUIOPÜ*\n\0ASDFGHJKLÖÄ°\0'YXCVBNM;:_\0*\0 ",
+    &[
+        (0x03, '²'),
+        (0x04, '³'),
+        (0x08, '{'),
+        (0x09, '['),
+        (0x0a, ']'),
+        (0x0b, '}'),
+        (0x0c, '\\'),
+        (0x10, '@'),
+        (0x12, '€'),
+        (0x1b, '~'),
+        (0x32, 'µ'),
+        (ISO_KEY, '|'),
+    ],
+    ('<', '>'),
+);
+
+/// The layouts that [find] knows.
+pub static LAYOUTS: [&Layout; 3] = [&US, &UK, &DE];
+
+/// Returns the layout with the given name.
+pub fn find(name: &str) -> Option<&'static Layout> {
+    LAYOUTS.iter().copied().find(|layout| layout.name == name)
+}
@@ -0,0 +1,25 @@
+#![cfg_attr(not(test), no_std)]
+#![feature(abi_x86_interrupt)]
+
+pub mod bench;
+pub mod boot;
+pub mod cmdline;
+pub mod debug;
+#[cfg(test)]
+mod fuzz;
+pub mod heap;
+pub mod input;
+pub mod interrupts;
+pub mod keyboard;
+pub mod log;
+pub mod output;
+pub mod pager;
+pub mod qr;
+pub mod rtc;
+pub mod screenshot;
+pub mod serial;
+pub mod shell;
+pub mod time;
+pub mod units;
+pub mod watch;
+pub mod writer;
@@ -0,0 +1,496 @@
+//! Kernel log: leveled messages that are printed to the console and kept in a [ring] buffer,
+//! so they can be replayed later with the shell's `dmesg`. Messages can be [filter]ed by the
+//! module they are logged from, and a message that [repeat]s the one before isn't printed again.
+pub mod filter;
+pub mod repeat;
+pub mod ring;
+
+pub use filter::{set_filter, LevelFilter};
+
+use crate::interrupts::{self, Context};
+use crate::{output, time};
+use crate::writer::color::Color;
+use crate::writer::colored::{Colored, Styled};
+use crate::writer::style::Style;
+use crate::writer::WRITER;
+use core::fmt::{self, Write};
+use repeat::Digest;
+use ring::{LogRing, MAX_MESSAGE_LEN};
+use spin::Mutex;
+
+/// Severity of a log message, from most to least severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// Returns the level stored as `byte` by [Level::as_byte].
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(Self::Error),
+            2 => Some(Self::Warn),
+            3 => Some(Self::Info),
+            4 => Some(Self::Debug),
+            5 => Some(Self::Trace),
+            _ => None,
+        }
+    }
+
+    /// Returns the compact representation of the level that the ring buffer stores.
+    pub fn as_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the tag printed in front of messages, padded to a common width.
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN ",
+            Self::Info => "INFO ",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        }
+    }
+
+    /// Returns the color the tag is printed in.
+    pub fn color(self) -> Color {
+        match self {
+            Self::Error => Color::RED,
+            Self::Warn => Color::YELLOW,
+            Self::Info => Color::GREEN,
+            Self::Debug => Color::CYAN,
+            Self::Trace => Color::ANSI_PALETTE[8],
+        }
+    }
+}
+
+/// How the records of a level are printed, see [set_level_style].
+#[derive(Clone, Copy)]
+struct LevelStyle {
+    style: Style,
+    prefix: &'static str,
+}
+
+/// The styles set by [set_level_style], indexed by level. `None` prints the level's tag in its
+/// color, followed by the message in the current style.
+static LEVEL_STYLES: Mutex<[Option<LevelStyle>; 5]> = Mutex::new([None; 5]);
+
+/// Prints the records of `level`, both the prefix and the message, in `style` instead of the
+/// default `[TAG] ` in the level's color, e.g. white on red with a `✗ ` prefix for errors. The
+/// style ends with the record.
+pub fn set_level_style(level: Level, style: Style, prefix: &'static str) {
+    LEVEL_STYLES.lock()[usize::from(level.as_byte() - 1)] = Some(LevelStyle { style, prefix });
+}
+
+/// Restores the default rendering of the records of `level`.
+pub fn reset_level_style(level: Level) {
+    LEVEL_STYLES.lock()[usize::from(level.as_byte() - 1)] = None;
+}
+
+/// All messages logged so far, up to the capacity of the ring.
+pub static LOG: Mutex<LogRing> = Mutex::new(LogRing::new());
+
+/// Collects a formatted message, silently cutting it off at the capacity of a ring entry, and
+/// the [Digest] of all of it.
+struct MessageBuffer {
+    bytes: [u8; MAX_MESSAGE_LEN],
+    len: usize,
+    digest: Digest,
+}
+
+impl MessageBuffer {
+    const fn new() -> Self {
+        Self { bytes: [0; MAX_MESSAGE_LEN], len: 0, digest: Digest::EMPTY }
+    }
+
+    /// Returns the kept start of the message.
+    fn as_str(&self) -> &str {
+        // Only whole chars are ever added
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+}
+
+impl Write for MessageBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.digest.update(s.as_bytes());
+        for c in s.chars() {
+            let mut encoded = [0; 4];
+            let encoded = c.encode_utf8(&mut encoded).as_bytes();
+            let Some(free) = self.bytes.get_mut(self.len..self.len + encoded.len()) else {
+                break;
+            };
+            free.copy_from_slice(encoded);
+            self.len += encoded.len();
+        }
+        Ok(())
+    }
+}
+
+/// Prints a log line with its level tag, e.g. `[INFO ] message`, or in the style set with
+/// [set_level_style]. Lines that the message wraps onto start under the message.
+///
+/// Inside an interrupt handler, the line starts with the handler's [Context] in a dim style,
+/// e.g. `[#PF] `, which also shows where the handlers print although they shouldn't.
+pub fn print_entry(level: Level, message: impl fmt::Display) {
+    let mut out = output::stdout();
+    let _ = write_context(&mut out, interrupts::context());
+    let _ = write_entry(&mut out, level, message);
+}
+
+/// Writes the prefix of log lines printed in `context`, which is empty for [Context::Main].
+fn write_context(out: &mut impl Write, context: Context) -> fmt::Result {
+    match context {
+        Context::Main => Ok(()),
+        context => write!(out, "\x1b[2m[{}]\x1b[22m ", context),
+    }
+}
+
+/// Like [print_entry], but writes to `out`, which has to end up on the console for the
+/// indentation of wrapped lines to work.
+pub fn write_entry(out: &mut impl Write, level: Level, message: impl fmt::Display) -> fmt::Result {
+    let custom = LEVEL_STYLES.lock()[usize::from(level.as_byte() - 1)];
+    match custom {
+        Some(LevelStyle { style, prefix }) => write!(out, "{}", Styled(prefix, style))?,
+        None => write!(out, "[{}] ", Colored(level.tag(), level.color()))?,
+    }
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.indent_wrapped_lines();
+    }
+    match custom {
+        Some(LevelStyle { style, .. }) => writeln!(out, "{}", Styled(message, style)),
+        None => writeln!(out, "{}", message),
+    }
+}
+
+/// Returns the byte offset of the first occurrence of `needle` in `haystack`, ignoring ASCII
+/// case. An empty needle matches at 0.
+pub fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    let (haystack, needle) = (haystack.as_bytes(), needle.as_bytes());
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|window| window.eq_ignore_ascii_case(needle))
+}
+
+/// Splits `message` into runs that alternately don't and do match `needle`, ignoring ASCII
+/// case; the flag of each run tells whether it is a match. Runs are never empty.
+pub fn match_runs<'a>(
+    message: &'a str,
+    needle: &'a str,
+) -> impl Iterator<Item = (&'a str, bool)> {
+    let mut rest = message;
+    let mut pending_match = None;
+    core::iter::from_fn(move || {
+        if let Some(matched) = pending_match.take() {
+            return Some((matched, true));
+        }
+        if rest.is_empty() {
+            return None;
+        }
+        match find_ignore_case(rest, needle).filter(|_| !needle.is_empty()) {
+            Some(start) => {
+                let (before, matched) = rest.split_at(start);
+                let (matched, after) = matched.split_at(needle.len());
+                rest = after;
+                match before.is_empty() {
+                    true => Some((matched, true)),
+                    false => {
+                        pending_match = Some(matched);
+                        Some((before, false))
+                    }
+                }
+            }
+            None => Some((core::mem::take(&mut rest), false)),
+        }
+    })
+}
+
+/// Displays a message with every occurrence of a substring in reverse video.
+pub struct Highlighted<'a> {
+    pub message: &'a str,
+    pub needle: &'a str,
+}
+
+impl fmt::Display for Highlighted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (run, matched) in match_runs(self.message, self.needle) {
+            match matched {
+                true => write!(f, "\x1b[7m{}\x1b[27m", run)?,
+                false => f.write_str(run)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, module_path: &str, args: fmt::Arguments) {
+    if !filter::enabled(level, module_path) {
+        return;
+    }
+    // Only the copy in the ring is cut off; the line is printed in full from the arguments
+    let mut message = MessageBuffer::new();
+    let _ = message.write_fmt(args);
+    let outcome = repeat::check(level, message.digest, time::uptime_ms());
+    if let Some(repeats) = outcome.flushed {
+        report_repeats(repeats, true);
+    }
+    if outcome.record {
+        LOG.lock().push(level, message.as_str());
+    }
+    if outcome.print {
+        print_entry(level, args);
+    }
+}
+
+/// Prints `  ... last message repeated N times` in a dim style, and records it at the level of
+/// the message if the log keeps only [RecordRepeats::Summary](repeat::RecordRepeats::Summary).
+/// Without `wait`, the log and the sinks whose locks are held are skipped.
+fn report_repeats(repeats: repeat::Repeats, wait: bool) {
+    let mut summary = MessageBuffer::new();
+    let _ = write!(summary, "last message repeated {} times", repeats.count);
+    let summary = summary.as_str();
+    if repeat::record() == repeat::RecordRepeats::Summary {
+        let log = match wait {
+            true => Some(LOG.lock()),
+            false => LOG.try_lock(),
+        };
+        if let Some(mut log) = log {
+            log.push(repeats.level, summary);
+        }
+    }
+    let line = format_args!("\x1b[2m  ... {}\x1b[22m\n", summary);
+    match wait {
+        true => output::write_fmt(line),
+        false => output::try_write_fmt(line),
+    }
+}
+
+/// Reports the repeats of the last message once no repeat came for
+/// [FLUSH_TIMEOUT_MS](repeat::FLUSH_TIMEOUT_MS). Skips anything whose lock is held, so it may
+/// be called from anywhere.
+pub fn tick(now: u64) {
+    if let Some(repeats) = repeat::timed_out(now) {
+        report_repeats(repeats, false);
+    }
+}
+
+/// Reports the repeats of the last message right away, and prints the next message even if it
+/// is the same, e.g. before a panic report. Skips anything whose lock is held.
+pub fn flush_repeats() {
+    if let Some(repeats) = repeat::flush() {
+        report_repeats(repeats, false);
+    }
+}
+
+/// Appends a message to the [LOG] without printing it, unless the log is locked, e.g. by the
+/// code that panicked. Returns whether the message was appended. Filters don't apply.
+pub fn try_record(level: Level, args: fmt::Arguments) -> bool {
+    let mut message = MessageBuffer::new();
+    let _ = message.write_fmt(args);
+    let Some(mut log) = LOG.try_lock() else { return false };
+    log.push(level, message.as_str());
+    true
+}
+
+/// Logs a message at the given [Level](log::Level): `log!(Level::Info, "{} cpus", n)`. It is
+/// dropped if the [filter](log::filter) of the calling module doesn't let it through.
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        $crate::log::_log($level, module_path!(), format_args!($($arg)*))
+    };
+}
+
+/// Logs a message at [Level::Error](log::Level::Error).
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::log::Level::Error, $($arg)*)
+    };
+}
+
+/// Logs a message at [Level::Warn](log::Level::Warn).
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::log::Level::Warn, $($arg)*)
+    };
+}
+
+/// Logs a message at [Level::Info](log::Level::Info).
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::log::Level::Info, $($arg)*)
+    };
+}
+
+/// Logs a message at [Level::Debug](log::Level::Debug).
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::log::Level::Debug, $($arg)*)
+    };
+}
+
+/// Logs a message at [Level::Trace](log::Level::Trace).
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::log!($crate::log::Level::Trace, $($arg)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::rect::Rect;
+    use crate::writer::ansi::{Action, Parser};
+
+    const LEVELS: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+    /// Removes the escape sequences from `text`.
+    fn strip_escapes(text: &str) -> String {
+        let mut parser = Parser::new();
+        let print = |c| match parser.advance(c) {
+            Action::Print(c) => Some(c),
+            _ => None,
+        };
+        text.chars().filter_map(print).collect()
+    }
+
+    #[test]
+    fn levels_round_trip_through_their_bytes() {
+        for level in LEVELS {
+            assert_eq!(Level::from_byte(level.as_byte()), Some(level));
+        }
+        assert_eq!(Level::from_byte(0), None);
+        assert_eq!(Level::from_byte(6), None);
+    }
+
+    #[test]
+    fn ring_records_render_as_lines_with_their_tags() {
+        // Holds off tests that change the level styles
+        let _console = crate::writer::test_console();
+        let mut ring = LogRing::new();
+        for (index, level) in LEVELS.into_iter().enumerate() {
+            ring.push(level, &format!("message {}", index));
+        }
+        let mut text = String::new();
+        for record in ring.iter() {
+            write!(text, "[{:>5}] ", record.sequence).unwrap();
+            write_entry(&mut text, record.level, record.message).unwrap();
+        }
+        let text = strip_escapes(&text);
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), LEVELS.len());
+        for (index, line) in lines.into_iter().enumerate() {
+            let (sequence, rest) = line.split_once("] ").unwrap();
+            assert_eq!(sequence.trim_start_matches('[').trim(), index.to_string());
+            let (tag, message) = rest.strip_prefix('[').unwrap().split_once("] ").unwrap();
+            let level = LEVELS.into_iter().find(|level| level.tag() == tag);
+            assert_eq!(level, Some(LEVELS[index]), "{:?}", line);
+            assert_eq!(message, format!("message {}", index));
+        }
+    }
+
+    #[test]
+    fn message_buffer_cuts_off_the_copy_but_digests_everything() {
+        let long = "é".repeat(MAX_MESSAGE_LEN);
+        let (mut first, mut second) = (MessageBuffer::new(), MessageBuffer::new());
+        write!(first, "{}a", long).unwrap();
+        write!(second, "{}b", long).unwrap();
+        assert_eq!(first.as_str(), &long[..MAX_MESSAGE_LEN]);
+        assert_eq!(first.as_str(), second.as_str());
+        assert_ne!(first.digest, second.digest);
+    }
+
+    #[test]
+    fn lines_from_handlers_start_with_the_innermost_context() {
+        let prefix = || {
+            let mut text = String::new();
+            write_context(&mut text, interrupts::context()).unwrap();
+            text
+        };
+        assert_eq!(prefix(), "");
+        let outer = interrupts::enter(Context::PageFault);
+        assert_eq!(prefix(), "\x1b[2m[#PF]\x1b[22m ");
+        let inner = interrupts::enter(Context::Main);
+        assert_eq!(prefix(), "");
+        drop(inner);
+        assert_eq!(strip_escapes(&prefix()), "[#PF] ");
+        drop(outer);
+        assert_eq!(prefix(), "");
+    }
+
+    /// Returns the runs of `message` with their flags, as owned strings.
+    fn runs(message: &str, needle: &str) -> Vec<(String, bool)> {
+        match_runs(message, needle).map(|(run, matched)| (run.to_owned(), matched)).collect()
+    }
+
+    /// Turns `(run, matched)` pairs into the owned form of [runs].
+    fn owned(runs: &[(&str, bool)]) -> Vec<(String, bool)> {
+        runs.iter().map(|&(run, matched)| (run.to_owned(), matched)).collect()
+    }
+
+    #[test]
+    fn runs_match_regardless_of_ascii_case() {
+        let expected = [("Disk ", false), ("ERROR", true), (", ", false), ("eRRor", true)];
+        assert_eq!(runs("Disk ERROR, eRRor", "Error"), owned(&expected));
+    }
+
+    #[test]
+    fn adjacent_matches_are_separate_runs_without_overlaps() {
+        assert_eq!(runs("abAB", "ab"), owned(&[("ab", true), ("AB", true)]));
+        assert_eq!(runs("aaa", "aa"), owned(&[("aa", true), ("a", false)]));
+    }
+
+    #[test]
+    fn matches_at_the_start_and_the_end_have_no_empty_runs_around_them() {
+        let expected = [("ok", true), ("ay, ", false), ("OK", true)];
+        assert_eq!(runs("okay, OK", "ok"), owned(&expected));
+        assert_eq!(runs("ok", "ok"), owned(&[("ok", true)]));
+    }
+
+    #[test]
+    fn an_empty_needle_matches_nothing() {
+        assert_eq!(runs("message", ""), owned(&[("message", false)]));
+        assert_eq!(runs("", ""), owned(&[]));
+        assert_eq!(runs("", "x"), owned(&[]));
+    }
+
+    #[test]
+    fn highlighted_messages_show_every_match_in_reverse_video() {
+        let highlighted = Highlighted { message: "Bc a bc", needle: "bc" }.to_string();
+        assert_eq!(highlighted, "\x1b[7mBc\x1b[27m a \x1b[7mbc\x1b[27m");
+        let plain = Highlighted { message: "Bc a bc", needle: "" }.to_string();
+        assert_eq!(plain, "Bc a bc");
+    }
+
+    #[test]
+    fn level_styles_apply_to_the_prefix_and_the_message_until_reset() {
+        let _console = crate::writer::test_console();
+        let alarm = Style { background: Color::RED, bold: true, ..Style::DEFAULT };
+        set_level_style(Level::Error, alarm, "✗ ");
+        print_entry(Level::Error, "disk");
+        reset_level_style(Level::Error);
+        print_entry(Level::Error, "disk");
+        let writer = WRITER.lock();
+        let region = writer.as_ref().unwrap().get_text_region(Rect::new(0, 0, 18, 2));
+        let rows: Vec<_> = region.collect();
+        assert_eq!(rows[0], "✗ disk");
+        assert!(rows[0].cells().all(|cell| cell.style == alarm));
+        assert_eq!(rows[1], "[ERROR] disk");
+        let colors: Vec<_> = rows[1].cells().map(|cell| cell.style.foreground).collect();
+        let (tag, message) = ([Color::RED; 5], [Style::DEFAULT.foreground; 4]);
+        assert_eq!(colors[1..6], tag);
+        assert_eq!(colors[8..], message);
+    }
+}
@@ -0,0 +1,266 @@
+//! Per-module filtering of log messages by the module path they are logged from.
+use super::Level;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+use spin::RwLock;
+
+/// Maximum number of module filters besides the default.
+pub const MAX_FILTERS: usize = 8;
+
+/// Maximum length of a module path prefix in bytes.
+pub const MAX_PREFIX_LEN: usize = 48;
+
+/// The most verbose level that passes a filter, or [LevelFilter::Off] for none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LevelFilter {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelFilter {
+    /// Parses the name of a filter, ignoring ASCII case: `off`, `error`, ..., `trace`.
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Off, Self::Error, Self::Warn, Self::Info, Self::Debug, Self::Trace]
+            .into_iter()
+            .find(|filter| filter.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+
+    /// Returns whether messages of `level` pass the filter.
+    pub fn allows(self, level: Level) -> bool {
+        level.as_byte() <= self as u8
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => Self::Off,
+            1 => Self::Error,
+            2 => Self::Warn,
+            3 => Self::Info,
+            4 => Self::Debug,
+            _ => Self::Trace,
+        }
+    }
+}
+
+impl fmt::Display for LevelFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Reasons why [set_filter] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterError {
+    /// All [MAX_FILTERS] filters are taken.
+    Full,
+    /// The prefix is longer than [MAX_PREFIX_LEN] bytes.
+    PrefixTooLong { len: usize },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "at most {} filters can be set", MAX_FILTERS),
+            Self::PrefixTooLong { len } => {
+                write!(f, "prefix of {} bytes exceeds the maximum of {}", len, MAX_PREFIX_LEN)
+            }
+        }
+    }
+}
+
+/// The level filter of the modules whose path starts with `prefix`.
+#[derive(Clone, Copy)]
+struct ModuleFilter {
+    prefix: [u8; MAX_PREFIX_LEN],
+    len: usize,
+    filter: LevelFilter,
+}
+
+impl ModuleFilter {
+    fn prefix(&self) -> &str {
+        // Only whole strs are ever stored
+        core::str::from_utf8(&self.prefix[..self.len]).unwrap_or_default()
+    }
+
+    /// Returns whether the filter applies to `path`: the prefix has to end at a module
+    /// boundary, so `kernel::key` doesn't match `kernel::keyboard`.
+    fn matches(&self, path: &str) -> bool {
+        let prefix = self.prefix();
+        path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+struct Filters {
+    default: LevelFilter,
+    modules: [Option<ModuleFilter>; MAX_FILTERS],
+}
+
+impl Filters {
+    /// The filter with the longest prefix that matches `path`, or the default.
+    fn lookup(&self, path: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .flatten()
+            .filter(|module| module.matches(path))
+            .max_by_key(|module| module.len)
+            .map_or(self.default, |module| module.filter)
+    }
+
+    /// The most verbose of all filters.
+    fn max(&self) -> LevelFilter {
+        let modules = self.modules.iter().flatten().map(|module| module.filter);
+        modules.fold(self.default, LevelFilter::max)
+    }
+}
+
+static FILTERS: RwLock<Filters> =
+    RwLock::new(Filters { default: LevelFilter::Trace, modules: [None; MAX_FILTERS] });
+
+/// [Filters::max] as a byte, so that the messages no filter lets through, typically the chatty
+/// debug and trace ones, are rejected without taking the lock.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LevelFilter::Trace as u8);
+
+fn update_max_level(filters: &Filters) {
+    MAX_LEVEL.store(filters.max() as u8, Ordering::Relaxed);
+}
+
+/// Returns whether a message of `level` logged from the module at `path` is kept.
+pub fn enabled(level: Level, path: &str) -> bool {
+    if !LevelFilter::from_byte(MAX_LEVEL.load(Ordering::Relaxed)).allows(level) {
+        return false;
+    }
+    FILTERS.read().lookup(path).allows(level)
+}
+
+/// Sets the filter of the modules whose path starts with `prefix`, e.g.
+/// `set_filter("kernel_with_bootloader::keyboard", LevelFilter::Warn)`. The filter with the
+/// longest matching prefix wins over the others and the [default](set_default_filter).
+pub fn set_filter(prefix: &str, filter: LevelFilter) -> Result<(), FilterError> {
+    if prefix.len() > MAX_PREFIX_LEN {
+        return Err(FilterError::PrefixTooLong { len: prefix.len() });
+    }
+    let mut filters = FILTERS.write();
+    let existing = filters.modules.iter().position(|module| match module {
+        Some(module) => module.prefix() == prefix,
+        None => false,
+    });
+    let index = existing
+        .or_else(|| filters.modules.iter().position(Option::is_none))
+        .ok_or(FilterError::Full)?;
+    let mut module = ModuleFilter { prefix: [0; MAX_PREFIX_LEN], len: prefix.len(), filter };
+    module.prefix[..prefix.len()].copy_from_slice(prefix.as_bytes());
+    filters.modules[index] = Some(module);
+    update_max_level(&filters);
+    Ok(())
+}
+
+/// Removes the filter set for `prefix`, so its modules fall back to a shorter prefix or the
+/// default. Returns whether there was one.
+pub fn remove_filter(prefix: &str) -> bool {
+    let mut filters = FILTERS.write();
+    let Some(module) = filters
+        .modules
+        .iter_mut()
+        .find(|module| module.is_some_and(|module| module.prefix() == prefix))
+    else {
+        return false;
+    };
+    *module = None;
+    update_max_level(&filters);
+    true
+}
+
+/// Sets the filter of the modules that no prefix matches. It starts at [LevelFilter::Trace].
+pub fn set_default_filter(filter: LevelFilter) {
+    let mut filters = FILTERS.write();
+    filters.default = filter;
+    update_max_level(&filters);
+}
+
+/// Returns the filter of the modules that no prefix matches.
+pub fn default_filter() -> LevelFilter {
+    FILTERS.read().default
+}
+
+/// Calls `f` with every module filter, as prefix and filter.
+pub fn for_each_filter(mut f: impl FnMut(&str, LevelFilter)) {
+    let filters = FILTERS.read();
+    for module in filters.modules.iter().flatten() {
+        f(module.prefix(), module.filter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns filters with the given default and module filters, in that order.
+    fn filters(default: LevelFilter, modules: &[(&str, LevelFilter)]) -> Filters {
+        let mut filters = Filters { default, modules: [None; MAX_FILTERS] };
+        for (slot, &(prefix, filter)) in filters.modules.iter_mut().zip(modules) {
+            let len = prefix.len();
+            let mut module = ModuleFilter { prefix: [0; MAX_PREFIX_LEN], len, filter };
+            module.prefix[..prefix.len()].copy_from_slice(prefix.as_bytes());
+            *slot = Some(module);
+        }
+        filters
+    }
+
+    #[test]
+    fn the_longest_matching_prefix_wins_in_any_order() {
+        let tcp = ("kernel::net::tcp", LevelFilter::Trace);
+        let net = ("kernel::net", LevelFilter::Warn);
+        for modules in [[tcp, net], [net, tcp]] {
+            let filters = filters(LevelFilter::Info, &modules);
+            assert_eq!(filters.lookup("kernel::net::tcp::retry"), LevelFilter::Trace);
+            assert_eq!(filters.lookup("kernel::net::tcp"), LevelFilter::Trace);
+            assert_eq!(filters.lookup("kernel::net::udp"), LevelFilter::Warn);
+            assert_eq!(filters.lookup("kernel::net"), LevelFilter::Warn);
+        }
+    }
+
+    #[test]
+    fn modules_that_no_prefix_matches_get_the_default() {
+        let filters = filters(LevelFilter::Info, &[("kernel::key", LevelFilter::Off)]);
+        assert_eq!(filters.lookup("kernel::key"), LevelFilter::Off);
+        // Prefixes end at module boundaries
+        assert_eq!(filters.lookup("kernel::keyboard"), LevelFilter::Info);
+        assert_eq!(filters.lookup("kernel"), LevelFilter::Info);
+        assert_eq!(filters.lookup("shell"), LevelFilter::Info);
+        assert_eq!(filters.max(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn set_filters_apply_until_removed() {
+        let (outer, inner) = ("filter_test::outer", "filter_test::outer::inner");
+        set_filter(outer, LevelFilter::Error).unwrap();
+        set_filter(inner, LevelFilter::Debug).unwrap();
+        assert!(!enabled(Level::Warn, "filter_test::outer::other"));
+        assert!(enabled(Level::Debug, "filter_test::outer::inner::deep"));
+        assert!(enabled(Level::Trace, "filter_test::other"));
+        assert!(remove_filter(inner));
+        assert!(!remove_filter(inner));
+        assert!(!enabled(Level::Debug, "filter_test::outer::inner::deep"));
+        assert!(remove_filter(outer));
+        assert!(enabled(Level::Debug, "filter_test::outer::inner::deep"));
+        let long = "x".repeat(MAX_PREFIX_LEN + 1);
+        let error = FilterError::PrefixTooLong { len: MAX_PREFIX_LEN + 1 };
+        assert_eq!(set_filter(&long, LevelFilter::Off), Err(error));
+    }
+}
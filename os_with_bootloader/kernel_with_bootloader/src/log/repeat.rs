@@ -0,0 +1,169 @@
+//! Suppression of repeated log messages: a message that is identical to the one before it, at
+//! the same level, isn't printed again. Instead, the number of repeats is printed once a
+//! different message arrives or no repeat came for [FLUSH_TIMEOUT_MS].
+use super::Level;
+use spin::Mutex;
+
+/// Time in milliseconds after the last repeat at which the number of repeats is printed even
+/// though no other message arrived.
+pub const FLUSH_TIMEOUT_MS: u64 = 1000;
+
+/// What the [LOG](super::LOG) keeps of suppressed repeats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordRepeats {
+    /// Every repeat, as if it was printed.
+    All,
+    /// Only the line with the number of repeats.
+    Summary,
+}
+
+/// The length and a hash of a whole message, which tell messages apart even beyond the bytes
+/// that the ring keeps of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Digest {
+    len: usize,
+    hash: u64,
+}
+
+impl Digest {
+    /// The digest of the empty message.
+    pub const EMPTY: Self = Self { len: 0, hash: 0xcbf2_9ce4_8422_2325 };
+
+    /// Adds the next bytes of the message, hashing them with FNV-1a.
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.len += bytes.len();
+        for &byte in bytes {
+            self.hash = (self.hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+/// A number of suppressed repeats of a message at a level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Repeats {
+    pub level: Level,
+    pub count: usize,
+}
+
+/// How to handle a message, see [check].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    /// Repeats of the previous message to report before the message.
+    pub flushed: Option<Repeats>,
+    /// Whether the message is printed.
+    pub print: bool,
+    /// Whether the message is stored in the log.
+    pub record: bool,
+}
+
+/// The last message and how often it was repeated since it was printed.
+struct State {
+    enabled: bool,
+    record: RecordRepeats,
+    level: Option<Level>,
+    digest: Digest,
+    count: usize,
+    /// Time of the last repeat.
+    last: u64,
+}
+
+impl State {
+    /// Returns the repeats that weren't reported yet and starts counting from zero.
+    fn take(&mut self) -> Option<Repeats> {
+        let count = core::mem::take(&mut self.count);
+        self.level.filter(|_| count > 0).map(|level| Repeats { level, count })
+    }
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    enabled: true,
+    record: RecordRepeats::All,
+    level: None,
+    digest: Digest::EMPTY,
+    count: 0,
+    last: 0,
+});
+
+/// Turns the suppression on, which is the default, or off. Turning it off forgets the last
+/// message without reporting its repeats.
+pub fn set_enabled(enabled: bool) {
+    let mut state = STATE.lock();
+    state.enabled = enabled;
+    (state.level, state.count) = (None, 0);
+}
+
+/// Sets what the log keeps of suppressed repeats; all of them by default.
+pub fn set_record(record: RecordRepeats) {
+    STATE.lock().record = record;
+}
+
+/// Returns what the log keeps of suppressed repeats.
+pub fn record() -> RecordRepeats {
+    STATE.lock().record
+}
+
+/// Decides how to handle a message with the given digest that is logged at the time `now` in
+/// milliseconds. A repeat of the last message is only counted; another message ends the run of
+/// repeats.
+pub fn check(level: Level, digest: Digest, now: u64) -> Outcome {
+    let mut state = STATE.lock();
+    if !state.enabled {
+        return Outcome { flushed: None, print: true, record: true };
+    }
+    if state.level == Some(level) && state.digest == digest {
+        state.count += 1;
+        state.last = now;
+        let record = state.record == RecordRepeats::All;
+        return Outcome { flushed: None, print: false, record };
+    }
+    let flushed = state.take();
+    state.level = Some(level);
+    state.digest = digest;
+    Outcome { flushed, print: true, record: true }
+}
+
+/// Returns the repeats of the last message if none came for [FLUSH_TIMEOUT_MS] before `now`,
+/// so that they are reported. A later repeat starts a new run. Skipped while the state is
+/// locked, so it may be called from anywhere.
+pub fn timed_out(now: u64) -> Option<Repeats> {
+    let mut state = STATE.try_lock()?;
+    if state.count == 0 || now.saturating_sub(state.last) < FLUSH_TIMEOUT_MS {
+        return None;
+    }
+    state.take()
+}
+
+/// Returns the repeats of the last message that weren't reported yet, e.g. before output that
+/// must not be mixed up with them, and forgets the message, so that the next one is printed
+/// even if it is the same. Skipped while the state is locked.
+pub fn flush() -> Option<Repeats> {
+    let mut state = STATE.try_lock()?;
+    let repeats = state.take();
+    state.level = None;
+    repeats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::ring::MAX_MESSAGE_LEN;
+
+    fn digest(message: &str) -> Digest {
+        let mut digest = Digest::EMPTY;
+        digest.update(message.as_bytes());
+        digest
+    }
+
+    #[test]
+    fn counts_repeats_of_whole_messages() {
+        let start = "x".repeat(MAX_MESSAGE_LEN);
+        let (first, second) = (digest(&format!("{}1", start)), digest(&format!("{}2", start)));
+        set_enabled(true);
+        assert!(check(Level::Info, first, 0).print);
+        assert!(!check(Level::Info, first, 1).print);
+        let outcome = check(Level::Info, second, 2);
+        assert!(outcome.print);
+        assert_eq!(outcome.flushed, Some(Repeats { level: Level::Info, count: 1 }));
+        assert!(check(Level::Warn, second, 3).print);
+    }
+}
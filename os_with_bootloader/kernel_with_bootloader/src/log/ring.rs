@@ -0,0 +1,134 @@
+use super::Level;
+
+/// Number of messages kept; older ones are overwritten.
+pub const CAPACITY: usize = 256;
+
+/// Maximum length of a message in bytes; longer ones are cut off.
+pub const MAX_MESSAGE_LEN: usize = 120;
+
+/// A message stored in the ring.
+#[derive(Clone, Copy)]
+struct Entry {
+    sequence: u64,
+    level: u8,
+    len: u8,
+    text: [u8; MAX_MESSAGE_LEN],
+}
+
+impl Entry {
+    const EMPTY: Entry = Entry { sequence: 0, level: 0, len: 0, text: [0; MAX_MESSAGE_LEN] };
+}
+
+/// A message as returned by [LogRing::iter].
+#[derive(Clone, Copy, Debug)]
+pub struct Record<'a> {
+    /// Number of messages logged before this one, so dropped messages show up as gaps.
+    pub sequence: u64,
+    pub level: Level,
+    pub message: &'a str,
+}
+
+/// Fixed-size buffer of the most recent log messages.
+pub struct LogRing {
+    entries: [Entry; CAPACITY],
+    /// Sequence number of the next message, which is also the number of messages ever logged.
+    next_sequence: u64,
+}
+
+impl LogRing {
+    pub const fn new() -> Self {
+        Self { entries: [Entry::EMPTY; CAPACITY], next_sequence: 0 }
+    }
+
+    /// Stores a message, overwriting the oldest one if the ring is full. `message` is cut off
+    /// at [MAX_MESSAGE_LEN] bytes, on a char boundary.
+    pub fn push(&mut self, level: Level, message: &str) {
+        let mut len = message.len().min(MAX_MESSAGE_LEN);
+        while !message.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut text = [0; MAX_MESSAGE_LEN];
+        text[..len].copy_from_slice(&message.as_bytes()[..len]);
+        let sequence = self.next_sequence;
+        self.entries[sequence as usize % CAPACITY] =
+            Entry { sequence, level: level.as_byte(), len: len as u8, text };
+        self.next_sequence += 1;
+    }
+
+    /// Returns the number of stored messages.
+    pub fn len(&self) -> usize {
+        (self.next_sequence as usize).min(CAPACITY)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next_sequence == 0
+    }
+
+    /// Returns the stored messages from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = Record<'_>> {
+        let first = self.next_sequence - self.len() as u64;
+        (first..self.next_sequence).filter_map(|sequence| {
+            let entry = &self.entries[sequence as usize % CAPACITY];
+            Some(Record {
+                sequence: entry.sequence,
+                level: Level::from_byte(entry.level)?,
+                message: core::str::from_utf8(&entry.text[..usize::from(entry.len)]).ok()?,
+            })
+        })
+    }
+
+    /// Returns the `count` newest messages from oldest to newest.
+    pub fn last(&self, count: usize) -> impl Iterator<Item = Record<'_>> {
+        self.iter().skip(self.len().saturating_sub(count))
+    }
+}
+
+impl Default for LogRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(ring: &LogRing) -> Vec<(u64, Level, &str)> {
+        ring.iter().map(|record| (record.sequence, record.level, record.message)).collect()
+    }
+
+    #[test]
+    fn keeps_messages_in_order() {
+        let mut ring = LogRing::new();
+        assert!(ring.is_empty());
+        ring.push(Level::Info, "first");
+        ring.push(Level::Warn, "second");
+        assert_eq!(ring.len(), 2);
+        let expected = [(0, Level::Info, "first"), (1, Level::Warn, "second")];
+        assert_eq!(messages(&ring), expected);
+    }
+
+    #[test]
+    fn overwrites_the_oldest_messages_once_full() {
+        let mut ring = LogRing::new();
+        for index in 0..CAPACITY + 3 {
+            ring.push(Level::Debug, if index % 2 == 0 { "even" } else { "odd" });
+        }
+        assert_eq!(ring.len(), CAPACITY);
+        let sequences: Vec<_> = ring.iter().map(|record| record.sequence).collect();
+        assert_eq!(sequences, (3..CAPACITY as u64 + 3).collect::<Vec<_>>());
+        let last: Vec<_> = ring.last(2).map(|record| record.sequence).collect();
+        assert_eq!(last, [CAPACITY as u64 + 1, CAPACITY as u64 + 2]);
+    }
+
+    #[test]
+    fn cuts_long_messages_off_on_a_char_boundary() {
+        let mut ring = LogRing::new();
+        let message = format!("a{}", "€".repeat(MAX_MESSAGE_LEN));
+        ring.push(Level::Error, &message);
+        let kept = ring.iter().next().unwrap().message;
+        // The euro sign takes 3 bytes, so the last one that fits ends before the limit
+        assert_eq!(kept.len(), MAX_MESSAGE_LEN - 2);
+        assert!(message.starts_with(kept));
+    }
+}
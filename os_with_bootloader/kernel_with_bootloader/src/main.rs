@@ -1,18 +1,72 @@
 #![no_std]
 #![no_main]
 
-mod writer;
 use bootloader_api::config::Mapping;
+use kernel_with_bootloader::shell::Shell;
+use kernel_with_bootloader::log::{self, Level};
+use kernel_with_bootloader::{boot, boot_stage, error, info, keyboard, output, println, warn};
+use kernel_with_bootloader::cmdline::{self, ConsoleOptions, FontSource};
+use kernel_with_bootloader::{heap, interrupts, qr, serial, time};
+use kernel_with_bootloader::writer::{self, color::Color, rect::Rect, style::Style, WRITER};
+use kernel_with_bootloader::writer::FrameBufferWriter;
 use x86_64::instructions::hlt;
-use writer::FrameBufferWriter;
 
 #[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    // Report the panic to the serial port and the log first, in case the screen is wedged,
+    // after the repeats of the last message so they don't end up after the report. Sinks whose
+    // locks are held, e.g. by the code that panicked, are skipped.
+    log::flush_repeats();
+    output::try_write_fmt(format_args!("\n=== PANIC ===\n{}\n=== END PANIC ===\n", info));
+    log::try_record(Level::Error, format_args!("{}", info));
+    // A poisoned console failed to render before, so the report above has to do
+    let poisoned = WRITER.try_lock().is_some_and(|writer| {
+        writer.as_ref().is_some_and(|writer| writer.is_poisoned())
+    });
+    if poisoned {
+        output::try_write_str("console disabled after a rendering fault\n");
+        loop {
+            hlt();
+        }
+    }
+    // SAFETY: nothing else runs anymore after a panic, so bypassing the lock of the global
+    // writer (which may be held by the code that panicked) can't race with anything.
+    if let Some(mut writer) = unsafe { writer::force_writer() } {
+        writer.set_style(Style {
+            foreground: Color::WHITE,
+            background: Color::RED,
+            ..Style::DEFAULT
+        });
+        writer.set_cursor_visible(false);
+        writer.set_clip_rect(None);
+        writer.clear();
+        draw_panic_qr(&mut writer, info);
+        writer.set_text_scale(2);
+        writer.print("KERNEL PANIC\n");
+        writer.set_text_scale(1);
+        println!(writer, "{}", info);
+    }
     loop {
         hlt();
     }
 }
 
+/// Draws a QR code of the panic message and location in the bottom right corner, as large as
+/// fits into half of the height and a third of the width, for machines without a serial port.
+/// The text is restricted to the left of it, so the code doesn't cover any.
+fn draw_panic_qr(writer: &mut FrameBufferWriter, info: &core::panic::PanicInfo) {
+    let code = qr::encode_fmt(format_args!("{}", info));
+    let (width, height) = writer.screen_size();
+    let modules = code.size() + 2 * qr::QUIET_ZONE;
+    let scale = (height / 2).min(width / 3) / modules;
+    if scale == 0 {
+        return;
+    }
+    let side = modules * scale;
+    code.draw(writer, (width - side) as isize, (height - side) as isize, scale);
+    writer.set_clip_rect(Some(Rect::new(0, 0, width - side, height)));
+}
+
 // Use the entry_point macro to register the entry point function:
 // bootloader_api::entry_point!(kernel_main)
 // Optionally pass a custom config
@@ -26,15 +80,89 @@ pub static BOOTLOADER_CONFIG: bootloader_api::BootloaderConfig = {
 bootloader_api::entry_point!(my_entry_point, config = &BOOTLOADER_CONFIG);
 
 fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
-    let frame_buffer_info = boot_info.framebuffer.as_mut().unwrap().info();
-    let buffer = boot_info.framebuffer.as_mut().unwrap().buffer_mut();
-    let mut frame_buffer_writer = FrameBufferWriter::new(buffer, frame_buffer_info);
+    let kernel_start = boot_info.kernel_image_offset;
+    let kernel_image = kernel_start..kernel_start + boot_info.kernel_len;
+    let framebuffer = boot_info.framebuffer.as_mut().unwrap();
+    let mut cmdline = [0; cmdline::MAX_LEN];
+    let cmdline = cmdline::read(&mut cmdline);
+    let options = ConsoleOptions::parse(cmdline);
+    if !options.serial {
+        output::set_enabled(output::SERIAL, false);
+    }
+    boot_stage!("serial init", { serial::init() });
+    boot_stage!("init IDT", {
+        // The stack grows down from about the frame of the entry point
+        let stack_top = &raw const kernel_image as u64;
+        let stack_size = BOOTLOADER_CONFIG.kernel_stack_size;
+        interrupts::record_region("stack", stack_top.saturating_sub(stack_size)..stack_top + 4096);
+        interrupts::record_region("kernel image", kernel_image);
+        interrupts::record_region("heap", heap::arena());
+        let pixels = framebuffer.buffer().as_ptr_range();
+        interrupts::record_region("framebuffer", pixels.start as u64..pixels.end as u64);
+        interrupts::init();
+    });
+    boot_stage!("TSC calibration", { time::init() });
+    let mut font = Ok(());
+    let result = boot_stage!("framebuffer init", {
+        writer::init_with(framebuffer, |writer| font = options.apply(writer))
+    });
+    for problem in cmdline::problems(cmdline) {
+        warn!("command line: {}", problem);
+    }
+    if let Err(error) = font {
+        warn!("console.font: {}, keeping the default size", error);
+    }
+    match result {
+        Ok(()) => {
+            if let Some(writer) = WRITER.lock().as_mut() {
+                // Programs driving the console over serial expect the replies there
+                writer.set_response_sink(Some(serial::write_str));
+            }
+            info!("framebuffer console ready");
+        }
+        Err(error) => error!("framebuffer console unavailable: {}", error),
+    }
+    match (options.font_source, boot_info.ramdisk_addr.into_option()) {
+        (FontSource::BuiltIn, _) => {}
+        (FontSource::Ramdisk, None) => warn!("font: there is no ramdisk, keeping the built-in one"),
+        (FontSource::Ramdisk, Some(ramdisk)) => {
+            // SAFETY: the bootloader maps the ramdisk for the kernel's lifetime, and nothing
+            // writes to it.
+            let ramdisk = unsafe {
+                core::slice::from_raw_parts(ramdisk as *const u8, boot_info.ramdisk_len as usize)
+            };
+            match boot_stage!("ramdisk font", { writer::psf::load(ramdisk) }) {
+                Ok(font) => {
+                    if let Some(writer) = WRITER.lock().as_mut() {
+                        writer.set_font(font);
+                    }
+                    info!("console font loaded from the ramdisk");
+                }
+                Err(error) => warn!("ramdisk font rejected, keeping the built-in one: {}", error),
+            }
+        }
+    }
+
+    if let Some(frame_buffer_writer) = WRITER.lock().as_mut() {
+        // Print the boot banner in large text
+        let scale = frame_buffer_writer.text_scale();
+        frame_buffer_writer.set_text_scale(2);
+        frame_buffer_writer.print("Hello, world!\n");
+        frame_buffer_writer.set_text_scale(scale);
 
-    // Print text with color and formatting
-    frame_buffer_writer.print("Hello, world!\nThis is a test.\n\\cBlue text\\r \tIndented Text");
+        // Print text with color and formatting
+        frame_buffer_writer.print("This is a test.\n\\cBlue text\\r \tIndented Text\n");
+    }
+    boot::report();
 
-    // Loop forever to keep the kernel running
+    // Poll the input sources forever; there are no interrupts that could wake up a halted CPU
+    let mut shell = Shell::new();
+    shell.start();
     loop {
-        hlt(); // Stop x86_64 from being unnecessarily busy while looping
+        keyboard::poll();
+        serial::poll();
+        shell.poll();
+        time::tick();
+        core::hint::spin_loop();
     }
 }
\ No newline at end of file
@@ -0,0 +1,458 @@
+//! Fan-out of the text printed by the print macros to several sinks: the framebuffer console,
+//! the serial port, and any other sink registered with [add_sink]. Every enabled sink receives
+//! the same text, optionally with the escape sequences removed.
+//!
+//! Until the console exists, its text is kept in an early log, which is replayed once the
+//! console is created.
+use crate::serial;
+use crate::writer::ansi::{Action, Parser};
+use crate::writer::style::Style;
+use crate::writer::{FrameBufferWriter, WRITER};
+use core::fmt::{self, Write};
+use spin::Mutex;
+
+/// Maximum number of sinks, including the console and the serial port.
+pub const MAX_SINKS: usize = 4;
+
+/// Something that receives the printed text.
+pub trait Sink: Sync {
+    /// Writes the text, waiting for the locks it needs.
+    fn write_str(&self, s: &str);
+
+    /// Writes the text unless a lock it needs is held, e.g. by code that panicked. Returns
+    /// whether the text was written.
+    fn try_write_str(&self, s: &str) -> bool;
+}
+
+/// Number of bytes the early log keeps; older text is dropped.
+pub const EARLY_LOG_CAPACITY: usize = 4096;
+
+/// Text printed to the console before it was created, see [retire_early_log].
+pub struct EarlyLog {
+    bytes: [u8; EARLY_LOG_CAPACITY],
+    /// Index of the oldest byte.
+    start: usize,
+    len: usize,
+    /// Number of bytes that were dropped to make room.
+    dropped: usize,
+    /// Whether the console was created, after which nothing is kept anymore.
+    retired: bool,
+}
+
+impl EarlyLog {
+    const fn new() -> Self {
+        Self { bytes: [0; EARLY_LOG_CAPACITY], start: 0, len: 0, dropped: 0, retired: false }
+    }
+
+    /// Appends the text, dropping the oldest bytes once the log is full.
+    fn push(&mut self, s: &str) {
+        if self.retired {
+            return;
+        }
+        for &byte in s.as_bytes() {
+            if self.len == EARLY_LOG_CAPACITY {
+                self.start = (self.start + 1) % EARLY_LOG_CAPACITY;
+                self.len -= 1;
+                self.dropped += 1;
+            }
+            self.bytes[(self.start + self.len) % EARLY_LOG_CAPACITY] = byte;
+            self.len += 1;
+        }
+    }
+
+    /// Returns the kept bytes, oldest first.
+    fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(|i| self.bytes[(self.start + i) % EARLY_LOG_CAPACITY])
+    }
+
+    /// Writes the kept text to `writer`. If text was dropped, a note says how much, and the
+    /// rest of the line that lost its start is dropped as well, since it may begin in the middle
+    /// of a char or an escape sequence.
+    pub fn replay(&self, writer: &mut FrameBufferWriter) {
+        writer.batch(|writer| self.replay_batched(writer));
+    }
+
+    /// Writes the kept text like [EarlyLog::replay], with the cursor drawn only at the end.
+    fn replay_batched(&self, writer: &mut FrameBufferWriter) {
+        let mut partial = 0;
+        if self.dropped > 0 {
+            partial = self.iter().position(|byte| byte == b'\n').map_or(self.len, |i| i + 1);
+            let dropped = self.dropped + partial;
+            let _ = writeln!(writer, "({} bytes of early log dropped)", dropped);
+        }
+        let (mut chunk, mut len) = ([0; 64], 0);
+        for byte in self.iter().skip(partial) {
+            chunk[len] = byte;
+            len += 1;
+            if len == chunk.len() {
+                writer.write_bytes(&chunk);
+                len = 0;
+            }
+        }
+        writer.write_bytes(&chunk[..len]);
+    }
+}
+
+static EARLY_LOG: Mutex<EarlyLog> = Mutex::new(EarlyLog::new());
+
+/// Stops keeping the console's text and returns what was kept so far. The caller creates the
+/// console while holding the lock of [WRITER], so that no text is lost in between.
+pub fn retire_early_log() -> EarlyLog {
+    let mut early = EARLY_LOG.lock();
+    let mut retired = EarlyLog::new();
+    retired.retired = true;
+    core::mem::replace(&mut *early, retired)
+}
+
+/// The global framebuffer [WRITER], or the early log before the writer exists.
+pub struct ConsoleSink;
+
+impl Sink for ConsoleSink {
+    fn write_str(&self, s: &str) {
+        match WRITER.lock().as_mut() {
+            Some(writer) => {
+                let _ = writer.write_str(s);
+            }
+            None => EARLY_LOG.lock().push(s),
+        }
+    }
+
+    fn try_write_str(&self, s: &str) -> bool {
+        let Some(mut writer) = WRITER.try_lock() else { return false };
+        match writer.as_mut() {
+            Some(writer) => {
+                let _ = writer.write_str(s);
+            }
+            None => {
+                let Some(mut early) = EARLY_LOG.try_lock() else { return false };
+                early.push(s);
+            }
+        }
+        true
+    }
+}
+
+/// The serial port COM1.
+pub struct SerialSink;
+
+impl Sink for SerialSink {
+    fn write_str(&self, s: &str) {
+        serial::write_str(s);
+    }
+
+    fn try_write_str(&self, s: &str) -> bool {
+        serial::try_write_str(s)
+    }
+}
+
+/// Identifies a registered sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SinkId(usize);
+
+/// The [ConsoleSink], registered from the start.
+pub const CONSOLE: SinkId = SinkId(0);
+
+/// The [SerialSink], registered from the start.
+pub const SERIAL: SinkId = SinkId(1);
+
+/// Reasons why [add_sink] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError {
+    /// All [MAX_SINKS] slots are taken.
+    Full,
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "at most {} sinks can be registered", MAX_SINKS),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    sink: &'static dyn Sink,
+    enabled: bool,
+    strip_escapes: bool,
+}
+
+impl Slot {
+    const fn new(sink: &'static dyn Sink) -> Self {
+        Self { sink, enabled: true, strip_escapes: false }
+    }
+}
+
+struct Sinks {
+    slots: [Option<Slot>; MAX_SINKS],
+    /// Track the escape sequences for the sinks that strip them, so that a sequence may be
+    /// split across writes.
+    strippers: [Parser; MAX_SINKS],
+}
+
+static SINKS: Mutex<Sinks> = Mutex::new(Sinks {
+    slots: [Some(Slot::new(&ConsoleSink)), Some(Slot::new(&SerialSink)), None, None],
+    strippers: [const { Parser::new() }; MAX_SINKS],
+});
+
+/// Registers another sink, which receives all text printed from now on. With `strip_escapes`,
+/// the escape sequences are removed from the text it receives.
+pub fn add_sink(sink: &'static dyn Sink, strip_escapes: bool) -> Result<SinkId, SinkError> {
+    let mut sinks = SINKS.lock();
+    let index = sinks.slots.iter().position(Option::is_none).ok_or(SinkError::Full)?;
+    sinks.slots[index] = Some(Slot { strip_escapes, ..Slot::new(sink) });
+    sinks.strippers[index] = Parser::new();
+    Ok(SinkId(index))
+}
+
+/// Unregisters a sink. This works for the console and the serial port as well.
+pub fn remove_sink(id: SinkId) {
+    SINKS.lock().slots[id.0] = None;
+}
+
+/// Pauses or resumes sending text to a sink.
+pub fn set_enabled(id: SinkId, enabled: bool) {
+    if let Some(slot) = &mut SINKS.lock().slots[id.0] {
+        slot.enabled = enabled;
+    }
+}
+
+/// Selects whether the escape sequences are removed from the text a sink receives.
+pub fn set_strip_escapes(id: SinkId, strip_escapes: bool) {
+    if let Some(slot) = &mut SINKS.lock().slots[id.0] {
+        slot.strip_escapes = strip_escapes;
+    }
+}
+
+/// Writes the text to every enabled sink.
+pub fn write_str(s: &str) {
+    write_all(s, true, None);
+}
+
+/// Like [write_str], but skips the sinks whose locks are held instead of waiting for them, and
+/// writes nothing if the sinks themselves are locked. For the panic path.
+pub fn try_write_str(s: &str) {
+    write_all(s, false, None);
+}
+
+/// Formats the arguments straight into the sinks, locking them separately for every piece, so
+/// that formatting the arguments may print as well.
+pub fn write_fmt(args: fmt::Arguments) {
+    let _ = FanOut { wait: true, skip: None }.write_fmt(args);
+}
+
+/// Like [write_fmt], but writes to the console in the style that `apply` makes of its current
+/// one, which is restored afterwards. The console is locked once for all of it, so that text
+/// printed meanwhile, e.g. by an interrupt handler, doesn't take on the style. The other
+/// sinks receive the text after that, so the arguments are formatted twice and must not print.
+pub fn write_fmt_styled(apply: impl FnOnce(&mut Style), args: fmt::Arguments) {
+    let console = lock(true).and_then(|sinks| sinks.slots[CONSOLE.0]);
+    let styled = console.is_some_and(|slot| slot.enabled) && {
+        let mut writer = WRITER.lock();
+        writer.as_mut().map(|writer| {
+            let saved = writer.style();
+            let mut style = saved;
+            apply(&mut style);
+            writer.set_style(style);
+            let _ = writer.write_fmt(args);
+            writer.set_style(saved);
+        })
+    }
+    .is_some();
+    let skip = styled.then_some(CONSOLE);
+    let _ = FanOut { wait: true, skip }.write_fmt(args);
+}
+
+/// Like [write_fmt], but in the degraded mode of [try_write_str].
+pub fn try_write_fmt(args: fmt::Arguments) {
+    let _ = FanOut { wait: false, skip: None }.write_fmt(args);
+}
+
+/// Returns the destination of the print macros as a [Write] implementation, for code that
+/// can write to any writer.
+pub fn stdout() -> FanOut {
+    FanOut { wait: true, skip: None }
+}
+
+/// Writes to every enabled sink, see [stdout].
+pub struct FanOut {
+    wait: bool,
+    /// A sink that already received the text.
+    skip: Option<SinkId>,
+}
+
+impl Write for FanOut {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_all(s, self.wait, self.skip);
+        Ok(())
+    }
+}
+
+fn lock(wait: bool) -> Option<spin::MutexGuard<'static, Sinks>> {
+    match wait {
+        true => Some(SINKS.lock()),
+        false => SINKS.try_lock(),
+    }
+}
+
+/// Writes the text to every enabled sink but `skip`. The sinks are never called while [SINKS]
+/// is locked, so a sink may print itself.
+fn write_all(s: &str, wait: bool, skip: Option<SinkId>) {
+    for index in 0..MAX_SINKS {
+        if skip == Some(SinkId(index)) {
+            continue;
+        }
+        let Some(slot) = lock(wait).and_then(|sinks| sinks.slots[index]) else { continue };
+        if !slot.enabled {
+            continue;
+        }
+        let write = |s: &str| match wait {
+            true => slot.sink.write_str(s),
+            false => {
+                slot.sink.try_write_str(s);
+            }
+        };
+        if !slot.strip_escapes {
+            write(s);
+            continue;
+        }
+        // Strip the sequences into a small buffer at a time
+        let mut rest = s;
+        while !rest.is_empty() {
+            let mut text = TextBuffer { bytes: [0; 64], len: 0 };
+            let Some(mut sinks) = lock(wait) else { return };
+            let consumed = text.fill_stripped(rest, &mut sinks.strippers[index]);
+            drop(sinks);
+            rest = &rest[consumed..];
+            write(text.as_str());
+        }
+    }
+}
+
+struct TextBuffer {
+    bytes: [u8; 64],
+    len: usize,
+}
+
+impl TextBuffer {
+    /// Feeds chars of `s` through `parser` until the buffer is full, keeping the text.
+    /// Returns the number of bytes of `s` that were consumed.
+    fn fill_stripped(&mut self, s: &str, parser: &mut Parser) -> usize {
+        let mut consumed = 0;
+        for c in s.chars() {
+            if self.len + c.len_utf8() > self.bytes.len() {
+                break;
+            }
+            consumed += c.len_utf8();
+            if let Action::Print(c) = parser.advance(c) {
+                c.encode_utf8(&mut self.bytes[self.len..]);
+                self.len += c.len_utf8();
+            }
+        }
+        consumed
+    }
+
+    fn as_str(&self) -> &str {
+        // Only whole chars are ever added
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sink that keeps everything it receives.
+    struct Capture(Mutex<String>);
+
+    impl Capture {
+        const fn new() -> Self {
+            Self(Mutex::new(String::new()))
+        }
+
+        /// Returns the text received since the last call.
+        fn take(&self) -> String {
+            core::mem::take(&mut *self.0.lock())
+        }
+    }
+
+    impl Sink for Capture {
+        fn write_str(&self, s: &str) {
+            self.0.lock().push_str(s);
+        }
+
+        fn try_write_str(&self, s: &str) -> bool {
+            self.write_str(s);
+            true
+        }
+    }
+
+    #[test]
+    fn every_sink_receives_the_text_with_or_without_its_escapes() {
+        static RAW: Capture = Capture::new();
+        static STRIPPED: Capture = Capture::new();
+        static EXTRA: Capture = Capture::new();
+        let _console = crate::writer::test_console();
+        let raw = add_sink(&RAW, false).unwrap();
+        let stripped = add_sink(&STRIPPED, true).unwrap();
+        assert_eq!(add_sink(&EXTRA, false), Err(SinkError::Full));
+        // The sequence is split across writes
+        write_str("\x1b[3");
+        write_fmt(format_args!("1m{}\x1b[0m ok", "red"));
+        assert_eq!(RAW.take(), "\x1b[31mred\x1b[0m ok");
+        assert_eq!(STRIPPED.take(), "red ok");
+        set_enabled(raw, false);
+        set_strip_escapes(stripped, false);
+        write_str("\x1b[1mbold");
+        assert_eq!(RAW.take(), "");
+        assert_eq!(STRIPPED.take(), "\x1b[1mbold");
+        remove_sink(raw);
+        assert_eq!(add_sink(&EXTRA, true), Ok(raw));
+        remove_sink(raw);
+        remove_sink(stripped);
+        assert_eq!(EXTRA.take(), "");
+    }
+
+    /// Replays `early` into a fresh console and returns the text of its rows.
+    fn replayed(early: &EarlyLog) -> Vec<String> {
+        let mut writer = WRITER.lock();
+        let writer = writer.as_mut().unwrap();
+        early.replay(writer);
+        let whole = crate::writer::rect::Rect::new(0, 0, usize::MAX, usize::MAX);
+        writer.get_text_region(whole).map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn the_early_log_replays_its_text_in_order() {
+        let _console = crate::writer::test_console();
+        let mut early = EarlyLog::new();
+        for s in ["one\n", "two ", "thr", "ee\n", "\x1b[1mfour\x1b[0m"] {
+            early.push(s);
+        }
+        assert_eq!(replayed(&early), ["one", "two three", "four", "", ""]);
+        // Nothing is kept once the console exists
+        early.retired = true;
+        early.push("lost");
+        assert_eq!(early.iter().count(), "one\ntwo three\n\x1b[1mfour\x1b[0m".len());
+    }
+
+    #[test]
+    fn a_full_early_log_drops_the_oldest_line_and_says_how_much_was_lost() {
+        let _console = crate::writer::test_console();
+        let mut early = EarlyLog::new();
+        let first = "x".repeat(EARLY_LOG_CAPACITY - "line\n".len() - 1) + "\n";
+        early.push(&first);
+        early.push("line\n");
+        // Exactly full, nothing is dropped yet
+        assert_eq!((early.len, early.dropped), (EARLY_LOG_CAPACITY, 0));
+        assert!(early.iter().eq(first.bytes().chain(*b"line\n")));
+
+        early.push("last");
+        assert_eq!((early.len, early.dropped), (EARLY_LOG_CAPACITY, 4));
+        assert!(early.iter().take(3).eq(*b"xxx"));
+        // The rest of the line that lost its start counts as dropped as well
+        assert_eq!(first.len(), 4091);
+        let rows = ["(4091 bytes of ear", "ly log dropped)", "line", "last", ""];
+        assert_eq!(replayed(&early), rows);
+    }
+}
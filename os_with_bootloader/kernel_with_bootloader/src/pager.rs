@@ -0,0 +1,137 @@
+//! Paging of long output: after a screenful of lines, the output pauses at a `--More--`
+//! prompt until a key is pressed, like `more`.
+//!
+//! Space shows the next screenful, Enter the next line, and `q` aborts, which makes further
+//! writes fail so that the producer stops.
+use crate::input::{self, Key};
+use crate::writer::WRITER;
+use crate::{keyboard, output, serial, time};
+use core::fmt::{self, Write};
+
+/// Prompt shown on the bottom line while the output is paused.
+const PROMPT: &str = "\x1b[5;7m--More--\x1b[25;27m";
+
+/// Text that erases the prompt and returns to the start of its line.
+const ERASE_PROMPT: &str = "\r        \r";
+
+/// Writes to the print macros' sinks, pausing after every screenful of display lines.
+pub struct Pager {
+    /// Number of display lines that fit between two pauses, or `None` to never pause because
+    /// there is no console.
+    page: Option<usize>,
+    /// Value of [display_lines] up to which the output may continue before pausing.
+    limit: usize,
+    aborted: bool,
+}
+
+/// Returns the number of display lines the console has started, or `None` without console.
+fn display_lines() -> Option<usize> {
+    WRITER.lock().as_ref().map(|writer| writer.display_lines())
+}
+
+impl Pager {
+    /// Starts paging at the current line. Without a console, it writes straight through.
+    pub fn new() -> Self {
+        let rows = WRITER.lock().as_ref().map(|writer| writer.rows());
+        let page = rows.map(|rows| rows.saturating_sub(1).max(1));
+        let start = display_lines().unwrap_or(0);
+        Self { page, limit: start + page.unwrap_or(0), aborted: false }
+    }
+
+    /// Returns whether the reader aborted with `q`.
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Shows the prompt and waits for a key, then erases the prompt. Returns whether to go on.
+    fn pause(&mut self, page: usize, lines: usize) -> bool {
+        output::write_str(PROMPT);
+        let key = loop {
+            keyboard::poll();
+            serial::poll();
+            time::tick();
+            match input::pop_key().map(|event| event.key) {
+                Some(key @ (Key::Char(' ' | 'q') | Key::Enter)) => break key,
+                _ => core::hint::spin_loop(),
+            }
+        };
+        output::write_str(ERASE_PROMPT);
+        match key {
+            Key::Char('q') => return false,
+            Key::Enter => self.limit = lines + 1,
+            _ => self.limit = lines + page,
+        }
+        true
+    }
+}
+
+impl Default for Pager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Pager {
+    /// Writes the text a char at a time, so that the output can pause exactly when the next
+    /// display line would push the first one of the page off the screen, whether it starts
+    /// with a newline or a soft wrap.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.aborted {
+            return Err(fmt::Error);
+        }
+        let Some(page) = self.page else {
+            output::write_str(s);
+            return Ok(());
+        };
+        for c in s.chars() {
+            let lines = display_lines().unwrap_or(0);
+            if lines >= self.limit && !self.pause(page, lines) {
+                self.aborted = true;
+                return Err(fmt::Error);
+            }
+            output::write_str(c.encode_utf8(&mut [0; 4]));
+        }
+        Ok(())
+    }
+}
+
+/// Runs `f` with a [Pager] for its output. The error of an aborted pager is returned, like any
+/// other error of `f`.
+pub fn run(f: impl FnOnce(&mut Pager) -> fmt::Result) -> fmt::Result {
+    f(&mut Pager::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::KeyEvent;
+    use crate::writer::rect::Rect;
+
+    /// Returns the text of the rows of the console.
+    fn screen() -> Vec<String> {
+        let writer = WRITER.lock();
+        let whole = Rect::new(0, 0, usize::MAX, usize::MAX);
+        let rows = writer.as_ref().unwrap().get_text_region(whole);
+        rows.map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn output_pauses_after_a_screenful_and_stops_on_q() {
+        let _console = crate::writer::test_console();
+        // 5 rows, one of which is kept for the prompt
+        let mut pager = Pager::new();
+        write!(pager, "1\n2\n3\n4\n").unwrap();
+        assert_eq!(screen(), ["1", "2", "3", "4", ""]);
+        // Keys other than space, Enter and q don't continue
+        for key in [Key::Char('x'), Key::Char(' '), Key::Enter, Key::Char('q')] {
+            input::push_key(KeyEvent::new(key));
+        }
+        // A screenful after space, a line after Enter, and nothing after q
+        let lines: String = (5..=20).map(|n| format!("{}\n", n)).collect();
+        assert_eq!(pager.write_str(&lines), Err(fmt::Error));
+        assert!(pager.aborted());
+        assert_eq!(screen(), ["6", "7", "8", "9", ""]);
+        assert!(input::pop_key().is_none());
+        assert_eq!(pager.write_str("21\n"), Err(fmt::Error));
+    }
+}
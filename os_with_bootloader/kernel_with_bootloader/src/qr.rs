@@ -0,0 +1,570 @@
+//! A small QR code encoder for the panic screen: byte mode at error correction level L, in
+//! versions 1 to 9, without allocation.
+//!
+//! The version is the smallest that fits the data. Data beyond the capacity of version 9,
+//! [MAX_BYTES], is cut off, so a long panic message still yields a code of its beginning.
+use crate::writer::color::Color;
+use crate::writer::FrameBufferWriter;
+use core::fmt::{self, Write};
+
+/// Largest supported version.
+const MAX_VERSION: usize = 9;
+
+/// Width and height of a code of the largest version in modules.
+const MAX_SIZE: usize = 17 + 4 * MAX_VERSION;
+
+/// Number of codewords in a code of the largest version.
+const MAX_CODEWORDS: usize = 292;
+
+/// Largest number of error correction codewords per block.
+const MAX_EC_PER_BLOCK: usize = 30;
+
+/// Number of data bytes that fit into a code of the largest version.
+pub const MAX_BYTES: usize = 230;
+
+/// Width of the light border around the code in modules, which scanners need to find it.
+pub const QUIET_ZONE: usize = 4;
+
+/// Codewords of each version at level L: the total, the error correction codewords of each
+/// block, and the number of blocks, which all have the same length.
+const VERSIONS: [(usize, usize, usize); MAX_VERSION] = [
+    (26, 7, 1),
+    (44, 10, 1),
+    (70, 15, 1),
+    (100, 20, 1),
+    (134, 26, 1),
+    (172, 18, 2),
+    (196, 20, 2),
+    (242, 24, 2),
+    (292, 30, 2),
+];
+
+/// Centers of the alignment patterns of each version, in both directions.
+const ALIGNMENT: [&[usize]; MAX_VERSION] = [
+    &[],
+    &[6, 18],
+    &[6, 22],
+    &[6, 26],
+    &[6, 30],
+    &[6, 34],
+    &[6, 22, 38],
+    &[6, 24, 42],
+    &[6, 26, 46],
+];
+
+/// Penalty weights of the mask evaluation: runs, 2×2 blocks, finder-like patterns and the
+/// balance of dark modules.
+const PENALTY_RUN: usize = 3;
+const PENALTY_BLOCK: usize = 3;
+const PENALTY_FINDER: usize = 40;
+const PENALTY_BALANCE: usize = 10;
+
+/// Rows of the pattern dark, light, three dark, light, dark next to four light modules, which
+/// scanners mistake for a finder.
+const FINDER_LIKE: [u16; 2] = [0b101_1101_0000, 0b000_0101_1101];
+
+/// Multiplies two elements of GF(256) modulo the QR polynomial x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_mul(x: u8, y: u8) -> u8 {
+    let mut z: u32 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11d);
+        z ^= u32::from((y >> i) & 1) * u32::from(x);
+    }
+    z as u8
+}
+
+/// Returns the coefficients of the Reed-Solomon generator polynomial of the given degree,
+/// highest first and without the leading 1.
+fn rs_divisor(degree: usize) -> [u8; MAX_EC_PER_BLOCK] {
+    let mut divisor = [0; MAX_EC_PER_BLOCK];
+    divisor[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            divisor[j] = gf_mul(divisor[j], root);
+            if j + 1 < degree {
+                divisor[j] ^= divisor[j + 1];
+            }
+        }
+        root = gf_mul(root, 0x02);
+    }
+    divisor
+}
+
+/// Writes the error correction codewords of `data` to `ec`, whose length is the degree.
+fn rs_remainder(data: &[u8], divisor: &[u8], ec: &mut [u8]) {
+    ec.fill(0);
+    for &byte in data {
+        let factor = byte ^ ec[0];
+        ec.rotate_left(1);
+        ec[ec.len() - 1] = 0;
+        for (ec, &coefficient) in ec.iter_mut().zip(divisor) {
+            *ec ^= gf_mul(coefficient, factor);
+        }
+    }
+}
+
+/// Appends bits to the data codewords, most significant bit first.
+struct BitWriter {
+    bytes: [u8; MAX_CODEWORDS],
+    len: usize,
+}
+
+impl BitWriter {
+    fn push(&mut self, value: u32, bits: usize) {
+        for i in (0..bits).rev() {
+            if (value >> i) & 1 != 0 {
+                self.bytes[self.len / 8] |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+/// A QR code: a square of dark and light modules.
+pub struct QrCode {
+    version: usize,
+    size: usize,
+    /// Bit `x` of row `y` is set for a dark module.
+    modules: [u64; MAX_SIZE],
+    /// Bit `x` of row `y` is set for a module of a function pattern, which masks don't change.
+    function: [u64; MAX_SIZE],
+}
+
+impl QrCode {
+    /// Encodes `data` in the smallest version that fits it, cutting it off after [MAX_BYTES].
+    /// Returns the code and the number of bytes of `data` that it holds.
+    pub fn encode(data: &[u8]) -> (Self, usize) {
+        let len = data.len().min(MAX_BYTES);
+        let data = &data[..len];
+        // Mode and length take 12 bits, in every supported version
+        let fits = |&(total, ec, blocks): &(usize, usize, usize)| total - ec * blocks >= len + 2;
+        let index = VERSIONS.iter().position(fits).unwrap_or(MAX_VERSION - 1);
+        let mut code = Self {
+            version: index + 1,
+            size: 17 + 4 * (index + 1),
+            modules: [0; MAX_SIZE],
+            function: [0; MAX_SIZE],
+        };
+        let mut codewords = [0; MAX_CODEWORDS];
+        let codewords = code.codewords(data, &mut codewords);
+        code.draw_function_patterns();
+        code.draw_codewords(codewords);
+        let mask = (0..8).min_by_key(|&mask| {
+            code.apply_mask(mask);
+            code.draw_format(mask);
+            let penalty = code.penalty();
+            code.apply_mask(mask);
+            penalty
+        });
+        let mask = mask.unwrap_or(0);
+        code.apply_mask(mask);
+        code.draw_format(mask);
+        (code, len)
+    }
+
+    /// Returns the version, from 1 to 9.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Returns the width and height in modules.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether the module in column `x` and row `y` is dark.
+    pub fn module(&self, x: usize, y: usize) -> bool {
+        y < self.size && x < self.size && (self.modules[y] >> x) & 1 != 0
+    }
+
+    fn set(&mut self, x: usize, y: usize, dark: bool) {
+        match dark {
+            true => self.modules[y] |= 1 << x,
+            false => self.modules[y] &= !(1 << x),
+        }
+    }
+
+    /// Sets a module of a function pattern.
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.set(x, y, dark);
+        self.function[y] |= 1 << x;
+    }
+
+    fn is_function(&self, x: usize, y: usize) -> bool {
+        (self.function[y] >> x) & 1 != 0
+    }
+
+    /// Builds the data codewords, padded to the capacity of the version, appends the error
+    /// correction codewords of each block, and interleaves the blocks into `out`.
+    fn codewords<'a>(&self, data: &[u8], out: &'a mut [u8; MAX_CODEWORDS]) -> &'a [u8] {
+        let (total, ec_len, blocks) = VERSIONS[self.version - 1];
+        let data_len = total - ec_len * blocks;
+        let mut bits = BitWriter { bytes: [0; MAX_CODEWORDS], len: 0 };
+        bits.push(0b0100, 4);
+        bits.push(data.len() as u32, 8);
+        for &byte in data {
+            bits.push(u32::from(byte), 8);
+        }
+        let terminator = (data_len * 8 - bits.len).min(4);
+        bits.push(0, terminator);
+        bits.push(0, bits.len.next_multiple_of(8) - bits.len);
+        for pad in [0xec, 0x11].into_iter().cycle().take(data_len - bits.len / 8) {
+            bits.push(pad, 8);
+        }
+
+        let block_len = data_len / blocks;
+        let divisor = rs_divisor(ec_len);
+        let mut ec = [[0; MAX_EC_PER_BLOCK]; 2];
+        for (block, ec) in ec.iter_mut().enumerate().take(blocks) {
+            let data = &bits.bytes[block * block_len..(block + 1) * block_len];
+            rs_remainder(data, &divisor[..ec_len], &mut ec[..ec_len]);
+        }
+        let mut len = 0;
+        for i in 0..block_len {
+            for block in 0..blocks {
+                out[len] = bits.bytes[block * block_len + i];
+                len += 1;
+            }
+        }
+        for i in 0..ec_len {
+            for ec in &ec[..blocks] {
+                out[len] = ec[i];
+                len += 1;
+            }
+        }
+        &out[..total]
+    }
+
+    /// Draws the finder, timing and alignment patterns and the version information, and
+    /// reserves the modules of the format information.
+    fn draw_function_patterns(&mut self) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            for dy in -4..=4isize {
+                for dx in -4..=4isize {
+                    let distance = dx.abs().max(dy.abs());
+                    let (x, y) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+                    if x < size && y < size {
+                        self.set_function(x, y, distance != 2 && distance != 4);
+                    }
+                }
+            }
+        }
+        let centers = ALIGNMENT[self.version - 1];
+        let last = centers.len().saturating_sub(1);
+        for (i, &x) in centers.iter().enumerate() {
+            for (j, &y) in centers.iter().enumerate() {
+                // The corners with finder patterns have none
+                if (i, j) == (0, 0) || (i, j) == (0, last) || (i, j) == (last, 0) {
+                    continue;
+                }
+                for dy in -2..=2isize {
+                    for dx in -2..=2isize {
+                        let dark = dx.abs().max(dy.abs()) != 1;
+                        let (x, y) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+                        self.set_function(x, y, dark);
+                    }
+                }
+            }
+        }
+        self.draw_format(0);
+        if self.version >= 7 {
+            let version = self.version as u32;
+            let mut remainder = version;
+            for _ in 0..12 {
+                remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1f25);
+            }
+            let bits = version << 12 | remainder;
+            for i in 0..18 {
+                let dark = (bits >> i) & 1 != 0;
+                let (a, b) = (size - 11 + i % 3, i / 3);
+                self.set_function(a, b, dark);
+                self.set_function(b, a, dark);
+            }
+        }
+    }
+
+    /// Draws both copies of the format information of level L with the given mask, and the
+    /// dark module next to the bottom left finder.
+    fn draw_format(&mut self, mask: usize) {
+        let data = (0b01 << 3 | mask) as u32;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = (data << 10 | remainder) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Places the bits of the codewords in the zigzag of column pairs from the bottom right,
+    /// skipping the function patterns. Modules left over stay light.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut bit = 0;
+        let mut right = size - 1;
+        loop {
+            // The vertical timing pattern takes a column of its own
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..size {
+                let y = if upward { size - 1 - vertical } else { vertical };
+                for x in [right, right - 1] {
+                    if !self.is_function(x, y) && bit < codewords.len() * 8 {
+                        self.set(x, y, (codewords[bit / 8] >> (7 - bit % 8)) & 1 != 0);
+                        bit += 1;
+                    }
+                }
+            }
+            if right < 3 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// Inverts the data modules that the given mask pattern selects; masking twice undoes it.
+    fn apply_mask(&mut self, mask: usize) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if invert && !self.is_function(x, y) {
+                    self.modules[y] ^= 1 << x;
+                }
+            }
+        }
+    }
+
+    /// Rates how hard the code is to scan: long runs of one color, 2×2 blocks, patterns that
+    /// look like finders, and an imbalance of dark and light modules add to the penalty.
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut penalty = 0;
+        for transposed in [false, true] {
+            let module = |i: usize, j: usize| match transposed {
+                false => self.module(j, i),
+                true => self.module(i, j),
+            };
+            for i in 0..size {
+                let (mut run, mut window) = (0, 0u16);
+                for j in 0..size {
+                    run = match j > 0 && module(i, j) == module(i, j - 1) {
+                        true => run + 1,
+                        false => 1,
+                    };
+                    if run == 5 {
+                        penalty += PENALTY_RUN;
+                    } else if run > 5 {
+                        penalty += 1;
+                    }
+                    window = ((window << 1) | u16::from(module(i, j))) & 0x7ff;
+                    if j >= 10 && FINDER_LIKE.contains(&window) {
+                        penalty += PENALTY_FINDER;
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let dark = self.module(x, y);
+                let same = [(x + 1, y), (x, y + 1), (x + 1, y + 1)];
+                if same.iter().all(|&(x, y)| self.module(x, y) == dark) {
+                    penalty += PENALTY_BLOCK;
+                }
+            }
+        }
+        let dark: usize = self.modules[..size].iter().map(|row| row.count_ones() as usize).sum();
+        let total = size * size;
+        // The size is odd, so the dark share is never exactly half
+        let k = (dark * 20).abs_diff(total * 10).div_ceil(total) - 1;
+        penalty + k * PENALTY_BALANCE
+    }
+
+    /// Draws the code with its quiet zone, dark on white, with the top left corner of the
+    /// quiet zone at `(x, y)` and `scale` pixels per module.
+    pub fn draw(&self, writer: &mut FrameBufferWriter, x: isize, y: isize, scale: usize) {
+        let side = (self.size + 2 * QUIET_ZONE) * scale;
+        writer.fill_rect(x, y, side, side, Color::WHITE);
+        let position = |origin: isize, module: usize| {
+            origin + ((QUIET_ZONE + module) * scale) as isize
+        };
+        for row in 0..self.size {
+            for column in (0..self.size).filter(|&column| self.module(column, row)) {
+                let (x, y) = (position(x, column), position(y, row));
+                writer.fill_rect(x, y, scale, scale, Color::BLACK);
+            }
+        }
+    }
+}
+
+/// Collects formatted text for [encode_fmt], silently dropping everything from the first char
+/// that doesn't fit.
+struct TextBuffer {
+    bytes: [u8; MAX_BYTES],
+    len: usize,
+    full: bool,
+}
+
+impl Write for TextBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars().take_while(|_| !self.full) {
+            let mut encoded = [0; 4];
+            let encoded = c.encode_utf8(&mut encoded).as_bytes();
+            let Some(free) = self.bytes.get_mut(self.len..self.len + encoded.len()) else {
+                self.full = true;
+                break;
+            };
+            free.copy_from_slice(encoded);
+            self.len += encoded.len();
+        }
+        Ok(())
+    }
+}
+
+/// Encodes formatted text, e.g. a panic message, cut off at the last char that fits into
+/// [MAX_BYTES], so that the code holds valid UTF-8.
+pub fn encode_fmt(args: fmt::Arguments) -> QrCode {
+    let mut text = TextBuffer { bytes: [0; MAX_BYTES], len: 0, full: false };
+    let _ = text.write_fmt(args);
+    QrCode::encode(&text.bytes[..text.len]).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the modules of a code row by row, `#` for dark and `.` for light ones.
+    fn art(code: &QrCode) -> Vec<String> {
+        let row = |y| (0..code.size()).map(|x| if code.module(x, y) { '#' } else { '.' }).collect();
+        (0..code.size()).map(row).collect()
+    }
+
+    // The expected modules come from an independent encoder, given the mask that the penalty
+    // of this one picks
+
+    #[test]
+    fn a_short_text_matches_the_reference_code_of_version_1() {
+        let expected = [
+            "#######..#.##.#######",
+            "#.....#.##.#..#.....#",
+            "#.###.#.##..#.#.###.#",
+            "#.###.#..#.#..#.###.#",
+            "#.###.#.#...#.#.###.#",
+            "#.....#.#..##.#.....#",
+            "#######.#.#.#.#######",
+            "........#####........",
+            "##.#..##.##...###.##.",
+            ".#####.###....#....##",
+            "..##.####.#.##...##.#",
+            "...#.#..#..#.....#.##",
+            "....#.##.##.#.#.#....",
+            "........####...##.#.#",
+            "#######.###..#.#.###.",
+            "#.....#..#####.##....",
+            "#.###.#..#.#..###...#",
+            "#.###.#.#.##...#.####",
+            "#.###.#..##.#...#.#.#",
+            "#.....#.###..##......",
+            "#######.#.###..#.#.#.",
+        ];
+        let (code, len) = QrCode::encode(b"hello");
+        assert_eq!((code.version(), code.size(), len), (1, 21, 5));
+        assert_eq!(art(&code), expected);
+    }
+
+    #[test]
+    fn a_code_with_two_blocks_and_version_information_matches_the_reference() {
+        let expected = [
+            "#######...#..#.###.##..#.##.###.#...#.#######",
+            "#.....#..#...###.###.#....###.####.#..#.....#",
+            "#.###.#.##..#.#...#..##.#..#...#...#..#.###.#",
+            "#.###.#..###....#...#.####...#.....##.#.###.#",
+            "#.###.#..##.##.###.########.###.#####.#.###.#",
+            "#.....#..###.###.####...#.###.###.....#.....#",
+            "#######.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#.#######",
+            "........#.##.#.###.##...###.###.#####........",
+            "###.#######..###.##.#####.###.###.##.##...#..",
+            "...#...#.#.##.#...#####.#..#...#.......#.##.#",
+            "##.##.####..#...#..#..####...#...#..##....###",
+            "##.#....#....#.###.....#.##.###.#######.#..#.",
+            ".#..####...#####.##.##....###.###.##..####...",
+            "#.###...####..#...#####.#..#...#.......#.##.#",
+            "##....##..#.#...#..#..####...#...#..##....###",
+            ".#.###..###.##.###.....#.##.###.#######.#..#.",
+            "..#.###.#.#..###.##.##....###.###.##..####...",
+            ".#........##..#...#####.#..#...#.......#.##.#",
+            ".##.#.#....#....#..#..####...#...#..##....###",
+            "#..##..#.#.#.#.###.....#.##.###.#######.#..#.",
+            "##.########.####.##.#####.###.###.########...",
+            "##..#...####..#...###...#..#...#....#...###.#",
+            "#.#.#.#.#.##....#...#.#.##...#...#.##.#.#.###",
+            "###.#...######.###..#...###.###.#####...#..#.",
+            "#...######.#####.########.###.###.#.######...",
+            "#...##.....#..#...#.#.###..#...#...#.#...##.#",
+            "....#.###..##...#...#..#.#...#...#.#.##.#.###",
+            "..#.##.##..#.#.###.#.#...##.###.###.#.###..#.",
+            "..##.###.#.#.###.###.##.#.###.###.#.#..#.#...",
+            "..#..#..##....#...#.#.###..#...#...#.#...##.#",
+            "###.#.#.###.#...#...#..#.#...#...#.#.##.#.###",
+            "#..###..##.###.###.#.#...##.###.###.#.###..#.",
+            ".#...###.#..#.##.###.##.#.###.###.#.#..#.#...",
+            "#..###....#.##....#.#.###..#...#...#.#...##.#",
+            "....#.#..##.....#...#..#.#...#...#.#.##.#.###",
+            ".####...#...##.###.#.#...##.###.###.#.###..##",
+            "#..##.#.#..#.###.########.###.###.#.######.##",
+            "........#.#...#...#.#...#..#...#...##...###.#",
+            "#######.###.##..#..##.#.##...#...#..#.#.#.###",
+            "#.....#.#.########..#...###.###.#####...#..#.",
+            "#.###.#.#..###.#.##.#####.###.###.########...",
+            "#.###.#..#.#.#....##...#...#...#....###.###..",
+            "#.###.#.#..##...#....#...#...#...#.##.###.#..",
+            "#.....#.#..#.#.###..###.###.###.####...#...#.",
+            "#######.#.##.###.####.###.###.###.#..#...#.##",
+        ];
+        let (code, len) = QrCode::encode(&[b'x'; 150]);
+        assert_eq!((code.version(), code.size(), len), (7, 45, 150));
+        assert_eq!(art(&code), expected);
+    }
+
+    #[test]
+    fn data_beyond_the_capacity_is_cut_off_at_a_char_boundary() {
+        let (code, len) = QrCode::encode(&[b'x'; 300]);
+        assert_eq!((code.version(), code.size(), len), (9, 53, MAX_BYTES));
+        // The 'é' would end one byte past the capacity
+        let text = std::format!("{}é", "x".repeat(MAX_BYTES - 1));
+        let (whole, _) = QrCode::encode(&text.as_bytes()[..MAX_BYTES - 1]);
+        assert_eq!(art(&encode_fmt(format_args!("{}", text))), art(&whole));
+    }
+}
@@ -0,0 +1,220 @@
+//! Wall-clock time read from the real-time clock of the CMOS.
+use core::fmt;
+use x86_64::instructions::port::Port;
+
+/// Registers of the real-time clock.
+const SECONDS: u8 = 0x00;
+const MINUTES: u8 = 0x02;
+const HOURS: u8 = 0x04;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+const STATUS_A: u8 = 0x0a;
+const STATUS_B: u8 = 0x0b;
+
+/// Status A: an update of the time registers is in progress, so they may be inconsistent.
+const UPDATE_IN_PROGRESS: u8 = 0x80;
+/// Status B: the registers hold binary values instead of BCD.
+const BINARY: u8 = 0x04;
+/// Status B: the hours are counted from 0 to 23 instead of 1 to 12.
+const HOURS_24: u8 = 0x02;
+/// Set in the hours register for PM in 12-hour mode.
+const PM: u8 = 0x80;
+
+/// A calendar date and time of day. The clock has no time zone; it is usually UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to 31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Returns the seconds since 1970-01-01 00:00:00.
+    pub fn unix_seconds(&self) -> i64 {
+        let days = days_from_civil(self.year.into(), self.month, self.day);
+        days * 86400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+    }
+
+    /// Returns the day of the week, from 0 for Monday to 6 for Sunday.
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        (days_from_civil(self.year.into(), self.month, self.day) + 3).rem_euclid(7) as u8
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Formats as `2024-02-29 13:05:09`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Returns whether `year` has a February 29th.
+pub fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Returns the number of days of `month` (1 to 12) in `year`.
+pub fn days_in_month(year: i64, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days from 1970-01-01 to the date in the proleptic Gregorian calendar.
+pub fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    // Count the years from March, so that the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Converts a binary-coded decimal byte, e.g. `0x59` to 59.
+pub fn bcd_to_binary(bcd: u8) -> u8 {
+    (bcd >> 4) * 10 + (bcd & 0x0f)
+}
+
+fn read_register(register: u8) -> u8 {
+    let mut index = Port::<u8>::new(0x70);
+    let mut data = Port::<u8>::new(0x71);
+    // SAFETY: the ports belong to the CMOS, which nothing else uses. Bit 7 of the index keeps
+    // the non-maskable interrupts enabled.
+    unsafe {
+        index.write(register & 0x7f);
+        data.read()
+    }
+}
+
+/// The raw time registers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Registers([u8; 6]);
+
+impl Registers {
+    fn read() -> Self {
+        while read_register(STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+            core::hint::spin_loop();
+        }
+        Self([SECONDS, MINUTES, HOURS, DAY, MONTH, YEAR].map(read_register))
+    }
+
+    /// Converts the registers according to the formats selected in status register B.
+    fn to_date_time(self, status_b: u8) -> DateTime {
+        let [second, minute, hour, day, month, year] = self.0;
+        let pm = hour & PM != 0;
+        let convert = |value: u8| match status_b & BINARY {
+            0 => bcd_to_binary(value),
+            _ => value,
+        };
+        let mut hour = convert(hour & !PM);
+        if status_b & HOURS_24 == 0 {
+            // 12 AM is midnight and 12 PM is noon
+            hour = hour % 12 + if pm { 12 } else { 0 };
+        }
+        DateTime {
+            // The century register isn't standardized, so assume the 21st century
+            year: 2000 + u16::from(convert(year)),
+            month: convert(month),
+            day: convert(day),
+            hour,
+            minute: convert(minute),
+            second: convert(second),
+        }
+    }
+}
+
+/// Reads the current date and time. The registers are read until two reads in a row agree,
+/// since an update may start in the middle of a read.
+pub fn now() -> DateTime {
+    let mut registers = Registers::read();
+    loop {
+        let again = Registers::read();
+        if again == registers {
+            break;
+        }
+        registers = again;
+    }
+    registers.to_date_time(read_register(STATUS_B))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the date and time of the given components.
+    fn date_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime { year, month, day, hour, minute, second }
+    }
+
+    #[test]
+    fn bcd_bytes_are_decoded_digit_by_digit() {
+        assert_eq!(bcd_to_binary(0x00), 0);
+        assert_eq!(bcd_to_binary(0x09), 9);
+        assert_eq!(bcd_to_binary(0x10), 10);
+        assert_eq!(bcd_to_binary(0x59), 59);
+        assert_eq!(bcd_to_binary(0x99), 99);
+    }
+
+    #[test]
+    fn registers_are_converted_in_every_format() {
+        // 2024-02-29 13:05:09 in BCD and in binary
+        let bcd = Registers([0x09, 0x05, 0x13, 0x29, 0x02, 0x24]);
+        let binary = Registers([9, 5, 13, 29, 2, 24]);
+        let expected = date_time(2024, 2, 29, 13, 5, 9);
+        assert_eq!(bcd.to_date_time(HOURS_24), expected);
+        assert_eq!(binary.to_date_time(HOURS_24 | BINARY), expected);
+        // In 12-hour mode, 1 PM has the PM bit set, and 12 AM and PM are midnight and noon
+        let twelve_hour = |hour: u8| Registers([0, 0, hour, 1, 1, 0]).to_date_time(0).hour;
+        assert_eq!(twelve_hour(0x01 | PM), 13);
+        assert_eq!(twelve_hour(0x01), 1);
+        assert_eq!(twelve_hour(0x12), 0);
+        assert_eq!(twelve_hour(0x12 | PM), 12);
+        let twelve_hour_binary = Registers([0, 0, 11 | PM, 1, 1, 0]).to_date_time(BINARY);
+        assert_eq!(twelve_hour_binary.hour, 23);
+    }
+
+    #[test]
+    fn leap_years_follow_the_gregorian_rules() {
+        let years = [(2023, false), (2024, true), (1900, false), (2000, true), (2100, false)];
+        for (year, leap) in years {
+            assert_eq!(is_leap_year(year), leap, "{}", year);
+            assert_eq!(days_in_month(year, 2), if leap { 29 } else { 28 });
+        }
+        assert_eq!(days_in_month(2023, 4), 30);
+        assert_eq!(days_in_month(2023, 12), 31);
+    }
+
+    #[test]
+    fn dates_count_days_from_the_unix_epoch() {
+        assert_eq!(date_time(1970, 1, 1, 0, 0, 0).unix_seconds(), 0);
+        assert_eq!(date_time(2000, 3, 1, 0, 0, 0).unix_seconds(), 951_868_800);
+        // The leap day is counted, so March 1st follows February 29th by a day
+        let leap_day = date_time(2024, 2, 29, 13, 5, 9);
+        assert_eq!(leap_day.unix_seconds(), 1_709_211_909);
+        let next = date_time(2024, 3, 1, 13, 5, 9).unix_seconds();
+        assert_eq!(next - leap_day.unix_seconds(), 86_400);
+        // A Thursday, and a Monday
+        assert_eq!(leap_day.weekday(), 3);
+        assert_eq!(date_time(2024, 1, 1, 0, 0, 0).weekday(), 0);
+        assert_eq!(leap_day.to_string(), "2024-02-29 13:05:09");
+    }
+}
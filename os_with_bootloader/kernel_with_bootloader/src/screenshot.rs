@@ -0,0 +1,116 @@
+//! Dumps the screen over the serial port, for bug reports and comparisons with a reference
+//! image.
+//!
+//! The image is framed by [BEGIN_MARKER] and [END_MARKER] lines, so it can be cut out of a
+//! captured serial log, e.g. with
+//! `sed -n '/BEGIN SCREENSHOT/,/END SCREENSHOT/{//!p}' serial.log | base64 -d > screen.ppm`.
+//!
+//! [copy_region_to_serial] sends the text of a part of the screen instead, between two
+//! [CUT_MARKER] lines.
+use crate::serial;
+use crate::writer::WRITER;
+use crate::writer::rect::Rect;
+
+pub const BEGIN_MARKER: &str = "-----BEGIN SCREENSHOT-----\n";
+pub const END_MARKER: &str = "-----END SCREENSHOT-----\n";
+
+/// Line before and after the text sent by [copy_region_to_serial].
+pub const CUT_MARKER: &str = "---8<---\n";
+
+/// Maximum length of a line of base64 text.
+const BASE64_LINE: usize = 76;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How the image data is sent between the markers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// The PPM bytes as they are; only survives captures that keep binary data intact.
+    Binary,
+    /// Base64 in lines of at most 76 characters, which survives terminal capture.
+    Base64,
+}
+
+/// Streaming base64 encoder that sends its output to the serial port.
+struct Base64Writer {
+    pending: [u8; 3],
+    pending_len: usize,
+    column: usize,
+}
+
+impl Base64Writer {
+    const fn new() -> Self {
+        Self { pending: [0; 3], pending_len: 0, column: 0 }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.pending[self.pending_len] = byte;
+            self.pending_len += 1;
+            if self.pending_len == 3 {
+                self.flush_group();
+            }
+        }
+    }
+
+    /// Encodes the pending bytes as one group of four characters, padded with `=`.
+    fn flush_group(&mut self) {
+        let [a, b, c] = self.pending;
+        let indices = [a >> 2, (a & 0x03) << 4 | b >> 4, (b & 0x0f) << 2 | c >> 6, c & 0x3f];
+        let mut group = [b'='; 4];
+        for (output, index) in group.iter_mut().zip(indices).take(self.pending_len + 1) {
+            *output = BASE64_ALPHABET[usize::from(index)];
+        }
+        serial::write_bytes(&group);
+        self.pending = [0; 3];
+        self.pending_len = 0;
+        self.column += group.len();
+        if self.column >= BASE64_LINE {
+            serial::write_bytes(b"\r\n");
+            self.column = 0;
+        }
+    }
+
+    fn finish(mut self) {
+        if self.pending_len > 0 {
+            self.flush_group();
+        }
+        if self.column > 0 {
+            serial::write_bytes(b"\r\n");
+        }
+    }
+}
+
+/// Sends the screen as a PPM image over the serial port. The writer stays locked meanwhile, so
+/// the image isn't torn by concurrent printing. Does nothing if there is no framebuffer console.
+pub fn screenshot(encoding: Encoding) {
+    let writer = WRITER.lock();
+    let Some(writer) = writer.as_ref() else { return };
+    serial::write_str(BEGIN_MARKER);
+    match encoding {
+        Encoding::Binary => writer.write_ppm(serial::write_bytes),
+        Encoding::Base64 => {
+            let mut base64 = Base64Writer::new();
+            writer.write_ppm(|bytes| base64.write(bytes));
+            base64.finish();
+        }
+    }
+    serial::write_str(END_MARKER);
+}
+
+/// Sends the text shown in a rectangle of cells of the text area over the serial port, one
+/// line per row without trailing blanks and without styles, e.g. to get a panic message or a
+/// hex dump off a machine. Does nothing if there is no framebuffer console.
+pub fn copy_region_to_serial(rect: Rect) {
+    let writer = WRITER.lock();
+    let Some(writer) = writer.as_ref() else { return };
+    serial::write_str(CUT_MARKER);
+    for row in writer.get_text_region(rect) {
+        for c in row.chars() {
+            serial::write_str(c.encode_utf8(&mut [0; 4]));
+        }
+        serial::write_str("\n");
+    }
+    serial::write_str(CUT_MARKER);
+}
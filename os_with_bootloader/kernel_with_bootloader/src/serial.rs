@@ -0,0 +1,126 @@
+//! Polled driver for the first serial port (COM1), used both as a mirror of the console output
+//! and as a second input source next to the keyboard.
+use crate::input;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+/// I/O port base of COM1.
+const COM1: u16 = 0x3f8;
+
+/// Line status bit that is set when a received byte is waiting.
+const DATA_READY: u8 = 1 << 0;
+
+/// Line status bit that is set when another byte may be sent.
+const TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// The global serial port. All registers are accessed while holding this lock.
+static SERIAL: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1));
+
+/// The registers of a 16550 compatible UART.
+pub struct SerialPort {
+    base: u16,
+    /// Decodes escape sequences in the received bytes.
+    decoder: input::SerialDecoder,
+}
+
+impl SerialPort {
+    const fn new(base: u16) -> Self {
+        Self { base, decoder: input::SerialDecoder::new() }
+    }
+
+    fn port(&self, offset: u16) -> Port<u8> {
+        Port::new(self.base + offset)
+    }
+
+    /// Configures the port for 38400 baud, 8 data bits, no parity and one stop bit, with
+    /// interrupts disabled.
+    fn init(&mut self) {
+        // SAFETY: the ports belong to the UART and are only accessed through this struct.
+        unsafe {
+            self.port(1).write(0x00); // Disable interrupts
+            self.port(3).write(0x80); // Enable the divisor latch
+            self.port(0).write(0x03); // Divisor 3 (low byte) = 38400 baud
+            self.port(1).write(0x00); //           (high byte)
+            self.port(3).write(0x03); // 8 bits, no parity, one stop bit
+            self.port(2).write(0xc7); // Enable and clear the FIFOs
+            self.port(4).write(0x03); // Data terminal ready, request to send
+        }
+    }
+
+    fn line_status(&self) -> u8 {
+        // Host tests run in user mode, which may not access I/O ports, so there the port is idle
+        if cfg!(test) {
+            return TRANSMIT_EMPTY;
+        }
+        // SAFETY: reading the line status register has no side effects.
+        unsafe { self.port(5).read() }
+    }
+
+    /// Sends a byte, waiting until the transmitter can take it.
+    fn send(&mut self, byte: u8) {
+        while self.line_status() & TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        if cfg!(test) {
+            return;
+        }
+        // SAFETY: the transmitter is empty, so the byte is not lost.
+        unsafe { self.port(0).write(byte) }
+    }
+
+    /// Sends the text, translating LF to CRLF for the terminal on the other end.
+    fn send_str(&mut self, s: &str) {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+    }
+
+    /// Returns the next received byte, if any.
+    fn try_receive(&mut self) -> Option<u8> {
+        if self.line_status() & DATA_READY == 0 {
+            return None;
+        }
+        // SAFETY: a byte is waiting, so reading the data register consumes exactly it.
+        Some(unsafe { self.port(0).read() })
+    }
+}
+
+/// Initializes COM1.
+pub fn init() {
+    SERIAL.lock().init();
+}
+
+/// Writes the text to COM1, translating LF to CRLF for the terminal on the other end.
+pub fn write_str(s: &str) {
+    SERIAL.lock().send_str(s);
+}
+
+/// Like [write_str], but gives up and returns `false` if the port is locked, e.g. by code that
+/// panicked.
+pub fn try_write_str(s: &str) -> bool {
+    let Some(mut serial) = SERIAL.try_lock() else { return false };
+    serial.send_str(s);
+    true
+}
+
+/// Writes the bytes to COM1 unchanged, e.g. for binary data.
+pub fn write_bytes(bytes: &[u8]) {
+    let mut serial = SERIAL.lock();
+    for &byte in bytes {
+        serial.send(byte);
+    }
+}
+
+/// Moves all received bytes into the input queue as [input::KeyEvent]s.
+pub fn poll() {
+    let mut serial = SERIAL.lock();
+    while let Some(byte) = serial.try_receive() {
+        if let Some(key) = serial.decoder.decode(byte) {
+            input::push_key(key);
+        }
+    }
+}
+
@@ -0,0 +1,816 @@
+//! A minimal line-based command shell reading from the [input] queue.
+use crate::heap::{self, HEAP_SIZE};
+use crate::input::{self, Key, KeyEvent};
+use crate::keyboard;
+use crate::log::filter::{self, LevelFilter};
+use crate::log::{self, Highlighted, LOG};
+use crate::pager;
+use crate::rtc;
+use crate::screenshot::{self, Encoding};
+use crate::time;
+use crate::units::Bytes;
+use crate::watch;
+use crate::writer::color::Color;
+use crate::writer::gauge::Gauge;
+use crate::writer::rect::Rect;
+use crate::writer::{overlay, plot};
+use crate::writer::grid::Cell;
+use crate::writer::rotation::Rotation;
+use crate::writer::status::{Ticker, MAX_TICKER_LEN};
+use crate::writer::style::{CursorShape, Style};
+use crate::writer::{MAX_HIDPI_SCALE, WRITER};
+use crate::{print, println};
+use core::cmp::Ordering;
+use core::fmt::{self, Write};
+
+/// Maximum length of an input line in bytes; further characters are ignored.
+const LINE_CAPACITY: usize = 128;
+
+/// Text printed in front of every input line.
+const PROMPT: &str = "> ";
+
+/// Time in milliseconds that the `toast` command shows its text for.
+const TOAST_DURATION_MS: u64 = 3000;
+
+/// Status field that the `ticker` command sets, left of the keyboard indicators.
+const TICKER_FIELD: usize = keyboard::INDICATOR_FIELD + 1;
+
+/// A command the shell can run.
+struct Command {
+    name: &'static str,
+    help: &'static str,
+    /// Runs the command with the rest of the line after the name.
+    run: fn(&str),
+}
+
+const COMMANDS: &[Command] = &[
+    Command { name: "help", help: "lists the available commands", run: help },
+    Command { name: "echo", help: "prints its arguments", run: echo },
+    Command { name: "clear", help: "clears the screen", run: clear },
+    Command { name: "reset", help: "[hard]: resets styles and modes (and clears)", run: reset },
+    Command { name: "recover", help: "resumes the console after a fault", run: recover },
+    Command { name: "linenum", help: "on|off: numbers every line", run: line_numbers },
+    Command { name: "wrapmark", help: "on|indent|off: marks wrapped lines", run: wrap_marker },
+    Command { name: "layout", help: "[NAME]: shows or sets the keyboard layout", run: layout },
+    Command { name: "date", help: "shows the date and time of the real-time clock", run: date },
+    Command { name: "dmesg", help: "[-n N]: shows the (last N) log messages", run: dmesg },
+    Command { name: "logfind", help: "TEXT: shows log messages containing TEXT", run: logfind },
+    Command {
+        name: "logfilter",
+        help: "[default|PREFIX LEVEL|PREFIX reset]: filters the log by module",
+        run: logfilter,
+    },
+    Command { name: "blank", help: "SECONDS|off: blanks the idle screen", run: blank },
+    Command { name: "header", help: "on|off: shows the title above the text", run: header },
+    Command { name: "headerfont", help: "16|20: sets the header font size", run: headerfont },
+    Command { name: "colors", help: "FG BG: sets the default colors, e.g. #c0c0c0", run: colors },
+    Command { name: "title", help: "TEXT: sets the title shown in the header", run: title },
+    Command { name: "toast", help: "TEXT: shows TEXT for a few seconds", run: toast },
+    Command { name: "ticker", help: "[TEXT]: scrolls TEXT through the header", run: ticker },
+    Command { name: "rotate", help: "0|90|180|270: rotates the screen clockwise", run: rotate },
+    Command { name: "hidpi", help: "1|2|3: enlarges all output", run: hidpi },
+    Command { name: "pointer", help: "X Y|off: shows the mouse pointer", run: pointer },
+    Command { name: "smooth", help: "on|off: animates scrolling", run: smooth },
+    Command { name: "scrollback", help: "[LINES|max]: limits the history", run: scrollback },
+    Command { name: "scrollmode", help: "redraw|copy: how scrolls are drawn", run: scroll_mode },
+    Command { name: "cursor", help: "block|underline|blink|steady: the text cursor", run: cursor },
+    Command { name: "doublebuf", help: "on|off: draws into a back buffer first", run: doublebuf },
+    Command { name: "overlay", help: "on|off: shows the output throughput", run: overlay },
+    Command { name: "stats", help: "[reset]: shows the writer counters", run: stats },
+    Command { name: "heap", help: "shows the usage of the kernel heap", run: heap },
+    Command { name: "plot", help: "N...: plots the numbers", run: plot },
+    Command { name: "bench", help: "measures the writer's performance", run: bench },
+    Command { name: "watch", help: "ADDR LEN|clear: shows memory live", run: watch },
+    Command { name: "screenshot", help: "[raw]: sends the screen over serial", run: screenshot },
+    Command { name: "copy", help: "R1 C1 R2 C2: sends the text in between over serial", run: copy },
+    Command { name: "panic", help: "[locked]: panics (while printing to the screen)", run: panic },
+];
+
+fn help(_: &str) {
+    let _ = pager::run(|out| {
+        let mut lines = COMMANDS.iter();
+        lines.try_for_each(|command| writeln!(out, "{:<12}{}", command.name, command.help))
+    });
+}
+
+fn echo(args: &str) {
+    println!("{}", args);
+}
+
+fn clear(_: &str) {
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.clear();
+    }
+}
+
+fn reset(args: &str) {
+    let hard = match args {
+        "" => false,
+        "hard" => true,
+        _ => return println!("usage: reset [hard]"),
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        match hard {
+            true => writer.hard_reset(),
+            false => writer.soft_reset(),
+        }
+    }
+}
+
+fn recover(_: &str) {
+    let result = WRITER.lock().as_mut().map(|writer| writer.recover());
+    if let Some(Err(error)) = result {
+        println!("console still unusable: {}", error);
+    }
+}
+
+fn line_numbers(args: &str) {
+    let enabled = match args {
+        "on" => true,
+        "off" => false,
+        _ => return println!("usage: linenum on|off"),
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_line_numbers(enabled);
+    }
+}
+
+fn wrap_marker(args: &str) {
+    let (enabled, indent) = match args {
+        "on" => (true, false),
+        "indent" => (true, true),
+        "off" => (false, false),
+        _ => return println!("usage: wrapmark on|indent|off"),
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_wrap_marker(enabled, indent);
+    }
+}
+
+fn layout(args: &str) {
+    if args.is_empty() {
+        print!("layout {}, available:", keyboard::layout().name());
+        for layout in keyboard::layout::LAYOUTS {
+            print!(" {}", layout.name());
+        }
+        return println!();
+    }
+    match keyboard::layout::find(args) {
+        Some(layout) => keyboard::set_layout(layout),
+        None => println!("unknown layout {}", args),
+    }
+}
+
+fn screenshot(args: &str) {
+    let encoding = match args {
+        "raw" => Encoding::Binary,
+        _ => Encoding::Base64,
+    };
+    screenshot::screenshot(encoding);
+}
+
+fn copy(args: &str) {
+    let mut numbers = args.split_whitespace().map(str::parse::<usize>);
+    let corners = [(); 4].map(|_| numbers.next().and_then(Result::ok));
+    let ([Some(r1), Some(c1), Some(r2), Some(c2)], None) = (corners, numbers.next()) else {
+        return println!("usage: copy R1 C1 R2 C2 (cells, counted from 0)");
+    };
+    screenshot::copy_region_to_serial(cell_rect((r1, c1), (r2, c2)));
+}
+
+/// Returns the rectangle of cells between two opposite corners, both included, given as row
+/// and column. Corners beyond the text area are clipped when the text is read.
+fn cell_rect((r1, c1): (usize, usize), (r2, c2): (usize, usize)) -> Rect {
+    let (top, left) = (r1.min(r2), c1.min(c2));
+    let (width, height) = (c1.max(c2) - left, r1.max(r2) - top);
+    Rect::new(left, top, width.saturating_add(1), height.saturating_add(1))
+}
+
+fn date(_: &str) {
+    const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let now = rtc::now();
+    println!("{} {}", WEEKDAYS[usize::from(now.weekday())], now);
+}
+
+fn dmesg(args: &str) {
+    let count = match args.strip_prefix("-n") {
+        Some(count) => match count.trim().parse() {
+            Ok(count) => count,
+            Err(_) => return println!("usage: dmesg [-n N]"),
+        },
+        None if args.is_empty() => usize::MAX,
+        None => return println!("usage: dmesg [-n N]"),
+    };
+    let _ = pager::run(|out| {
+        for record in LOG.lock().last(count) {
+            write!(out, "[{:>5}] ", record.sequence)?;
+            log::write_entry(out, record.level, record.message)?;
+        }
+        Ok(())
+    });
+}
+
+fn logfind(needle: &str) {
+    if needle.is_empty() {
+        return println!("usage: logfind TEXT");
+    }
+    let log = LOG.lock();
+    let matches = |message| log::find_ignore_case(message, needle).is_some();
+    let _ = pager::run(|out| {
+        for record in log.iter().filter(|record| matches(record.message)) {
+            write!(out, "[{:>5}] ", record.sequence)?;
+            log::write_entry(out, record.level, Highlighted { message: record.message, needle })?;
+        }
+        Ok(())
+    });
+}
+
+fn logfilter(args: &str) {
+    const USAGE: &str = "usage: logfilter [default|PREFIX off|error|warn|info|debug|trace|reset]";
+    if args.is_empty() {
+        println!("default: {}", filter::default_filter());
+        return filter::for_each_filter(|prefix, filter| println!("{}: {}", prefix, filter));
+    }
+    let Some((prefix, level)) = args.split_once(' ') else { return println!("{}", USAGE) };
+    let level = level.trim();
+    if level == "reset" {
+        if !filter::remove_filter(prefix) {
+            println!("no filter for {}", prefix);
+        }
+        return;
+    }
+    let Some(level) = LevelFilter::parse(level) else { return println!("{}", USAGE) };
+    match prefix {
+        "default" => filter::set_default_filter(level),
+        prefix => {
+            if let Err(error) = log::set_filter(prefix, level) {
+                println!("logfilter: {}", error);
+            }
+        }
+    }
+}
+
+fn heap(_: &str) {
+    const BAR_WIDTH: usize = 32;
+    let stats = heap::alloc_stats();
+    let gauge = Gauge::new(stats.arena_used, HEAP_SIZE, BAR_WIDTH);
+    let bytes = |bytes: usize| Bytes(bytes as u64);
+    println!("arena      {} {} of {}", gauge, bytes(stats.arena_used), bytes(HEAP_SIZE));
+    println!("in use     {} (peak {})", bytes(stats.current), bytes(stats.peak));
+    println!("allocated  {} in {} allocations", bytes(stats.allocated), stats.allocations);
+    println!("freed      {}", bytes(stats.freed));
+    if stats.failures > 0 {
+        println!("failed     {} allocations", stats.failures);
+    }
+}
+
+fn blank(args: &str) {
+    let timeout = match args {
+        "off" => None,
+        seconds => match seconds.parse::<u64>().ok().and_then(|seconds| seconds.checked_mul(1000)) {
+            Some(timeout) => Some(timeout),
+            None => return println!("usage: blank SECONDS|off"),
+        },
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_screensaver_timeout(timeout);
+    }
+}
+
+fn header(args: &str) {
+    let enabled = match args {
+        "on" => true,
+        "off" => false,
+        _ => return println!("usage: header on|off"),
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_header_band(enabled);
+    }
+}
+
+fn colors(args: &str) {
+    let mut specs = args.split_whitespace().map(Color::from_spec);
+    let (Some(Some(foreground)), Some(Some(background)), None) =
+        (specs.next(), specs.next(), specs.next())
+    else {
+        return println!("usage: colors FG BG, as #rrggbb or rgb:r/g/b");
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.recolor_default_colors(foreground, background);
+    }
+}
+
+fn headerfont(args: &str) {
+    let Ok(pixels) = args.parse() else { return println!("usage: headerfont 16|20") };
+    let result = WRITER.lock().as_mut().map(|writer| writer.set_header_font(pixels));
+    if let Some(Err(error)) = result {
+        println!("{}", error);
+    }
+}
+
+fn title(text: &str) {
+    // Through the escape sequence, so that a terminal on the serial port shows it as well
+    print!("\x1b]0;{}\x07", text);
+}
+
+fn toast(text: &str) {
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.toast(text, TOAST_DURATION_MS, time::uptime_ms());
+    }
+}
+
+fn ticker(text: &str) {
+    let mut cells = [Cell::blank(Style { reverse: true, ..Style::DEFAULT }); MAX_TICKER_LEN];
+    let mut len = 0;
+    for (cell, c) in cells.iter_mut().zip(text.chars()) {
+        cell.c = c;
+        len += 1;
+    }
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_status_ticker(TICKER_FIELD, &cells[..len], Ticker::DEFAULT);
+    }
+}
+
+fn rotate(args: &str) {
+    let Some(rotation) = Rotation::from_degrees(args) else {
+        return println!("usage: rotate 0|90|180|270");
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_rotation(rotation);
+    }
+}
+
+fn hidpi(args: &str) {
+    let scale = args.parse().ok().filter(|scale| (1..=MAX_HIDPI_SCALE).contains(scale));
+    let Some(scale) = scale else { return println!("usage: hidpi 1|2|3") };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_hidpi_scale(scale);
+    }
+}
+
+fn pointer(args: &str) {
+    if args == "off" {
+        return overlay::hide_cursor();
+    }
+    let mut numbers = args.split_whitespace().map(str::parse::<usize>);
+    let (Some(Ok(x)), Some(Ok(y)), None) = (numbers.next(), numbers.next(), numbers.next()) else {
+        return println!("usage: pointer X Y|off");
+    };
+    overlay::show_cursor(x, y);
+}
+
+fn smooth(args: &str) {
+    let enabled = match args {
+        "on" => true,
+        "off" => false,
+        _ => return println!("usage: smooth on|off"),
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_smooth_scroll(enabled);
+    }
+}
+
+fn scrollback(args: &str) {
+    let lines = match args {
+        "" => None,
+        "max" => Some(usize::MAX),
+        lines => match lines.parse() {
+            Ok(lines) => Some(lines),
+            Err(_) => return println!("usage: scrollback [LINES|max]"),
+        },
+    };
+    let Some((len, capacity)) = WRITER.lock().as_mut().map(|writer| {
+        if let Some(lines) = lines {
+            writer.set_scrollback_lines(lines);
+        }
+        writer.scrollback_size()
+    }) else {
+        return;
+    };
+    println!("{} of {} lines, Shift+PageUp/PageDown to scroll", len, capacity);
+}
+
+fn scroll_mode(args: &str) {
+    let redraw = match args {
+        "redraw" => true,
+        "copy" => false,
+        _ => return println!("usage: scrollmode redraw|copy"),
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_scroll_redraw(redraw);
+    }
+}
+
+fn cursor(args: &str) {
+    let (shape, blink) = match args {
+        "block" => (Some(CursorShape::Block), None),
+        "underline" => (Some(CursorShape::Underline), None),
+        "blink" => (None, Some(true)),
+        "steady" => (None, Some(false)),
+        _ => return println!("usage: cursor block|underline|blink|steady"),
+    };
+    let mut writer = WRITER.lock();
+    let Some(writer) = writer.as_mut() else { return };
+    if let Some(shape) = shape {
+        writer.set_cursor_shape(shape);
+    }
+    if let Some(blink) = blink {
+        writer.set_cursor_blink(blink);
+    }
+}
+
+fn doublebuf(args: &str) {
+    let enabled = match args {
+        "on" => true,
+        "off" => false,
+        _ => return println!("usage: doublebuf on|off"),
+    };
+    let result = WRITER.lock().as_mut().map(|writer| writer.set_double_buffering(enabled));
+    if let Some(Err(error)) = result {
+        println!("{}", error);
+    }
+}
+
+fn overlay(args: &str) {
+    let enabled = match args {
+        "on" => true,
+        "off" => false,
+        _ => return println!("usage: overlay on|off"),
+    };
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.set_throughput_overlay(enabled, time::uptime_ms());
+    }
+}
+
+fn stats(args: &str) {
+    let stats = match WRITER.lock().as_mut() {
+        Some(writer) if args == "reset" => return writer.reset_stats(),
+        Some(writer) => writer.stats(),
+        None => return,
+    };
+    println!("{:#?}", stats);
+}
+
+fn plot(args: &str) {
+    const MAX_VALUES: usize = 32;
+    let mut values = [0; MAX_VALUES];
+    let mut len = 0;
+    for arg in args.split_whitespace().take(MAX_VALUES) {
+        let Ok(value) = arg.parse() else { return println!("usage: plot N...") };
+        values[len] = value;
+        len += 1;
+    }
+    let values = &values[..len];
+    println!("{}", plot::sparkline(values, MAX_VALUES));
+    print!("{}", plot::bar_chart(&[], values));
+}
+
+fn bench(_: &str) {
+    crate::bench::bench();
+}
+
+fn watch(args: &str) {
+    if args == "clear" {
+        return watch::watch_clear();
+    }
+    let parsed = args.split_once(' ').and_then(|(addr, len)| {
+        let addr = usize::from_str_radix(addr.trim_start_matches("0x"), 16).ok()?;
+        Some((addr, len.trim().parse().ok()?))
+    });
+    let Some((addr, len)) = parsed else {
+        return println!("usage: watch ADDR LEN|clear (ADDR in hex)");
+    };
+    if let Err(error) = watch::watch_add("shell", addr, len) {
+        println!("watch: {}", error);
+    }
+}
+
+/// Panics when it is formatted, for a panic in the middle of printing.
+struct PanickingDisplay;
+
+impl fmt::Display for PanickingDisplay {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        panic!("panic while printing")
+    }
+}
+
+/// Tests the panic screen, also with the console locked, which the panic handler must bypass.
+fn panic(args: &str) {
+    match args {
+        "" => panic!("panic command"),
+        "locked" => {
+            if let Some(writer) = WRITER.lock().as_mut() {
+                let _ = write!(writer, "{}", PanickingDisplay);
+            }
+        }
+        _ => println!("usage: panic [locked]"),
+    }
+}
+
+/// State of the shell: the line that is being typed, and where its echo is on the screen.
+///
+/// The echo is edited with the cursor movement sequences, so that a terminal on the serial
+/// port follows along if it is as wide as the screen. Positions on the screen count cells from
+/// the start of the row that the prompt is on, assuming that every char takes one cell and
+/// that wrapped rows aren't indented.
+pub struct Shell {
+    line: [u8; LINE_CAPACITY],
+    len: usize,
+    /// Byte index in the line at which typed chars are inserted.
+    cursor: usize,
+    /// Column at which the line starts, after the prompt.
+    start_column: usize,
+    /// Number of cells per row at the current text scale.
+    row_width: usize,
+    /// Position of the cursor on the screen.
+    echo: usize,
+    /// Whether the cursor is past the end of a full row, which it only gets by writing the
+    /// last cell, so that it is in the row above `echo`.
+    pending_wrap: bool,
+}
+
+impl Shell {
+    pub const fn new() -> Self {
+        Self {
+            line: [0; LINE_CAPACITY],
+            len: 0,
+            cursor: 0,
+            start_column: 0,
+            row_width: 1,
+            echo: 0,
+            pending_wrap: false,
+        }
+    }
+
+    /// Prints the prompt for the first line.
+    pub fn start(&mut self) {
+        self.prompt();
+    }
+
+    /// Prints the prompt and starts an empty line after it.
+    fn prompt(&mut self) {
+        print!("{}", PROMPT);
+        (self.len, self.cursor, self.pending_wrap) = (0, 0, false);
+        if let Some(writer) = WRITER.lock().as_ref() {
+            self.start_column = writer.cursor_position().0;
+            self.row_width = (writer.columns() / writer.text_scale()).max(1);
+        }
+        self.echo = self.start_column;
+    }
+
+    /// Handles all keys that are waiting in the input queue.
+    pub fn poll(&mut self) {
+        while let Some(event) = input::pop_key() {
+            self.handle_key(event);
+        }
+    }
+
+    /// Edits the current line with the key, echoing it, or runs the line on [Key::Enter].
+    ///
+    /// Left/Right move within the line and Home/End to its ends, typed chars are inserted,
+    /// Backspace and Delete remove the char before or at the cursor, and Ctrl+U clears the
+    /// line. Shift+PageUp/PageDown/Home/End navigate the scrollback; any other key returns the
+    /// view to the live text first.
+    pub fn handle_key(&mut self, event: KeyEvent) {
+        if let Some(writer) = WRITER.lock().as_mut() {
+            writer.wake();
+            writer.dismiss_toast();
+            let page = writer.rows().saturating_sub(1).max(1);
+            match (event.key, event.modifiers.shift) {
+                (Key::PageUp, true) => return writer.scroll_view_up(page),
+                (Key::PageDown, true) => return writer.scroll_view_down(page),
+                (Key::Home, true) => return writer.view_oldest(),
+                (Key::End, true) => return writer.view_live(),
+                _ => writer.view_live(),
+            }
+        }
+        let before = self.line()[..self.cursor].chars().next_back();
+        let at = self.line()[self.cursor..].chars().next();
+        match event.key {
+            Key::Char('u') if event.modifiers.ctrl => {
+                let chars = self.line().chars().count();
+                self.move_to(0);
+                self.len = 0;
+                self.erase(chars);
+            }
+            Key::Char(c) => self.insert(c),
+            Key::Backspace => {
+                if let Some(before) = before {
+                    self.cursor -= before.len_utf8();
+                    self.move_to(self.cursor);
+                    self.remove(before.len_utf8());
+                }
+            }
+            Key::Delete => {
+                if let Some(at) = at {
+                    self.remove(at.len_utf8());
+                }
+            }
+            Key::Left => {
+                if let Some(before) = before {
+                    self.move_to(self.cursor - before.len_utf8());
+                }
+            }
+            Key::Right => {
+                if let Some(at) = at {
+                    self.move_to(self.cursor + at.len_utf8());
+                }
+            }
+            Key::Home => self.move_to(0),
+            Key::End => self.move_to(self.len),
+            Key::Enter => {
+                self.move_to(self.len);
+                println!();
+                self.execute();
+                self.prompt();
+            }
+            // There is no history yet
+            Key::Up | Key::Down | Key::PageUp | Key::PageDown => {}
+        }
+    }
+
+    /// Inserts `c` at the cursor, if there is room, and echoes the rest of the line after it.
+    fn insert(&mut self, c: char) {
+        let mut bytes = [0; 4];
+        let encoded = c.encode_utf8(&mut bytes).as_bytes();
+        let Some(end) = Some(self.len + encoded.len()).filter(|&end| end <= LINE_CAPACITY) else {
+            return;
+        };
+        self.line.copy_within(self.cursor..self.len, self.cursor + encoded.len());
+        self.line[self.cursor..self.cursor + encoded.len()].copy_from_slice(encoded);
+        self.len = end;
+        self.echo_from(self.cursor);
+        self.move_to(self.cursor + encoded.len());
+    }
+
+    /// Removes `len` bytes at the cursor, which is where the echo is, and echoes the rest of the
+    /// line in their place.
+    fn remove(&mut self, len: usize) {
+        self.line.copy_within(self.cursor + len..self.len, self.cursor);
+        self.len -= len;
+        self.erase(1);
+    }
+
+    /// Echoes the rest of the line from the cursor, followed by `blanks` spaces that erase
+    /// what was there before, and moves the cursor back.
+    fn erase(&mut self, blanks: usize) {
+        self.echo_from(self.cursor);
+        for _ in 0..blanks {
+            print!(" ");
+        }
+        self.advance(blanks);
+        self.move_to(self.cursor);
+    }
+
+    /// Prints the line from the byte index `start`, at which the echo must be.
+    fn echo_from(&mut self, start: usize) {
+        let tail = &self.line()[start..];
+        print!("{}", tail);
+        self.advance(tail.chars().count());
+    }
+
+    /// Records that `cells` cells were printed at the echo position.
+    fn advance(&mut self, cells: usize) {
+        if cells > 0 {
+            self.echo += cells;
+            self.pending_wrap = self.echo.is_multiple_of(self.row_width);
+        }
+    }
+
+    /// Moves the cursor, in the line and on the screen, to the byte index `index`.
+    fn move_to(&mut self, index: usize) {
+        self.cursor = index;
+        let chars = self.line()[..index].chars().count();
+        let target = self.start_column + chars;
+        if target > 0 && target.is_multiple_of(self.row_width) && index == self.len {
+            // The row below may not exist yet, so the last char is written again to end up
+            // past the end of its row
+            let last = self.line()[..index].chars().next_back().unwrap_or(' ');
+            self.move_to(index - last.len_utf8().min(index));
+            self.cursor = index;
+            print!("{}", last);
+            return self.advance(1);
+        }
+        let row = |position: usize, pending_wrap: bool| {
+            position / self.row_width - usize::from(pending_wrap)
+        };
+        let (from, to) = (row(self.echo, self.pending_wrap), row(target, false));
+        match from.cmp(&to) {
+            Ordering::Less => print!("\x1b[{}B", to - from),
+            Ordering::Greater => print!("\x1b[{}A", from - to),
+            Ordering::Equal => {}
+        }
+        print!("\x1b[{}G", target % self.row_width + 1);
+        (self.echo, self.pending_wrap) = (target, false);
+    }
+
+    fn line(&self) -> &str {
+        // Only whole chars are ever added or removed
+        core::str::from_utf8(&self.line[..self.len]).unwrap_or_default()
+    }
+
+    /// Runs the command named by the first word of the line.
+    fn execute(&self) {
+        let line = self.line().trim();
+        if line.is_empty() {
+            return;
+        }
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        match COMMANDS.iter().find(|command| command.name == name) {
+            Some(command) => (command.run)(args.trim_start()),
+            None => println!("unknown command: {}", name),
+        }
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Modifiers;
+
+    #[test]
+    fn cell_rects_include_both_corners_up_to_usize_max() {
+        assert_eq!(cell_rect((2, 5), (0, 1)), Rect::new(1, 0, 5, 3));
+        assert_eq!(cell_rect((3, 3), (3, 3)), Rect::new(3, 3, 1, 1));
+        let rect = cell_rect((0, usize::MAX), (usize::MAX, 0));
+        assert_eq!(rect, Rect::new(0, 0, usize::MAX, usize::MAX));
+        let corner = cell_rect((usize::MAX, usize::MAX), (usize::MAX, usize::MAX));
+        assert_eq!((corner.right(), corner.bottom()), (usize::MAX, usize::MAX));
+    }
+
+    #[test]
+    fn shift_page_up_views_the_scrollback_until_a_key_is_typed() {
+        let _console = crate::writer::test_console();
+        let viewing = || WRITER.lock().as_ref().unwrap().is_viewing_scrollback();
+        for n in 1..=12 {
+            crate::println!("{}", n);
+        }
+        let mut shell = Shell::new();
+        let shift = Modifiers { shift: true, ..Modifiers::NONE };
+        shell.handle_key(KeyEvent { key: Key::PageUp, modifiers: shift });
+        assert!(viewing());
+        // Output of others keeps the view, unlike the echo of a typed key
+        crate::println!("13");
+        assert!(viewing());
+        shell.handle_key(KeyEvent::new(Key::Char('x')));
+        assert!(!viewing());
+        assert_eq!(shell.line(), "x");
+
+        shell.handle_key(KeyEvent { key: Key::Home, modifiers: shift });
+        assert!(viewing());
+        shell.handle_key(KeyEvent { key: Key::End, modifiers: shift });
+        assert!(!viewing());
+    }
+
+    /// Types `keys` into the shell, without modifiers.
+    fn press(shell: &mut Shell, keys: impl IntoIterator<Item = Key>) {
+        for key in keys {
+            shell.handle_key(KeyEvent::new(key));
+        }
+    }
+
+    /// Returns the first two rows of the console and the cell of its cursor.
+    fn screen() -> ([String; 2], (usize, usize)) {
+        let writer = WRITER.lock();
+        let writer = writer.as_ref().unwrap();
+        let mut rows = writer.get_text_region(Rect::new(0, 0, usize::MAX, 2));
+        let mut row = || rows.next().unwrap().to_string();
+        ([row(), row()], writer.cursor_position())
+    }
+
+    #[test]
+    fn the_echo_follows_cursor_moves_insertions_and_deletions() {
+        let _console = crate::writer::test_console();
+        let mut shell = Shell::new();
+        shell.start();
+        press(&mut shell, "helo".chars().map(Key::Char));
+        press(&mut shell, [Key::Left, Key::Char('l')]);
+        assert_eq!(shell.line(), "hello");
+        assert_eq!(screen(), (["> hello".into(), "".into()], (6, 0)));
+        press(&mut shell, [Key::Home, Key::Delete]);
+        assert_eq!(screen(), (["> ello".into(), "".into()], (2, 0)));
+        press(&mut shell, [Key::End, Key::Backspace]);
+        assert_eq!(screen(), (["> ell".into(), "".into()], (5, 0)));
+
+        // The 18 columns of the console wrap the line
+        press(&mut shell, "0123456789abcdefghij".chars().map(Key::Char));
+        assert_eq!(screen(), (["> ell0123456789abc".into(), "defghij".into()], (7, 1)));
+        // Removing a char pulls the rest of the line back across the row boundary
+        press(&mut shell, [Key::Home, Key::Right, Key::Right, Key::Backspace]);
+        assert_eq!(shell.line(), "el0123456789abcdefghij");
+        assert_eq!(screen(), (["> el0123456789abcd".into(), "efghij".into()], (3, 0)));
+        // Inserting pushes it forward again
+        press(&mut shell, [Key::Char('L')]);
+        assert_eq!(screen(), (["> eLl0123456789abc".into(), "defghij".into()], (4, 0)));
+        let ctrl = Modifiers { ctrl: true, ..Modifiers::NONE };
+        shell.handle_key(KeyEvent { key: Key::Char('u'), modifiers: ctrl });
+        assert_eq!(shell.line(), "");
+        assert_eq!(screen(), ([">".into(), "".into()], (2, 0)));
+    }
+}
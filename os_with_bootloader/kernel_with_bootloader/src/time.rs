@@ -0,0 +1,203 @@
+//! Uptime measured with the time stamp counter, which is calibrated against the PIT once.
+//!
+//! There are no timer interrupts yet, so periodic work is driven by [tick], which the main loop
+//! calls whenever it is idle.
+use crate::units::Micros;
+use crate::writer::WRITER;
+use core::arch::x86_64::_rdtsc;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use x86_64::instructions::port::Port;
+
+/// Frequency of the PIT's input clock in Hz.
+const PIT_FREQUENCY: u64 = 1_193_182;
+
+/// Duration of the calibration in milliseconds.
+const CALIBRATION_MS: u64 = 10;
+
+/// Time stamp counter ticks per millisecond, measured by [init].
+static TSC_PER_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Value of the time stamp counter at [init].
+static TSC_AT_BOOT: AtomicU64 = AtomicU64::new(0);
+
+/// Measures the frequency of the time stamp counter by letting PIT channel 2 count down for
+/// [CALIBRATION_MS] milliseconds.
+pub fn init() {
+    let mut gate = Port::<u8>::new(0x61);
+    let mut command = Port::<u8>::new(0x43);
+    let mut channel2 = Port::<u8>::new(0x42);
+    let count = (PIT_FREQUENCY * CALIBRATION_MS / 1000) as u16;
+    // SAFETY: the ports belong to the PIT and the speaker gate, which nothing else uses.
+    let (start, end) = unsafe {
+        // Enable the gate of channel 2, but keep the speaker off
+        let value = gate.read();
+        gate.write((value & !0x02) | 0x01);
+        // Channel 2, low and high byte, mode 0 (interrupt on terminal count)
+        command.write(0xb0);
+        channel2.write(count as u8);
+        channel2.write((count >> 8) as u8);
+        let start = _rdtsc();
+        // Bit 5 reflects the output of channel 2, which goes high at the terminal count
+        while gate.read() & 0x20 == 0 {
+            core::hint::spin_loop();
+        }
+        (start, _rdtsc())
+    };
+    TSC_PER_MS.store(((end - start) / CALIBRATION_MS).max(1), Ordering::Relaxed);
+    TSC_AT_BOOT.store(end, Ordering::Relaxed);
+}
+
+/// Returns the milliseconds since [init], or 0 before it.
+pub fn uptime_ms() -> u64 {
+    let per_ms = cycles_per_ms();
+    cycles().saturating_sub(TSC_AT_BOOT.load(Ordering::Relaxed)).checked_div(per_ms).unwrap_or(0)
+}
+
+/// Returns the current value of the time stamp counter.
+pub fn cycles() -> u64 {
+    #[cfg(test)]
+    if let Some(clock) = fake::CLOCK.get() {
+        return clock.cycles;
+    }
+    // SAFETY: reading the time stamp counter has no side effects.
+    unsafe { _rdtsc() }
+}
+
+/// Converts a number of time stamp counter cycles to microseconds, or 0 before [init].
+pub fn cycles_to_us(cycles: u64) -> u64 {
+    let per_ms = cycles_per_ms();
+    (cycles as u128 * 1000).checked_div(per_ms as u128).unwrap_or(0) as u64
+}
+
+/// Returns the time stamp counter ticks per millisecond, or 0 before [init].
+fn cycles_per_ms() -> u64 {
+    #[cfg(test)]
+    if let Some(clock) = fake::CLOCK.get() {
+        return clock.per_ms;
+    }
+    TSC_PER_MS.load(Ordering::Relaxed)
+}
+
+/// A clock for host tests that only moves when the test says so, instead of the time stamp
+/// counter. It is per thread, so tests running at the same time don't see each other's time.
+#[cfg(test)]
+pub(crate) mod fake {
+    use core::cell::Cell;
+
+    #[derive(Clone, Copy)]
+    pub(crate) struct Clock {
+        pub cycles: u64,
+        pub per_ms: u64,
+    }
+
+    std::thread_local! {
+        pub(crate) static CLOCK: Cell<Option<Clock>> = const { Cell::new(None) };
+    }
+
+    /// Makes the time stamp counter of this thread read `cycles`, ticking `per_ms` times per
+    /// millisecond.
+    pub(crate) fn set(cycles: u64, per_ms: u64) {
+        CLOCK.set(Some(Clock { cycles, per_ms }));
+    }
+
+    /// Moves the time stamp counter of this thread, which has to be [set], ahead by `cycles`.
+    pub(crate) fn advance(cycles: u64) {
+        let clock = CLOCK.get().expect("the fake clock should be set");
+        CLOCK.set(Some(Clock { cycles: clock.cycles + cycles, ..clock }));
+    }
+}
+
+/// Number of [time_it!] blocks that are currently running.
+static TIMING_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+#[doc(hidden)]
+pub fn _time_it_enter() -> u64 {
+    TIMING_DEPTH.fetch_add(1, Ordering::Relaxed);
+    cycles()
+}
+
+#[doc(hidden)]
+pub fn _time_it_exit(label: &str, start: u64) {
+    let time = Micros(cycles_to_us(cycles() - start));
+    let depth = TIMING_DEPTH.fetch_sub(1, Ordering::Relaxed).saturating_sub(1);
+    crate::debug!("\x1b[2m{:indent$}{}: {}\x1b[22m", "", label, time, indent = depth * 2);
+}
+
+/// Runs the block and logs how long it took, e.g. `boot: 1.234 ms`, in a dim style at
+/// [Level::Debug](crate::log::Level::Debug). Evaluates to the value of the block. Nested
+/// measurements are indented under the ones they run in, which are logged after them.
+///
+/// With the `no-timing` feature it compiles to just the block.
+#[cfg(not(feature = "no-timing"))]
+#[macro_export]
+macro_rules! time_it {
+    ($label:expr, $body:block) => {{
+        let start = $crate::time::_time_it_enter();
+        let value = $body;
+        $crate::time::_time_it_exit($label, start);
+        value
+    }};
+}
+
+/// Runs the block. Measuring is disabled by the `no-timing` feature.
+#[cfg(feature = "no-timing")]
+#[macro_export]
+macro_rules! time_it {
+    ($label:expr, $body:block) => {
+        $body
+    };
+}
+
+/// Runs the periodic work, e.g. the writer's screensaver. Skips anything whose lock is held,
+/// so it may be called from anywhere.
+pub fn tick() {
+    let now = uptime_ms();
+    crate::watch::tick(now);
+    crate::log::tick(now);
+    if let Some(writer) = WRITER.try_lock().as_mut().and_then(|writer| writer.as_mut()) {
+        writer.tick(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::ring::Record;
+    use crate::log::LOG;
+
+    /// Returns the messages logged after the first `skip` ones, without their escapes.
+    fn messages_after(skip: u64) -> Vec<String> {
+        let log = LOG.lock();
+        let records = log.iter().filter(|record| record.sequence >= skip);
+        let undim = |record: Record| record.message.replace("\x1b[2m", "").replace("\x1b[22m", "");
+        records.map(undim).collect()
+    }
+
+    #[test]
+    fn cycles_are_converted_with_the_calibrated_rate() {
+        fake::set(5_000, 2_000);
+        assert_eq!(cycles(), 5_000);
+        assert_eq!(cycles_to_us(3_000), 1_500);
+        assert_eq!(cycles_to_us(1), 0);
+        fake::advance(1_000);
+        assert_eq!(cycles(), 6_000);
+    }
+
+    #[test]
+    fn nested_measurements_are_logged_after_the_outer_ones_and_indented() {
+        let _console = crate::writer::test_console();
+        fake::set(1_000_000, 1_000);
+        let skip = LOG.lock().iter().last().map_or(0, |record| record.sequence + 1);
+        let value = crate::time_it!("outer", {
+            fake::advance(250);
+            let inner = crate::time_it!("inner", {
+                fake::advance(1_500);
+                7
+            });
+            fake::advance(2_000_000);
+            inner * 6
+        });
+        assert_eq!(value, 42);
+        assert_eq!(messages_after(skip), ["  inner: 1.500 ms", "outer: 2.002 s"]);
+    }
+}
@@ -0,0 +1,135 @@
+//! Human-readable sizes and durations for logs and tables: `{}` of [Bytes] and [Micros] shows
+//! `4.00 KiB` or `1.234 ms` instead of raw counts.
+//!
+//! Both format without allocating and honor the width, fill and alignment of the formatter,
+//! e.g. `{:>10}`, so that columns of them line up.
+use core::fmt::{self, Write};
+
+/// Binary prefixes of [Bytes], each 1024 times the one before, starting at KiB.
+const PREFIXES: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Enough for the longest text of [Bytes] and [Micros].
+const BUFFER_LEN: usize = 32;
+
+/// Text formatted on the stack, so that it can be padded as a whole.
+struct Buffer {
+    bytes: [u8; BUFFER_LEN],
+    len: usize,
+}
+
+impl Buffer {
+    fn new() -> Self {
+        Self { bytes: [0; BUFFER_LEN], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only whole strs are ever written
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Formats `write` into a [Buffer] and pads the result as the formatter says.
+fn pad(f: &mut fmt::Formatter<'_>, write: impl FnOnce(&mut Buffer) -> fmt::Result) -> fmt::Result {
+    let mut buffer = Buffer::new();
+    write(&mut buffer)?;
+    f.pad(buffer.as_str())
+}
+
+/// A size in bytes. Sizes below 1 KiB are shown exactly, e.g. `512 B`, larger ones with two
+/// decimals and the largest binary prefix that keeps the number at 1 or above, e.g. `1.21 GiB`.
+/// Rounding moves on to the next prefix, so 1 MiB minus one byte is `1.00 MiB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bytes(pub u64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = u128::from(self.0);
+        if bytes < 1024 {
+            return pad(f, |buffer| write!(buffer, "{} B", bytes));
+        }
+        let mut prefix = 0;
+        let hundredths = loop {
+            let unit = 1u128 << (10 * (prefix + 1));
+            let hundredths = (bytes * 100 + unit / 2) / unit;
+            if hundredths < 1024 * 100 || prefix == PREFIXES.len() - 1 {
+                break hundredths;
+            }
+            prefix += 1;
+        };
+        pad(f, |buffer| {
+            write!(buffer, "{}.{:02} {}", hundredths / 100, hundredths % 100, PREFIXES[prefix])
+        })
+    }
+}
+
+/// A duration in microseconds. Durations below 1 ms are shown in us, e.g. `250 us`, longer ones
+/// in ms or s with three decimals, e.g. `1.234 ms` or `2.500 s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Micros(pub u64);
+
+impl fmt::Display for Micros {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let us = self.0;
+        pad(f, |buffer| match us {
+            0..1_000 => write!(buffer, "{} us", us),
+            1_000..1_000_000 => write!(buffer, "{}.{:03} ms", us / 1_000, us % 1_000),
+            _ => {
+                // Round to whole milliseconds, which can't overflow in u128
+                let ms = (u128::from(us) + 500) / 1_000;
+                write!(buffer, "{}.{:03} s", ms / 1_000, ms % 1_000)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_to_two_decimals_of_the_largest_prefix() {
+        let text = |bytes| Bytes(bytes).to_string();
+        assert_eq!(text(0), "0 B");
+        assert_eq!(text(1023), "1023 B");
+        assert_eq!(text(1024), "1.00 KiB");
+        assert_eq!(text(1536), "1.50 KiB");
+        // The last size that rounds to below 1024 KiB, and the first that rounds up to 1 MiB
+        assert_eq!(text(1_048_570), "1023.99 KiB");
+        assert_eq!(text(1_048_571), "1.00 MiB");
+        assert_eq!(text((1 << 20) - 1), "1.00 MiB");
+        assert_eq!(text((1 << 30) - 1), "1.00 GiB");
+        assert_eq!(text(1 << 60), "1.00 EiB");
+        assert_eq!(text(u64::MAX), "16.00 EiB");
+    }
+
+    #[test]
+    fn micros_switch_units_at_a_thousand() {
+        let text = |us| Micros(us).to_string();
+        assert_eq!(text(0), "0 us");
+        assert_eq!(text(999), "999 us");
+        assert_eq!(text(1_000), "1.000 ms");
+        assert_eq!(text(999_999), "999.999 ms");
+        assert_eq!(text(1_000_000), "1.000 s");
+        assert_eq!(text(1_999_499), "1.999 s");
+        assert_eq!(text(1_999_500), "2.000 s");
+        assert_eq!(text(u64::MAX), "18446744073709.552 s");
+    }
+
+    #[test]
+    fn honors_width_fill_and_alignment() {
+        assert_eq!(format!("{:>10}|", Bytes(512)), "     512 B|");
+        assert_eq!(format!("{:<10}|", Bytes(512)), "512 B     |");
+        assert_eq!(format!("{:^11}|", Bytes(1024)), " 1.00 KiB  |");
+        assert_eq!(format!("{:*>10}", Micros(250)), "****250 us");
+        assert_eq!(format!("{:3}", Micros(1_234)), "1.234 ms");
+    }
+}
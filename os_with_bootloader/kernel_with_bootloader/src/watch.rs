@@ -0,0 +1,214 @@
+//! Live display of memory ranges for debugging: the watched bytes are re-read on every
+//! [tick] and drawn in the bottom right corner of the screen, with recently changed bytes
+//! highlighted.
+use crate::writer::color::Color;
+use crate::writer::grid::Cell;
+use crate::writer::style::Style;
+use crate::writer::WRITER;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+/// Maximum number of watched ranges.
+pub const MAX_WATCHES: usize = 4;
+
+/// Maximum number of bytes per watched range.
+pub const MAX_LEN: usize = 16;
+
+/// Milliseconds between samples.
+const INTERVAL_MS: u64 = 200;
+
+/// Number of samples a changed byte stays highlighted for.
+const HIGHLIGHT_SAMPLES: u8 = 3;
+
+/// Width in cells of a displayed line: the label, and three cells per byte.
+const LINE_WIDTH: usize = 12 + 3 * MAX_LEN;
+
+/// Decides whether `len` bytes at an address can be read without faulting.
+pub type Validator = fn(addr: usize, len: usize) -> bool;
+
+/// Rejects only the first page, since there is no page table walk yet.
+fn not_null_page(addr: usize, _len: usize) -> bool {
+    addr >= 0x1000
+}
+
+/// A watched range and its most recent sample.
+#[derive(Clone, Copy)]
+struct Watch {
+    label: &'static str,
+    addr: usize,
+    len: usize,
+    /// The bytes read by the last sample, or `None` if they couldn't be read.
+    values: Option<[u8; MAX_LEN]>,
+    /// Remaining samples each byte stays highlighted for.
+    highlight: [u8; MAX_LEN],
+}
+
+/// Reasons why [watch_add] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchError {
+    /// All [MAX_WATCHES] slots are taken.
+    Full,
+    /// The length is 0 or larger than [MAX_LEN].
+    InvalidLength(usize),
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "at most {} ranges can be watched", MAX_WATCHES),
+            Self::InvalidLength(len) => write!(f, "invalid length {} (1..={})", len, MAX_LEN),
+        }
+    }
+}
+
+struct Watches {
+    slots: [Option<Watch>; MAX_WATCHES],
+    validator: Validator,
+    last_sample: u64,
+    /// Number of lines drawn by the last tick, which need to be restored once removed.
+    drawn_lines: usize,
+}
+
+static WATCHES: Mutex<Watches> = Mutex::new(Watches {
+    slots: [None; MAX_WATCHES],
+    validator: not_null_page,
+    last_sample: 0,
+    drawn_lines: 0,
+});
+
+/// Whether [forget_drawn_lines] was registered as a resize hook of the [WRITER].
+static RESIZE_HOOK_ADDED: AtomicBool = AtomicBool::new(false);
+
+/// Forgets the lines drawn at the positions of the old layout, which the writer cleared, and
+/// draws them at the new ones with the next tick.
+fn forget_drawn_lines(_columns: usize, _rows: usize) {
+    if let Some(mut watches) = WATCHES.try_lock() {
+        watches.drawn_lines = 0;
+        watches.last_sample = 0;
+    }
+}
+
+/// Registers [forget_drawn_lines] with the [WRITER], once.
+fn add_resize_hook() {
+    if RESIZE_HOOK_ADDED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(writer) = WRITER.lock().as_mut() {
+        RESIZE_HOOK_ADDED.store(writer.add_resize_hook(forget_drawn_lines), Ordering::Relaxed);
+    }
+}
+
+/// Replaces the check that decides whether a range can be read. By default only the first page
+/// is rejected.
+pub fn set_validator(validator: Validator) {
+    WATCHES.lock().validator = validator;
+}
+
+/// Starts watching `len` bytes at `addr`.
+pub fn watch_add(label: &'static str, addr: usize, len: usize) -> Result<(), WatchError> {
+    if !(1..=MAX_LEN).contains(&len) {
+        return Err(WatchError::InvalidLength(len));
+    }
+    let mut watches = WATCHES.lock();
+    let slot = watches.slots.iter_mut().find(|slot| slot.is_none()).ok_or(WatchError::Full)?;
+    *slot = Some(Watch { label, addr, len, values: None, highlight: [0; MAX_LEN] });
+    add_resize_hook();
+    Ok(())
+}
+
+/// Stops watching all ranges and restores the text below the display.
+pub fn watch_clear() {
+    let mut watches = WATCHES.lock();
+    watches.slots = [None; MAX_WATCHES];
+    restore(&mut watches);
+}
+
+/// Reads the range with volatile, bounds-checked byte reads, if the validator allows it.
+fn sample(watch: &Watch, validator: Validator) -> Option<[u8; MAX_LEN]> {
+    if !validator(watch.addr, watch.len) {
+        return None;
+    }
+    let mut values = [0; MAX_LEN];
+    for (i, value) in values.iter_mut().enumerate().take(watch.len) {
+        // SAFETY: the validator vouched that the range can be read.
+        *value = unsafe { core::ptr::read_volatile((watch.addr + i) as *const u8) };
+    }
+    Some(values)
+}
+
+/// Formats a line of cells of the display.
+struct LineWriter {
+    cells: [Cell; LINE_WIDTH],
+    len: usize,
+    style: Style,
+}
+
+impl Write for LineWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if let Some(cell) = self.cells.get_mut(self.len) {
+                *cell = Cell::new(c, self.style);
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Restores the cells below the lines drawn by the last tick.
+fn restore(watches: &mut Watches) {
+    let mut writer = WRITER.lock();
+    let Some(writer) = writer.as_mut() else { return };
+    let (columns, rows) = (writer.columns(), writer.rows());
+    for line in 0..core::mem::take(&mut watches.drawn_lines) {
+        let row = rows.saturating_sub(line + 1);
+        writer.restore_cells(row, columns.saturating_sub(LINE_WIDTH)..columns);
+    }
+}
+
+/// Samples the watched ranges if the interval has passed, and draws them.
+pub fn tick(now: u64) {
+    let Some(mut watches) = WATCHES.try_lock() else { return };
+    if now.saturating_sub(watches.last_sample) < INTERVAL_MS {
+        return;
+    }
+    watches.last_sample = now;
+    let validator = watches.validator;
+    for watch in watches.slots.iter_mut().flatten() {
+        let values = sample(watch, validator);
+        if let (Some(old), Some(new)) = (watch.values, values) {
+            for i in 0..watch.len {
+                watch.highlight[i] = match old[i] != new[i] {
+                    true => HIGHLIGHT_SAMPLES,
+                    false => watch.highlight[i].saturating_sub(1),
+                };
+            }
+        }
+        watch.values = values;
+    }
+
+    let Some(mut writer) = WRITER.try_lock() else { return };
+    let Some(writer) = writer.as_mut() else { return };
+    let normal = Style { reverse: true, ..Style::DEFAULT };
+    let changed = Style { foreground: Color::ANSI_PALETTE[11], ..Style::DEFAULT };
+    let (columns, rows) = (writer.columns(), writer.rows());
+    let watched = watches.slots.iter().flatten();
+    for (line, watch) in watched.enumerate() {
+        let cells = [Cell::new(' ', normal); LINE_WIDTH];
+        let mut text = LineWriter { cells, len: 0, style: normal };
+        let _ = write!(text, "{:>10}: ", watch.label);
+        for i in 0..watch.len {
+            text.style = if watch.highlight[i] > 0 { changed } else { normal };
+            let _ = match watch.values {
+                Some(values) => write!(text, "{:02x}", values[i]),
+                None => write!(text, "??"),
+            };
+            text.style = normal;
+            let _ = write!(text, " ");
+        }
+        let row = rows.saturating_sub(line + 1);
+        writer.paint_cells(columns.saturating_sub(LINE_WIDTH), row, &text.cells);
+    }
+    watches.drawn_lines = watches.slots.iter().flatten().count();
+}
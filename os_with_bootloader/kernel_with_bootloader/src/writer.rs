@@ -1,195 +1,5537 @@
+pub mod ansi;
+pub mod color;
+pub mod colored;
 mod constants;
+mod double_buffer;
+pub mod error;
+pub mod font;
+pub mod gauge;
+pub mod glyph;
+mod glyph_cache;
+pub mod grid;
+pub mod handle;
+pub mod overlay;
+pub mod plot;
+pub mod psf;
+pub mod raw;
+pub mod rect;
+pub mod rotation;
+pub mod scrollback;
+pub mod snapshot;
+mod sprite;
+pub mod stats;
+pub mod status;
+pub mod style;
+pub mod surface;
+mod toast;
+mod unicode;
+mod utf8;
 use core::{
+    cell::UnsafeCell,
     fmt::{self, Write},
     ptr,
+    sync::atomic::{AtomicUsize, Ordering},
 };
+use ansi::{Action, CsiSequence, Parser, Response};
 use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+use color::Color;
 use constants::font_constants;
-use constants::font_constants::{BACKUP_CHAR, CHAR_RASTER_HEIGHT, FONT_WEIGHT};
-use noto_sans_mono_bitmap::{get_raster, RasterizedChar};
+use double_buffer::DoubleBuffer;
+use error::{
+    BackBufferTooSmall, FrameBufferError, Inconsistency, SnapshotError, UnsupportedFontSize,
+};
+use glyph::Glyph;
+use glyph_cache::GlyphCache;
+use handle::ConsoleHandle;
+use constants::font_constants::{BACKSPACE, BACKUP_CHAR, CHAR_RASTER_HEIGHT, HEADER_RASTER_HEIGHT};
+use font::{FontGlyph, FontProvider, Metrics};
+use grid::{Cell, CellGrid, TextRow};
+use raw::RawRegion;
+use rect::Rect;
+use rotation::Rotation;
+use overlay::ThroughputOverlay;
+use scrollback::Scrollback;
+use snapshot::{CellLayout, Geometry, RegionSnapshot, SavedCells};
+use sprite::Sprite;
+use stats::Stats;
+use status::{StatusFields, Ticker};
+use style::{CursorShape, Style, StyleStack, TextEffect};
+use surface::PixelSurface;
+use toast::Toast;
+pub use double_buffer::BACK_BUFFER_BYTES;
+pub use toast::MAX_TOAST_LEN;
+use utf8::Utf8Decoder;
+
+/// Additional vertical space between lines, on top of the font's leading.
+const LINE_SPACING: usize = 0;
 
-/// Additional vertical space between lines
-const LINE_SPACING: usize = 2;
+/// Leading between lines in percent of the raster height.
+const LEADING_PERCENT: usize = 12;
 
 /// Additional horizontal space between characters.
 const LETTER_SPACING: usize = 0;
 
+/// Returns the height of a line at text scale 1 for the given raster height: the raster height
+/// plus a leading proportional to it.
+const fn leaded_height(raster_height: usize) -> usize {
+    raster_height + (raster_height * LEADING_PERCENT).div_ceil(100) + LINE_SPACING
+}
+
+/// Returns the width and height of a cell at text scale 1 in a font with the given metrics: the
+/// raster width plus the letter spacing, and the [leaded_height].
+fn cell_size(metrics: Metrics) -> (usize, usize) {
+    (metrics.width + LETTER_SPACING, leaded_height(metrics.height))
+}
+
+/// Size of a cell of the grid in the default font, i.e. at text scale 1. Registered and
+/// synthesized glyphs have this size.
+const CELL_WIDTH: usize = font_constants::CHAR_RASTER_WIDTH + LETTER_SPACING;
+const CELL_HEIGHT: usize = leaded_height(CHAR_RASTER_HEIGHT.val());
+
 /// Padding from the border. Prevent that font is too close to border.
 const BORDER_PADDING: usize = 1;
 
-// ANSI-like color codes
-const COLOR_BLUE: [u8; 3] = [255, 0, 0]; // RGB for blue
-const COLOR_WHITE: [u8; 3] = [255, 255, 255]; // RGB for white (default color)
+/// Largest supported factor for [FrameBufferWriter::set_hidpi_scale].
+pub const MAX_HIDPI_SCALE: usize = 3;
+
+/// Returns the HiDPI scale for a framebuffer: how many times it fits 1920×1080 in both
+/// directions, whichever way it is mounted, between 1 and [MAX_HIDPI_SCALE].
+pub fn hidpi_scale_for(info: &FrameBufferInfo) -> usize {
+    let (long, short) = (info.width.max(info.height), info.width.min(info.height));
+    (long / 1920).min(short / 1080).clamp(1, MAX_HIDPI_SCALE)
+}
+
+/// Largest supported factor for [FrameBufferWriter::set_text_scale].
+const MAX_TEXT_SCALE: usize = 2;
+
+/// Drawn dim in the reserved last column of rows that soft-wrap, see
+/// [FrameBufferWriter::set_wrap_marker]. The fallback is used by fonts without the arrow.
+const WRAP_MARKER: char = '↩';
+const WRAP_MARKER_FALLBACK: char = '\\';
+
+/// Distance of the tab stops in columns of the current text scale. They are fixed, so no reset
+/// has to restore them.
+const TAB_WIDTH: usize = 8;
+
+/// Text grid size emulated by [FrameBufferWriter::set_vga_text_mode].
+const VGA_COLUMNS: usize = 80;
+const VGA_ROWS: usize = 25;
+
+/// Number of pixels that are encoded on the stack before being copied to the framebuffer.
+const SPAN_CHUNK: usize = 64;
+
+/// Maximum length of the title in bytes; longer titles are cut off at a char boundary.
+const TITLE_CAPACITY: usize = 64;
+
+/// Default time without input or output after which the screen is blanked.
+const SCREENSAVER_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+
+/// Pixel rows that a smooth scroll moves per step, see [FrameBufferWriter::set_smooth_scroll].
+const SMOOTH_SCROLL_STEP: usize = 3;
+
+/// Minimum time in milliseconds between two steps of a smooth scroll.
+const SMOOTH_SCROLL_INTERVAL_MS: u64 = 16;
+
+/// Number of rows from the top that a scroll by redrawing compares, see
+/// [FrameBufferWriter::set_scroll_redraw]. Rows further down are always redrawn.
+const REDRAW_COMPARED_ROWS: usize = 256;
+
+/// Time in milliseconds for which blinking text is shown or hidden.
+const BLINK_INTERVAL_MS: u64 = 500;
+
+/// Height in pixels of the [CursorShape::Underline] cursor.
+const UNDERLINE_CURSOR_HEIGHT: usize = 2;
+
+/// Largest number of blinking cells that are redrawn by a tick. With more, blinking text stays
+/// shown until some of it is gone.
+const MAX_BLINKING_CELLS: usize = 256;
+
+/// Offsets at which the glyph is repeated in the effect color for [TextEffect::Outline].
+const OUTLINE_OFFSETS: [(isize, isize); 8] =
+    [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+/// Copies of a glyph's coverage in one color, at offsets in raster pixels, see
+/// [FrameBufferWriter::draw_raster].
+type Layer<'a> = (&'a [(isize, isize)], Color);
+
+/// Replaces the colors of `style` that are the `old` defaults with the `new` ones, given as
+/// foreground and background.
+fn recolor(style: &mut Style, old: (Color, Color), new: (Color, Color)) {
+    if style.foreground == old.0 {
+        style.foreground = new.0;
+    }
+    if style.background == old.1 {
+        style.background = new.1;
+    }
+}
+
+/// Parses the color selected by the parameters following an extended color SGR parameter
+/// (38 or 48): `2;r;g;b` for a 24-bit color, or `5;n` for index `n` of the 256-color palette.
+/// Indices above 255 select no color.
+fn parse_extended_color(params: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    match params.next()? {
+        5 => u8::try_from(params.next()?).ok().map(Color::indexed),
+        2 => {
+            let mut channel = || params.next().map(|value| value.min(255) as u8);
+            Some(Color::new(channel()?, channel()?, channel()?))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the storage of a writer after the first one, which got the static storage: none.
+#[cfg(not(test))]
+fn spare_storage<T: Clone>(_value: T, _len: usize) -> &'static mut [T] {
+    &mut []
+}
+
+/// Returns the storage of a writer after the first one, which got the static storage. Host
+/// tests create a writer per test, so each one gets `len` copies of `value` of its own.
+#[cfg(test)]
+fn spare_storage<T: Clone>(value: T, len: usize) -> &'static mut [T] {
+    Vec::leak(vec![value; len])
+}
+
+/// The writer used by the print macros that don't name a writer explicitly. Set up by [init].
+pub static WRITER: ConsoleHandle = ConsoleHandle::new();
+
+/// Makes a fresh writer of 200×120 pixels the global [WRITER] for a host test that prints
+/// through it. Such tests run one at a time, each while it holds the returned guard.
+#[cfg(test)]
+pub(crate) fn test_console() -> spin::MutexGuard<'static, ()> {
+    static CONSOLE: spin::Mutex<()> = spin::Mutex::new(());
+    let guard = CONSOLE.lock();
+    let (width, height, bytes_per_pixel) = (200, 120, 4);
+    let info = FrameBufferInfo {
+        byte_len: width * height * bytes_per_pixel,
+        width,
+        height,
+        pixel_format: PixelFormat::Rgb,
+        bytes_per_pixel,
+        stride: width,
+    };
+    let writer = FrameBufferWriter::from_surface(surface::MemorySurface::zeroed(info));
+    *WRITER.lock() = Some(writer.unwrap());
+    guard
+}
+
+/// Location and layout of the framebuffer of [WRITER] and how it draws into it, recorded by
+/// [init], [reinit] and the setters of the rotation and the HiDPI scale for [force_writer].
+#[derive(Clone, Copy)]
+struct RawFrameBuffer {
+    start: usize,
+    len: usize,
+    info: FrameBufferInfo,
+    rotation: Rotation,
+    hidpi_scale: usize,
+}
+
+/// Two copies of the [RawFrameBuffer], so that [force_writer] can read one without a lock, which
+/// the code that panicked may hold, even if the panic interrupted an update of the other one.
+/// [RAW_FRAMEBUFFER_SLOT] selects the current one.
+struct RawFrameBufferSlots([UnsafeCell<Option<RawFrameBuffer>>; 2]);
+
+// SAFETY: the slots are only written by [record_raw_framebuffer], which the lock of [WRITER]
+// serializes, and never while they are current.
+unsafe impl Sync for RawFrameBufferSlots {}
+
+static RAW_FRAMEBUFFER: RawFrameBufferSlots =
+    RawFrameBufferSlots([const { UnsafeCell::new(None) }; 2]);
+
+/// Index of the current slot of [RAW_FRAMEBUFFER].
+static RAW_FRAMEBUFFER_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes `raw` the record of [force_writer]. Must only be called while [WRITER] is locked.
+fn record_raw_framebuffer(raw: RawFrameBuffer) {
+    let slot = 1 - RAW_FRAMEBUFFER_SLOT.load(Ordering::Relaxed);
+    // SAFETY: the lock of WRITER keeps out other writes, and reads use the current slot
+    unsafe { *RAW_FRAMEBUFFER.0[slot].get() = Some(raw) };
+    RAW_FRAMEBUFFER_SLOT.store(slot, Ordering::Release);
+}
+
+/// Returns the current record of [force_writer].
+fn raw_framebuffer() -> Option<RawFrameBuffer> {
+    let slot = RAW_FRAMEBUFFER_SLOT.load(Ordering::Acquire);
+    // SAFETY: the current slot is only written again after the other one became current in
+    // between, which can't happen during a read in the panic handler or under the lock of WRITER
+    unsafe { *RAW_FRAMEBUFFER.0[slot].get() }
+}
+
+/// Maximum number of hooks registered with [FrameBufferWriter::add_resize_hook].
+pub const MAX_RESIZE_HOOKS: usize = 4;
+
+/// Called with the new number of columns and rows of the text area, see
+/// [FrameBufferWriter::add_resize_hook].
+pub type ResizeHook = fn(usize, usize);
+
+/// Creates the global [WRITER] for the given surface. On error, [WRITER] stays empty and
+/// printing only reaches the serial port.
+///
+/// The text printed before is replayed into the writer from the early log of [crate::output],
+/// or dropped if there is no writer.
+pub fn init(surface: impl PixelSurface) -> Result<(), FrameBufferError> {
+    init_with(surface, |_| {})
+}
+
+/// Like [init], but lets `configure` set the writer up before the early log is replayed, so
+/// that all text is shown with its settings. `configure` must not print, as [WRITER] is locked.
+pub fn init_with(
+    surface: impl PixelSurface,
+    configure: impl FnOnce(&mut FrameBufferWriter),
+) -> Result<(), FrameBufferError> {
+    let info = surface.info();
+    let framebuffer = surface.into_bytes();
+    let (start, len) = (framebuffer.as_mut_ptr() as usize, framebuffer.len());
+    let writer = FrameBufferWriter::try_new(framebuffer, info);
+    let mut global = WRITER.lock();
+    let early = crate::output::retire_early_log();
+    let mut writer = writer?;
+    configure(&mut writer);
+    early.replay(&mut writer);
+    let (rotation, hidpi_scale) = (writer.rotation, writer.hidpi_scale);
+    record_raw_framebuffer(RawFrameBuffer { start, len, info, rotation, hidpi_scale });
+    writer.global = true;
+    *global = Some(writer);
+    Ok(())
+}
+
+/// Moves the global [WRITER] to another surface, e.g. after a mode change, keeping its text,
+/// see [FrameBufferWriter::reinit]. Creates the writer like [init] if there is none yet.
+pub fn reinit(surface: impl PixelSurface) -> Result<(), FrameBufferError> {
+    let mut writer = WRITER.lock();
+    let Some(writer) = writer.as_mut() else {
+        drop(writer);
+        return init(surface);
+    };
+    let info = surface.info();
+    let framebuffer = surface.into_bytes();
+    let (start, len) = (framebuffer.as_mut_ptr() as usize, framebuffer.len());
+    writer.reinit(framebuffer, info)?;
+    let (rotation, hidpi_scale) = (writer.rotation, writer.hidpi_scale);
+    record_raw_framebuffer(RawFrameBuffer { start, len, info, rotation, hidpi_scale });
+    Ok(())
+}
 
-/// Returns the raster of the given char or the raster of [font_constants::BACKUP_CHAR].
-fn get_char_raster(c: char) -> RasterizedChar {
-    fn get(c: char) -> Option<RasterizedChar> {
-        get_raster(c, FONT_WEIGHT, CHAR_RASTER_HEIGHT)
+/// Creates a fresh writer over the framebuffer of the global [WRITER], ignoring its lock, so
+/// that a panic can still be reported if it happened while the lock was held. It draws with the
+/// rotation and HiDPI scale of [WRITER]. Returns `None` if [init] hasn't been called yet.
+///
+/// The normal print macros never use this.
+///
+/// # Safety
+///
+/// The returned writer aliases the framebuffer of [WRITER]. This is only sound in the panic
+/// handler, once all other execution has stopped and nothing will use [WRITER] again.
+pub unsafe fn force_writer() -> Option<FrameBufferWriter> {
+    let raw = raw_framebuffer()?;
+    // SAFETY: the memory was a valid framebuffer slice at init, and the caller guarantees that
+    // nothing else accesses it anymore.
+    let framebuffer = unsafe { core::slice::from_raw_parts_mut(raw.start as *mut u8, raw.len) };
+    let mut writer = FrameBufferWriter::try_new(framebuffer, raw.info).ok()?;
+    if raw.hidpi_scale != writer.hidpi_scale {
+        writer.set_hidpi_scale(raw.hidpi_scale);
     }
-    get(c).unwrap_or_else(|| get(BACKUP_CHAR).expect("Should get raster of backup char."))
+    if raw.rotation != writer.rotation {
+        writer.set_rotation(raw.rotation);
+    }
+    Some(writer)
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    crate::output::write_fmt(args);
+}
+
+#[doc(hidden)]
+pub fn _print_colored(color: Color, args: fmt::Arguments) {
+    _print_styled(|style| style.foreground = color, args);
+}
+
+/// Prints with the current style of the global [WRITER] modified by `apply`, see
+/// [crate::output::write_fmt_styled].
+#[doc(hidden)]
+pub fn _print_styled(apply: impl FnOnce(&mut Style), args: fmt::Arguments) {
+    crate::output::write_fmt_styled(apply, args);
 }
 
+/// Prints to the given writer, or to the global [WRITER] if the first argument is the format
+/// string.
 #[macro_export]
 macro_rules! print {
+    ($fmt:literal $($arg:tt)*) => {
+        $crate::writer::_print(format_args!($fmt $($arg)*))
+    };
     ($writer:expr, $($arg:tt)*) => {{
         use core::fmt::Write;
         let _ = write!($writer, $($arg)*);
     }};
 }
 
+/// Like [print!], but appends a newline.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::writer::_print(format_args!("\n"))
+    };
+    ($fmt:literal $($arg:tt)*) => {
+        $crate::writer::_print(format_args!(concat!($fmt, "\n") $($arg)*))
+    };
+    ($writer:expr, $($arg:tt)*) => {{
+        use core::fmt::Write;
+        let _ = writeln!($writer, $($arg)*);
+    }};
+}
+
+/// Prints in the given foreground [Color](color::Color) and restores the previous style
+/// afterwards: `print_colored!(Color::BLUE, "{}", x)` for the global [WRITER], or
+/// `print_colored!(writer, Color::BLUE, "{}", x)` for an explicit writer.
+#[macro_export]
+macro_rules! print_colored {
+    ($color:expr, $fmt:literal $($arg:tt)*) => {
+        $crate::writer::_print_colored($color, format_args!($fmt $($arg)*))
+    };
+    ($writer:expr, $color:expr, $fmt:literal $($arg:tt)*) => {
+        $writer.print_colored($color, format_args!($fmt $($arg)*))
+    };
+}
+
+/// Like [print_colored!], but appends a newline. The newline is printed in the given color too.
+#[macro_export]
+macro_rules! println_colored {
+    ($color:expr, $fmt:literal $($arg:tt)*) => {
+        $crate::writer::_print_colored($color, format_args!(concat!($fmt, "\n") $($arg)*))
+    };
+    ($writer:expr, $color:expr, $fmt:literal $($arg:tt)*) => {
+        $writer.print_colored($color, format_args!(concat!($fmt, "\n") $($arg)*))
+    };
+}
+
 /// Allows logging text to a pixel-based framebuffer.
 pub struct FrameBufferWriter {
     framebuffer: &'static mut [u8],
     info: FrameBufferInfo,
     x_pos: usize,
     y_pos: usize,
-    current_color: [u8; 3],
+    style: Style,
+    inverted: bool,
+    text_scale: usize,
+    /// Height of the tallest cell written on the current line so far.
+    current_line_height: usize,
+    /// Area that pixel output is restricted to and that text is laid out in.
+    clip: Option<Rect>,
+    /// Characters currently displayed in the text area.
+    grid: CellGrid,
+    parser: Parser,
+    /// Whether written characters shift the rest of the line to the right instead of
+    /// overwriting it.
+    insert_mode: bool,
+    /// Styles saved by the push escape sequence.
+    style_stack: StyleStack,
+    /// Cell of the cursor and style saved by `ESC[s` for `ESC[u`.
+    saved_cursor: Option<((usize, usize), Style)>,
+    /// Style that resets return to.
+    default_style: Style,
+    /// Whether lines started by explicit newlines are prefixed with their number.
+    line_numbers: bool,
+    /// Number of explicit newlines written so far, continuing across scrolls and clears.
+    line_count: usize,
+    /// Number of lines started so far by newlines and soft wraps.
+    display_lines: usize,
+    /// Number of newlines that the text passed to the current [Write::write_str] call has yet
+    /// to write, including the one being written.
+    pending_newlines: usize,
+    /// Whether escape sequences and control characters are shown literally, see
+    /// [FrameBufferWriter::set_raw_mode].
+    raw_mode: bool,
+    /// How the output is rotated on the physical framebuffer. All other coordinates are
+    /// logical, i.e. relative to the rotated screen.
+    rotation: Rotation,
+    /// Number of framebuffer pixels that a logical pixel covers in each direction, see
+    /// [FrameBufferWriter::set_hidpi_scale]. All other coordinates are in logical pixels.
+    hidpi_scale: usize,
+    /// Whether this is the writer of [WRITER], whose rotation and HiDPI scale [force_writer]
+    /// follows.
+    global: bool,
+    /// Whether scrolls are animated by [FrameBufferWriter::tick].
+    smooth_scroll: bool,
+    /// Whether scrolls redraw the rows that changed from the grid instead of moving pixels.
+    scroll_redraw: bool,
+    /// Number of pixel rows that the screen lags behind the text area during a smooth scroll.
+    /// Text at logical row `y` is shown `scroll_lag` rows lower.
+    scroll_lag: usize,
+    /// Time of the last step of the smooth scroll.
+    last_scroll_step: u64,
+    /// Whether nothing was written since the last explicit newline.
+    at_line_start: bool,
+    /// Horizontal offset in pixels of the text of the current line, which soft-wrapped lines
+    /// are indented by.
+    wrap_indent: usize,
+    /// Whether the last column is reserved for the marker of soft wraps, see
+    /// [FrameBufferWriter::set_wrap_marker].
+    wrap_marker: bool,
+    /// Whether rows continuing a soft-wrapped line start one cell further right.
+    indent_continuations: bool,
+    /// Receives the replies to query sequences like the cursor position report.
+    response_sink: Option<fn(&str)>,
+    /// Called with the new number of columns and rows after [FrameBufferWriter::reinit].
+    resize_hooks: [Option<ResizeHook>; MAX_RESIZE_HOOKS],
+    /// Whether the cursor advances by the width of each glyph's raster instead of a fixed cell
+    /// width.
+    proportional: bool,
+    /// Lines that scrolled off the top of the text area.
+    scrollback: Scrollback,
+    /// Number of lines the view is scrolled back into the scrollback; 0 shows the live text.
+    view_offset: usize,
+    /// Time without activity after which the screen is blanked, or `None` to never blank it.
+    screensaver_timeout: Option<u64>,
+    /// Time of the last [FrameBufferWriter::tick] that saw activity.
+    last_activity: u64,
+    /// Whether there was output or input since the last tick.
+    active: bool,
+    /// Whether the screen is blanked by the screensaver.
+    blanked: bool,
+    stats: Stats,
+    /// The rates shown in the top right corner, if enabled.
+    overlay: Option<ThroughputOverlay>,
+    /// Whether the top line is reserved for the title, see [FrameBufferWriter::set_header_band].
+    header: bool,
+    /// Font that all text is drawn in.
+    font: &'static dyn FontProvider,
+    /// The glyphs of `font` that were looked up before.
+    glyph_cache: GlyphCache,
+    /// Metrics of the size that chars are drawn in: the text's, except while the header band
+    /// is drawn.
+    metrics: Metrics,
+    /// Metrics of the header band's size, which may be smaller than the text's.
+    header_metrics: Metrics,
+    title: [u8; TITLE_CAPACITY],
+    title_len: usize,
+    /// Fields shown at the right end of the header band.
+    status: StatusFields,
+    /// Bytes of a char that [FrameBufferWriter::write_bytes] received only partially.
+    utf8: Utf8Decoder,
+    /// Whether a pixel operation went out of the framebuffer, after which the framebuffer
+    /// isn't touched anymore until [FrameBufferWriter::recover].
+    poisoned: bool,
+    /// The mouse pointer, if shown.
+    pointer: Option<Sprite>,
+    /// Whether the cursor is shown, see [FrameBufferWriter::set_cursor_visible].
+    cursor_visible: bool,
+    /// How the cursor is drawn, see [FrameBufferWriter::set_cursor_shape].
+    cursor_shape: CursorShape,
+    /// Whether the cursor blinks, see [FrameBufferWriter::set_cursor_blink].
+    cursor_blink: bool,
+    /// Whether a blinking cursor is in the shown half of its blink.
+    cursor_blink_on: bool,
+    /// The grid cell that the cursor is drawn over, if it is drawn.
+    drawn_cursor: Option<(usize, usize)>,
+    /// Number of [FrameBufferWriter::batch] calls in progress.
+    batch_depth: usize,
+    /// Whether blinking text is currently shown, see [FrameBufferWriter::tick].
+    blink_visible: bool,
+    /// Time of the last change of `blink_visible`.
+    last_blink: u64,
+    /// The cells shown in reverse video, in columns and rows, see
+    /// [FrameBufferWriter::highlight_region].
+    highlight: Option<Rect>,
+    /// The toast that is shown, see [FrameBufferWriter::toast].
+    toast: Option<Toast>,
+    /// The buffer for the pixels below a toast, while it isn't in the toast's snapshot.
+    toast_buffer: Option<&'static mut [u8]>,
+    /// The screen while `framebuffer` is the back buffer, see
+    /// [FrameBufferWriter::set_double_buffering].
+    double_buffer: Option<DoubleBuffer>,
+    /// The back buffer while it isn't drawn into; empty for all but the first writer.
+    back_buffer: &'static mut [u8],
 }
 
 impl FrameBufferWriter {
     /// Creates a new logger that uses the given framebuffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the framebuffer doesn't match its info, see [FrameBufferWriter::try_new].
     pub fn new(framebuffer: &'static mut [u8], info: FrameBufferInfo) -> Self {
+        match Self::try_new(framebuffer, info) {
+            Ok(logger) => logger,
+            Err(error) => panic!("unusable framebuffer: {}", error),
+        }
+    }
+
+    /// Creates a new logger that draws into `surface`, see [FrameBufferWriter::try_new].
+    pub fn from_surface(surface: impl PixelSurface) -> Result<Self, FrameBufferError> {
+        let info = surface.info();
+        Self::try_new(surface.into_bytes(), info)
+    }
+
+    /// Creates a new logger that uses the given framebuffer, after checking that the buffer
+    /// length, stride and pixel format described by `info` are consistent with each other.
+    pub fn try_new(
+        framebuffer: &'static mut [u8],
+        info: FrameBufferInfo,
+    ) -> Result<Self, FrameBufferError> {
+        FrameBufferError::check(framebuffer.len(), &info)?;
         let mut logger = Self {
             framebuffer,
             info,
             x_pos: BORDER_PADDING,
             y_pos: BORDER_PADDING,
-            current_color: COLOR_WHITE,
+            style: Style::DEFAULT,
+            inverted: false,
+            text_scale: 1,
+            current_line_height: 0,
+            clip: None,
+            grid: CellGrid::new(),
+            parser: Parser::new(),
+            insert_mode: false,
+            style_stack: StyleStack::new(),
+            saved_cursor: None,
+            default_style: Style::DEFAULT,
+            line_numbers: false,
+            line_count: 0,
+            display_lines: 0,
+            pending_newlines: 0,
+            rotation: Rotation::None,
+            hidpi_scale: hidpi_scale_for(&info),
+            global: false,
+            smooth_scroll: false,
+            scroll_redraw: true,
+            scroll_lag: 0,
+            last_scroll_step: 0,
+            raw_mode: false,
+            at_line_start: true,
+            wrap_indent: 0,
+            wrap_marker: false,
+            indent_continuations: false,
+            response_sink: None,
+            resize_hooks: [None; MAX_RESIZE_HOOKS],
+            proportional: false,
+            scrollback: Scrollback::new(),
+            view_offset: 0,
+            screensaver_timeout: Some(SCREENSAVER_TIMEOUT_MS),
+            last_activity: 0,
+            active: true,
+            blanked: false,
+            stats: Stats::default(),
+            overlay: None,
+            header: false,
+            font: &font::NOTO_SANS_MONO,
+            glyph_cache: GlyphCache::new(),
+            metrics: font::NOTO_SANS_MONO.metrics(CHAR_RASTER_HEIGHT.val()),
+            header_metrics: font::NOTO_SANS_MONO.metrics(HEADER_RASTER_HEIGHT.val()),
+            title: [0; TITLE_CAPACITY],
+            title_len: 0,
+            status: StatusFields::new(),
+            utf8: Utf8Decoder::new(),
+            poisoned: false,
+            pointer: None,
+            cursor_visible: true,
+            cursor_shape: CursorShape::Block,
+            cursor_blink: false,
+            cursor_blink_on: true,
+            drawn_cursor: None,
+            batch_depth: 0,
+            blink_visible: true,
+            last_blink: 0,
+            highlight: None,
+            toast: None,
+            toast_buffer: Some(toast::take_buffer()),
+            double_buffer: None,
+            back_buffer: double_buffer::take_buffer(),
         };
+        logger.glyph_cache.load(logger.font, logger.metrics.height);
+        logger.resize_grid();
         logger.clear();
-        logger
+        Ok(logger)
     }
 
     /// Moves the cursor to the next line. Handles vertical overflow by scrolling the screen.
+    ///
+    /// The cursor advances by the height of the tallest cell on the current line, so a line
+    /// containing scaled text is followed by a correspondingly larger gap.
     fn newline(&mut self) {
-        self.y_pos += font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
-        if self.y_pos >= self.height() {
-            self.scroll_screen();
-        }
+        self.display_lines += 1;
+        self.y_pos += self.current_line_height.max(self.line_height());
+        self.current_line_height = 0;
+        self.ensure_line_fits(self.line_height());
         self.carriage_return();
     }
 
     /// Moves the cursor to the beginning of the current line.
     fn carriage_return(&mut self) {
-        self.x_pos = BORDER_PADDING;
+        self.x_pos = self.line_start();
+    }
+
+    /// Returns the x position at which the text of the current line starts.
+    fn line_start(&self) -> usize {
+        self.text_area().x + BORDER_PADDING + self.wrap_indent
+    }
+
+    /// Scrolls the text area up by whole lines if a line of the given height wouldn't fit below
+    /// the cursor.
+    ///
+    /// Room is made for the lines that the text being written will start as well, up to a
+    /// screenful, so that long text scrolls once per screenful instead of once per line.
+    fn ensure_line_fits(&mut self, line_height: usize) {
+        let bottom = self.y_pos + line_height;
+        let area_bottom = self.text_area().bottom();
+        if bottom > area_bottom {
+            let lines = (bottom - area_bottom).div_ceil(self.base_line_height());
+            let batch = self.pending_newlines.min(self.rows().saturating_sub(1));
+            self.scroll_screen(lines.max(batch) * self.base_line_height());
+        }
     }
 
-    /// Scrolls the screen up by one line when vertical overflow occurs.
-    fn scroll_screen(&mut self) {
-        let line_height = font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
-        let bytes_per_line = self.info.stride * line_height * self.info.bytes_per_pixel;
-        let screen_size = self.framebuffer.len();
+    /// Scrolls the text area up by `pixels` rows when vertical overflow occurs.
+    ///
+    /// With smooth scrolling, the pixels are left in place and [FrameBufferWriter::tick] moves
+    /// them later. A scroll that is still running then finishes at once, so output is never
+    /// held up by the animation.
+    fn scroll_screen(&mut self, pixels: usize) {
+        self.settle_scroll();
+        let area = self.text_area();
+        let pixels = pixels.min(self.y_pos - area.y);
 
-        // Move all lines up by one line
-        self.framebuffer.copy_within(bytes_per_line..screen_size, 0);
+        let lines = pixels / self.base_line_height();
+        let mut changed = None;
+        if self.smooth_scroll && !self.output_suppressed() {
+            self.scroll_lag = pixels;
+        } else if self.scroll_redraw
+            && lines * self.base_line_height() == pixels
+            && !self.output_suppressed()
+        {
+            changed = Some(self.rows_changed_by_scroll(lines));
+        } else {
+            // Move the rows of the grid up by the requested amount. Like a scroll by redrawing,
+            // this leaves the border around them and the pixels below the last row alone.
+            let (left, top) = self.cell_origin(0, 0);
+            let height = self.grid.rows() * self.base_line_height();
+            let kept = height.saturating_sub(pixels);
+            self.move_rows(top + pixels, top, kept);
 
-        // Clear the last line
-        let last_line_start = screen_size - bytes_per_line;
-        self.framebuffer[last_line_start..].fill(0);
+            // Clear the freed rows at the bottom
+            let width = self.grid.columns() * self.base_cell_width();
+            let (_, background) = self.style.colors();
+            let (x, y) = (left as isize, (top + kept) as isize);
+            self.fill_rect(x, y, width, height - kept, background);
+        }
+        stats::bump(&mut self.stats.scrolls);
+        for row in 0..lines {
+            if let Some(cells) = self.grid.row(row) {
+                self.scrollback.push(cells);
+            }
+        }
+        self.grid.scroll_up(lines, self.style);
+        self.shift_highlight(-(lines as isize));
+        if let Some(changed) = changed {
+            self.redraw_rows(&changed);
+        }
+        if self.scroll_lag == 0 {
+            self.repaint_overlay();
+        }
+        if self.view_offset > 0 {
+            // Keep showing the same lines, unless they were dropped from the scrollback
+            let offset = self.view_offset + lines;
+            self.view_offset = offset.min(self.scrollback.len());
+            if offset > self.scrollback.len() {
+                self.render_view();
+            }
+        }
 
         // Adjust the y position
-        self.y_pos -= line_height;
+        self.y_pos -= pixels;
     }
 
-    /// Erases all text on the screen. Resets self.x_pos and self.y_pos.
-    pub fn clear(&mut self) {
-        self.x_pos = BORDER_PADDING;
-        self.y_pos = BORDER_PADDING;
-        self.framebuffer.fill(0);
+    /// Returns a bit per grid row, from the top, that is set if scrolling the grid up by
+    /// `lines` changes the row's cells, and erases the cursor before the grid changes. Rows
+    /// past [REDRAW_COMPARED_ROWS] count as changed, as do all rows if any holds enlarged or
+    /// shifted glyphs, which reach into the neighboring rows, and the highlighted rows.
+    fn rows_changed_by_scroll(&mut self, lines: usize) -> [u64; REDRAW_COMPARED_ROWS / 64] {
+        self.dismiss_toast();
+        self.erase_cursor();
+        let blank = Cell::blank(self.style);
+        let tall = |cell: &Cell| cell.scale > 1 || cell.shift > 0;
+        let rows = self.grid.rows();
+        let any_tall = (0..rows).any(|row| self.grid.row(row).unwrap_or_default().iter().any(tall));
+        let highlighted =
+            self.highlight.map_or(0..0, |rect| rect.y.saturating_sub(lines)..rect.bottom());
+        let mut changed = [0; REDRAW_COMPARED_ROWS / 64];
+        for row in 0..rows.min(REDRAW_COMPARED_ROWS) {
+            let old = self.grid.row(row).unwrap_or_default();
+            let new = self.grid.row(row + lines);
+            let same = match new {
+                Some(new) => old == new,
+                None => old.iter().all(|cell| *cell == blank),
+            };
+            if !same || any_tall || highlighted.contains(&row) {
+                changed[row / 64] |= 1 << (row % 64);
+            }
+        }
+        changed
     }
 
-    /// Returns the width of the framebuffer.
-    fn width(&self) -> usize {
-        self.info.width
+    /// Redraws the grid rows whose bits are set, see
+    /// [FrameBufferWriter::rows_changed_by_scroll], and all rows past the ones that the bits
+    /// cover.
+    fn redraw_rows(&mut self, rows: &[u64; REDRAW_COMPARED_ROWS / 64]) {
+        let columns = self.grid.columns();
+        for row in 0..self.grid.rows() {
+            let bit = rows.get(row / 64).map(|word| word >> (row % 64) & 1);
+            if bit != Some(0) {
+                self.redraw_region_cells(Rect::new(0, row, columns, 1));
+            }
+        }
+        stats::bump(&mut self.stats.redrawn_scrolls);
     }
 
-    /// Returns the height of the framebuffer.
-    fn height(&self) -> usize {
-        self.info.height
+    /// Moves `height` pixel rows of the text area from `src` to `dest`; the ranges may overlap.
+    /// While the scrollback is viewed, the screen is left alone.
+    fn move_rows(&mut self, src: usize, dest: usize, height: usize) {
+        if self.output_suppressed() || height == 0 {
+            return;
+        }
+        self.dismiss_toast();
+        self.erase_cursor();
+        self.without_pointer(|writer| writer.move_pixel_rows(src, dest, height));
     }
 
-    /// Writes a single char to the framebuffer. Takes care of special control characters, such as
-    /// newlines and carriage returns.
-    fn write_char(&mut self, c: char) {
-        match c {
-            '\n' => self.newline(),
-            '\r' => self.carriage_return(),
-            '\t' => {
-                let tab_size = 4; // Number of spaces for a tab
-                for _ in 0..tab_size {
-                    self.write_char(' ');
-                }
-            },
-            c => {
-                let new_xpos = self.x_pos + font_constants::CHAR_RASTER_WIDTH;
-                if new_xpos >= self.width() {
-                    self.newline();
-                }
-                let new_ypos = self.y_pos + font_constants::CHAR_RASTER_HEIGHT.val() + BORDER_PADDING;
-                if new_ypos >= self.height() {
-                    self.scroll_screen();
+    fn move_pixel_rows(&mut self, src: usize, dest: usize, height: usize) {
+        let area = self.text_area();
+        let scale = self.hidpi_scale;
+        let bytes = height * area.width * scale * scale * self.info.bytes_per_pixel;
+        self.stats.scrolled_bytes = self.stats.scrolled_bytes.saturating_add(bytes as u64);
+        if self.rotation == Rotation::None && area.x == 0 && area.width == self.width() {
+            let (start, end) = (self.row_offset(src), self.row_offset(src + height));
+            return self.copy_bytes(start..end, self.row_offset(dest));
+        }
+        // Copy the rows in the order that doesn't overwrite rows that still have to be moved
+        match dest < src {
+            true => (0..height).for_each(|row| self.copy_row(area, src + row, dest + row)),
+            false => (0..height).rev().for_each(|row| self.copy_row(area, src + row, dest + row)),
+        }
+    }
+
+    /// Copies the bytes `src` of the framebuffer to `dest`, unless either range is out of it.
+    fn copy_bytes(&mut self, src: core::ops::Range<usize>, dest: usize) {
+        let len = self.framebuffer.len();
+        let dest_end = dest.checked_add(src.len());
+        if self.poisoned || src.end > len || dest_end.is_none_or(|end| end > len) {
+            return self.poison();
+        }
+        self.mark_dirty(dest..dest + src.len());
+        self.framebuffer.copy_within(src, dest);
+    }
+
+    /// Draws all output into a back buffer of [BACK_BUFFER_BYTES] instead of the framebuffer,
+    /// and copies the bytes that changed to the framebuffer in one pass after each batch of
+    /// output and on [FrameBufferWriter::tick], or on [FrameBufferWriter::present]. This way,
+    /// scrolls and redraws never show half done. Fails if the framebuffer is larger than the
+    /// back buffer, which only the first writer has.
+    ///
+    /// Turning it off presents the pending changes and draws into the framebuffer again.
+    pub fn set_double_buffering(&mut self, enabled: bool) -> Result<(), BackBufferTooSmall> {
+        match (enabled, self.double_buffer.take()) {
+            (true, None) => {
+                let (required, len) = (self.framebuffer.len(), self.back_buffer.len());
+                if len < required {
+                    return Err(BackBufferTooSmall { required, len });
                 }
-                self.write_rendered_char(get_char_raster(c));
+                let front = core::mem::take(&mut self.framebuffer);
+                let buffer = core::mem::take(&mut self.back_buffer);
+                let (double_buffer, back) = DoubleBuffer::new(front, buffer);
+                (self.framebuffer, self.double_buffer) = (back, Some(double_buffer));
             }
+            (false, Some(mut double_buffer)) => {
+                double_buffer.present(self.framebuffer);
+                let back = core::mem::take(&mut self.framebuffer);
+                // SAFETY: while double buffering, the framebuffer is the part of the back buffer
+                // that DoubleBuffer::new returned.
+                (self.framebuffer, self.back_buffer) = unsafe { double_buffer.into_parts(back) };
+            }
+            (_, double_buffer) => self.double_buffer = double_buffer,
+        }
+        Ok(())
+    }
+
+    /// Returns whether output is drawn into the back buffer first.
+    pub fn double_buffering(&self) -> bool {
+        self.double_buffer.is_some()
+    }
+
+    /// Copies the output that was drawn into the back buffer since it was last presented to
+    /// the screen, while double buffering.
+    pub fn present(&mut self) {
+        if let Some(double_buffer) = self.double_buffer.as_mut() {
+            let bytes = double_buffer.present(self.framebuffer);
+            self.stats.presented_bytes = self.stats.presented_bytes.saturating_add(bytes as u64);
+        }
+    }
+
+    /// Records that the given bytes of the framebuffer changed, so that they are presented
+    /// while double buffering.
+    fn mark_dirty(&mut self, bytes: core::ops::Range<usize>) {
+        if let Some(double_buffer) = self.double_buffer.as_mut() {
+            double_buffer.mark(bytes);
+        }
+    }
+
+    /// Animates scrolls through [FrameBufferWriter::tick], a few pixel rows at a time, instead
+    /// of moving the text by whole lines at once. Turning it off finishes a running scroll.
+    ///
+    /// The steps are drawn straight into the framebuffer, so they may tear on some displays
+    /// unless [double buffering](FrameBufferWriter::set_double_buffering) is on.
+    pub fn set_smooth_scroll(&mut self, enabled: bool) {
+        self.smooth_scroll = enabled;
+        if !enabled {
+            self.settle_scroll();
+        }
+    }
+
+    /// Makes scrolls redraw the rows of the text area whose text changed from the cell grid,
+    /// which is the default, instead of moving the pixels of its rows. Reading the framebuffer
+    /// back is slow at high resolutions, and rows that stay the same, e.g. blank ones, aren't
+    /// touched.
+    /// Anything drawn over the text area without being recorded in the grid is lost by the
+    /// redraw, though, while moving the pixels keeps it. Smooth scrolls always move pixels.
+    pub fn set_scroll_redraw(&mut self, enabled: bool) {
+        self.scroll_redraw = enabled;
+    }
+
+    /// Returns whether scrolls redraw the rows that changed.
+    pub fn scroll_redraw(&self) -> bool {
+        self.scroll_redraw
+    }
+
+    /// Returns whether scrolls are animated.
+    pub fn smooth_scroll(&self) -> bool {
+        self.smooth_scroll
+    }
+
+    /// Finishes a running smooth scroll at once.
+    fn settle_scroll(&mut self) {
+        let lag = core::mem::take(&mut self.scroll_lag);
+        if lag == 0 {
+            return;
+        }
+        let area = self.text_area();
+        self.move_rows(area.y + lag, area.y, area.height - lag);
+        self.reveal(area.bottom() - lag, area.bottom());
+        self.repaint_overlay();
+    }
+
+    /// Moves a running smooth scroll on by one step.
+    fn step_scroll(&mut self) {
+        if self.output_suppressed() {
+            // The screen is repainted from the grid when it shows the live text again
+            self.scroll_lag = 0;
+            return;
+        }
+        let area = self.text_area();
+        let step = self.scroll_lag.min(SMOOTH_SCROLL_STEP);
+        self.move_rows(area.y + step, area.y, area.height - step);
+        self.scroll_lag -= step;
+        let end = area.bottom() - self.scroll_lag;
+        self.reveal(end - step, end);
+        if self.scroll_lag == 0 {
+            self.repaint_overlay();
         }
     }
 
-    /// Prints a rendered char into the framebuffer.
-    /// Updates self.x_pos.
-    fn write_rendered_char(&mut self, rendered_char: RasterizedChar) {
-        for (y, row) in rendered_char.raster().iter().enumerate() {
-            for (x, byte) in row.iter().enumerate() {
-                if *byte > 0 {
-                    self.write_pixel(self.x_pos + x, self.y_pos + y, *byte);
+    /// Clears the logical pixel rows `start..end` and draws the rows of the grid that overlap
+    /// them.
+    fn reveal(&mut self, start: usize, end: usize) {
+        self.clear_rows(start, end);
+        let top = self.text_area().y + BORDER_PADDING;
+        let line_height = self.base_line_height();
+        let first = start.saturating_sub(top) / line_height;
+        let last = end.saturating_sub(top).div_ceil(line_height).min(self.grid.rows());
+        self.draw_cells(first..last, 0..self.grid.columns(), Self::shown_cell);
+    }
+
+    /// Copies the part of the logical pixel row `src` inside `area` to row `dest`.
+    fn copy_row(&mut self, area: Rect, src: usize, dest: usize) {
+        match self.rotation {
+            // The pixels of a row are contiguous, in reverse order when rotated by 180°
+            Rotation::None | Rotation::Cw180 => {
+                let left = match self.rotation {
+                    Rotation::None => area.x,
+                    _ => area.right() - 1,
+                };
+                let (src, dest) = (self.pixel_offset(left, src), self.pixel_offset(left, dest));
+                self.copy_scaled(src, dest, area.width);
+            }
+            // A row is a physical column, so every pixel is in another physical row
+            Rotation::Cw90 | Rotation::Cw270 => {
+                for x in area.x..area.right() {
+                    let (src, dest) = (self.pixel_offset(x, src), self.pixel_offset(x, dest));
+                    self.copy_scaled(src, dest, 1);
                 }
             }
         }
-        self.x_pos += rendered_char.width() + LETTER_SPACING;
     }
 
-    /// Writes a pixel to the framebuffer at the specified position.
-    fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
-        let pixel_offset = y * self.info.stride + x;
-        let color = [
-            (self.current_color[0] as u16 * intensity as u16 / 255) as u8,
-            (self.current_color[1] as u16 * intensity as u16 / 255) as u8,
-            (self.current_color[2] as u16 * intensity as u16 / 255) as u8,
-        ];
-        let bytes_per_pixel = self.info.bytes_per_pixel;
-        let byte_offset = pixel_offset * bytes_per_pixel;
-        self.framebuffer[byte_offset..(byte_offset + bytes_per_pixel)]
-            .copy_from_slice(&color[..bytes_per_pixel]);
-        let _ = unsafe { ptr::read_volatile(&self.framebuffer[byte_offset]) };
+    /// Copies `width` logical pixels that are contiguous in the framebuffer from the offset
+    /// `src` to `dest`, with all the framebuffer rows that they cover.
+    fn copy_scaled(&mut self, src: usize, dest: usize, width: usize) {
+        let len = width * self.hidpi_scale * self.info.bytes_per_pixel;
+        for row in 0..self.hidpi_scale {
+            let offset = row * self.framebuffer_row_len();
+            self.copy_bytes(src + offset..src + offset + len, dest + offset);
+        }
     }
 
-    /// Prints text with automatic wrapping, scrolling, and ANSI-like escape sequences.
-    pub fn print(&mut self, text: &str) {
-        let mut chars = text.chars().peekable();
-        while let Some(c) = chars.next() {
-            match c {
-                '\\' => {
-                    if let Some(next) = chars.next() {
-                        match next {
-                            'c' => self.current_color = COLOR_BLUE,  // Change to blue
-                            'r' => self.current_color = COLOR_WHITE, // Reset to white
-                            _ => self.write_char(c),                // Unknown sequence
-                        }
-                    }
+    /// Scrolls the lines of the text area up by `lines` (`ESC[nS`), or down with a negative
+    /// count (`ESC[nT`), without moving the cursor. The lines that are exposed are blank, and
+    /// the ones that are pushed out are dropped rather than kept in the scrollback.
+    pub fn scroll_lines(&mut self, lines: isize) {
+        self.settle_scroll();
+        let count = lines.unsigned_abs().min(self.rows());
+        let line_height = self.base_line_height();
+        let top = self.text_area().y + BORDER_PADDING;
+        let (pixels, height) = (count * line_height, self.rows() * line_height);
+        if lines > 0 {
+            self.move_rows(top + pixels, top, height - pixels);
+            self.clear_rows(top + height - pixels, top + height);
+            self.grid.scroll_up(count, self.style);
+            self.shift_highlight(-(count as isize));
+        } else {
+            self.move_rows(top, top + pixels, height - pixels);
+            self.clear_rows(top, top + pixels);
+            self.grid.scroll_down(count, self.style);
+            self.shift_highlight(count as isize);
+        }
+        stats::bump(&mut self.stats.scrolls);
+        self.repaint_overlay();
+    }
+
+    /// Erases all text in the text area. Resets self.x_pos and self.y_pos, and drops a char
+    /// that [FrameBufferWriter::write_bytes] received only partially.
+    pub fn clear(&mut self) {
+        let area = self.text_area();
+        self.scroll_lag = 0;
+        self.utf8 = Utf8Decoder::new();
+        self.x_pos = area.x + BORDER_PADDING;
+        self.y_pos = area.y + BORDER_PADDING;
+        self.current_line_height = 0;
+        self.at_line_start = true;
+        self.wrap_indent = 0;
+        self.drawn_cursor = None;
+        self.clear_rows(area.y, area.bottom());
+        self.grid.clear(self.style);
+        self.highlight = None;
+        stats::bump(&mut self.stats.clears);
+        self.repaint_overlay();
+        self.paint_cursor();
+    }
+
+    /// Fills the pixel rows `start..end` of the text area with the background color.
+    fn clear_rows(&mut self, start: usize, end: usize) {
+        let covered = |toast: &Toast| toast.rect.y < end && start < toast.rect.bottom();
+        if self.toast.as_ref().is_some_and(covered) {
+            self.dismiss_toast();
+        }
+        let (area, (_, background)) = (self.text_area(), self.style.colors());
+        let height = end.saturating_sub(start);
+        self.fill_rect(area.x as isize, start as isize, area.width, height, background);
+    }
+
+    /// Restricts all pixel output to `clip` and lays out subsequent text inside of it, wrapping
+    /// at its right edge and scrolling only its content. The cursor moves to its top left
+    /// corner. `None` restores full-screen behavior.
+    pub fn set_clip_rect(&mut self, clip: Option<Rect>) {
+        self.settle_scroll();
+        self.clip = clip.map(|clip| clip.intersection(&self.screen_rect()));
+        let area = self.text_area();
+        self.x_pos = area.x + BORDER_PADDING;
+        self.y_pos = area.y + BORDER_PADDING;
+        self.current_line_height = 0;
+        self.resize_grid();
+        self.draw_header();
+    }
+
+    /// Reserves the top line of the text area for a band that shows the title, set with an
+    /// `ESC]0;title BEL` sequence, in reverse video. Text never scrolls into it. Changing the
+    /// layout clears the text area.
+    pub fn set_header_band(&mut self, enabled: bool) {
+        self.settle_scroll();
+        self.header = enabled;
+        self.resize_grid();
+        self.clear();
+        self.draw_header();
+    }
+
+    /// Sets the raster height of the text in pixels, which fails for sizes that the font doesn't
+    /// have. Clears the text area, like [FrameBufferWriter::set_font].
+    pub fn set_font_size(&mut self, pixels: usize) -> Result<(), UnsupportedFontSize> {
+        let metrics = self.font.metrics(pixels);
+        if metrics.height != pixels {
+            return Err(UnsupportedFontSize(pixels));
+        }
+        self.settle_scroll();
+        self.metrics = metrics;
+        self.glyph_cache.load(self.font, metrics.height);
+        self.resize_grid();
+        self.clear();
+        self.draw_header();
+        Ok(())
+    }
+
+    /// Sets the raster height of the header band in pixels, which fails for sizes that the font
+    /// doesn't have. The band is as high as a line in that size, so a smaller size than the
+    /// text's leaves more rows for the text. Changing the layout of an enabled band clears the
+    /// text area.
+    pub fn set_header_font(&mut self, pixels: usize) -> Result<(), UnsupportedFontSize> {
+        let metrics = self.font.metrics(pixels);
+        if metrics.height != pixels {
+            return Err(UnsupportedFontSize(pixels));
+        }
+        self.header_metrics = metrics;
+        if self.header {
+            self.set_header_band(true);
+        }
+        Ok(())
+    }
+
+    /// Returns the title set by the last `ESC]0;title BEL` or `ESC]2;title BEL` sequence.
+    pub fn title(&self) -> &str {
+        // Only whole chars are ever stored
+        core::str::from_utf8(&self.title[..self.title_len]).unwrap_or_default()
+    }
+
+    /// Sets the title, cutting it off after [TITLE_CAPACITY] bytes, and updates the header band.
+    pub fn set_title(&mut self, title: &str) {
+        let mut len = title.len().min(TITLE_CAPACITY);
+        while !title.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.title[..len].copy_from_slice(&title.as_bytes()[..len]);
+        self.title_len = len;
+        self.draw_header();
+    }
+
+    /// Sets a field at the right end of the header band, where field 0 is the rightmost, and
+    /// updates the band if the field changed. The cells keep their own styles, so the field
+    /// can highlight parts of it. Fields don't scroll and aren't part of the grid.
+    pub fn set_status_field(&mut self, index: usize, cells: &[Cell]) {
+        if self.status.set(index, cells) {
+            self.draw_header();
+        }
+    }
+
+    /// Sets a field like [FrameBufferWriter::set_status_field], but to text that scrolls
+    /// through a field of `ticker.width` cells if it is longer, stepping every
+    /// `ticker.divider` calls of [FrameBufferWriter::tick]. Only the field is redrawn on a step.
+    /// Setting the field to anything else, e.g. text that fits, stops the scrolling.
+    pub fn set_status_ticker(&mut self, index: usize, cells: &[Cell], ticker: Ticker) {
+        if self.status.set_ticker(index, cells, ticker) {
+            self.draw_header();
+        }
+    }
+
+    /// Returns the area of the header band, if it is enabled.
+    fn header_rect(&self) -> Option<Rect> {
+        let bounds = self.bounds();
+        let height = (BORDER_PADDING + cell_size(self.header_metrics).1).min(bounds.height);
+        self.header.then(|| Rect::new(bounds.x, bounds.y, bounds.width, height))
+    }
+
+    /// Fills the header band, if it is enabled, and draws the status fields at its right end
+    /// and the title centered in the rest. Characters that don't fit are dropped.
+    fn draw_header(&mut self) {
+        let Some(band) = self.header_rect() else { return };
+        let style = Style { reverse: true, bold: true, ..self.default_style };
+        let (x, y) = (band.x as isize, band.y as isize);
+        self.fill_rect(x, y, band.width, band.height, style.colors().1);
+
+        let saved = (self.text_scale, self.metrics);
+        (self.text_scale, self.metrics) = (1, self.header_metrics);
+        let (left, cell_width) = (band.x + BORDER_PADDING, self.base_cell_width());
+        let y = (band.y + BORDER_PADDING) as isize;
+        let right = self.draw_status_fields(band, u8::MAX);
+
+        let (title, title_len) = (self.title, self.title_len);
+        let title = core::str::from_utf8(&title[..title_len]).unwrap_or_default();
+        let columns = (right - left) / cell_width;
+        let len = title.chars().count().min(columns);
+        let x = left + (columns - len) / 2 * cell_width;
+        for (i, c) in title.chars().take(len).enumerate() {
+            self.draw_char_at((x + i * cell_width) as isize, y, c, style);
+        }
+        (self.text_scale, self.metrics) = saved;
+    }
+
+    /// Draws the status fields that scrolled, as returned by [StatusFields::tick], over their
+    /// old cells.
+    fn redraw_status_fields(&mut self, fields: u8) {
+        let Some(band) = self.header_rect() else { return };
+        let saved = (self.text_scale, self.metrics);
+        (self.text_scale, self.metrics) = (1, self.header_metrics);
+        self.draw_status_fields(band, fields);
+        (self.text_scale, self.metrics) = saved;
+    }
+
+    /// Lays out the status fields from the right end of the header band and draws those with a
+    /// bit in `fields`, where field 0 is the lowest. Fields that don't fit are skipped. The
+    /// header metrics must be in place. Returns the left end of the space left for the title.
+    fn draw_status_fields(&mut self, band: Rect, fields: u8) -> usize {
+        let (left, cell_width) = (band.x + BORDER_PADDING, self.base_cell_width());
+        let y = (band.y + BORDER_PADDING) as isize;
+        let mut right = band.right();
+        for index in 0..status::MAX_FIELDS {
+            let mut field = [Cell::BLANK; status::FIELD_WIDTH];
+            let len = self.status.get(index).len();
+            field[..len].copy_from_slice(self.status.get(index));
+            if len == 0 || right < left + len * cell_width {
+                continue;
+            }
+            right -= len * cell_width;
+            if fields & 1 << index != 0 {
+                for (i, cell) in field[..len].iter().enumerate() {
+                    self.draw_char_at((right + i * cell_width) as isize, y, cell.c, cell.style);
                 }
-                _ => self.write_char(c),
             }
+            // Keep a blank cell between the fields and the title
+            right = right.saturating_sub(cell_width).max(left);
         }
+        right
     }
-}
 
-unsafe impl Send for FrameBufferWriter {}
-unsafe impl Sync for FrameBufferWriter {}
+    /// Switches a VGA-style text mode on or off.
+    ///
+    /// While enabled, the text area is an 80×25 grid of cells centered on the screen, surrounded
+    /// by a black letterbox. If the screen is too small for 80×25 cells at the current font size,
+    /// the largest grid that fits is used instead.
+    pub fn set_vga_text_mode(&mut self, enabled: bool) {
+        if !enabled {
+            self.set_clip_rect(None);
+            return;
+        }
+        let (cell_width, line_height) = (self.base_cell_width(), self.base_line_height());
+        let max_columns = self.width().saturating_sub(BORDER_PADDING) / cell_width;
+        let max_rows = self.height().saturating_sub(BORDER_PADDING) / line_height;
+        let width = BORDER_PADDING + VGA_COLUMNS.min(max_columns) * cell_width;
+        let height = BORDER_PADDING + VGA_ROWS.min(max_rows) * line_height;
+        let area = Rect::new((self.width() - width) / 2, (self.height() - height) / 2, width, height);
 
-impl Write for FrameBufferWriter {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            self.write_char(c);
+        self.set_clip_rect(None);
+        let (screen_width, screen_height) = (self.width(), self.height());
+        self.fill_rect(0, 0, screen_width, screen_height, Color::BLACK);
+        self.set_clip_rect(Some(area));
+        self.clear();
+    }
+
+    /// Returns the number of character columns of the text area that text is written to, which
+    /// excludes the column reserved by [FrameBufferWriter::set_wrap_marker].
+    pub fn columns(&self) -> usize {
+        self.grid_columns().saturating_sub(usize::from(self.wrap_marker))
+    }
+
+    /// Returns the number of cells that fit into a row of the text area.
+    fn grid_columns(&self) -> usize {
+        self.text_area().width.saturating_sub(BORDER_PADDING) / self.base_cell_width()
+    }
+
+    /// Returns the width and height of the screen in pixels, after the rotation.
+    pub fn screen_size(&self) -> (usize, usize) {
+        (self.width(), self.height())
+    }
+
+    /// Returns the number of character rows of the text area.
+    pub fn rows(&self) -> usize {
+        self.text_area().height.saturating_sub(BORDER_PADDING) / self.base_line_height()
+    }
+
+    /// Returns the text shown in a rectangle of cells of the text area, one [TextRow] per row
+    /// with trailing blanks trimmed, e.g. to check output without comparing pixels. While the
+    /// view is scrolled back, this is the text of the scrollback that is shown. The rectangle
+    /// is clipped to the text area.
+    pub fn get_text_region(&self, region: Rect) -> impl Iterator<Item = TextRow<'_>> {
+        let columns = region.x..region.right();
+        (region.y..region.bottom()).map_while(move |row| {
+            let cells = self.shown_row(row)?;
+            let start = columns.start.min(cells.len());
+            Some(TextRow::new(&cells[start..columns.end.clamp(start, cells.len())]))
+        })
+    }
+
+    /// Returns the cells shown in a row of the text area, which come from the scrollback while
+    /// the view is scrolled back.
+    fn shown_row(&self, row: usize) -> Option<&[Cell]> {
+        if row >= self.grid.rows() {
+            return None;
         }
-        Ok(())
+        let line = self.scrollback.len() - self.view_offset + row;
+        match self.scrollback.line(line) {
+            Some(cells) => Some(cells),
+            None => self.grid.row(line - self.scrollback.len()),
+        }
+    }
+
+    /// Matches the dimensions of the cell grid to the text area and blanks it.
+    fn resize_grid(&mut self) {
+        let (columns, rows) = (self.grid_columns(), self.rows());
+        self.grid.resize(columns, rows, self.style);
+        self.highlight = None;
+        self.scrollback.resize(columns);
+        self.view_offset = 0;
+    }
+
+    /// Returns the whole screen as a rectangle.
+    fn screen_rect(&self) -> Rect {
+        Rect::new(0, 0, self.width(), self.height())
+    }
+
+    /// Returns the area that all pixel output is restricted to: the clip rectangle or the whole
+    /// screen.
+    fn bounds(&self) -> Rect {
+        self.clip.unwrap_or(self.screen_rect())
+    }
+
+    /// Returns the area that text is laid out in: the [bounds](Self::bounds) without the
+    /// header band.
+    fn text_area(&self) -> Rect {
+        let bounds = self.bounds();
+        match self.header_rect() {
+            Some(band) => {
+                Rect::new(bounds.x, band.bottom(), bounds.width, bounds.height - band.height)
+            }
+            None => bounds,
+        }
+    }
+
+    /// Inverts every visible pixel on the screen (night mode).
+    ///
+    /// While inverted, newly rendered text uses inverted colors as well, so it stays consistent
+    /// with the transformed screen. Calling this again restores normal mode.
+    pub fn invert_screen(&mut self) {
+        self.inverted = !self.inverted;
+        self.invert_rows(0, self.info.height);
+    }
+
+    /// Flips all color channels of the pixels in the framebuffer rows `start..end`, which aren't
+    /// scaled.
+    ///
+    /// Padding bytes at the end of each row and bits of a pixel that don't hold a color channel
+    /// are left untouched. The bulk of each row is processed a machine word at a time.
+    fn invert_rows(&mut self, start: usize, end: usize) {
+        self.without_pointer(|writer| writer.invert_pixel_rows(start, end));
+    }
+
+    fn invert_pixel_rows(&mut self, start: usize, end: usize) {
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let row_len = self.info.width * bytes_per_pixel;
+        // The bits that differ between white and black are exactly the color channels.
+        let (white, black) = (self.encode_color(Color::WHITE), self.encode_color(Color::BLACK));
+        let mut pixel_mask = [0u8; 8];
+        for (index, byte) in pixel_mask.iter_mut().enumerate().take(white.len()) {
+            *byte = white[index] ^ black[index];
+        }
+        let byte_mask = |offset: usize| pixel_mask.get(offset % bytes_per_pixel).copied().unwrap_or(0);
+
+        self.mark_dirty(start * self.framebuffer_row_len()..end * self.framebuffer_row_len());
+        for y in start..end {
+            let row_start = y * self.framebuffer_row_len();
+            let Some(row) = self.framebuffer.get_mut(row_start..row_start + row_len) else {
+                return self.poison();
+            };
+            // SAFETY: every bit pattern is a valid u64.
+            let (head, words, tail) = unsafe { row.align_to_mut::<u64>() };
+            let head_len = head.len();
+            for (offset, byte) in head.iter_mut().enumerate() {
+                *byte ^= byte_mask(offset);
+            }
+
+            // The channel layout repeats every `bytes_per_pixel` bytes, so the word masks repeat
+            // after at most `bytes_per_pixel` words.
+            let mut word_masks = [0u64; 8];
+            let cycle = bytes_per_pixel.clamp(1, word_masks.len());
+            for (phase, mask) in word_masks.iter_mut().enumerate().take(cycle) {
+                let base = head_len + phase * 8;
+                *mask = u64::from_ne_bytes(core::array::from_fn(|i| byte_mask(base + i)));
+            }
+            for (index, word) in words.iter_mut().enumerate() {
+                *word ^= word_masks[index % cycle];
+            }
+
+            let tail_start = head_len + words.len() * 8;
+            for (offset, byte) in tail.iter_mut().enumerate() {
+                *byte ^= byte_mask(tail_start + offset);
+            }
+        }
+    }
+
+    /// Returns the style used for subsequently written text.
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    /// Sets the style used for subsequently written text.
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    /// Sets the foreground color of subsequently written text, like `ESC[38;2;r;g;bm`. Glyphs
+    /// are blended from the background towards any color, see [Color::blend].
+    pub fn set_foreground_color(&mut self, color: Color) {
+        self.style.foreground = color;
     }
-}
\ No newline at end of file
+
+    /// Sets the background color of subsequently written text, like `ESC[48;2;r;g;bm`.
+    pub fn set_background_color(&mut self, color: Color) {
+        self.style.background = color;
+    }
+
+    /// Returns the foreground and background color that resets return to.
+    pub fn default_colors(&self) -> (Color, Color) {
+        (self.default_style.foreground, self.default_style.background)
+    }
+
+    /// Sets the foreground and background color that SGR 0 and the other resets return to, e.g.
+    /// light gray on dark blue as the console's baseline. The current style switches as well
+    /// where it uses the old defaults, so [FrameBufferWriter::clear] fills with the new
+    /// background. Text that is shown already keeps its colors, see
+    /// [FrameBufferWriter::recolor_default_colors].
+    pub fn set_default_colors(&mut self, foreground: Color, background: Color) {
+        let old = self.default_colors();
+        (self.default_style.foreground, self.default_style.background) = (foreground, background);
+        recolor(&mut self.style, old, (foreground, background));
+    }
+
+    /// Like [FrameBufferWriter::set_default_colors], but also switches the text in the grid and
+    /// the scrollback that uses the old defaults and redraws the screen, so that the change
+    /// applies retroactively. OSC 10 and 11 do this.
+    pub fn recolor_default_colors(&mut self, foreground: Color, background: Color) {
+        let old = self.default_colors();
+        self.set_default_colors(foreground, background);
+        let new = (foreground, background);
+        for row in 0..self.grid.rows() {
+            for cell in self.grid.row_mut(row).unwrap_or_default() {
+                recolor(&mut cell.style, old, new);
+            }
+        }
+        for line in 0..self.scrollback.len() {
+            for cell in self.scrollback.line_mut(line).unwrap_or_default() {
+                recolor(&mut cell.style, old, new);
+            }
+        }
+        match self.view_offset {
+            0 => self.redraw(),
+            _ => self.render_view(),
+        }
+    }
+
+    /// Sets the factor by which subsequently written glyphs are enlarged, e.g. 2 for headings.
+    ///
+    /// Each raster pixel becomes a `scale`×`scale` block and the cell advance and line height grow
+    /// accordingly. Values outside of `1..=2` are clamped.
+    pub fn set_text_scale(&mut self, scale: usize) {
+        self.text_scale = scale.clamp(1, MAX_TEXT_SCALE);
+    }
+
+    /// Returns the factor by which written glyphs are enlarged.
+    pub fn text_scale(&self) -> usize {
+        self.text_scale
+    }
+
+    /// Switches between insert mode, where written characters shift the rest of the line to the
+    /// right, and replace mode, where they overwrite it.
+    pub fn set_insert_mode(&mut self, enabled: bool) {
+        self.insert_mode = enabled;
+    }
+
+    /// Enables or disables prefixing every line started by an explicit newline with its number,
+    /// e.g. `0042 | `. Takes effect at the next line start.
+    pub fn set_line_numbers(&mut self, enabled: bool) {
+        self.line_numbers = enabled;
+    }
+
+    /// Writes the number of the current line, zero-padded to at least four digits, and makes
+    /// soft-wrapped continuation lines align under the text that follows it.
+    fn write_line_prefix(&mut self) {
+        let number = self.line_count + 1;
+        let digits = (number.checked_ilog10().unwrap_or(0) as usize + 1).max(4);
+        let saved = self.style;
+        self.style.dim = true;
+        for exponent in (0..digits as u32).rev() {
+            let digit = number / 10usize.pow(exponent) % 10;
+            self.write_char(char::from_digit(digit as u32, 10).unwrap_or('?'));
+        }
+        for c in " | ".chars() {
+            self.write_char(c);
+        }
+        self.style = saved;
+        self.indent_wrapped_lines();
+    }
+
+    /// Returns whether nothing was written since the last explicit newline, e.g. to prefix only
+    /// the lines that start with the next output.
+    pub fn is_at_line_start(&self) -> bool {
+        self.at_line_start
+    }
+
+    /// Returns the number of lines started so far by newlines and soft wraps, e.g. to page
+    /// output by screenfuls.
+    pub fn display_lines(&self) -> usize {
+        self.display_lines
+    }
+
+    /// Makes soft-wrapped continuation lines of the current line start at the cursor's column,
+    /// e.g. under a message that follows a prefix. The next newline ends this.
+    pub fn indent_wrapped_lines(&mut self) {
+        self.wrap_indent = self.x_pos.saturating_sub(self.text_area().x + BORDER_PADDING);
+    }
+
+    /// Reserves the last column of the text area for a dim `↩` that marks rows whose line
+    /// soft-wraps into the next row, so that continuations can be told apart from new lines.
+    /// With `indent`, the continuation rows start one cell further right, after the indent of
+    /// [FrameBufferWriter::indent_wrapped_lines]. While enabled, [FrameBufferWriter::columns]
+    /// excludes the reserved column.
+    pub fn set_wrap_marker(&mut self, enabled: bool, indent: bool) {
+        self.wrap_marker = enabled;
+        self.indent_continuations = enabled && indent;
+    }
+
+    /// Returns the x position that text wraps at: the right edge of the text area, or the start
+    /// of the column reserved for the wrap marker.
+    fn wrap_right(&self) -> usize {
+        match self.wrap_marker {
+            true => self.cell_origin(self.columns(), 0).0,
+            false => self.text_area().right(),
+        }
+    }
+
+    /// Continues the current line on the next row, marking the row it leaves if enabled.
+    fn soft_wrap(&mut self) {
+        if self.wrap_marker {
+            let (_, row) = self.cursor_cell();
+            let size = self.metrics.height;
+            let marker = match self.font.glyph(WRAP_MARKER, size) {
+                Some(_) => WRAP_MARKER,
+                None => WRAP_MARKER_FALLBACK,
+            };
+            let style = Style { dim: true, ..self.style };
+            // The reserved column is a single cell wide at any text scale
+            let scale = core::mem::replace(&mut self.text_scale, 1);
+            self.draw_char_at_cell(self.columns(), row, marker, style);
+            self.text_scale = scale;
+        }
+        self.newline();
+        if self.indent_continuations {
+            self.x_pos += self.base_cell_width();
+        }
+        stats::bump(&mut self.stats.soft_wraps);
+    }
+
+    /// Switches between a fixed advance of one cell per character, which keeps the columns of
+    /// the cell grid exact, and advancing by the width of each glyph's raster. Fixed advance is
+    /// the default.
+    pub fn set_proportional(&mut self, enabled: bool) {
+        self.proportional = enabled;
+    }
+
+    /// Returns the horizontal advance of a single cell at the current text scale.
+    fn cell_width(&self) -> usize {
+        self.base_cell_width() * self.text_scale
+    }
+
+    /// Returns the height of a line at the current text scale.
+    fn line_height(&self) -> usize {
+        self.base_line_height() * self.text_scale
+    }
+
+    /// Returns the width of a cell of the grid, i.e. at text scale 1, in the size that chars are
+    /// currently drawn in.
+    fn base_cell_width(&self) -> usize {
+        cell_size(self.metrics).0
+    }
+
+    /// Returns the height of a row of the grid, i.e. at text scale 1: the raster height plus
+    /// a leading proportional to it. Everything that deals with lines is derived from this.
+    fn base_line_height(&self) -> usize {
+        cell_size(self.metrics).1
+    }
+
+    /// Draws all text in another font, after which the layout follows the font's cells. The
+    /// text and the header band keep their sizes if the font has them. Clears the text area.
+    pub fn set_font(&mut self, font: &'static dyn FontProvider) {
+        self.settle_scroll();
+        self.font = font;
+        self.metrics = font.metrics(self.metrics.height);
+        self.header_metrics = font.metrics(self.header_metrics.height);
+        self.glyph_cache.load(font, self.metrics.height);
+        self.resize_grid();
+        self.clear();
+        self.draw_header();
+    }
+
+    /// Returns the glyph of the given char in the current font and size: the one registered with
+    /// [glyph::register_glyph], the font's, a synthesized glyph for box drawing and block element
+    /// characters that the font lacks, or the font's glyph of [font_constants::BACKUP_CHAR].
+    ///
+    /// Registered and synthesized glyphs cover a cell of the default font, so they are only used
+    /// while cells have that size. Block elements are synthesized even if the font has them,
+    /// as fonts rarely fill exact fractions of their cells. The font's glyphs come from the
+    /// glyph cache.
+    fn glyph(&mut self, c: char) -> Glyph {
+        let cell = (self.base_cell_width(), self.base_line_height());
+        let default_cell = cell == (CELL_WIDTH, CELL_HEIGHT);
+        if let Some(glyph) = glyph::registered(c).filter(|_| default_cell) {
+            return glyph;
+        }
+        let synthesized = || glyph::synthesize(c).filter(|_| default_cell);
+        if let Some(glyph) = glyph::is_block_element(c).then(synthesized).flatten() {
+            return glyph;
+        }
+        let size = self.metrics.height;
+        if let Some(glyph) = self.glyph_cache.glyph(self.font, c, size) {
+            return Glyph::Font(glyph);
+        }
+        let backup = || self.font.glyph(BACKUP_CHAR, size).unwrap_or(FontGlyph::new(&[], 0));
+        synthesized().unwrap_or_else(|| Glyph::Font(backup()))
+    }
+
+    /// Switches to another framebuffer, e.g. after a mode change, after checking it like
+    /// [FrameBufferWriter::try_new]. On error, the writer keeps using the old one.
+    ///
+    /// The text of the scrollback and the grid, up to the cursor or the last non-blank row, is
+    /// printed again in the new layout as far as the grid storage allows, so lines that are too
+    /// long for the new width wrap. The cursor ends up after the text. Text keeps its style,
+    /// but is printed at scale 1, and trailing blanks are dropped. Afterwards, the hooks added
+    /// with [FrameBufferWriter::add_resize_hook] are called, and the writer stays locked while
+    /// they run. Double buffering is turned off.
+    pub fn reinit(
+        &mut self,
+        framebuffer: &'static mut [u8],
+        info: FrameBufferInfo,
+    ) -> Result<(), FrameBufferError> {
+        FrameBufferError::check(framebuffer.len(), &info)?;
+        self.settle_scroll();
+        // Turning it off never fails
+        let _ = self.set_double_buffering(false);
+        self.pointer = None;
+        let (_, cursor_row) = self.cursor_cell();
+        let last_text_row = (0..self.grid.rows()).rev().find(|&row| {
+            let row = self.grid.row(row).unwrap_or_default();
+            row.iter().any(|cell| cell.c != ' ')
+        });
+        let rows = last_text_row.map_or(0, |row| row + 1).max(cursor_row + 1);
+
+        (self.framebuffer, self.info) = (framebuffer, info);
+        (self.clip, self.poisoned, self.blanked) = (None, false, false);
+        let reserved = self.grid_columns() * self.rows();
+        let stash = self.grid.stash(&self.scrollback, rows, reserved);
+        self.resize_grid();
+        let (_, background) = self.style.colors();
+        self.fill_rect(0, 0, self.width(), self.height(), background);
+        self.clear();
+        self.draw_header();
+        self.replay(&stash);
+
+        let (columns, rows) = (self.columns(), self.rows());
+        for hook in self.resize_hooks.into_iter().flatten() {
+            hook(columns, rows);
+        }
+        Ok(())
+    }
+
+    /// Prints the rows saved by [CellGrid::stash] again, one line per row.
+    fn replay(&mut self, stash: &grid::Stash) {
+        let saved = (self.style, self.text_scale, self.line_numbers, self.insert_mode);
+        (self.text_scale, self.line_numbers, self.insert_mode) = (1, false, false);
+        for row in 0..stash.rows() {
+            if row > 0 {
+                self.write_char('\n');
+            }
+            let len = {
+                let cells = self.grid.stashed_row(stash, row);
+                // The reserved column only holds wrap markers
+                let cells = &cells[..cells.len() - usize::from(self.wrap_marker).min(cells.len())];
+                cells.iter().rposition(|cell| cell.c != ' ').map_or(0, |column| column + 1)
+            };
+            for column in 0..len {
+                let cell = self.grid.stashed_row(stash, row)[column];
+                if cell.c != Cell::CONTINUATION {
+                    self.style = cell.style;
+                    self.write_char(cell.c);
+                }
+            }
+        }
+        (self.style, self.text_scale, self.line_numbers, self.insert_mode) = saved;
+    }
+
+    /// Registers a function that is called with the new number of columns and rows after
+    /// [FrameBufferWriter::reinit], e.g. to drop cell positions that a widget remembers. It
+    /// must not lock the [WRITER]. Returns `false` if [MAX_RESIZE_HOOKS] hooks are registered
+    /// already.
+    pub fn add_resize_hook(&mut self, hook: ResizeHook) -> bool {
+        match self.resize_hooks.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(hook);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the grid cell that the cursor is in.
+    fn cursor_cell(&self) -> (usize, usize) {
+        let area = self.text_area();
+        let column = (self.x_pos - area.x - BORDER_PADDING) / self.base_cell_width();
+        let row = (self.y_pos - area.y - BORDER_PADDING) / self.base_line_height();
+        (column, row)
+    }
+
+    /// Returns the cell that the cursor is at, in columns and rows of the current text scale
+    /// like [FrameBufferWriter::print_at]. After the last column of a row is written, the
+    /// column is one past the last until the next char wraps.
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let area = self.text_area();
+        let column = (self.x_pos - area.x - BORDER_PADDING) / self.cell_width();
+        let row = (self.y_pos - area.y - BORDER_PADDING) / self.line_height();
+        (column, row)
+    }
+
+    /// Moves the cursor by the given numbers of cells of the current text scale, stopping at
+    /// the edges of the text area instead of wrapping or scrolling.
+    fn move_cursor(&mut self, columns: isize, rows: isize) {
+        let area = self.text_area();
+        let (left, top) = (area.x + BORDER_PADDING, area.y + BORDER_PADDING);
+        let (cell_width, line_height) = (self.cell_width(), self.line_height());
+        let last_column = ((self.wrap_right() - left) / cell_width).saturating_sub(1);
+        let last_row = (area.bottom().saturating_sub(top) / line_height).saturating_sub(1);
+        let (column, row) = self.cursor_position();
+        let column = column.saturating_add_signed(columns).min(last_column);
+        let row = row.saturating_add_signed(rows).min(last_row);
+        self.x_pos = left + column * cell_width;
+        if rows != 0 {
+            self.y_pos = top + row * line_height;
+            self.current_line_height = 0;
+        }
+        self.at_line_start = false;
+    }
+
+    /// Returns the pixel position of the top left corner of a grid cell.
+    fn cell_origin(&self, column: usize, row: usize) -> (usize, usize) {
+        let area = self.text_area();
+        let x = area.x + BORDER_PADDING + column * self.base_cell_width();
+        let y = area.y + BORDER_PADDING + row * self.base_line_height();
+        (x, y)
+    }
+
+    /// Writes formatted text in the given foreground color and restores the previous style
+    /// afterwards. Used by [print_colored!].
+    pub fn print_colored(&mut self, color: Color, args: fmt::Arguments) {
+        let saved = self.style;
+        self.style.foreground = color;
+        let _ = self.write_fmt(args);
+        self.style = saved;
+    }
+
+    /// Returns the width of the screen in logical pixels, which is the height of the
+    /// framebuffer if the rotation swaps the axes.
+    fn width(&self) -> usize {
+        let (width, height) = self.physical_size();
+        match self.rotation.swaps_axes() {
+            true => height,
+            false => width,
+        }
+    }
+
+    /// Returns the height of the screen, see [FrameBufferWriter::width].
+    fn height(&self) -> usize {
+        let (width, height) = self.physical_size();
+        match self.rotation.swaps_axes() {
+            true => width,
+            false => height,
+        }
+    }
+
+    /// Returns the size of the framebuffer in logical pixels, before the rotation. Framebuffer
+    /// pixels right of and below the last whole logical pixel are left alone.
+    fn physical_size(&self) -> (usize, usize) {
+        (self.info.width / self.hidpi_scale, self.info.height / self.hidpi_scale)
+    }
+
+    /// Returns the number of framebuffer pixels that a logical pixel covers in each direction.
+    pub fn hidpi_scale(&self) -> usize {
+        self.hidpi_scale
+    }
+
+    /// Draws everything at `scale` times the size, between 1 and [MAX_HIDPI_SCALE], for screens
+    /// with a high resolution: every pixel of glyphs, the cursor and the drawing primitives
+    /// covers `scale`×`scale` framebuffer pixels, and all coordinates and sizes, like
+    /// [FrameBufferWriter::columns], are in these larger pixels. Unlike the text scale, this
+    /// applies to all output, so it clears the screen and drops the clip rectangle.
+    ///
+    /// The writer starts with [hidpi_scale_for] the framebuffer.
+    pub fn set_hidpi_scale(&mut self, scale: usize) {
+        self.scroll_lag = 0;
+        self.hide_pointer();
+        self.clip = None;
+        let (_, background) = self.style.colors();
+        // At scale 1, the fill reaches the framebuffer pixels that no larger pixel covers
+        self.hidpi_scale = 1;
+        self.fill_rect(0, 0, self.width(), self.height(), background);
+        let largest = self.info.width.min(self.info.height).max(1);
+        self.hidpi_scale = scale.clamp(1, MAX_HIDPI_SCALE).min(largest);
+        self.resize_grid();
+        self.clear();
+        self.draw_header();
+        self.record_geometry();
+    }
+
+    /// Returns how the output is rotated on the framebuffer.
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    /// Rotates the output clockwise on the framebuffer, for panels that are mounted sideways or
+    /// upside down. This swaps the width and the height for 90° and 270°, so it clears the screen
+    /// and drops the clip rectangle.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.scroll_lag = 0;
+        self.hide_pointer();
+        self.rotation = rotation;
+        self.clip = None;
+        self.resize_grid();
+        let (_, background) = self.style.colors();
+        self.fill_rect(0, 0, self.width(), self.height(), background);
+        self.clear();
+        self.draw_header();
+        self.record_geometry();
+    }
+
+    /// Updates the record of [force_writer] if this is the writer of [WRITER], which is locked
+    /// then, so that the panic screen is drawn with its rotation and HiDPI scale.
+    fn record_geometry(&self) {
+        if let Some(raw) = raw_framebuffer().filter(|_| self.global) {
+            let (rotation, hidpi_scale) = (self.rotation, self.hidpi_scale);
+            record_raw_framebuffer(RawFrameBuffer { rotation, hidpi_scale, ..raw });
+        }
+    }
+
+    /// Writes a single char to the framebuffer. Takes care of special control characters, such as
+    /// newlines and carriage returns, and of characters without width, like combining marks.
+    fn write_char(&mut self, c: char) {
+        if unicode::is_zero_width(c) {
+            return self.overstrike(c);
+        }
+        if self.at_line_start && c != '\r' && c != BACKSPACE {
+            self.at_line_start = false;
+            if self.line_numbers {
+                self.write_line_prefix();
+            }
+        }
+        match c {
+            '\n' => {
+                self.wrap_indent = 0;
+                self.newline();
+                self.pending_newlines = self.pending_newlines.saturating_sub(1);
+                self.line_count += 1;
+                stats::bump(&mut self.stats.lines);
+                self.at_line_start = true;
+            }
+            '\r' => self.carriage_return(),
+            BACKSPACE => {
+                let line_start = self.line_start();
+                self.x_pos = self.x_pos.saturating_sub(self.cell_width()).max(line_start);
+                // Step onto the first half of a wide character instead of its second half
+                let (column, row) = self.cursor_cell();
+                if column > 0 && self.is_wide_char_at(column - 1, row) {
+                    self.x_pos -= self.base_cell_width();
+                }
+            }
+            '\t' => {
+                // Up to the next tab stop, but not past the end of the line
+                let (column, _) = self.cursor_position();
+                let room = self.wrap_right().saturating_sub(self.x_pos) / self.cell_width();
+                for _ in 0..(TAB_WIDTH - column % TAB_WIDTH).min(room) {
+                    self.write_char(' ');
+                }
+            }
+            c => {
+                // A wide character wraps as a whole
+                let new_xpos = self.x_pos + self.cell_width() * unicode::columns(c);
+                if new_xpos > self.wrap_right() {
+                    self.soft_wrap();
+                }
+                self.ensure_line_fits(self.line_height());
+                if self.current_line_height > 0 && self.line_height() > self.current_line_height {
+                    self.align_line_baseline(self.text_scale);
+                }
+                self.current_line_height = self.current_line_height.max(self.line_height());
+                if self.insert_mode {
+                    self.insert_cells(self.text_scale * unicode::columns(c));
+                }
+                self.render_char(c);
+                stats::bump(&mut self.stats.chars_written);
+            }
+        }
+    }
+
+    /// Renders `c` into the cell at the cursor and advances the cursor.
+    ///
+    /// The whole cell is erased to the background color first, so no fragments of a previously
+    /// drawn, wider glyph remain visible.
+    ///
+    /// A wide character takes two cells; the second one is recorded as a continuation in the grid.
+    fn render_char(&mut self, c: char) {
+        let (x, y) = (self.x_pos as isize, self.y_pos as isize);
+        let columns = unicode::columns(c);
+        let (column, row) = self.cursor_cell();
+        let scale = self.text_scale;
+        self.dismiss_toast_over(Rect::new(column, row, scale * columns, scale));
+        let (width, height) = (self.cell_width() * columns, self.line_height());
+        self.fill_rect(x, y, width, height, self.style.colors().1);
+
+        let shift = self.baseline_shift(self.text_scale);
+        self.record_char(column, row, c, self.style, shift);
+        self.y_pos += shift;
+        let glyph = self.glyph(c);
+        self.write_rendered_char(glyph, columns);
+        self.y_pos -= shift;
+        if self.highlight.is_some_and(|highlight| highlight.contains(column, row)) {
+            self.redraw_cells(row, column..column + columns);
+        }
+        self.repaint_overlay_over(column, row, columns);
+    }
+
+    /// Records `c` in the grid at the given cell, in the given style and the current text
+    /// scale, covering as many cells as it is drawn over.
+    fn record_char(&mut self, column: usize, row: usize, c: char, style: Style, shift: usize) {
+        let scale = self.text_scale;
+        let cells_wide = scale * unicode::columns(c);
+        self.dismiss_toast_over(Rect::new(column, row, cells_wide, scale));
+        self.erase_wide_fragments(row, column..column + cells_wide);
+        for i in 0..cells_wide * scale {
+            let cell = match i {
+                0 => Cell { c, style, scale: scale as u8, shift: shift as u8 },
+                _ => Cell { scale: 0, ..Cell::new(Cell::CONTINUATION, style) },
+            };
+            self.grid.set(column + i % cells_wide, row + i / cells_wide, cell);
+        }
+    }
+
+    /// Repaints the throughput overlay if a char of `columns` columns at the given cell drew
+    /// over it.
+    fn repaint_overlay_over(&mut self, column: usize, row: usize, columns: usize) {
+        if row == 0 && column + self.text_scale * columns > self.overlay_columns().start {
+            self.repaint_overlay();
+        }
+    }
+
+    /// Draws `c` in `style` with its cell's top left corner at the pixel position `(x, y)`,
+    /// which needn't be aligned to the cell grid, at the current text scale. The cell's
+    /// background is filled as well. Pixels outside of the screen or the clip rectangle are
+    /// clipped.
+    ///
+    /// Neither the cursor nor the grid are changed, so the char disappears on redraws. See
+    /// [FrameBufferWriter::draw_char_at_cell] for a char that is kept.
+    pub fn draw_char_at(&mut self, x: isize, y: isize, c: char, style: Style) {
+        let columns = unicode::columns(c);
+        let (width, height) = (self.cell_width() * columns, self.line_height());
+        // A cell that misses the bounds is skipped, so that far away ones can't overflow
+        let misses = |start: isize, len: usize, low: usize, high: usize| {
+            start >= high as isize || start.saturating_add(len as isize) <= low as isize
+        };
+        let bounds = self.bounds();
+        let misses_columns = misses(x, width, bounds.x, bounds.right());
+        if misses_columns || misses(y, height, bounds.y, bounds.bottom()) {
+            return;
+        }
+        let saved = self.style;
+        self.style = style;
+        self.fill_rect(x, y, width, height, style.colors().1);
+        let glyph = self.glyph(c);
+        self.draw_glyph(&glyph, columns, x, y);
+        self.style = saved;
+    }
+
+    /// Draws `c` in `style` at the given cell of the text area, like
+    /// [FrameBufferWriter::draw_char_at], and records it in the grid, so that it is redrawn
+    /// like printed text. The cursor doesn't move. Cells outside of the grid are dropped.
+    pub fn draw_char_at_cell(&mut self, column: usize, row: usize, c: char, style: Style) {
+        self.record_char(column, row, c, style, 0);
+        let (x, y) = self.cell_origin(column, row);
+        self.draw_char_at(x as isize, y as isize, c, style);
+        self.repaint_overlay_over(column, row, unicode::columns(c));
+    }
+
+    /// Returns whether the cell holds the first half of a wide character at text scale 1.
+    fn is_wide_char_at(&self, column: usize, row: usize) -> bool {
+        let cell = self.grid.get(column, row);
+        cell.is_some_and(|cell| cell.scale == 1 && unicode::columns(cell.c) == 2)
+    }
+
+    /// Blanks the halves of wide characters that are cut off by overwriting the given cells of a
+    /// row, so that no half glyph remains on the screen or in the grid.
+    fn erase_wide_fragments(&mut self, row: usize, columns: core::ops::Range<usize>) {
+        let is_wide = |column: &usize| self.is_wide_char_at(*column, row);
+        let first_half = columns.start.checked_sub(1).filter(is_wide);
+        let second_half = columns.end.checked_sub(1).filter(is_wide).map(|column| column + 1);
+        for column in [first_half, second_half].into_iter().flatten() {
+            let Some(&cell) = self.grid.get(column, row) else { continue };
+            self.grid.set(column, row, Cell::blank(cell.style));
+            let (x, y) = self.cell_origin(column, row);
+            let (width, height) = (self.base_cell_width(), self.base_line_height());
+            self.fill_rect(x as isize, y as isize, width, height, cell.style.colors().1);
+        }
+    }
+
+    /// Draws a combining mark over the cell before the cursor without advancing it, if the font
+    /// has a glyph for it. Other zero-width characters, like the zero width joiner, and marks
+    /// without a preceding character on the line are dropped. The grid keeps the base character
+    /// only, so a redraw loses the mark.
+    fn overstrike(&mut self, c: char) {
+        if !unicode::is_combining(c) || self.x_pos < self.line_start() + self.cell_width() {
+            return;
+        }
+        let Some(glyph) = self.font.glyph(c, self.metrics.height) else { return };
+        let saved = (self.x_pos, self.y_pos);
+        self.x_pos -= self.cell_width();
+        self.y_pos += self.baseline_shift(self.text_scale);
+        self.write_rendered_char(Glyph::Font(glyph), 1);
+        (self.x_pos, self.y_pos) = saved;
+    }
+
+    /// Returns how far a glyph of the given scale is drawn below the top of the line, so that its
+    /// baseline coincides with the one of the largest text on the line.
+    fn baseline_shift(&self, scale: usize) -> usize {
+        let line_scale = (self.current_line_height / self.base_line_height()).max(scale);
+        (line_scale - scale) * self.metrics.ascent
+    }
+
+    /// Moves the glyphs written on the current line so far down, so that their baseline lines
+    /// up with the one of text of the given, larger scale.
+    fn align_line_baseline(&mut self, scale: usize) {
+        let (cursor_column, row) = self.cursor_cell();
+        let (width, height) = (self.base_cell_width(), self.current_line_height);
+        for column in 0..cursor_column {
+            let Some(&cell) = self.grid.get(column, row) else { break };
+            let (x, y) = self.cell_origin(column, row);
+            self.fill_rect(x as isize, y as isize, width, height, cell.style.colors().1);
+            if cell.c != Cell::CONTINUATION {
+                let shift = scale.saturating_sub(usize::from(cell.scale)) * self.metrics.ascent;
+                self.grid.set(column, row, Cell { shift: shift as u8, ..cell });
+            }
+        }
+        self.redraw_cells(row, 0..cursor_column);
+    }
+
+    /// Erases part of the screen for an ED sequence with the given parameter, without moving
+    /// the cursor.
+    fn erase_in_display(&mut self, mode: u16) {
+        let (_, row) = self.cursor_cell();
+        let (columns, rows) = (self.grid.columns(), self.grid.rows());
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                self.erase_cells(row + self.text_scale..rows, 0..columns);
+            }
+            1 => {
+                self.erase_cells(0..row, 0..columns);
+                self.erase_in_line(1);
+            }
+            2 | 3 => self.erase_cells(0..rows, 0..columns),
+            _ => {}
+        }
+    }
+
+    /// Erases part of the cursor's line for an EL sequence with the given parameter, without
+    /// moving the cursor.
+    fn erase_in_line(&mut self, mode: u16) {
+        let (column, row) = self.cursor_cell();
+        let columns = match mode {
+            0 => column..self.grid.columns(),
+            1 => 0..column + self.text_scale,
+            2 => 0..self.grid.columns(),
+            _ => return,
+        };
+        self.erase_cells(row..row + self.text_scale, columns);
+    }
+
+    /// Blanks the given cells of the grid in the background of the current style and redraws
+    /// them. Cells outside of the grid are skipped.
+    fn erase_cells(&mut self, rows: core::ops::Range<usize>, columns: core::ops::Range<usize>) {
+        let columns = columns.start..columns.end.min(self.grid.columns());
+        if columns.is_empty() {
+            return;
+        }
+        for row in rows.start..rows.end.min(self.grid.rows()) {
+            self.erase_wide_fragments(row, columns.clone());
+            if let Some(cells) = self.grid.row_mut(row) {
+                cells[columns.clone()].fill(Cell::blank(self.style));
+            }
+            self.redraw_cells(row, columns.clone());
+        }
+        self.repaint_overlay();
+    }
+
+    /// Shifts the rest of the line right by `count` cells to make room for a character at the
+    /// cursor, dropping the cells pushed past the right edge. The shifted cells are re-rendered
+    /// from the grid so that their attributes stay intact.
+    fn insert_cells(&mut self, count: usize) {
+        let (column, row) = self.cursor_cell();
+        for row in row..row + self.text_scale {
+            self.grid.insert_cells(column, row, count, self.style);
+            self.redraw_cells(row, column + count..self.grid.columns());
+        }
+        self.repaint_overlay();
+    }
+
+    /// Re-renders the given cells of a grid row from their recorded characters and styles.
+    fn redraw_cells(&mut self, row: usize, columns: core::ops::Range<usize>) {
+        self.dismiss_toast_over(Rect::new(columns.start, row, columns.len(), 1));
+        self.draw_cells(row..row + 1, columns, Self::shown_cell);
+    }
+
+    /// Returns the grid cell at the given position as it is shown, i.e. in reverse video if it
+    /// is highlighted.
+    fn shown_cell(&self, column: usize, row: usize) -> Option<Cell> {
+        let cell = *self.grid.get(column, row)?;
+        let reverse = cell.style.reverse;
+        match self.highlight.is_some_and(|highlight| highlight.contains(column, row)) {
+            true => Some(Cell { style: Style { reverse: !reverse, ..cell.style }, ..cell }),
+            false => Some(cell),
+        }
+    }
+
+    /// Renders the cells returned by `cell_at` for the given columns and rows of the text area,
+    /// without recording them in the grid. Blinking cells are drawn hidden while blinking text
+    /// is.
+    ///
+    /// All backgrounds are drawn before any glyph, so that glyphs reaching into the rows below,
+    /// because they are larger or shifted to the baseline, stay intact.
+    fn draw_cells(
+        &mut self,
+        rows: core::ops::Range<usize>,
+        columns: core::ops::Range<usize>,
+        cell_at: impl Fn(&Self, usize, usize) -> Option<Cell>,
+    ) {
+        let saved = (self.x_pos, self.y_pos, self.style, self.text_scale);
+        let (width, height) = (self.base_cell_width(), self.base_line_height());
+        let style_of = |writer: &Self, cell: Cell| match cell.style.blink && !writer.blink_visible {
+            true => cell.style.hidden(),
+            false => cell.style,
+        };
+        for row in rows.clone() {
+            for column in columns.clone() {
+                let Some(cell) = cell_at(self, column, row) else { break };
+                let (x, y) = self.cell_origin(column, row);
+                let background = style_of(self, cell).colors().1;
+                self.fill_rect(x as isize, y as isize, width, height, background);
+            }
+        }
+        for row in rows {
+            for column in columns.clone() {
+                let Some(cell) = cell_at(self, column, row) else { break };
+                if cell.c == Cell::CONTINUATION {
+                    continue;
+                }
+                let (x, y) = self.cell_origin(column, row);
+                (self.x_pos, self.y_pos) = (x, y + usize::from(cell.shift));
+                self.style = style_of(self, cell);
+                self.text_scale = usize::from(cell.scale).max(1);
+                let glyph = self.glyph(cell.c);
+                self.write_rendered_char(glyph, unicode::columns(cell.c));
+            }
+        }
+        (self.x_pos, self.y_pos, self.style, self.text_scale) = saved;
+    }
+
+    /// Feeds a character through the escape sequence parser and writes or executes the result.
+    ///
+    /// A CR returns to the start of the line right away, so that the cursor and its reported
+    /// position are right even if nothing follows. A LF after it only moves down then: CRLF
+    /// starts a single line, whether or not it is split across writes.
+    fn put_char(&mut self, c: char) {
+        self.wake();
+        if self.raw_mode {
+            return self.put_literal(c);
+        }
+        match self.parser.advance(c) {
+            Action::Print(c) => self.write_char(c),
+            Action::Csi(sequence) => {
+                stats::bump(&mut self.stats.escape_sequences);
+                self.execute_csi(&sequence);
+            }
+            Action::Osc => {
+                stats::bump(&mut self.stats.escape_sequences);
+                self.execute_osc();
+            }
+            Action::Malformed => stats::bump(&mut self.stats.malformed_sequences),
+            Action::None => {}
+        }
+    }
+
+    /// Selects raw mode, in which text is shown literally, for data that may contain escape
+    /// sequences, e.g. file contents: nothing but newlines is interpreted, other control
+    /// characters are shown in caret notation (`^[` for ESC), and [FrameBufferWriter::print]
+    /// doesn't handle its backslash codes. A partially received sequence is dropped either way.
+    pub fn set_raw_mode(&mut self, enabled: bool) {
+        self.raw_mode = enabled;
+        self.parser = Parser::new();
+    }
+
+    /// Returns whether raw mode is selected.
+    pub fn raw_mode(&self) -> bool {
+        self.raw_mode
+    }
+
+    /// Prints text in raw mode, restoring the previous mode afterwards.
+    pub fn print_raw(&mut self, text: &str) {
+        self.batch(|writer| {
+            let saved = writer.raw_mode;
+            writer.set_raw_mode(true);
+            let _ = writer.write_str(text);
+            writer.set_raw_mode(saved);
+        })
+    }
+
+    /// Writes a char without any interpretation besides newlines, see
+    /// [FrameBufferWriter::set_raw_mode].
+    fn put_literal(&mut self, c: char) {
+        match c {
+            '\n' => self.write_char(c),
+            '\0'..='\x1f' | '\x7f' => {
+                self.write_char('^');
+                self.write_char(((c as u8) ^ 0x40) as char);
+            }
+            // C1 control characters have no caret notation
+            '\u{80}'..='\u{9f}' => self.write_char(char::REPLACEMENT_CHARACTER),
+            c => self.write_char(c),
+        }
+    }
+
+    /// Executes a parsed control sequence. Unsupported sequences are ignored.
+    fn execute_csi(&mut self, sequence: &CsiSequence) {
+        match (sequence.private, sequence.intermediate, sequence.final_char) {
+            // Set / reset mode; 4 is the insert/replace mode (IRM)
+            (None, None, 'h') if sequence.params().contains(&4) => self.insert_mode = true,
+            (None, None, 'l') if sequence.params().contains(&4) => self.insert_mode = false,
+            // Show / hide the cursor (DECTCEM)
+            (Some('?'), None, 'h') if sequence.params().contains(&25) => {
+                self.set_cursor_visible(true)
+            }
+            (Some('?'), None, 'l') if sequence.params().contains(&25) => {
+                self.set_cursor_visible(false)
+            }
+            (None, None, 'm') => self.select_graphic_rendition(sequence.params()),
+            // Cursor style (DECSCUSR): 0 to 2 a block, 3 and 4 an underline, blinking for odd
+            // numbers. Bars aren't supported.
+            (None, Some(' '), 'q') => {
+                let param = sequence.params().first().copied().unwrap_or(0);
+                let shape = match param {
+                    0..=2 => CursorShape::Block,
+                    3 | 4 => CursorShape::Underline,
+                    _ => return,
+                };
+                self.set_cursor_blink(param % 2 == 1);
+                self.set_cursor_shape(shape);
+            }
+            // Soft terminal reset (DECSTR)
+            (None, Some('!'), 'p') => self.soft_reset(),
+            // Push / pop the whole style (XTPUSHSGR / XTPOPSGR); popping an empty stack resets
+            // to the default style
+            (None, Some('#'), '{') => self.style_stack.push(self.style),
+            (None, Some('#'), '}') => {
+                self.style = self.style_stack.pop().unwrap_or(self.default_style)
+            }
+            // Save / restore the cursor (SCOSC / SCORC): its cell and the style. Without a saved
+            // cursor, restoring goes to the top left in the default style.
+            (None, None, 's') => self.saved_cursor = Some((self.cursor_position(), self.style)),
+            (None, None, 'u') => {
+                let saved = self.saved_cursor.unwrap_or(((0, 0), self.default_style));
+                let ((column, row), style) = saved;
+                let (current_column, current_row) = self.cursor_position();
+                let columns = column as isize - current_column as isize;
+                self.move_cursor(columns, row as isize - current_row as isize);
+                self.style = style;
+            }
+            // Device status report: 5 asks for the status, 6 for the cursor position (CPR)
+            (None, None, 'n') => match sequence.param(0, 0) {
+                5 => self.respond(format_args!("\x1b[0n")),
+                6 => {
+                    let (column, row) = self.cursor_cell();
+                    self.respond(format_args!("\x1b[{};{}R", row + 1, column + 1));
+                }
+                _ => {}
+            },
+            // Cursor up (CUU) / down (CUD) / forward (CUF) / back (CUB) by the given number of
+            // cells, and to the given column (CHA)
+            (None, None, 'A') => self.move_cursor(0, -(sequence.param(0, 1) as isize)),
+            (None, None, 'B') => self.move_cursor(0, sequence.param(0, 1) as isize),
+            (None, None, 'C') => self.move_cursor(sequence.param(0, 1) as isize, 0),
+            (None, None, 'D') => self.move_cursor(-(sequence.param(0, 1) as isize), 0),
+            (None, None, 'G') => {
+                let (column, _) = self.cursor_position();
+                self.move_cursor(sequence.param(0, 1) as isize - 1 - column as isize, 0);
+            }
+            // Cursor position (CUP, HVP): to the given row and column, counted from 1
+            (None, None, 'H' | 'f') => {
+                let (column, row) = self.cursor_position();
+                let column = sequence.param(1, 1) as isize - 1 - column as isize;
+                self.move_cursor(column, sequence.param(0, 1) as isize - 1 - row as isize);
+            }
+            // Erase in display (ED) / in line (EL): 0 from the cursor to the end, 1 from the
+            // start to the cursor, 2 all. 3, which erases the scrollback as well, erases the
+            // screen only.
+            (None, None, 'J') => self.erase_in_display(sequence.param(0, 0)),
+            (None, None, 'K') => self.erase_in_line(sequence.param(0, 0)),
+            // Scroll up (SU) / down (SD) by the given number of lines
+            (None, None, 'S') => self.scroll_lines(sequence.param(0, 1) as isize),
+            (None, None, 'T') => self.scroll_lines(-(sequence.param(0, 1) as isize)),
+            // Window manipulation: 18 reports the size of the text area in characters
+            (None, None, 't') if sequence.param(0, 0) == 18 => {
+                self.respond(format_args!("\x1b[8;{};{}t", self.rows(), self.columns()));
+            }
+            _ => {}
+        }
+    }
+
+    /// Executes the operating system command that the parser just completed. Only 0 and 2, which
+    /// set the title, and 10 and 11, which set or with `?` query the default foreground and
+    /// background color, are supported.
+    fn execute_osc(&mut self) {
+        let parser = self.parser.clone();
+        let (foreground, background) = self.default_colors();
+        match parser.osc_string().split_once(';') {
+            Some(("0" | "2", title)) => self.set_title(title),
+            Some(("10", "?")) => self.report_color(10, foreground),
+            Some(("11", "?")) => self.report_color(11, background),
+            Some(("10", spec)) => {
+                if let Some(color) = Color::from_spec(spec) {
+                    self.recolor_default_colors(color, background);
+                }
+            }
+            Some(("11", spec)) => {
+                if let Some(color) = Color::from_spec(spec) {
+                    self.recolor_default_colors(foreground, color);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Replies to a query of OSC 10 or 11 with the color in the notation of xterm.
+    fn report_color(&self, command: u8, color: Color) {
+        let channel = |value: u8| u16::from(value) * 0x101;
+        let (r, g, b) = (channel(color.r), channel(color.g), channel(color.b));
+        self.respond(format_args!("\x1b]{};rgb:{:04x}/{:04x}/{:04x}\x1b\\", command, r, g, b));
+    }
+
+    /// Sets the function that receives the replies to query sequences, e.g. the serial port, so
+    /// that a program driving the console over it can query the cursor position. Without a
+    /// sink, queries are ignored.
+    pub fn set_response_sink(&mut self, sink: Option<fn(&str)>) {
+        self.response_sink = sink;
+    }
+
+    fn respond(&self, args: fmt::Arguments) {
+        if let Some(sink) = self.response_sink {
+            sink(Response::new(args).as_str());
+        }
+    }
+
+    /// Applies the parameters of an SGR (`ESC[...m`) sequence to the current style. An empty
+    /// parameter list is equivalent to 0.
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        let mut params = params.iter().copied().peekable();
+        if params.peek().is_none() {
+            self.reset_style();
+        }
+        let defaults = self.default_style;
+        let style = &mut self.style;
+        while let Some(param) = params.next() {
+            match param {
+                0 => *style = defaults,
+                1 => style.bold = true,
+                2 => style.dim = true,
+                4 => style.underline = true,
+                5 | 6 => style.blink = true,
+                7 => style.reverse = true,
+                22 => (style.bold, style.dim) = (false, false),
+                24 => style.underline = false,
+                25 => style.blink = false,
+                27 => style.reverse = false,
+                30..=37 => style.foreground = Color::ANSI_PALETTE[usize::from(param - 30)],
+                38 => {
+                    if let Some(color) = parse_extended_color(&mut params) {
+                        style.foreground = color;
+                    }
+                }
+                39 => style.foreground = defaults.foreground,
+                40..=47 => style.background = Color::ANSI_PALETTE[usize::from(param - 40)],
+                48 => {
+                    if let Some(color) = parse_extended_color(&mut params) {
+                        style.background = color;
+                    }
+                }
+                49 => style.background = defaults.background,
+                90..=97 => style.foreground = Color::ANSI_PALETTE[usize::from(param - 90 + 8)],
+                100..=107 => style.background = Color::ANSI_PALETTE[usize::from(param - 100 + 8)],
+                _ => {}
+            }
+        }
+    }
+
+    /// Restores every attribute of the current style to the writer's defaults.
+    pub fn reset_style(&mut self) {
+        self.style = self.default_style;
+    }
+
+    /// Resets the state that escape sequences and programs change to its defaults, without
+    /// erasing the screen (DECSTR, `ESC[!p`), e.g. after aborting a full-screen program: the
+    /// default style with an empty style stack, replace mode, no raw mode, no highlight, a
+    /// visible cursor, no saved cursor, and no partially received escape sequence or char. The
+    /// tab stops are fixed every [TAB_WIDTH] columns, and settings of the console itself, like
+    /// the default colors, the text scale or the rotation, are kept.
+    pub fn soft_reset(&mut self) {
+        self.reset_style();
+        self.style_stack = StyleStack::new();
+        self.saved_cursor = None;
+        self.insert_mode = false;
+        self.set_raw_mode(false);
+        self.utf8 = Utf8Decoder::new();
+        self.clear_highlight();
+        self.set_cursor_visible(true);
+    }
+
+    /// Like [FrameBufferWriter::soft_reset], and additionally shows the live text and erases
+    /// the screen.
+    pub fn hard_reset(&mut self) {
+        self.soft_reset();
+        self.view_live();
+        self.clear();
+    }
+
+    /// Prints `text` starting at the given cell of the text area without moving the cursor.
+    ///
+    /// The text doesn't wrap or scroll; characters beyond the right edge of the text area are
+    /// dropped. Control characters are ignored.
+    pub fn print_at(&mut self, column: usize, row: usize, text: &str) {
+        self.wake();
+        let scale = self.text_scale;
+        // Positions count in cells of the current text scale
+        let (mut column, row) = (column * scale, row * scale);
+        let right = self.text_area().right();
+        for c in text.chars().filter(|c| !c.is_control()) {
+            let (x, _) = self.cell_origin(column, row);
+            let columns = unicode::columns(c);
+            if x + self.cell_width() * columns > right {
+                break;
+            }
+            // Not part of the cursor's line, so not aligned to its baseline
+            self.draw_char_at_cell(column, row, c, self.style);
+            column += scale * columns;
+        }
+    }
+
+    /// Hands the raw framebuffer and its layout to `f`, e.g. for drawing graphics, without
+    /// giving up the writer.
+    ///
+    /// The cell grid still records the text that was printed, so [FrameBufferWriter::redraw]
+    /// restores it if `f` drew over it.
+    pub fn with_raw<R>(&mut self, f: impl FnOnce(&mut [u8], &FrameBufferInfo) -> R) -> R {
+        self.settle_scroll();
+        self.mark_dirty(0..self.framebuffer.len());
+        self.without_pointer(|writer| f(writer.framebuffer, &writer.info))
+    }
+
+    /// Like [FrameBufferWriter::with_raw], but only gives access to the part of `rect` that is
+    /// inside the clip rectangle. With a [Rotation] or a HiDPI scale, the region covers the
+    /// framebuffer pixels of `rect`, which are neither rotated nor scaled.
+    pub fn with_raw_region<R>(&mut self, rect: Rect, f: impl FnOnce(RawRegion) -> R) -> R {
+        self.settle_scroll();
+        let rect = self.framebuffer_rect(rect.intersection(&self.text_area()));
+        let row_len = self.framebuffer_row_len();
+        self.mark_dirty(rect.y * row_len..rect.bottom() * row_len);
+        self.without_pointer(|writer| f(RawRegion::new(writer.framebuffer, writer.info, rect)))
+    }
+
+    /// Returns the rectangle of framebuffer pixels that the logical rectangle `rect` covers.
+    fn framebuffer_rect(&self, rect: Rect) -> Rect {
+        let (width, height) = self.physical_size();
+        let physical = self.rotation.rect_to_physical(rect, width, height);
+        let scale = self.hidpi_scale;
+        let (x, y) = (physical.x * scale, physical.y * scale);
+        Rect::new(x, y, physical.width * scale, physical.height * scale)
+    }
+
+    /// Returns the number of bytes that [FrameBufferWriter::save_region] needs for the pixels of
+    /// `rect`.
+    pub fn region_bytes(&self, rect: Rect) -> usize {
+        let rect = self.framebuffer_rect(rect.intersection(&self.screen_rect()));
+        rect.width * rect.height * self.info.bytes_per_pixel
+    }
+
+    /// Saves the pixels of `rect` into `buffer`, which needs
+    /// [FrameBufferWriter::region_bytes], so that [FrameBufferWriter::restore_region] can put
+    /// them back, e.g. after drawing a popup over them. The grid cells that lie completely
+    /// inside of `rect` are saved as well, if there are at most [snapshot::MAX_CELLS].
+    ///
+    /// The rectangle is cut to the screen, but not to the clip rectangle. Neither the text
+    /// cursor nor the mouse pointer end up in the snapshot.
+    pub fn save_region<'a>(
+        &mut self,
+        rect: Rect,
+        buffer: &'a mut [u8],
+    ) -> Result<RegionSnapshot<'a>, SnapshotError> {
+        let rect = rect.intersection(&self.screen_rect());
+        let (required, len) = (self.region_bytes(rect), buffer.len());
+        if required > len {
+            return Err(SnapshotError::BufferTooSmall { required, len });
+        }
+        self.settle_scroll();
+        let physical = self.framebuffer_rect(rect);
+        let row_len = physical.width * self.info.bytes_per_pixel;
+        self.batch(|writer| {
+            writer.without_pointer(|writer| {
+                let rows = physical.y..physical.bottom();
+                let saved_rows = buffer[..required].chunks_exact_mut(row_len.max(1));
+                for (y, saved) in rows.zip(saved_rows) {
+                    let start = y * writer.framebuffer_row_len()
+                        + physical.x * writer.info.bytes_per_pixel;
+                    if let Some(row) = writer.framebuffer.get(start..start + row_len) {
+                        saved.copy_from_slice(row);
+                    }
+                }
+            })
+        });
+        let area = self.cells_inside(rect);
+        let cells = (1..=snapshot::MAX_CELLS).contains(&(area.width * area.height)).then(|| {
+            let mut cells = [Cell::BLANK; snapshot::MAX_CELLS];
+            let rows = cells.chunks_exact_mut(area.width).take(area.height);
+            for (row, saved) in rows.enumerate() {
+                if let Some(row) = self.grid.row(area.y + row) {
+                    saved.copy_from_slice(&row[area.x..area.right()]);
+                }
+            }
+            SavedCells { layout: self.cell_layout(), area, cells }
+        });
+        let geometry = Geometry::new(&self.info, self.rotation, self.hidpi_scale);
+        Ok(RegionSnapshot { rect, physical, geometry, pixels: buffer, cells })
+    }
+
+    /// Puts the pixels and grid cells saved by [FrameBufferWriter::save_region] back, ignoring
+    /// the clip rectangle. While the screen is blanked, only the cells are restored.
+    ///
+    /// Fails without touching the screen if the framebuffer, the rotation or, for a snapshot
+    /// with cells, the text layout changed since the snapshot was taken.
+    pub fn restore_region(&mut self, snapshot: &RegionSnapshot) -> Result<(), SnapshotError> {
+        let layout = self.cell_layout();
+        let cells_fit = snapshot.cells.as_ref().is_none_or(|saved| saved.layout == layout);
+        let geometry = Geometry::new(&self.info, self.rotation, self.hidpi_scale);
+        if snapshot.geometry != geometry || !cells_fit {
+            return Err(SnapshotError::GeometryChanged);
+        }
+        self.settle_scroll();
+        let physical = snapshot.physical;
+        let row_len = physical.width * self.info.bytes_per_pixel;
+        self.batch(|writer| {
+            if let Some(saved) = &snapshot.cells {
+                let area = saved.area;
+                let rows = saved.cells.chunks_exact(area.width).take(area.height);
+                for (row, cells) in rows.enumerate() {
+                    if let Some(row) = writer.grid.row_mut(area.y + row) {
+                        row[area.x..area.right()].copy_from_slice(cells);
+                    }
+                }
+            }
+            if writer.blanked || writer.poisoned {
+                return;
+            }
+            writer.without_pointer(|writer| {
+                let rows = physical.y..physical.bottom();
+                for (y, saved) in rows.zip(snapshot.pixels.chunks_exact(row_len.max(1))) {
+                    let start = y * writer.framebuffer_row_len()
+                        + physical.x * writer.info.bytes_per_pixel;
+                    writer.mark_dirty(start..start + row_len);
+                    let Some(row) = writer.framebuffer.get_mut(start..start + row_len) else {
+                        return writer.poison();
+                    };
+                    row.copy_from_slice(saved);
+                }
+            })
+        });
+        Ok(())
+    }
+
+    /// Returns where the grid cells are on the screen, which saved cells must match.
+    fn cell_layout(&self) -> CellLayout {
+        CellLayout {
+            origin: self.cell_origin(0, 0),
+            cell_size: (self.base_cell_width(), self.base_line_height()),
+            grid_size: (self.grid.columns(), self.grid.rows()),
+        }
+    }
+
+    /// Returns the grid cells that lie completely inside of `rect`, as a rectangle of columns
+    /// and rows.
+    fn cells_inside(&self, rect: Rect) -> Rect {
+        let CellLayout { origin: (x, y), cell_size: (width, height), grid_size } =
+            self.cell_layout();
+        let first = |start: usize, origin: usize, size: usize| {
+            start.saturating_sub(origin).div_ceil(size)
+        };
+        let end = |end: usize, origin: usize, size: usize| end.saturating_sub(origin) / size;
+        let columns = first(rect.x, x, width)..end(rect.right(), x, width).min(grid_size.0);
+        let rows = first(rect.y, y, height)..end(rect.bottom(), y, height).min(grid_size.1);
+        Rect::new(columns.start, rows.start, columns.len(), rows.len())
+    }
+
+    /// Repaints the whole text area from the cell grid, discarding anything that was drawn over
+    /// the text, e.g. through [FrameBufferWriter::with_raw].
+    pub fn redraw(&mut self) {
+        let area = self.text_area();
+        self.scroll_lag = 0;
+        self.drawn_cursor = None;
+        self.clear_rows(area.y, area.bottom());
+        self.draw_cells(0..self.grid.rows(), 0..self.grid.columns(), Self::shown_cell);
+        self.repaint_overlay();
+        self.draw_header();
+        self.paint_cursor();
+    }
+
+    /// Returns the counters of the work done since creation or the last
+    /// [FrameBufferWriter::reset_stats].
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Sets all counters back to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+        if let Some(overlay) = &mut self.overlay {
+            overlay.restart(self.stats);
+        }
+    }
+
+    /// Shows or hides an overlay in the top right corner with the characters written and the
+    /// scrolls per second, updated every second by [FrameBufferWriter::tick]. The overlay is
+    /// drawn over the text without changing its layout; hiding it restores the text below.
+    pub fn set_throughput_overlay(&mut self, enabled: bool, now: u64) {
+        match enabled {
+            true if self.overlay.is_none() => {
+                self.overlay = Some(ThroughputOverlay::new(now, self.stats));
+                self.repaint_overlay();
+            }
+            false if self.overlay.take().is_some() => {
+                self.redraw_cells(0, self.overlay_columns());
+            }
+            _ => {}
+        }
+    }
+
+    /// Draws `cells` starting at the given cell of the text area, over the text and without
+    /// recording them in the grid, e.g. for a display that is refreshed periodically. Cells
+    /// beyond the right edge are dropped. [FrameBufferWriter::restore_cells] removes them again.
+    pub fn paint_cells(&mut self, column: usize, row: usize, cells: &[Cell]) {
+        let end = (column + cells.len()).min(self.grid.columns());
+        self.draw_cells(row..row + 1, column..end, |_, cell_column, _| {
+            cells.get(cell_column - column).copied()
+        });
+    }
+
+    /// Redraws the given cells of a row from the grid, removing anything painted over them.
+    pub fn restore_cells(&mut self, row: usize, columns: core::ops::Range<usize>) {
+        self.redraw_cells(row, columns);
+    }
+
+    /// Shows the cells of `rect`, in columns and rows of the text area, in reverse video, e.g.
+    /// to mark a selection or search matches, replacing the previous highlight. The grid keeps
+    /// the styles; text printed into the rectangle is highlighted as well, and the highlight
+    /// moves along with scrolled text. Clearing the text area or changing its size removes it.
+    pub fn highlight_region(&mut self, rect: Rect) {
+        let grid = Rect::new(0, 0, self.grid.columns(), self.grid.rows());
+        let rect = Some(rect.intersection(&grid)).filter(|rect| rect.width > 0 && rect.height > 0);
+        let previous = core::mem::replace(&mut self.highlight, rect);
+        self.batch(|writer| {
+            for rect in [previous, rect].into_iter().flatten() {
+                writer.redraw_region_cells(rect);
+            }
+        });
+    }
+
+    /// Removes the highlight of [FrameBufferWriter::highlight_region], showing the cells in
+    /// their own styles again.
+    pub fn clear_highlight(&mut self) {
+        if let Some(rect) = self.highlight.take() {
+            self.batch(|writer| writer.redraw_region_cells(rect));
+        }
+    }
+
+    /// Returns the highlighted cells, if any.
+    pub fn highlight(&self) -> Option<Rect> {
+        self.highlight
+    }
+
+    /// Redraws the cells of `rect`, in columns and rows, from the grid.
+    fn redraw_region_cells(&mut self, rect: Rect) {
+        if self.output_suppressed() {
+            return;
+        }
+        self.draw_cells(rect.y..rect.bottom(), rect.x..rect.right(), Self::shown_cell);
+        if rect.y == 0 {
+            self.repaint_overlay_over(rect.x, 0, rect.width);
+        }
+    }
+
+    /// Moves the highlight down by `rows`, or up if negative, along with the text, cutting off
+    /// the rows that leave the grid.
+    fn shift_highlight(&mut self, rows: isize) {
+        let Some(rect) = self.highlight else { return };
+        let top = rect.y as isize + rows;
+        let bottom = (rect.bottom() as isize + rows).min(self.grid.rows() as isize);
+        let (top, bottom) = (top.max(0) as usize, bottom.max(0) as usize);
+        self.highlight = Some(Rect::new(rect.x, top, rect.width, bottom.saturating_sub(top)))
+            .filter(|rect| rect.height > 0);
+    }
+
+    /// Returns the columns of the first row that the throughput overlay covers.
+    fn overlay_columns(&self) -> core::ops::Range<usize> {
+        let columns = self.grid.columns();
+        columns.saturating_sub(overlay::WIDTH)..columns
+    }
+
+    /// Draws the throughput overlay, if enabled, over the cells of the grid.
+    fn repaint_overlay(&mut self) {
+        let Some(overlay) = &self.overlay else { return };
+        let mut text = [' '; overlay::WIDTH];
+        for (target, c) in text.iter_mut().rev().zip(overlay.text().chars().rev()) {
+            *target = c;
+        }
+        let style = Style { reverse: true, ..self.default_style };
+        let columns = self.overlay_columns();
+        let start = columns.start;
+        let offset = overlay::WIDTH - columns.len();
+        self.draw_cells(0..1, columns, |_, column, _| {
+            Some(Cell::new(text[column - start + offset], style))
+        });
+    }
+
+    /// Sets the time in milliseconds without input or output after which the screen is
+    /// blanked, or `None` to never blank it.
+    pub fn set_screensaver_timeout(&mut self, timeout: Option<u64>) {
+        self.screensaver_timeout = timeout;
+    }
+
+    /// Advances the screensaver to the current time in milliseconds, blanking the screen if
+    /// there was no activity for the timeout, moves a smooth scroll on and blinks blinking
+    /// text.
+    pub fn tick(&mut self, now: u64) {
+        if self.toast.as_ref().is_some_and(|toast| now >= toast.until) {
+            self.dismiss_toast();
+        }
+        let scrolled = self.status.tick();
+        if scrolled != 0 && !self.blanked {
+            self.redraw_status_fields(scrolled);
+        }
+        if now.saturating_sub(self.last_blink) >= BLINK_INTERVAL_MS {
+            self.last_blink = now;
+            self.toggle_blink();
+        }
+        let since_step = now.saturating_sub(self.last_scroll_step);
+        if self.scroll_lag > 0 && since_step >= SMOOTH_SCROLL_INTERVAL_MS {
+            self.last_scroll_step = now;
+            self.step_scroll();
+        }
+        if core::mem::take(&mut self.active) {
+            self.last_activity = now;
+        }
+        self.blink_cursor(now);
+        let stats = self.stats;
+        if self.overlay.as_mut().is_some_and(|overlay| overlay.update(now, stats)) {
+            self.repaint_overlay();
+        }
+        let idle = now.saturating_sub(self.last_activity);
+        let timed_out = self.screensaver_timeout.is_some_and(|timeout| idle >= timeout);
+        // A poisoned writer doesn't trust its framebuffer anymore, so it isn't blanked either
+        if timed_out && !self.blanked && !self.poisoned {
+            // Black is all zeros in every pixel format. The cell grid keeps the content.
+            self.without_pointer(|writer| {
+                writer.mark_dirty(0..writer.framebuffer.len());
+                writer.framebuffer.fill(0)
+            });
+            self.blanked = true;
+        }
+        self.present();
+    }
+
+    /// Hides blinking text if it is shown and shows it otherwise, by redrawing the blinking
+    /// cells. Over [MAX_BLINKING_CELLS], the text is only shown.
+    fn toggle_blink(&mut self) {
+        if self.output_suppressed() || self.toast.is_some() {
+            return;
+        }
+        let mut count = 0;
+        for row in self.grid.blinking_rows() {
+            let cells = self.grid.row(row).unwrap_or_default();
+            let blinking = cells.iter().filter(|cell| cell.style.blink);
+            let row_count = blinking.filter(|cell| cell.c != Cell::CONTINUATION).count();
+            if row_count == 0 {
+                self.grid.set_blinking(row, false);
+            }
+            count += row_count;
+        }
+        let visible = !self.blink_visible || count > MAX_BLINKING_CELLS;
+        if count == 0 || visible == self.blink_visible {
+            self.blink_visible = true;
+            return;
+        }
+        self.blink_visible = visible;
+        self.batch(|writer| {
+            for row in writer.grid.blinking_rows() {
+                for column in 0..writer.grid.columns() {
+                    let Some(&cell) = writer.grid.get(column, row) else { break };
+                    if cell.style.blink && cell.c != Cell::CONTINUATION {
+                        let columns = unicode::columns(cell.c);
+                        writer.redraw_cells(row, column..column + columns);
+                        writer.repaint_overlay_over(column, row, columns);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Shows `message` in a bordered box centered near the top of the text area for
+    /// `duration_ms` from `now`, replacing the current toast, e.g. for a warning that shouldn't
+    /// end up in the log. Long messages are cut off at [MAX_TOAST_LEN] chars and at the width
+    /// of the screen.
+    ///
+    /// The box is drawn over the text, which is restored exactly once the toast is dismissed:
+    /// by [FrameBufferWriter::tick] at the end of its duration, by
+    /// [FrameBufferWriter::dismiss_toast], e.g. on a key press, or early by output that would
+    /// draw over it, like text printed into its cells or a scroll. Blinking text pauses
+    /// meanwhile.
+    pub fn toast(&mut self, message: &str, duration_ms: u64, now: u64) {
+        self.wake();
+        self.dismiss_toast();
+        let (cell_width, line_height) = (self.cell_width(), self.line_height());
+        let area = self.text_area();
+        let room = (area.width / cell_width).saturating_sub(4).min(MAX_TOAST_LEN);
+        let len = message.chars().filter(|c| !c.is_control()).take(room).count();
+        if len == 0 || area.height < 4 * line_height {
+            return;
+        }
+        let (width, height) = ((len + 4) * cell_width, 3 * line_height);
+        let (x, y) = (area.x + (area.width - width) / 2, area.y + BORDER_PADDING + line_height);
+        let rect = Rect::new(x, y, width, height);
+        let buffer = self.toast_buffer.take().unwrap_or_default();
+        let snapshot = match self.region_bytes(rect) <= buffer.len() {
+            true => self.save_region(rect, buffer).ok(),
+            false => {
+                self.toast_buffer = Some(buffer);
+                None
+            }
+        };
+        let cells = self.cells_inside(rect);
+        // Includes the cells that the box covers only partially
+        let cells = Rect::new(
+            cells.x.saturating_sub(1),
+            cells.y.saturating_sub(1),
+            cells.width + 2,
+            cells.height + 2,
+        );
+        let until = now.saturating_add(duration_ms);
+        // Before the toast is shown, since erasing the cursor redraws its cell
+        self.erase_cursor();
+        self.toast = Some(Toast { rect, cells, until, snapshot });
+        let style = Style { reverse: true, ..self.default_style };
+        let mut text = message.chars().filter(|c| !c.is_control());
+        self.batch(|writer| {
+            for row in 0..3 {
+                for column in 0..len + 4 {
+                    let c = match (row, column) {
+                        (0, 0) => '┌',
+                        (0, c) if c == len + 3 => '┐',
+                        (2, 0) => '└',
+                        (2, c) if c == len + 3 => '┘',
+                        (0 | 2, _) => '─',
+                        (_, 0) => '│',
+                        (_, c) if c == len + 3 => '│',
+                        (_, c) if c == 1 || c == len + 2 => ' ',
+                        _ => text.next().unwrap_or(' '),
+                    };
+                    let (x, y) = (x + column * cell_width, y + row * line_height);
+                    writer.draw_char_at(x as isize, y as isize, c, style);
+                }
+            }
+        });
+    }
+
+    /// Removes the toast, if one is shown, and restores what it covered.
+    pub fn dismiss_toast(&mut self) {
+        let Some(toast) = self.toast.take() else { return };
+        match toast.snapshot {
+            Some(snapshot) => {
+                if self.restore_region(&snapshot).is_err() {
+                    // The layout changed, which redrew the screen anyway
+                    self.redraw();
+                }
+                self.toast_buffer = Some(snapshot.into_buffer());
+            }
+            None => self.batch(|writer| writer.redraw_region_cells(toast.cells)),
+        }
+    }
+
+    /// Returns whether a toast is shown.
+    pub fn has_toast(&self) -> bool {
+        self.toast.is_some()
+    }
+
+    /// Dismisses the toast if it covers any of the given cells, in columns and rows, before
+    /// they are drawn.
+    fn dismiss_toast_over(&mut self, cells: Rect) {
+        if self.toast.as_ref().is_some_and(|toast| !toast.cells.intersection(&cells).is_empty()) {
+            self.dismiss_toast();
+        }
+    }
+
+    /// Records activity and restores the screen if it is blanked. Output calls this before it
+    /// draws anything, so nothing is drawn invisibly.
+    pub fn wake(&mut self) {
+        self.active = true;
+        self.cursor_blink_on = true;
+        if core::mem::take(&mut self.blanked) {
+            match self.view_offset {
+                0 => self.redraw(),
+                _ => self.render_view(),
+            }
+        }
+    }
+
+    /// Checks the invariants that the writer's state keeps whatever it is fed, e.g. after
+    /// every chunk of the stress test in `fuzz.rs`: the cursor is inside of the text area, the
+    /// cell grid fits it, and the view, the text scale and the highlight are valid.
+    pub fn check_consistency(&self) -> Result<(), Inconsistency> {
+        let area = self.text_area();
+        let (x, y) = (self.x_pos, self.y_pos);
+        let (left, top) = (area.x + BORDER_PADDING, area.y + BORDER_PADDING);
+        if x < left || x > area.right() || y < top || y >= area.bottom().max(top + 1) {
+            return Err(Inconsistency::CursorOutOfBounds { x, y, area });
+        }
+        let (columns, rows) = (self.grid.columns(), self.grid.rows());
+        let expected = (self.grid_columns(), self.rows());
+        if columns != expected.0 || rows > expected.1 {
+            return Err(Inconsistency::GridSize { columns, rows, expected });
+        }
+        if self.text_scale == 0 {
+            return Err(Inconsistency::ZeroTextScale);
+        }
+        let (offset, len) = (self.view_offset, self.scrollback.len());
+        if offset > len {
+            return Err(Inconsistency::ViewOffset { offset, len });
+        }
+        let grid = Rect::new(0, 0, columns, rows);
+        match self.highlight.filter(|&rect| rect.intersection(&grid) != rect) {
+            Some(rect) => Err(Inconsistency::HighlightOutOfBounds(rect)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether pixel output must be dropped, because the view shows the scrollback, the
+    /// screen is blanked, or the writer is poisoned.
+    fn output_suppressed(&self) -> bool {
+        self.view_offset > 0 || self.blanked || self.poisoned
+    }
+
+    /// Stops all pixel output after an operation that would have gone out of the framebuffer,
+    /// which can only be caused by a bug or by a geometry that changed behind the writer's
+    /// back. Text is still accepted and recorded in the grid and the scrollback, so
+    /// [FrameBufferWriter::recover] can show it again.
+    fn poison(&mut self) {
+        self.poisoned = true;
+    }
+
+    /// Returns whether pixel output stopped after a fault, see [FrameBufferWriter::recover].
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Resumes pixel output after a fault: checks the framebuffer geometry again, clears the
+    /// screen and redraws the text from the grid. The writer stays poisoned if the geometry is
+    /// still inconsistent. The text is only lost if the geometry changed the number of columns
+    /// or rows.
+    pub fn recover(&mut self) -> Result<(), FrameBufferError> {
+        FrameBufferError::check(self.framebuffer.len(), &self.info)?;
+        self.poisoned = false;
+        if (self.grid.columns(), self.grid.rows()) != (self.grid_columns(), self.rows()) {
+            self.resize_grid();
+        }
+        let (_, background) = self.style.colors();
+        self.fill_rect(0, 0, self.width(), self.height(), background);
+        match self.view_offset {
+            0 => self.redraw(),
+            _ => self.render_view(),
+        }
+        Ok(())
+    }
+
+    /// Returns whether the view shows lines from the scrollback instead of the live text.
+    pub fn is_viewing_scrollback(&self) -> bool {
+        self.view_offset > 0
+    }
+
+    /// Limits the scrollback to the given number of lines, dropping the lines that it holds.
+    /// It never keeps more than fit into its static storage, and only the first writer has
+    /// that storage. `usize::MAX`, the default, keeps as many as fit.
+    pub fn set_scrollback_lines(&mut self, lines: usize) {
+        self.view_live();
+        self.scrollback.set_max_lines(lines);
+    }
+
+    /// Returns the number of lines that the scrollback holds and the number that it keeps at most
+    /// at the current width of the text area.
+    pub fn scrollback_size(&self) -> (usize, usize) {
+        (self.scrollback.len(), self.scrollback.capacity())
+    }
+
+    /// Scrolls the view back by up to `lines` lines into the scrollback. Output that arrives
+    /// meanwhile is recorded, but doesn't move the view.
+    pub fn scroll_view_up(&mut self, lines: usize) {
+        self.set_view_offset(self.view_offset.saturating_add(lines));
+    }
+
+    /// Scrolls the view forward by up to `lines` lines, returning to the live text at the end.
+    pub fn scroll_view_down(&mut self, lines: usize) {
+        self.set_view_offset(self.view_offset.saturating_sub(lines));
+    }
+
+    /// Scrolls the view back to the oldest line of the scrollback.
+    pub fn view_oldest(&mut self) {
+        self.set_view_offset(usize::MAX);
+    }
+
+    /// Returns the view to the live text, showing all output that arrived meanwhile.
+    pub fn view_live(&mut self) {
+        self.set_view_offset(0);
+    }
+
+    fn set_view_offset(&mut self, offset: usize) {
+        let offset = offset.min(self.scrollback.len());
+        if offset == self.view_offset {
+            return;
+        }
+        self.view_offset = offset;
+        match offset {
+            0 => self.redraw(),
+            _ => self.render_view(),
+        }
+    }
+
+    /// Draws the lines selected by the view offset, followed by the top of the live text, and a
+    /// reverse-video marker in the top right cell.
+    fn render_view(&mut self) {
+        // Pixel output is suppressed while the view is scrolled back, except for this
+        let offset = core::mem::replace(&mut self.view_offset, 0);
+        let area = self.text_area();
+        self.scroll_lag = 0;
+        self.blink_visible = true;
+        self.drawn_cursor = None;
+        self.clear_rows(area.y, area.bottom());
+        let first = self.scrollback.len() - offset;
+        self.draw_cells(0..self.grid.rows(), 0..self.grid.columns(), |writer, column, row| {
+            let line = first + row;
+            match writer.scrollback.line(line) {
+                Some(cells) => cells.get(column).copied(),
+                None => writer.grid.get(column, line - writer.scrollback.len()).copied(),
+            }
+        });
+        self.repaint_overlay();
+        let style = Style { reverse: true, ..self.default_style };
+        let marker = Cell::new('^', style);
+        let last = self.grid.columns().saturating_sub(1);
+        self.draw_cells(0..1, last..last + 1, |_, _, _| Some(marker));
+        self.draw_header();
+        self.view_offset = offset;
+    }
+
+    /// Prints a rendered char into the framebuffer at the cursor, see
+    /// [FrameBufferWriter::draw_glyph]. Updates self.x_pos.
+    fn write_rendered_char(&mut self, rendered_char: Glyph, columns: usize) {
+        let (x, y) = (self.x_pos as isize, self.y_pos as isize);
+        self.x_pos += self.draw_glyph(&rendered_char, columns, x, y);
+    }
+
+    /// Draws a rendered char with its top left corner at `(x, y)` in the current style and text
+    /// scale, including its shadow or outline and its underline, and returns its advance.
+    ///
+    /// The advance is exactly `columns` cells, with narrower rasters centered in them, unless
+    /// proportional advance is enabled.
+    fn draw_glyph(&mut self, rendered_char: &Glyph, columns: usize, x0: isize, y0: isize) -> usize {
+        let style = self.style;
+        let scale = self.text_scale as isize;
+        let (x, advance) = match self.proportional {
+            true => (0, (rendered_char.width() + LETTER_SPACING) * self.text_scale),
+            false => {
+                let width = self.metrics.width * columns;
+                let padding = width.saturating_sub(rendered_char.width());
+                (padding as isize / 2 * scale, self.cell_width() * columns)
+            }
+        };
+        let effect: &[(isize, isize)] = match style.effect {
+            TextEffect::None => &[],
+            TextEffect::Shadow => &[(1, 1)],
+            TextEffect::Outline => &OUTLINE_OFFSETS,
+        };
+        // Bold overstrikes shifted by one pixel, which works for any font
+        let strokes: &[(isize, isize)] = match style.bold {
+            true => &[(0, 0), (1, 0)],
+            false => &[(0, 0)],
+        };
+        let (foreground, _) = style.colors();
+        let layers = [(effect, style.effect_color), (strokes, foreground)];
+        self.draw_raster(rendered_char, x0 + x, y0, layers);
+        if style.underline {
+            let y = y0 + (self.metrics.height as isize - 1) * scale;
+            self.fill_rect(x0, y, advance, self.text_scale, foreground);
+        }
+        advance
+    }
+
+    /// Draws the coverage of a rendered char with its top left corner at `(x0, y0)`, enlarged by
+    /// the current text scale, in two layers of a color each: the effect, then the text. A
+    /// layer repeats the coverage at offsets in raster pixels, which are combined by taking the
+    /// largest coverage of every pixel, so that the edges of one copy don't overwrite the
+    /// inside of another. Pixels that fall outside of the screen are clipped, and so are the
+    /// columns of rasters wider than [SPAN_CHUNK].
+    ///
+    /// Each run of covered pixels is written as one span, blending every pixel once. While
+    /// double buffering, the pixels are blended with what the back buffer already holds, so
+    /// that glyphs drawn over each other, like combining marks over their base char, stay
+    /// intact. Otherwise they are blended with the background color of the current style,
+    /// which the cells are filled with first, as reading back the framebuffer would be slow.
+    fn draw_raster(&mut self, rendered_char: &Glyph, x0: isize, y0: isize, layers: [Layer; 2]) {
+        let scale = self.text_scale;
+        let (_, background) = self.style.colors();
+        let blend = |base: Color, [effect, text]: [u8; 2]| {
+            base.blend(layers[0].1, effect).blend(layers[1].1, text)
+        };
+        let read_back = self.double_buffer.is_some();
+        let offsets = || layers.iter().flat_map(|&(offsets, _)| offsets);
+        let left = offsets().map(|&(dx, _)| dx).min().unwrap_or(0).min(0);
+        let top = offsets().map(|&(_, dy)| dy).min().unwrap_or(0).min(0);
+        let right = offsets().map(|&(dx, _)| dx).max().unwrap_or(0).max(0);
+        let bottom = offsets().map(|&(_, dy)| dy).max().unwrap_or(0).max(0);
+        let width = (rendered_char.width() as isize + right - left).min(SPAN_CHUNK as isize);
+        let height = rendered_char.height() as isize + bottom - top;
+        let mut coverage = [[0u8; 2]; SPAN_CHUNK];
+        let mut span = [Color::BLACK; SPAN_CHUNK];
+        let mut buffer = [0; font::MAX_BITS_WIDTH];
+        for y in 0..height {
+            let coverage = &mut coverage[..width as usize];
+            coverage.fill([0; 2]);
+            for (layer, &(offsets, _)) in layers.iter().enumerate() {
+                for &(dx, dy) in offsets {
+                    let Ok(source) = usize::try_from(y + top - dy) else { continue };
+                    if source >= rendered_char.height() {
+                        continue;
+                    }
+                    let row = rendered_char.row(source, &mut buffer);
+                    let start = (dx - left) as usize;
+                    for (pixel, &value) in coverage.iter_mut().skip(start).zip(row) {
+                        pixel[layer] = pixel[layer].max(value);
+                    }
+                }
+            }
+            let mut x = 0;
+            while x < coverage.len() {
+                if coverage[x] == [0; 2] {
+                    x += 1;
+                    continue;
+                }
+                let run_start = x;
+                let fits = |x: usize| (x - run_start + 1) * scale <= span.len();
+                while x < coverage.len() && coverage[x] != [0; 2] && fits(x) {
+                    x += 1;
+                }
+                let run = &coverage[run_start..x];
+                let span = &mut span[..run.len() * scale];
+                let px = x0 + ((run_start as isize + left) * scale as isize);
+                for sy in 0..scale {
+                    let py = y0 + ((y + top) * scale as isize + sy as isize);
+                    // Without read back, every row of the scaled run is the same
+                    if read_back || sy == 0 {
+                        for (i, pixel) in span.iter_mut().enumerate() {
+                            let base = match read_back {
+                                true => self.drawn_color(px + i as isize, py),
+                                false => None,
+                            };
+                            *pixel = blend(base.unwrap_or(background), run[i / scale]);
+                        }
+                    }
+                    self.write_row(px, py, span);
+                }
+            }
+        }
+    }
+
+    /// Returns the color drawn at `(x, y)`, where a span written there would end up, before
+    /// night mode inverts it, or `None` outside of the screen.
+    fn drawn_color(&self, x: isize, y: isize) -> Option<Color> {
+        let (x, y) = (usize::try_from(x).ok()?, usize::try_from(y).ok()?);
+        let color = self.get_pixel(x, self.shown_y(y)?)?;
+        Some(if self.inverted { color.inverted() } else { color })
+    }
+
+    /// Writes a horizontal span of pixels starting at `(x, y)`. Pixels outside of the screen or
+    /// the clip rectangle are clipped.
+    pub fn write_row(&mut self, x: isize, y: isize, pixels: &[Color]) {
+        if let Some((offset, skip, len)) = self.clip_span(x, y, pixels.len()) {
+            self.write_span(offset, len, |i| pixels[skip + i]);
+            self.touch_pointer(x + skip as isize, y, len);
+        }
+    }
+
+    /// Fills a horizontal span of `len` pixels starting at `(x, y)` with a single color. Pixels
+    /// outside of the screen or the clip rectangle are clipped.
+    pub fn fill_row(&mut self, x: isize, y: isize, len: usize, color: Color) {
+        if let Some((offset, skip, len)) = self.clip_span(x, y, len) {
+            self.write_span(offset, len, |_| color);
+            self.touch_pointer(x + skip as isize, y, len);
+        }
+    }
+
+    /// Fills a rectangle with a single color. Pixels outside of the screen or the clip rectangle
+    /// are clipped.
+    pub fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        for row in 0..height {
+            self.fill_row(x, y + row as isize, width, color);
+        }
+    }
+
+    /// Returns the number of bytes from one framebuffer row to the next.
+    fn framebuffer_row_len(&self) -> usize {
+        self.info.stride * self.info.bytes_per_pixel
+    }
+
+    /// Returns the byte offset of the first pixel of the physical row `y` in the framebuffer,
+    /// which is the first of the framebuffer rows that it covers.
+    fn row_offset(&self, y: usize) -> usize {
+        y * self.hidpi_scale * self.framebuffer_row_len()
+    }
+
+    /// Returns the byte offset of the pixel at the logical position `(x, y)` in the framebuffer,
+    /// i.e. of the top left framebuffer pixel that it covers.
+    fn pixel_offset(&self, x: usize, y: usize) -> usize {
+        let (width, height) = self.physical_size();
+        let (x, y) = self.rotation.to_physical(x, y, width, height);
+        self.row_offset(y) + x * self.hidpi_scale * self.info.bytes_per_pixel
+    }
+
+    /// Clips a horizontal span of `len` pixels starting at `(x, y)` to the screen and the clip
+    /// rectangle.
+    ///
+    /// Returns the byte offset of the first visible pixel, the number of pixels cut off at the
+    /// start, and the number of visible pixels, or `None` if nothing is visible.
+    fn clip_span(&self, x: isize, y: isize, len: usize) -> Option<(usize, usize, usize)> {
+        let bounds = self.bounds();
+        if y < bounds.y as isize || y >= bounds.bottom() as isize {
+            return None;
+        }
+        let y = self.shown_y(y as usize)? as isize;
+        let start = x.max(bounds.x as isize);
+        let end = x.saturating_add_unsigned(len).min(bounds.right() as isize);
+        if end <= start {
+            return None;
+        }
+        let offset = self.pixel_offset(start as usize, y as usize);
+        Some((offset, start.abs_diff(x), end.abs_diff(start)))
+    }
+
+    /// Returns the row at which the logical row `y` is currently shown, which differs inside the
+    /// text area during a smooth scroll, or `None` if it is still below the visible part.
+    fn shown_y(&self, y: usize) -> Option<usize> {
+        let area = self.text_area();
+        if self.scroll_lag == 0 || y < area.y || y >= area.bottom() {
+            return Some(y);
+        }
+        Some(y + self.scroll_lag).filter(|&shown| shown < area.bottom())
+    }
+
+    /// Encodes `len` pixels of a logical row, produced by `pixel` from their index, and copies
+    /// them to the framebuffer starting at `offset`, each as a block of the HiDPI scale. This is
+    /// the only place that writes pixel data.
+    fn write_span(&mut self, offset: usize, len: usize, pixel: impl Fn(usize) -> Color) {
+        if self.output_suppressed() {
+            return;
+        }
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let scale = self.hidpi_scale;
+        match self.rotation {
+            Rotation::None => {}
+            // The span runs right to left in memory
+            Rotation::Cw180 => {
+                let Some(start) = offset.checked_sub((len - 1) * scale * bytes_per_pixel) else {
+                    return self.poison();
+                };
+                return self.write_scaled(start, len, |i| pixel(len - 1 - i));
+            }
+            // The span runs down or up a physical column
+            Rotation::Cw90 | Rotation::Cw270 => {
+                let step = self.row_offset(1);
+                for i in 0..len {
+                    let offset = match self.rotation {
+                        Rotation::Cw90 => offset.checked_add(i * step),
+                        _ => offset.checked_sub(i * step),
+                    };
+                    let Some(offset) = offset else { return self.poison() };
+                    let encoded = self.encode_color(pixel(i));
+                    let block = scale * bytes_per_pixel;
+                    for row in 0..scale {
+                        let start = offset + row * self.framebuffer_row_len();
+                        self.mark_dirty(start..start + block);
+                        let Some(target) = self.framebuffer.get_mut(start..start + block) else {
+                            return self.poison();
+                        };
+                        for target in target.chunks_exact_mut(bytes_per_pixel) {
+                            target.copy_from_slice(&encoded[..bytes_per_pixel]);
+                        }
+                    }
+                }
+                return self.flush(offset);
+            }
+        }
+        self.write_scaled(offset, len, pixel);
+    }
+
+    /// Writes `len` logical pixels that are contiguous in the framebuffer, starting at `offset`,
+    /// into all the framebuffer rows that they cover.
+    fn write_scaled(&mut self, offset: usize, len: usize, pixel: impl Fn(usize) -> Color) {
+        let scale = self.hidpi_scale;
+        for row in 0..scale {
+            let offset = offset + row * self.framebuffer_row_len();
+            self.write_contiguous(offset, len * scale, |i| pixel(i / scale));
+        }
+    }
+
+    /// Writes `len` pixels that are contiguous in the framebuffer, starting at `offset`.
+    fn write_contiguous(&mut self, offset: usize, len: usize, pixel: impl Fn(usize) -> Color) {
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let mut chunk = [0u8; SPAN_CHUNK * 4];
+        let mut last = None;
+        let mut encoded = [0u8; 4];
+        let mut done = 0;
+        while done < len {
+            let count = (len - done).min(SPAN_CHUNK);
+            for i in 0..count {
+                let color = pixel(done + i);
+                if last != Some(color) {
+                    encoded = self.encode_color(color);
+                    last = Some(color);
+                }
+                chunk[i * bytes_per_pixel..(i + 1) * bytes_per_pixel]
+                    .copy_from_slice(&encoded[..bytes_per_pixel]);
+            }
+            let start = offset + done * bytes_per_pixel;
+            self.mark_dirty(start..start + count * bytes_per_pixel);
+            let Some(target) = self.framebuffer.get_mut(start..start + count * bytes_per_pixel)
+            else {
+                return self.poison();
+            };
+            target.copy_from_slice(&chunk[..count * bytes_per_pixel]);
+            done += count;
+        }
+        self.flush(offset);
+    }
+
+    /// Reads the byte at `offset` back, so that the writes before it can't be optimized away.
+    fn flush(&self, offset: usize) {
+        if let Some(byte) = self.framebuffer.get(offset) {
+            // SAFETY: the reference is valid for reads.
+            let _ = unsafe { ptr::read_volatile(byte) };
+        }
+    }
+
+    /// Converts a color to the byte layout of a pixel in the framebuffer's pixel format. Applies
+    /// night mode.
+    fn encode_color(&self, color: Color) -> [u8; 4] {
+        let color = if self.inverted { color.inverted() } else { color };
+        match self.info.pixel_format {
+            PixelFormat::Rgb => [color.r, color.g, color.b, 0],
+            PixelFormat::Bgr => [color.b, color.g, color.r, 0],
+            PixelFormat::U8 => {
+                let gray = (color.r as u16 * 77 + color.g as u16 * 150 + color.b as u16 * 29) >> 8;
+                [gray as u8, 0, 0, 0]
+            }
+            PixelFormat::Unknown { red_position, green_position, blue_position } => {
+                let value = (color.r as u32) << red_position
+                    | (color.g as u32) << green_position
+                    | (color.b as u32) << blue_position;
+                value.to_le_bytes()
+            }
+            _ => [color.r, color.g, color.b, 0],
+        }
+    }
+
+    /// Converts the bytes of a pixel in the framebuffer's pixel format back to a color, as it
+    /// appears on screen (so night mode is not undone).
+    fn decode_color(&self, bytes: &[u8]) -> Color {
+        let mut raw = [0u8; 4];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        match self.info.pixel_format {
+            PixelFormat::Rgb => Color::new(raw[0], raw[1], raw[2]),
+            PixelFormat::Bgr => Color::new(raw[2], raw[1], raw[0]),
+            PixelFormat::U8 => Color::new(raw[0], raw[0], raw[0]),
+            PixelFormat::Unknown { red_position, green_position, blue_position } => {
+                let value = u32::from_le_bytes(raw);
+                let channel = |position: u8| (value >> position) as u8;
+                Color::new(channel(red_position), channel(green_position), channel(blue_position))
+            }
+            _ => Color::new(raw[0], raw[1], raw[2]),
+        }
+    }
+
+    /// Shows or hides the text cursor, which is drawn as the cell at the cursor in reverse video.
+    /// Like `ESC[?25h` and `ESC[?25l` (DECTCEM), the setting stays until it is changed again,
+    /// across scrolls, clears and redraws. Full-screen output like the panic screen hides it.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+        match visible {
+            true => self.paint_cursor(),
+            false => self.erase_cursor(),
+        }
+    }
+
+    /// Returns whether the text cursor is shown, see [FrameBufferWriter::set_cursor_visible].
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Draws the text cursor as a block, the default, or as an underline.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor_shape = shape;
+        self.paint_cursor();
+    }
+
+    /// Returns how the text cursor is drawn.
+    pub fn cursor_shape(&self) -> CursorShape {
+        self.cursor_shape
+    }
+
+    /// Makes the text cursor blink, every [BLINK_INTERVAL_MS] of [FrameBufferWriter::tick], or
+    /// stay, which is the default. The cursor is shown whenever there is input or output, and
+    /// starts blinking again once there is none. Hiding it only redraws the cell below it from
+    /// the grid, so the char there stays intact.
+    pub fn set_cursor_blink(&mut self, blink: bool) {
+        self.cursor_blink = blink;
+        self.cursor_blink_on = true;
+        self.paint_cursor();
+    }
+
+    /// Returns whether the text cursor blinks.
+    pub fn cursor_blink(&self) -> bool {
+        self.cursor_blink
+    }
+
+    /// Shows or hides a blinking cursor according to the time in milliseconds since the last
+    /// activity.
+    fn blink_cursor(&mut self, now: u64) {
+        let idle = now.saturating_sub(self.last_activity);
+        let on = !self.cursor_blink || (idle / BLINK_INTERVAL_MS).is_multiple_of(2);
+        if on == self.cursor_blink_on {
+            return;
+        }
+        self.cursor_blink_on = on;
+        match on {
+            true => self.paint_cursor(),
+            false => self.erase_cursor(),
+        }
+    }
+
+    /// Runs `f`, which writes text, as one batch: the cursor is erased before and drawn once at
+    /// its final position afterwards, instead of for every char. Batches nest, and all output
+    /// methods are batches, so this only saves work around several of them.
+    pub fn batch<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        if self.batch_depth == 0 {
+            self.erase_cursor();
+        }
+        self.batch_depth += 1;
+        let result = f(self);
+        self.batch_depth -= 1;
+        self.paint_cursor();
+        if self.batch_depth == 0 {
+            self.present();
+        }
+        result
+    }
+
+    /// Draws the cursor over its cell, unless it is hidden or in the hidden half of its blink,
+    /// a batch is in progress, pixel output is suppressed, a pending wrap leaves it past the
+    /// last column, or it is below the toast.
+    fn paint_cursor(&mut self) {
+        let hidden = !self.cursor_visible || !self.cursor_blink_on;
+        if hidden || self.batch_depth > 0 || self.output_suppressed() {
+            return;
+        }
+        self.erase_cursor();
+        let (column, row) = self.cursor_cell();
+        let toast = self.toast.as_ref();
+        let below_toast = toast.is_some_and(|toast| toast.cells.contains(column, row));
+        if column >= self.grid.columns() || row >= self.grid.rows() || below_toast {
+            return;
+        }
+        match self.cursor_shape {
+            CursorShape::Block => {
+                self.draw_cells(row..row + 1, column..column + 1, |writer, column, row| {
+                    let cell = writer.shown_cell(column, row)?;
+                    let style = Style { reverse: !cell.style.reverse, blink: false, ..cell.style };
+                    Some(Cell { style, ..cell })
+                });
+            }
+            CursorShape::Underline => {
+                let Some(cell) = self.shown_cell(column, row) else { return };
+                let (x, y) = self.cell_origin(column, row);
+                let (width, height) = (self.base_cell_width(), self.base_line_height());
+                let line = UNDERLINE_CURSOR_HEIGHT.min(height);
+                let (foreground, _) = cell.style.colors();
+                self.fill_rect(x as isize, (y + height - line) as isize, width, line, foreground);
+            }
+        }
+        self.drawn_cursor = Some((column, row));
+    }
+
+    /// Restores the cell that the cursor is drawn over from the grid.
+    fn erase_cursor(&mut self) {
+        let Some((column, row)) = self.drawn_cursor.take() else { return };
+        self.redraw_cells(row, column..column + 1);
+        self.repaint_overlay_over(column, row, 1);
+    }
+
+    /// Shows the mouse pointer with its tip at the logical position `(x, y)`, or moves it there,
+    /// restoring the pixels it covered before. It is clipped at the edges of the screen and
+    /// stays on top of everything that is drawn below it.
+    pub fn show_pointer(&mut self, x: usize, y: usize) {
+        self.hide_pointer();
+        let mut pointer = Sprite::new(x, y);
+        let rect = pointer.rect().intersection(&self.screen_rect());
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                *pointer.saved_mut(x, y) = self.read_raw(x, y);
+                self.draw_pointer_pixel(&pointer, x, y);
+            }
+        }
+        self.pointer = Some(pointer);
+    }
+
+    /// Removes the mouse pointer, restoring the pixels it covered.
+    pub fn hide_pointer(&mut self) {
+        let Some(mut pointer) = self.pointer.take() else { return };
+        let rect = pointer.rect().intersection(&self.screen_rect());
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                if let Some(bytes) = *pointer.saved_mut(x, y) {
+                    self.write_raw(x, y, bytes);
+                }
+            }
+        }
+    }
+
+    /// Returns the position of the mouse pointer's tip, if it is shown.
+    pub fn pointer_position(&self) -> Option<(usize, usize)> {
+        self.pointer.as_ref().map(Sprite::position)
+    }
+
+    /// Runs `f`, which changes pixels other than through spans, with the mouse pointer hidden,
+    /// and shows it again over the result.
+    fn without_pointer<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let position = self.pointer_position();
+        self.hide_pointer();
+        let result = f(self);
+        if let Some((x, y)) = position {
+            self.show_pointer(x, y);
+        }
+        result
+    }
+
+    /// Keeps the mouse pointer on top after a span of `len` pixels starting at the logical
+    /// position `(x, y)` was written: the new pixels below it are saved, and it is drawn over
+    /// them again.
+    fn touch_pointer(&mut self, x: isize, y: isize, len: usize) {
+        if self.pointer.is_none() || self.output_suppressed() {
+            return;
+        }
+        let Some(y) = self.shown_y(y as usize) else { return };
+        let Some(mut pointer) = self.pointer.take() else { return };
+        let rect = pointer.rect();
+        if (rect.y..rect.bottom()).contains(&y) {
+            let start = (x as usize).max(rect.x);
+            let end = (x as usize + len).min(rect.right()).min(self.width());
+            for x in start..end {
+                *pointer.saved_mut(x, y) = self.read_raw(x, y);
+                self.draw_pointer_pixel(&pointer, x, y);
+            }
+        }
+        self.pointer = Some(pointer);
+    }
+
+    /// Draws the pixel of the mouse pointer at the logical position `(x, y)`, unless it is
+    /// transparent there.
+    fn draw_pointer_pixel(&mut self, pointer: &Sprite, x: usize, y: usize) {
+        if let Some(color) = pointer.color_at(x, y) {
+            self.write_raw(x, y, self.encode_color(color));
+        }
+    }
+
+    /// Returns the framebuffer bytes of the pixel at the logical position `(x, y)`, i.e. of the
+    /// top left framebuffer pixel that it covers, or `None` outside of the screen.
+    fn read_raw(&self, x: usize, y: usize) -> Option<[u8; 4]> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let offset = self.pixel_offset(x, y);
+        let bytes = self.framebuffer.get(offset..offset + self.info.bytes_per_pixel)?;
+        let mut raw = [0; 4];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        Some(raw)
+    }
+
+    /// Sets the framebuffer bytes of the pixel at the logical position `(x, y)`, i.e. of all
+    /// framebuffer pixels that it covers, unless it is outside of the screen or the screen is
+    /// blanked. Unlike the spans, this ignores the clip rectangle and the scrollback view.
+    fn write_raw(&mut self, x: usize, y: usize, bytes: [u8; 4]) {
+        if self.blanked || self.poisoned || x >= self.width() || y >= self.height() {
+            return;
+        }
+        let (offset, len) = (self.pixel_offset(x, y), self.info.bytes_per_pixel);
+        for row in 0..self.hidpi_scale {
+            let start = offset + row * self.framebuffer_row_len();
+            self.mark_dirty(start..start + self.hidpi_scale * len);
+            let Some(target) = self.framebuffer.get_mut(start..start + self.hidpi_scale * len)
+            else {
+                continue;
+            };
+            for target in target.chunks_exact_mut(len) {
+                target.copy_from_slice(&bytes[..len]);
+            }
+        }
+    }
+
+    /// Returns the color of the pixel at the logical position `(x, y)` as it appears on the
+    /// screen, decoded from the framebuffer's pixel format, or `None` outside of the screen.
+    ///
+    /// In the RGB formats, colors come back exactly as written; in the grayscale format, only
+    /// their gray level is kept.
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        let raw = self.read_raw(x, y)?;
+        Some(self.decode_color(&raw[..self.info.bytes_per_pixel]))
+    }
+
+    /// Streams the visible screen as a binary PPM image (`P6`, RGB888) to `sink`, converting
+    /// from the native pixel format and skipping the padding at the end of every row. The image
+    /// shows the screen upright, undoing the [Rotation], in logical pixels.
+    pub fn write_ppm(&self, mut sink: impl FnMut(&[u8])) {
+        struct Header<'a, F>(&'a mut F);
+        impl<F: FnMut(&[u8])> Write for Header<'_, F> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                (self.0)(s.as_bytes());
+                Ok(())
+            }
+        }
+        let _ = write!(Header(&mut sink), "P6\n{} {}\n255\n", self.width(), self.height());
+
+        let mut chunk = [0u8; SPAN_CHUNK * 3];
+        for y in 0..self.height() {
+            for start in (0..self.width()).step_by(SPAN_CHUNK) {
+                let count = SPAN_CHUNK.min(self.width() - start);
+                for (x, rgb) in (start..start + count).zip(chunk.chunks_exact_mut(3)) {
+                    let Some(color) = self.get_pixel(x, y) else { return };
+                    rgb.copy_from_slice(&[color.r, color.g, color.b]);
+                }
+                sink(&chunk[..count * 3]);
+            }
+        }
+    }
+
+    /// Writes text that is only available as bytes, e.g. received over the serial port, like
+    /// [Write::write_str]. Invalid UTF-8 is shown as the replacement character, and a char that
+    /// is split across calls is written once its last byte arrives.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.batch(|writer| {
+            let mut decoder = writer.utf8;
+            for &byte in bytes {
+                decoder.push(byte, &mut |c| writer.put_char(c));
+            }
+            writer.utf8 = decoder;
+        })
+    }
+
+    /// Prints text with automatic wrapping, scrolling, and ANSI-like escape sequences.
+    ///
+    /// Supported sequences: `\c` switches to blue text, `\s` and `\o` draw a shadow or
+    /// an outline behind the glyphs, and `\r` resets to the default style. In raw mode, the text
+    /// is printed literally.
+    pub fn print(&mut self, text: &str) {
+        self.batch(|writer| writer.print_batched(text))
+    }
+
+    /// Prints text like [FrameBufferWriter::print] inside its batch.
+    fn print_batched(&mut self, text: &str) {
+        if self.raw_mode {
+            let _ = self.write_str(text);
+            return;
+        }
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        match next {
+                            'c' => self.style.foreground = Color::BLUE,  // Change to blue
+                            's' => self.style.effect = TextEffect::Shadow, // Drop shadow
+                            'o' => self.style.effect = TextEffect::Outline, // Outline
+                            'r' => self.reset_style(),                   // Reset style
+                            _ => self.put_char(c),                       // Unknown sequence
+                        }
+                    }
+                }
+                _ => self.put_char(c),
+            }
+        }
+    }
+}
+
+impl Write for FrameBufferWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.batch(|writer| {
+            writer.pending_newlines = s.bytes().filter(|&byte| byte == b'\n').count();
+            for c in s.chars() {
+                writer.put_char(c);
+            }
+            writer.pending_newlines = 0;
+        });
+        Ok(())
+    }
+
+    /// Writes all pieces of the formatted text as one batch, so that the cursor is drawn once.
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.batch(|writer| fmt::write(writer, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Returns a writer over zeroed memory of the host, with `padding` pixels at the end of
+    /// every row.
+    fn writer(format: PixelFormat, bytes_per_pixel: usize, padding: usize) -> FrameBufferWriter {
+        sized_writer(format, bytes_per_pixel, (200, 120), padding)
+    }
+
+    /// Like [writer], for a framebuffer of the given width and height.
+    fn sized_writer(
+        format: PixelFormat,
+        bytes_per_pixel: usize,
+        (width, height): (usize, usize),
+        padding: usize,
+    ) -> FrameBufferWriter {
+        let stride = width + padding;
+        let byte_len = stride * height * bytes_per_pixel;
+        let info = FrameBufferInfo {
+            byte_len,
+            width,
+            height,
+            pixel_format: format,
+            bytes_per_pixel,
+            stride,
+        };
+        FrameBufferWriter::from_surface(surface::MemorySurface::zeroed(info)).unwrap()
+    }
+
+    /// The pixel formats and sizes that [FrameBufferError::check] accepts.
+    const FORMATS: [(PixelFormat, usize); 5] = [
+        (PixelFormat::Rgb, 3),
+        (PixelFormat::Rgb, 4),
+        (PixelFormat::Bgr, 4),
+        (PixelFormat::U8, 1),
+        (PixelFormat::Unknown { red_position: 8, green_position: 16, blue_position: 24 }, 4),
+    ];
+
+    #[test]
+    fn inverting_twice_restores_the_framebuffer() {
+        for (pixel_format, bytes_per_pixel) in FORMATS {
+            for padding in [0, 3] {
+                let mut writer = writer(pixel_format, bytes_per_pixel, padding);
+                writer.print("inverted \x1b[31mtext\x1b[0m");
+                for (index, byte) in writer.framebuffer.iter_mut().enumerate() {
+                    *byte = (index * 7 + index / 5) as u8;
+                }
+                let before = writer.framebuffer.to_vec();
+                writer.invert_screen();
+                assert_ne!(*writer.framebuffer, *before, "{:?}", pixel_format);
+                writer.invert_screen();
+                assert_eq!(*writer.framebuffer, *before, "{:?} padding {}", pixel_format, padding);
+            }
+        }
+    }
+
+    #[test]
+    fn pixels_round_trip_through_every_format() {
+        let colors = [Color::BLACK, Color::WHITE, Color::new(1, 2, 3), Color::new(250, 128, 7)];
+        let grays = [0, 1, 127, 128, 254, 255].map(|gray| Color::new(gray, gray, gray));
+        for (pixel_format, bytes_per_pixel) in FORMATS {
+            let writer = writer(pixel_format, bytes_per_pixel, 0);
+            let round_trip = |color| {
+                let bytes = writer.encode_color(color);
+                writer.decode_color(&bytes[..bytes_per_pixel])
+            };
+            for color in grays {
+                assert_eq!(round_trip(color), color, "{:?}", pixel_format);
+            }
+            for color in colors {
+                let (r, g, b) = (u16::from(color.r), u16::from(color.g), u16::from(color.b));
+                let luminance = ((r * 77 + g * 150 + b * 29) >> 8) as u8;
+                let expected = match pixel_format {
+                    // Gray framebuffers keep only the luminance
+                    PixelFormat::U8 => Color::new(luminance, luminance, luminance),
+                    _ => color,
+                };
+                assert_eq!(round_trip(color), expected, "{:?}", pixel_format);
+            }
+        }
+        let color = Color::new(1, 2, 3);
+        assert_eq!(writer(PixelFormat::Rgb, 3, 0).encode_color(color)[..3], [1, 2, 3]);
+        assert_eq!(writer(PixelFormat::Bgr, 4, 0).encode_color(color), [3, 2, 1, 0]);
+        // The last entry of FORMATS has the channels at bits 8, 16 and 24
+        let (unknown, _) = FORMATS[4];
+        assert_eq!(writer(unknown, 4, 0).encode_color(color), [0, 1, 2, 3]);
+        assert_eq!(writer(PixelFormat::U8, 1, 0).encode_color(Color::WHITE)[0], 255);
+    }
+
+    #[test]
+    fn screensaver_blanks_after_the_timeout_and_output_restores_the_screen() {
+        let mut writer = writer(PixelFormat::Bgr, 4, 0);
+        writer.set_screensaver_timeout(Some(1000));
+        writer.print("some text");
+        writer.tick(0);
+        let shown = writer.framebuffer.to_vec();
+        writer.tick(999);
+        assert!(!writer.blanked);
+        assert_eq!(*writer.framebuffer, *shown);
+        writer.tick(1000);
+        assert!(writer.blanked);
+        assert!(writer.framebuffer.iter().all(|&byte| byte == 0));
+        // Any output wakes the screen up, even a sequence that changes nothing
+        writer.print("\x1b[m");
+        assert!(!writer.blanked);
+        assert_eq!(*writer.framebuffer, *shown);
+        // Output counts as activity, so the timeout starts over
+        writer.tick(1500);
+        assert!(!writer.blanked);
+        writer.tick(2500);
+        assert!(writer.blanked);
+    }
+
+    #[test]
+    fn screensaver_leaves_a_poisoned_framebuffer_alone() {
+        let mut writer = writer(PixelFormat::Bgr, 4, 0);
+        writer.set_screensaver_timeout(Some(1000));
+        writer.print("some text");
+        let shown = writer.framebuffer.to_vec();
+        writer.poison();
+        writer.tick(1000);
+        assert!(!writer.blanked);
+        assert_eq!(*writer.framebuffer, *shown);
+    }
+
+    /// Returns the pixels of the cell at the given column of the first row, as the brightness of
+    /// their red channel, row by row.
+    fn cell_pixels(writer: &FrameBufferWriter, column: usize) -> Vec<u8> {
+        let (x, y) = writer.cell_origin(column, 0);
+        let (width, height) = (writer.cell_width(), writer.line_height());
+        let pixel = |(dx, dy)| writer.get_pixel(x + dx, y + dy).unwrap().r;
+        (0..height).flat_map(|dy| (0..width).map(move |dx| (dx, dy))).map(pixel).collect()
+    }
+
+    #[test]
+    fn bold_never_darkens_the_regular_glyph() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        for c in ' '..='~' {
+            writer.print("\x1b[0;97;40m\x1b[H\x1b[2J");
+            writer.put_char(c);
+            writer.print("\x1b[1m");
+            writer.put_char(c);
+            let (regular, bold) = (cell_pixels(&writer, 0), cell_pixels(&writer, 1));
+            for (index, (regular, bold)) in regular.iter().zip(&bold).enumerate() {
+                assert!(bold >= regular, "{:?} pixel {}: {} < {}", c, index, bold, regular);
+            }
+        }
+    }
+
+    /// Returns the colors of the pixels of the cell at row 0.
+    fn cell_colors(writer: &FrameBufferWriter, column: usize) -> Vec<Color> {
+        let (x, y) = writer.cell_origin(column, 0);
+        let (width, height) = (writer.cell_width(), writer.line_height());
+        let pixel = |(dx, dy)| writer.get_pixel(x + dx, y + dy).unwrap();
+        (0..height).flat_map(|dy| (0..width).map(move |dx| (dx, dy))).map(pixel).collect()
+    }
+
+    #[test]
+    fn double_buffered_glyphs_blend_with_what_is_drawn() {
+        for double_buffering in [false, true] {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_double_buffering(double_buffering).unwrap();
+            writer.print("\x1b[0;97;40m\x1b[H\x1b[2J");
+            let (x, y) = writer.cell_origin(1, 0);
+            let (width, height) = (writer.cell_width(), writer.line_height());
+            writer.fill_rect(x as isize, y as isize, width, height, Color::RED);
+            let glyph = writer.glyph('@');
+            writer.draw_glyph(&glyph, 1, x as isize, y as isize);
+            // Edges of the white glyph are blended with red, or with the black background
+            let pixels = cell_colors(&writer, 1);
+            let reddish = pixels.iter().all(|pixel| pixel.r >= Color::RED.r);
+            assert_eq!(reddish, double_buffering);
+        }
+    }
+
+    #[test]
+    fn sgr_backgrounds_fill_the_cells() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        let palette = (40..=47).chain(100..=107).zip(Color::ANSI_PALETTE);
+        let indexed = [
+            ("48;5;196", Color::new(255, 0, 0)),
+            ("48;5;244", Color::new(128, 128, 128)),
+            ("48;2;1;2;3", Color::new(1, 2, 3)),
+        ];
+        let sequences = palette.map(|(param, color)| (param.to_string(), color));
+        let sequences = sequences.chain(indexed.map(|(params, color)| (params.into(), color)));
+        for (params, color) in sequences {
+            writer.print(&format!("\x1b[0m\x1b[H\x1b[2J\x1b[{}m \x1b[49m ", params));
+            assert!(cell_colors(&writer, 0).iter().all(|&pixel| pixel == color), "{}", params);
+            let default = writer.default_style.background;
+            assert!(cell_colors(&writer, 1).iter().all(|&pixel| pixel == default), "{}", params);
+        }
+    }
+
+    #[test]
+    fn tabs_advance_to_the_next_stop_but_stay_on_the_line() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print("\x1b[0m\x1b[H\x1b[2Ja\tb\tc");
+        let line = |writer: &FrameBufferWriter| {
+            let row = writer.get_text_region(Rect::new(0, 0, usize::MAX, 1)).next();
+            row.unwrap().to_string()
+        };
+        assert_eq!(line(&writer), format!("a{}b{}c", " ".repeat(7), " ".repeat(7)));
+        assert_eq!(writer.cursor_position(), (17, 0));
+        // Stops are counted from the left edge, and a soft reset keeps them
+        writer.print("\x1b[!p\r\t");
+        assert_eq!(writer.cursor_position(), (8, 0));
+        let columns = writer.columns();
+        assert!(!columns.is_multiple_of(TAB_WIDTH), "{} columns", columns);
+        writer.print(&format!("\x1b[1;{}H\t", columns - 1));
+        assert_eq!(writer.cursor_position(), (columns, 0));
+        writer.print("\t");
+        assert_eq!(writer.cursor_position(), (columns, 0));
+    }
+
+    #[test]
+    fn text_regions_are_clipped_to_the_text_area() {
+        let mut writer = writer(PixelFormat::Rgb, 3, 0);
+        writer.print("\x1b[H\x1b[2Jab\ncd\n");
+        let text = |rect| writer.get_text_region(rect).map(|row| row.to_string()).collect();
+        let text: Vec<String> = text(Rect::new(1, 0, usize::MAX, usize::MAX));
+        assert_eq!(text[..3], ["b", "d", ""]);
+        assert_eq!(text.len(), writer.rows());
+        let past = Rect::new(usize::MAX, usize::MAX, usize::MAX, 1);
+        assert_eq!(writer.get_text_region(past).count(), 0);
+    }
+
+    #[test]
+    fn psf_glyphs_light_the_pixels_of_their_bits() {
+        let font = psf::Psf2Font::parse(psf::tests::mapped_font()).unwrap();
+        let mut writer = writer(PixelFormat::U8, 1, 0);
+        writer.set_font(Box::leak(Box::new(font)));
+        writer.print("\x1b[0;97;40mH\u{2610}");
+        let lit = |column| cell_pixels(&writer, column).iter().filter(|&&gray| gray > 0).count();
+        let bits = |glyph: &[u8]| glyph.iter().map(|row| row.count_ones() as usize).sum::<usize>();
+        assert_eq!(lit(0), bits(&psf::tests::H));
+        assert_eq!(lit(1), 2 * 8 + 2 * 6);
+    }
+
+    /// Returns a writer that draws in the PSF fixture font of [psf::tests::mapped_font], whose
+    /// pixels are either covered or not, with the cursor hidden.
+    fn psf_writer() -> FrameBufferWriter {
+        let font = psf::Psf2Font::parse(psf::tests::mapped_font()).unwrap();
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_font(Box::leak(Box::new(font)));
+        writer.set_cursor_visible(false);
+        writer
+    }
+
+    /// Returns the pixels of the given grid cells, one string per pixel row with the char that
+    /// `key` picks for each pixel.
+    fn cell_art(
+        writer: &FrameBufferWriter,
+        rows: core::ops::Range<usize>,
+        columns: core::ops::Range<usize>,
+        key: impl Fn(Color) -> char,
+    ) -> Vec<String> {
+        let (x, y) = writer.cell_origin(columns.start, rows.start);
+        let width = columns.len() * writer.base_cell_width();
+        let pixel = |dx, dy| key(writer.get_pixel(x + dx, y + dy).unwrap());
+        let row = |dy| (0..width).map(|dx| pixel(dx, dy)).collect();
+        (0..rows.len() * writer.base_line_height()).map(row).collect()
+    }
+
+    /// Returns the text of every row of the text area, without trailing blanks.
+    fn screen_text(writer: &FrameBufferWriter) -> Vec<String> {
+        let rows = writer.get_text_region(Rect::new(0, 0, usize::MAX, usize::MAX));
+        rows.map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn shadows_and_outlines_are_drawn_in_the_effect_color() {
+        let key = |color| match color {
+            Color::WHITE => '#',
+            Color::RED => 'x',
+            Color::BLACK => '.',
+            _ => '?',
+        };
+        let mut writer = psf_writer();
+        writer.set_style(Style { effect_color: Color::RED, ..Style::DEFAULT });
+        writer.print("\\sH");
+        let shadow = [
+            ".#....#.", ".#x...#x", ".#x...#x", ".######x", ".#xxxx#x", ".#x...#x", "..x....x",
+            "........", "........",
+        ];
+        assert_eq!(cell_art(&writer, 0..1, 0..1, key), shadow);
+        writer.print("\\o H");
+        let outline = [
+            "x#x..x#x", "x#x..x#x", "x#xxxx#x", "x######x", "x#xxxx#x", "x#x..x#x", "xxx..xxx",
+            "........", "........",
+        ];
+        let blank = "........";
+        let expected: Vec<_> = outline.iter().map(|row| format!("{blank}{row}{blank}")).collect();
+        assert_eq!(cell_art(&writer, 0..1, 1..4, key), expected);
+    }
+
+    #[test]
+    fn scaled_glyphs_cover_blocks_of_pixels_and_wrap_at_half_the_columns() {
+        let key = |color| if color == Color::WHITE { '#' } else { '.' };
+        let mut writer = psf_writer();
+        writer.print("H");
+        let regular = cell_art(&writer, 0..1, 0..1, key);
+        writer.print("\x1b[H\x1b[2J");
+        writer.set_text_scale(2);
+        writer.print("H");
+        let widen = |row: &String| row.chars().flat_map(|c| [c, c]).collect::<String>();
+        let doubled: Vec<_> = regular.iter().flat_map(|row| [widen(row), widen(row)]).collect();
+        assert_eq!(cell_art(&writer, 0..2, 0..2, key), doubled);
+
+        let columns = writer.columns();
+        assert!(columns.is_multiple_of(2), "{} columns", columns);
+        writer.print(&"H".repeat(columns / 2));
+        let text = screen_text(&writer);
+        // The second row of the first line holds the lower halves of its chars
+        assert_eq!(text[..3], ["H".repeat(columns / 2), String::new(), "H".into()]);
+        assert_eq!(writer.cursor_position(), (1, 1));
+    }
+
+    #[test]
+    fn glyph_edges_blend_with_the_background_of_the_cell() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        let (foreground, background) = (Color::new(255, 255, 85), Color::new(0, 0, 170));
+        writer.print("\x1b[0;93;44m@");
+        let glyph = writer.glyph('@');
+        let mut buffer = [0; font::MAX_BITS_WIDTH];
+        let width = writer.cell_width();
+        let mut expected = vec![background; width * writer.line_height()];
+        let mut edges = 0;
+        for y in 0..glyph.height() {
+            for (x, &coverage) in glyph.row(y, &mut buffer).iter().enumerate() {
+                expected[y * width + x] = background.blend(foreground, coverage);
+                edges += usize::from(coverage > 0 && coverage < 255);
+            }
+        }
+        assert!(edges > 0);
+        assert_eq!(cell_colors(&writer, 0), expected);
+    }
+
+    #[test]
+    fn rows_are_clipped_at_the_edges_of_the_screen() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 3);
+        writer.set_cursor_visible(false);
+        let (width, height) = writer.screen_size();
+        let (right, bottom) = (width as isize, height as isize);
+        let pixels: Vec<_> = (0..10).map(|i| Color::new(i, 1, 2)).collect();
+        writer.write_row(-3, 0, &pixels);
+        writer.write_row(right - 4, bottom - 1, &pixels);
+        let row = |writer: &FrameBufferWriter, y| {
+            (0..width).map(|x| writer.get_pixel(x, y).unwrap()).collect::<Vec<_>>()
+        };
+        let mut first = vec![Color::BLACK; width];
+        first[..7].copy_from_slice(&pixels[3..]);
+        let mut last = vec![Color::BLACK; width];
+        last[width - 4..].copy_from_slice(&pixels[..4]);
+        assert_eq!(row(&writer, 0), first);
+        assert_eq!(row(&writer, height - 1), last);
+        // Spans that miss the screen change nothing, not even the padding of the rows
+        let before = writer.framebuffer.to_vec();
+        let misses = [(0, -1), (0, bottom), (-10, 5), (right, 5), (isize::MIN, 5), (isize::MAX, 5)];
+        for (x, y) in misses {
+            writer.write_row(x, y, &pixels);
+        }
+        assert_eq!(*writer.framebuffer, *before);
+        assert!(!writer.is_poisoned());
+    }
+
+    #[test]
+    fn drawing_stops_at_every_edge_of_the_clip_rectangle() {
+        let mut writer = writer(PixelFormat::Rgb, 3, 0);
+        writer.set_cursor_visible(false);
+        let clip = Rect::new(10, 20, 50, 60);
+        writer.set_clip_rect(Some(clip));
+        let (width, height) = writer.screen_size();
+        writer.fill_rect(-5, -5, width + 10, height + 10, Color::RED);
+        for y in 0..height {
+            for x in 0..width {
+                let inside = clip.contains(x, y);
+                let expected = if inside { Color::RED } else { Color::BLACK };
+                assert_eq!(writer.get_pixel(x, y), Some(expected), "({}, {})", x, y);
+            }
+        }
+        // Text wraps at the right edge and starts inside of the left one
+        writer.print("\x1b[0;97;41m");
+        writer.print(&"H".repeat(writer.columns() + 1));
+        assert_eq!(writer.cursor_position(), (1, 1));
+        assert_eq!(writer.get_pixel(clip.x - 1, clip.y + 1), Some(Color::BLACK));
+        writer.set_clip_rect(None);
+        writer.fill_rect(0, 0, 1, 1, Color::GREEN);
+        assert_eq!(writer.get_pixel(0, 0), Some(Color::GREEN));
+    }
+
+    #[test]
+    fn overwriting_a_glyph_leaves_no_pixels_of_it() {
+        let mut eight = writer(PixelFormat::Rgb, 4, 0);
+        eight.set_cursor_visible(false);
+        eight.print("8");
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.print("W");
+        let w = cell_colors(&writer, 0);
+        writer.print("\r8");
+        assert_eq!(cell_colors(&writer, 0), cell_colors(&eight, 0));
+        // The W lights pixels that the 8 leaves dark
+        let dark = cell_colors(&eight, 0).into_iter().map(|pixel| pixel == Color::BLACK);
+        assert!(w.iter().zip(dark).any(|(&pixel, dark)| dark && pixel != Color::BLACK));
+    }
+
+    #[test]
+    fn insert_mode_shifts_the_rest_of_the_row() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.print("abcdef\x1b[1;3H\x1b[4hXY\x1b[4lZ");
+        assert_eq!(screen_text(&writer)[0], "abXYZdef");
+        let mut expected = self::writer(PixelFormat::Rgb, 4, 0);
+        expected.set_cursor_visible(false);
+        expected.print("abXYZdef");
+        assert_eq!(*writer.framebuffer, *expected.framebuffer);
+        // Cells pushed past the right edge are dropped instead of wrapping
+        let line: String = ('a'..='z').cycle().take(writer.columns()).collect();
+        writer.print(&format!("\x1b[2;1H{}\x1b[2;1H\x1b[4h-", line));
+        let text = screen_text(&writer);
+        assert_eq!(text[1], format!("-{}", &line[..line.len() - 1]));
+        assert_eq!(text[2], "");
+    }
+
+    #[test]
+    fn vga_text_mode_centers_the_largest_grid_up_to_80_by_25() {
+        // 80×25 cells of 11×23 pixels fit into 1920×1080 but not into 640×480
+        for (size, grid) in [((1920, 1080), (80, 25)), ((640, 480), (58, 20))] {
+            let mut writer = sized_writer(PixelFormat::Bgr, 4, size, 0);
+            writer.set_cursor_visible(false);
+            writer.print("\x1b[44m");
+            writer.set_vga_text_mode(true);
+            let (cell_width, line_height) = (writer.base_cell_width(), writer.base_line_height());
+            let (columns, rows) = (writer.columns(), writer.rows());
+            assert_eq!((columns, rows), grid);
+            let area = writer.text_area();
+            let grid_size = (1 + columns * cell_width, 1 + rows * line_height);
+            assert_eq!((area.width, area.height), grid_size);
+            assert_eq!((area.x, area.y), ((size.0 - area.width) / 2, (size.1 - area.height) / 2));
+            // Either the grid is 80×25, or one more cell wouldn't fit
+            assert!(columns == 80 || area.width + cell_width > size.0, "{:?}", size);
+            assert!(rows == 25 || area.height + line_height > size.1, "{:?}", size);
+            let blue = Some(Color::new(0, 0, 170));
+            let (right, bottom) = (area.right() - 1, area.bottom() - 1);
+            assert_eq!(writer.get_pixel(area.x, area.y), blue);
+            assert_eq!(writer.get_pixel(right, bottom), blue);
+            // At 640×480, the 58 columns leave no room on the left
+            let letterbox = [
+                area.x.checked_sub(1).map(|x| (x, area.y)),
+                area.y.checked_sub(1).map(|y| (area.x, y)),
+                Some((right + 1, bottom)),
+                Some((right, bottom + 1)),
+            ];
+            for (x, y) in letterbox.into_iter().flatten() {
+                assert_eq!(writer.get_pixel(x, y), Some(Color::BLACK), "{:?}", size);
+            }
+        }
+    }
+
+    #[test]
+    fn force_writer_follows_the_rotation_and_hidpi_scale_of_the_global_writer() {
+        let _global = WRITER.lock();
+        let mut global = writer(PixelFormat::Rgb, 4, 0);
+        let info = global.info;
+        let framebuffer = Vec::leak(vec![0u8; info.byte_len]);
+        let (start, len) = (framebuffer.as_mut_ptr() as usize, framebuffer.len());
+        let (rotation, hidpi_scale) = (Rotation::None, 1);
+        record_raw_framebuffer(RawFrameBuffer { start, len, info, rotation, hidpi_scale });
+        global.global = true;
+        global.set_rotation(Rotation::Cw90);
+        global.set_hidpi_scale(2);
+        // Writers other than the global one leave the record alone
+        writer(PixelFormat::Rgb, 4, 0).set_rotation(Rotation::Cw180);
+
+        // SAFETY: the framebuffer is only used by the forced writer
+        let forced = unsafe { force_writer() }.unwrap();
+        assert_eq!((forced.rotation(), forced.hidpi_scale()), (Rotation::Cw90, 2));
+        assert_eq!(forced.screen_size(), (60, 100));
+        assert_eq!(forced.framebuffer.as_ptr() as usize, start);
+    }
+
+    #[test]
+    fn colored_values_nest_with_their_padding_and_restore_the_outer_color() {
+        use colored::{green, red};
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        let outer = writer.style().foreground;
+        write!(writer, "{}!", red(format_args!("<{:>3}|{:<3}>", green("a"), green('b')))).unwrap();
+        let row = &writer.grid.row(0).unwrap()[..10];
+        let text: String = row.iter().map(|cell| cell.c).collect();
+        assert_eq!(text, "<  a|b  >!");
+        // The padding is part of the value, so it is in the inner color
+        let (r, g) = (Color::RED, Color::GREEN);
+        let expected = [r, g, g, g, r, g, g, g, r, outer];
+        let colors: Vec<_> = row.iter().map(|cell| cell.style.foreground).collect();
+        assert_eq!(colors, expected);
+        assert_eq!(writer.style().foreground, outer);
+    }
+
+    #[test]
+    fn pushed_styles_pop_in_reverse_order_and_an_empty_pop_restores_the_default() {
+        // The styles at each level, as set by SGR alone
+        let mut fresh = writer(PixelFormat::Rgb, 4, 0);
+        let mut sgr = |sequence| {
+            fresh.print(sequence);
+            fresh.style()
+        };
+        let expected = [
+            sgr("\x1b[31;32;1;34;4m"),
+            sgr("\x1b[0;31;32;1m"),
+            sgr("\x1b[0;31m"),
+            Style::DEFAULT,
+        ];
+
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print("\x1b[31m\x1b[#{\x1b[32;1m\x1b[#{\x1b[34;4m\x1b[#{\x1b[7m");
+        let reverse = writer.style();
+        assert!(reverse.reverse && reverse.underline && reverse.bold);
+        let mut popped = Vec::new();
+        for _ in 0..4 {
+            writer.print("\x1b[#}");
+            popped.push(writer.style());
+        }
+        assert_eq!(popped, expected);
+    }
+
+    #[test]
+    fn sgr_0_resets_every_attribute_and_both_colors() {
+        for attributes in ["1", "4", "7", "5", "2", "1;4;7;5"] {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_cursor_visible(false);
+            writer.print(&format!("\x1b[{};31;44m\x1b[0m", attributes));
+            assert_eq!(writer.style(), Style::DEFAULT, "{}", attributes);
+            assert_eq!(writer.style().colors(), (Color::WHITE, Color::BLACK));
+            // Text after the reset is drawn in the default colors only
+            writer.print("\u{2588} ");
+            let colors = [cell_colors(&writer, 0), cell_colors(&writer, 1)].concat();
+            assert!(colors.contains(&Color::WHITE) && colors.contains(&Color::BLACK));
+            let default = [Color::WHITE, Color::BLACK];
+            assert!(colors.iter().all(|color| default.contains(color)), "{}", attributes);
+        }
+    }
+
+    #[test]
+    fn wrapped_lines_keep_their_number() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_line_numbers(true);
+        // 11 cells are left for the text after the prefixes of the 18 columns, so the second
+        // line fills its row exactly
+        writer.print("abcdefghijklmnopqrstuvwxyz\nabcdefghijk\nz");
+        let expected = [
+            "0001 | abcdefghijk",
+            "       lmnopqrstuv",
+            "       wxyz",
+            "0002 | abcdefghijk",
+            "0003 | z",
+        ];
+        assert_eq!(screen_text(&writer), expected);
+    }
+
+    /// Returns the colors of all pixels of the screen, row by row.
+    fn screen_pixels(writer: &FrameBufferWriter) -> Vec<Option<Color>> {
+        let (width, height) = writer.screen_size();
+        (0..height).flat_map(|y| (0..width).map(move |x| writer.get_pixel(x, y))).collect()
+    }
+
+    #[test]
+    fn the_grid_and_the_cursor_are_redrawn_over_raw_drawing() {
+        let mut expected = writer(PixelFormat::Rgb, 4, 0);
+        expected.print("\x1b[32mHi\nthere\x1b[0m ok");
+        let expected = screen_pixels(&expected);
+
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print("\x1b[32mHi\nthere\x1b[0m");
+        writer.with_raw(|framebuffer, _| framebuffer.fill(0xff));
+        writer.redraw();
+        // The cursor continues where the text ended
+        writer.print(" ok");
+        assert_eq!(screen_pixels(&writer), expected);
+    }
+
+    #[test]
+    fn raw_regions_only_reach_their_rectangle_and_are_redrawn_over() {
+        let mut expected = writer(PixelFormat::Rgb, 4, 0);
+        expected.print("\x1b[32mHi\nthere\x1b[0m ok");
+        let expected = screen_pixels(&expected);
+
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print("\x1b[32mHi\nthere\x1b[0m");
+        let before = screen_pixels(&writer);
+        let rect = Rect::new(5, 10, 30, 20);
+        writer.with_raw_region(rect, |mut region| {
+            let mut y = 0;
+            while let Some(row) = region.row_mut(y) {
+                row.fill(0xff);
+                y += 1;
+            }
+            assert_eq!(y, 20);
+        });
+        let width = writer.screen_size().0;
+        for (i, (after, before)) in screen_pixels(&writer).iter().zip(&before).enumerate() {
+            let (x, y) = (i % width, i / width);
+            let white = Some(Color::WHITE);
+            assert_eq!(*after, if rect.contains(x, y) { white } else { *before }, "{x} {y}");
+        }
+        writer.redraw();
+        writer.print(" ok");
+        assert_eq!(screen_pixels(&writer), expected);
+    }
+
+    /// Returns the text of the rows that the view shows, from the scrollback or the grid.
+    fn shown_text(writer: &FrameBufferWriter) -> Vec<String> {
+        let rows = (0..writer.rows()).map_while(|row| writer.shown_row(row));
+        rows.map(|cells| TextRow::new(cells).to_string()).collect()
+    }
+
+    #[test]
+    fn the_view_pages_through_the_scrollback_within_its_bounds() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        let lines: Vec<_> = (1..=12).map(|n| n.to_string()).collect();
+        writer.print(&lines.join("\n"));
+        // 5 rows show lines 8 to 12, the other 7 are in the scrollback
+        let shown = |first: usize| (first..first + 5).map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(shown_text(&writer), shown(8));
+        writer.scroll_view_up(4);
+        assert_eq!(shown_text(&writer), shown(4));
+        // The view stops at the oldest line
+        writer.scroll_view_up(4);
+        assert_eq!(shown_text(&writer), shown(1));
+        writer.scroll_view_up(4);
+        assert_eq!(shown_text(&writer), shown(1));
+
+        // Output meanwhile doesn't move the view
+        writer.print("\n13");
+        assert_eq!(shown_text(&writer), shown(1));
+        assert!(writer.is_viewing_scrollback());
+        writer.scroll_view_down(4);
+        assert_eq!(shown_text(&writer), shown(5));
+        // The view stops at the live text, which includes the new output
+        writer.scroll_view_down(100);
+        assert_eq!(shown_text(&writer), shown(9));
+        assert!(!writer.is_viewing_scrollback());
+        writer.view_oldest();
+        assert_eq!(shown_text(&writer), shown(1));
+        writer.view_live();
+        assert_eq!(shown_text(&writer), shown(9));
+    }
+
+    #[test]
+    fn stats_count_the_work_of_the_writer() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        // Creating the writer cleared the screen
+        assert_eq!(writer.stats(), Stats { clears: 1, ..Stats::default() });
+        writer.reset_stats();
+        // An escape or a non-ASCII char aborts a sequence
+        writer.print("ab\ncd\x1b[31m\x1b[3\x1b[0m\x1b[3é");
+        // The 22 chars on the second row wrap once at 18 columns, and from the third row, 4 more
+        // rows scroll twice
+        writer.print(&"y".repeat(20));
+        writer.print("\n\n\n\n");
+        writer.clear();
+        let expected = Stats {
+            chars_written: 24,
+            lines: 5,
+            soft_wraps: 1,
+            scrolls: 2,
+            redrawn_scrolls: 2,
+            clears: 1,
+            escape_sequences: 2,
+            malformed_sequences: 2,
+            ..Stats::default()
+        };
+        assert_eq!(writer.stats(), expected);
+
+        // Scrolls that move the pixels count them instead
+        writer.set_scroll_redraw(false);
+        writer.reset_stats();
+        writer.print("\n\n\n\n\n");
+        let stats = writer.stats();
+        // The pixel rows of the grid below its first row move up
+        let moved = (writer.rows() - 1) * writer.line_height() * 200 * 4;
+        assert_eq!((stats.scrolls, stats.redrawn_scrolls), (1, 0));
+        assert_eq!(stats.scrolled_bytes, moved as u64);
+    }
+
+    #[test]
+    fn narrow_and_wide_glyphs_advance_by_the_same_cell() {
+        let end_of = |text: &str| {
+            let mut writer = sized_writer(PixelFormat::Rgb, 4, (1000, 60), 0);
+            writer.write_str(text).unwrap();
+            (writer.cursor_position(), writer.x_pos)
+        };
+        let narrow = end_of(&"i".repeat(80));
+        assert_eq!(narrow.0, (80, 0));
+        assert_eq!(narrow, end_of(&"W".repeat(80)));
+    }
+
+    std::thread_local! {
+        /// Replies that [capture_response] received on this thread.
+        static RESPONSES: RefCell<String> = const { RefCell::new(String::new()) };
+    }
+
+    /// A response sink that collects the replies for [take_responses].
+    fn capture_response(response: &str) {
+        RESPONSES.with_borrow_mut(|responses| responses.push_str(response));
+    }
+
+    /// Returns the replies received since the last call.
+    fn take_responses() -> String {
+        RESPONSES.take()
+    }
+
+    #[test]
+    fn status_reports_reply_with_the_one_based_cursor_position() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.write_str("\x1b[6n").unwrap();
+        writer.set_response_sink(Some(capture_response));
+        writer.write_str("\x1b[5n\x1b[6n").unwrap();
+        assert_eq!(take_responses(), "\x1b[0n\x1b[1;1R");
+        writer.write_str("ab\ncdef\x1b[6n").unwrap();
+        assert_eq!(take_responses(), "\x1b[2;5R");
+        writer.write_str("\x1b[5;18H\x1b[6n").unwrap();
+        assert_eq!(take_responses(), "\x1b[5;18R");
+        writer.set_response_sink(None);
+        writer.write_str("\x1b[6n").unwrap();
+        assert_eq!(take_responses(), "");
+    }
+
+    #[test]
+    fn osc_0_and_2_set_the_title_with_either_terminator() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.write_str("\x1b]0;first\x07").unwrap();
+        assert_eq!(writer.title(), "first");
+        writer.write_str("\x1b]2;second\x1b\\").unwrap();
+        assert_eq!(writer.title(), "second");
+        // Other commands and aborted strings leave the title alone
+        writer.write_str("\x1b]1;icon\x07\x1b]0;cut\n").unwrap();
+        assert_eq!(writer.title(), "second");
+        let long = "é".repeat(TITLE_CAPACITY);
+        writer.write_str(&format!("\x1b]0;{}\x07", long)).unwrap();
+        assert_eq!(writer.title(), &long[..TITLE_CAPACITY]);
+        assert!(screen_text(&writer).iter().all(|line| line.trim().is_empty()));
+    }
+
+    #[test]
+    fn crlf_starts_a_single_line_and_a_bare_cr_returns_to_its_start() {
+        let numbered = |chunks: &[&str]| {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_cursor_visible(false);
+            writer.set_line_numbers(true);
+            for chunk in chunks {
+                writer.write_str(chunk).unwrap();
+            }
+            screen_text(&writer)[..3].to_vec()
+        };
+        let two_lines = ["0001 | ab", "0002 | cd", ""];
+        assert_eq!(numbered(&["ab\r\ncd"]), two_lines);
+        assert_eq!(numbered(&["ab\r", "\ncd"]), two_lines);
+        assert_eq!(numbered(&["ab\n\rcd"]), two_lines);
+        assert_eq!(numbered(&["ab\r\n\r\ncd"]), ["0001 | ab", "0002 |", "0003 | cd"]);
+        assert_eq!(numbered(&["abc\rX"]), ["0001 | Xbc", "", ""]);
+        assert_eq!(numbered(&["abc\r", "X"]), ["0001 | Xbc", "", ""]);
+    }
+
+    #[test]
+    fn a_trailing_cr_moves_the_cursor_before_the_next_write() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_response_sink(Some(capture_response));
+        writer.write_str("abc\r").unwrap();
+        assert_eq!(writer.cursor_position(), (0, 0));
+        writer.write_str("\x1b[6n").unwrap();
+        assert_eq!(take_responses(), "\x1b[1;1R");
+        writer.write_str("\n\x1b[6n").unwrap();
+        assert_eq!(take_responses(), "\x1b[2;1R");
+    }
+
+    #[test]
+    fn bytes_written_one_at_a_time_show_the_same_text_as_a_str() {
+        let text = "héllo €\n\x1b[31m😀x";
+        let mut whole = writer(PixelFormat::Rgb, 4, 0);
+        whole.write_str(text).unwrap();
+        let mut bytewise = writer(PixelFormat::Rgb, 4, 0);
+        for byte in text.bytes() {
+            bytewise.write_bytes(&[byte]);
+        }
+        assert_eq!(screen_text(&bytewise), screen_text(&whole));
+        assert_eq!(screen_pixels(&bytewise), screen_pixels(&whole));
+        let mut invalid = writer(PixelFormat::Rgb, 4, 0);
+        for byte in b"a\xffb\xe2\x82c" {
+            invalid.write_bytes(&[*byte]);
+        }
+        assert_eq!(screen_text(&invalid)[0], "a\u{fffd}b\u{fffd}c");
+    }
+
+    #[test]
+    fn combining_marks_and_joiners_take_no_cell() {
+        let written = |text: &str| {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_cursor_visible(false);
+            writer.write_str(text).unwrap();
+            writer
+        };
+        let plain = written("ex");
+        let accented = written("e\u{301}x");
+        assert_eq!(accented.cursor_position(), (2, 0));
+        assert_eq!(screen_text(&accented)[0], "ex");
+        // The font has no glyph for the accent, which leaves the e alone instead of drawing a
+        // replacement box over it
+        assert_eq!(screen_pixels(&accented), screen_pixels(&plain));
+        let joined = written("e\u{200d}x\u{feff}");
+        assert_eq!(joined.cursor_position(), (2, 0));
+        assert_eq!(screen_pixels(&joined), screen_pixels(&plain));
+        // A mark without a char before it on the line is dropped
+        let lone = written("\u{301}ex");
+        assert_eq!(screen_pixels(&lone), screen_pixels(&plain));
+    }
+
+    #[test]
+    fn wide_chars_take_two_cells_and_wrap_as_a_whole() {
+        let written = |text: &str| {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_cursor_visible(false);
+            writer.write_str(text).unwrap();
+            writer
+        };
+        let wide = written("中x");
+        assert_eq!(wide.cursor_position(), (3, 0));
+        let cells: Vec<_> = wide.grid.row(0).unwrap()[..3].iter().map(|cell| cell.c).collect();
+        assert_eq!(cells, ['中', Cell::CONTINUATION, 'x']);
+        assert_eq!(screen_text(&wide)[0], "中x");
+        // With one cell left on the line, the char moves to the next one
+        let wrapped = written(&format!("{}中", "a".repeat(17)));
+        assert_eq!(screen_text(&wrapped)[..2], ["a".repeat(17), "中".to_owned()]);
+        assert_eq!(wrapped.cursor_position(), (2, 1));
+    }
+
+    #[test]
+    fn backspace_steps_over_both_cells_of_a_wide_char() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.write_str("a中\x08").unwrap();
+        assert_eq!(writer.cursor_position(), (1, 0));
+        // Overwriting the first half blanks the second one as well
+        writer.write_str("b").unwrap();
+        assert_eq!(writer.cursor_position(), (2, 0));
+        let mut expected = sized_writer(PixelFormat::Rgb, 4, (200, 120), 0);
+        expected.set_cursor_visible(false);
+        expected.write_str("ab").unwrap();
+        assert_eq!(screen_text(&writer), screen_text(&expected));
+        assert_eq!(screen_pixels(&writer), screen_pixels(&expected));
+    }
+
+    /// Collapses runs of equal rows of [cell_art] into one row each, followed by `×` and the
+    /// length of the run.
+    fn collapsed(art: Vec<String>) -> Vec<String> {
+        let mut rows: Vec<(String, usize)> = Vec::new();
+        for row in art {
+            match rows.last_mut() {
+                Some((last, count)) if *last == row => *count += 1,
+                _ => rows.push((row, 1)),
+            }
+        }
+        rows.into_iter().map(|(row, count)| format!("{} ×{}", row, count)).collect()
+    }
+
+    #[test]
+    fn box_drawing_and_block_chars_are_synthesized_to_fill_their_cells() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.write_str("┌─┐\n│ │\n└─┘\n█▊▌▎▂").unwrap();
+        let key = |color: Color| match color.r {
+            0 => '.',
+            255 => '#',
+            _ => '?',
+        };
+        // The lines of neighboring cells meet at their edges
+        let frame = [
+            "................................. ×11",
+            ".....#######################..... ×1",
+            ".....#.....................#..... ×45",
+            ".....#######################..... ×1",
+            "................................. ×11",
+        ];
+        assert_eq!(collapsed(cell_art(&writer, 0..3, 0..3, key)), frame);
+        // Blocks of the full, three quarters, half and a quarter of the width, and a lower quarter
+        let bar = [
+            "###################...######.....###................... ×17",
+            "###################...######.....###........########### ×6",
+        ];
+        assert_eq!(collapsed(cell_art(&writer, 3..4, 0..5, key)), bar);
+    }
+
+    #[test]
+    fn registered_glyphs_are_drawn_pixel_for_pixel() {
+        let checkerboard = (0..CELL_HEIGHT).flat_map(|y| (0..CELL_WIDTH).map(move |x| (x, y)));
+        let coverage = checkerboard.map(|(x, y)| [0, 255][(x + y) % 2]).collect::<Vec<u8>>();
+        let coverage = Vec::leak(coverage);
+        glyph::register_glyph('\u{e000}', CELL_WIDTH, CELL_HEIGHT, coverage).unwrap();
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.write_str("\u{e000}").unwrap();
+        let (foreground, background) = writer.style.colors();
+        let expected = coverage.iter().map(|&c| [background, foreground][c as usize / 255]);
+        assert_eq!(cell_colors(&writer, 0), expected.collect::<Vec<_>>());
+        let error = glyph::GlyphError::InvalidDimensions { width: 3, height: 3, len: 8 };
+        assert_eq!(glyph::register_glyph('\u{e001}', 3, 3, &[0; 8]), Err(error));
+    }
+
+    #[test]
+    fn scroll_sequences_shift_the_text_and_leave_the_cursor() {
+        let shown = |text: &str| {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_cursor_visible(false);
+            writer.write_str(text).unwrap();
+            writer
+        };
+        let mut scrolled = shown("1\n2\n3\n4\n5");
+        scrolled.write_str("\x1b[2S").unwrap();
+        assert_eq!(screen_text(&scrolled), ["3", "4", "5", "", ""]);
+        assert_eq!(screen_pixels(&scrolled), screen_pixels(&shown("3\n4\n5")));
+        assert_eq!(scrolled.cursor_position(), (1, 4));
+        scrolled.write_str("\x1b[T").unwrap();
+        assert_eq!(screen_text(&scrolled), ["", "3", "4", "5", ""]);
+        assert_eq!(screen_pixels(&scrolled), screen_pixels(&shown("\n3\n4\n5")));
+        // Scrolling by more than the screen blanks all of it
+        scrolled.write_str("\x1b[9S").unwrap();
+        assert_eq!(screen_pixels(&scrolled), screen_pixels(&shown("")));
+    }
+
+    #[test]
+    fn rotated_output_lands_on_the_turned_framebuffer() {
+        // Every char stands for the top left pixel of a block of 10×10 physical pixels
+        let physical_art = |writer: &FrameBufferWriter| -> Vec<String> {
+            let (width, height) = (writer.info.width, writer.info.height);
+            let key = |x: usize, y: usize| match writer.framebuffer[(y * width + x) * 4..][..3] {
+                [170, 0, 0] => 'R',
+                [0, 170, 0] => 'G',
+                _ => '.',
+            };
+            let row = |y| (0..width).step_by(10).map(|x| key(x, y)).collect();
+            (0..height).step_by(10).map(row).collect()
+        };
+        let goldens = [
+            (Rotation::None, ["RR....", "......", "......", ".....G"]),
+            (Rotation::Cw90, [".....R", ".....R", "......", "G....."]),
+            (Rotation::Cw180, ["G.....", "......", "......", "....RR"]),
+            (Rotation::Cw270, [".....G", "......", "R.....", "R....."]),
+        ];
+        for (rotation, golden) in goldens {
+            let mut writer = sized_writer(PixelFormat::Rgb, 4, (60, 40), 0);
+            writer.set_cursor_visible(false);
+            writer.set_rotation(rotation);
+            // A wide block at the top left and a square one at the bottom right, logically
+            let (width, height) = (writer.width() as isize, writer.height() as isize);
+            writer.fill_rect(0, 0, 20, 10, Color::RED);
+            writer.fill_rect(width - 10, height - 10, 10, 10, Color::GREEN);
+            assert_eq!(physical_art(&writer), golden, "{:?}", rotation);
+        }
+    }
+
+    #[test]
+    fn rotated_text_reads_the_same_in_logical_pixels() {
+        let glyph = |rotation| {
+            let mut writer = sized_writer(PixelFormat::Rgb, 4, (60, 40), 0);
+            writer.set_cursor_visible(false);
+            writer.set_rotation(rotation);
+            writer.write_str("F").unwrap();
+            cell_pixels(&writer, 0)
+        };
+        let upright = glyph(Rotation::None);
+        assert!(upright.contains(&255));
+        for rotation in [Rotation::Cw90, Rotation::Cw180, Rotation::Cw270] {
+            assert_eq!(glyph(rotation), upright, "{:?}", rotation);
+        }
+    }
+
+    #[test]
+    fn raw_mode_shows_controls_in_caret_notation_and_keeps_backslash_codes() {
+        let mut plain = writer(PixelFormat::Rgb, 4, 0);
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.set_raw_mode(true);
+        writer.write_str("a\x1b[31mb\tc\x7f\nd\u{85}\0").unwrap();
+        writer.print("\\c\\s\\r!");
+        assert_eq!(screen_text(&writer), ["a^[[31mb^Ic^?", "d\u{fffd}^@\\c\\s\\r!", "", "", ""]);
+        // Nothing was interpreted: the same chars written plainly give the same pixels
+        plain.set_cursor_visible(false);
+        plain.write_str("a^[[31mb^Ic^?\nd\u{fffd}^@\\c\\s\\r!").unwrap();
+        assert_eq!(screen_pixels(&writer), screen_pixels(&plain));
+    }
+
+    #[test]
+    fn raw_mode_drops_a_partial_sequence_and_print_raw_restores_the_mode() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.write_str("\x1b[3").unwrap();
+        writer.set_raw_mode(true);
+        writer.write_str("1mx").unwrap();
+        writer.set_raw_mode(false);
+        writer.print_raw("\x1b[2J");
+        assert!(!writer.raw_mode());
+        writer.write_str("\x1b[1m").unwrap();
+        assert_eq!(screen_text(&writer), ["1mx^[[2J", "", "", "", ""]);
+    }
+
+    #[test]
+    fn a_smooth_scroll_takes_one_frame_per_step_and_ends_like_a_plain_one() {
+        let text = "1\n2\n3\n4\n5\n6";
+        let mut plain = writer(PixelFormat::Rgb, 4, 0);
+        plain.set_cursor_visible(false);
+        plain.print(text);
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.set_smooth_scroll(true);
+        writer.print(text);
+        let line_height = writer.base_line_height();
+        assert_eq!(writer.scroll_lag, line_height);
+        let steps = line_height.div_ceil(SMOOTH_SCROLL_STEP);
+
+        let mut frames = Vec::new();
+        let mut now = 0;
+        while writer.scroll_lag > 0 {
+            // A tick before the interval is up leaves the scroll where it is
+            writer.tick(now + SMOOTH_SCROLL_INTERVAL_MS - 1);
+            assert_eq!(writer.scroll_lag, line_height - frames.len() * SMOOTH_SCROLL_STEP);
+            now += SMOOTH_SCROLL_INTERVAL_MS;
+            writer.tick(now);
+            frames.push(screen_pixels(&writer));
+            assert!(frames.len() <= steps, "the scroll didn't finish");
+        }
+        assert_eq!(frames.len(), steps);
+        assert_eq!(frames.last(), Some(&screen_pixels(&plain)));
+        assert!(frames.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn a_poisoned_writer_keeps_the_text_and_recover_shows_it_again() {
+        let text = ["before\n", "\x1b[31mred\x1b[0m\n", "1\n2\n3\n4\nlast"];
+        let mut expected = writer(PixelFormat::Rgb, 4, 0);
+        expected.print(&text.concat());
+
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print(text[0]);
+        writer.poison();
+        let frozen = writer.framebuffer.to_vec();
+        // Text, scrolls and fills all leave the framebuffer alone
+        writer.print(text[1]);
+        writer.fill_rect(0, 0, 50, 50, Color::GREEN);
+        writer.print(text[2]);
+        writer.tick(0);
+        assert_eq!(*writer.framebuffer, *frozen);
+        assert_eq!(screen_text(&writer), screen_text(&expected));
+
+        // A geometry that is still wrong keeps the writer poisoned
+        writer.info.stride = writer.info.width - 1;
+        assert!(matches!(writer.recover(), Err(FrameBufferError::StrideTooSmall { .. })));
+        assert!(writer.is_poisoned());
+        assert_eq!(*writer.framebuffer, *frozen);
+
+        writer.info.stride = writer.info.width;
+        writer.recover().unwrap();
+        assert!(!writer.is_poisoned());
+        assert_eq!(screen_pixels(&writer), screen_pixels(&expected));
+    }
+
+    #[test]
+    fn chars_drawn_across_the_edges_keep_only_their_visible_pixels() {
+        let style = Style { background: Color::BLUE, ..Style::DEFAULT };
+        let new_writer = || {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_cursor_visible(false);
+            writer
+        };
+        let mut writer = new_writer();
+        let (width, height) = writer.screen_size();
+        let (cell_width, line_height) = (writer.cell_width(), writer.line_height());
+        writer.draw_char_at(0, 0, 'W', style);
+        let cell: Vec<_> = (0..line_height)
+            .flat_map(|y| (0..cell_width).map(move |x| (x, y)))
+            .map(|(x, y)| writer.get_pixel(x, y).unwrap())
+            .collect();
+        // The char shows the part of the cell that overlaps the screen, and nothing else changes
+        let (right, bottom) = ((width - 4) as isize, (height - 6) as isize);
+        for (x, y) in [(-5, -7), (right, -7), (-5, bottom), (right, bottom), (-3, 40)] {
+            let mut writer = new_writer();
+            writer.draw_char_at(x, y, 'W', style);
+            for (px, py) in (0..height).flat_map(|py| (0..width).map(move |px| (px, py))) {
+                let (dx, dy) = (px as isize - x, py as isize - y);
+                let inside = (0..cell_width as isize).contains(&dx)
+                    && (0..line_height as isize).contains(&dy);
+                let expected = match inside {
+                    true => cell[dy as usize * cell_width + dx as usize],
+                    false => Color::BLACK,
+                };
+                assert_eq!(writer.get_pixel(px, py), Some(expected), "({x}, {y}) at ({px}, {py})");
+            }
+        }
+        // Cells that miss the screen don't draw at all
+        let mut writer = new_writer();
+        let before = writer.framebuffer.to_vec();
+        let misses = [
+            (-(cell_width as isize), 0),
+            (0, -(line_height as isize)),
+            (width as isize, 0),
+            (0, height as isize),
+            (isize::MIN, isize::MIN),
+            (isize::MAX, isize::MAX),
+        ];
+        for (x, y) in misses {
+            writer.draw_char_at(x, y, 'W', style);
+        }
+        assert_eq!(*writer.framebuffer, *before);
+        assert!(!writer.is_poisoned());
+    }
+
+    #[test]
+    fn the_pointer_leaves_the_framebuffer_bit_identical_when_it_moves_and_hides() {
+        let text = "\x1b[41mred\x1b[0m \x1b[1mbold\x1b[0m\nmore text";
+        for (format, bytes_per_pixel) in [(PixelFormat::Rgb, 3), (PixelFormat::Bgr, 4)] {
+            let mut writer = writer(format, bytes_per_pixel, 2);
+            writer.print(text);
+            let before = writer.framebuffer.to_vec();
+            let (width, height) = writer.screen_size();
+            writer.show_pointer(3, 4);
+            assert_eq!(writer.get_pixel(3, 4), Some(Color::BLACK));
+            assert_eq!(writer.get_pixel(4, 6), Some(Color::WHITE));
+            assert_ne!(*writer.framebuffer, *before);
+            // Moves overlap the last position, cross the text and get clipped at the edges
+            for (x, y) in [(5, 6), (width - 4, 2), (20, height - 3), (width - 1, height - 1)] {
+                writer.show_pointer(x, y);
+                assert_eq!(writer.pointer_position(), Some((x, y)));
+                assert_eq!(writer.get_pixel(x, y), Some(Color::BLACK));
+            }
+            writer.hide_pointer();
+            assert_eq!(writer.pointer_position(), None);
+            assert!(*writer.framebuffer == *before, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn text_written_below_the_pointer_is_kept_when_it_hides() {
+        let mut expected = writer(PixelFormat::Rgb, 4, 0);
+        expected.print("\x1b[44mcovered\x1b[0m");
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.show_pointer(2, 2);
+        writer.print("\x1b[44mcovered\x1b[0m");
+        // The pointer stays on top of the new text
+        assert_eq!(writer.get_pixel(2, 2), Some(Color::BLACK));
+        writer.hide_pointer();
+        assert!(*writer.framebuffer == *expected.framebuffer);
+    }
+
+    /// Returns a zeroed framebuffer of the given size with 4 byte RGB pixels, for
+    /// [FrameBufferWriter::reinit].
+    fn framebuffer((width, height): (usize, usize)) -> (&'static mut [u8], FrameBufferInfo) {
+        let info = FrameBufferInfo {
+            byte_len: width * height * 4,
+            width,
+            height,
+            pixel_format: PixelFormat::Rgb,
+            bytes_per_pixel: 4,
+            stride: width,
+        };
+        (Vec::leak(vec![0; info.byte_len]), info)
+    }
+
+    #[test]
+    fn reinit_to_a_narrower_screen_wraps_the_lines_again_and_keeps_the_cursor_after_them() {
+        let long = "0123456789abcdefghijklmnopqrst";
+        let mut writer = sized_writer(PixelFormat::Rgb, 4, (400, 120), 0);
+        writer.print(&std::format!("short\n{long}"));
+        assert_eq!(writer.cursor_cell(), (30, 1));
+        let (framebuffer, info) = framebuffer((200, 120));
+        writer.reinit(framebuffer, info).unwrap();
+        assert_eq!((writer.columns(), writer.rows()), (18, 5));
+        assert_eq!(screen_text(&writer), ["short", &long[..18], &long[18..], "", ""]);
+        assert_eq!(writer.cursor_cell(), (12, 2));
+        // The text continues at the cursor
+        writer.print("!");
+        assert_eq!(screen_text(&writer)[2], std::format!("{}!", &long[18..]));
+    }
+
+    #[test]
+    fn reinit_to_fewer_rows_keeps_the_last_ones_and_moves_the_others_to_the_scrollback() {
+        let long = "0123456789abcdefghijklmnopqrst";
+        let mut writer = sized_writer(PixelFormat::Rgb, 4, (400, 120), 0);
+        writer.print(&std::format!("short\n{long}\nend"));
+        let (framebuffer, info) = framebuffer((200, 72));
+        writer.reinit(framebuffer, info).unwrap();
+        assert_eq!(writer.rows(), 3);
+        assert_eq!(screen_text(&writer), [&long[..18], &long[18..], "end"]);
+        assert_eq!(writer.cursor_cell(), (3, 2));
+        let line = writer.scrollback.line(writer.scrollback.len() - 1).unwrap();
+        assert_eq!(TextRow::new(line).to_string(), "short");
+    }
+
+    #[test]
+    fn status_fields_are_drawn_in_the_smaller_header_font() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.set_header_band(true);
+        let header_cell = cell_size(writer.header_metrics);
+        let text_cell = cell_size(writer.metrics);
+        assert!(header_cell.0 < text_cell.0 && header_cell.1 < text_cell.1);
+        let cells = ['T', 'l'].map(|c| Cell::new(c, Style::DEFAULT));
+        writer.set_status_field(0, &cells);
+        // The band holds one row of the header font, and the text area starts below it
+        let band = writer.header_rect().unwrap();
+        assert_eq!(band.height, BORDER_PADDING + header_cell.1);
+        assert_eq!(writer.text_area().y, band.bottom());
+
+        let key = |color| match color {
+            Color::WHITE => '#',
+            Color::BLACK => '.',
+            _ => '+',
+        };
+        let (width, _) = writer.screen_size();
+        let field = width - 2 * header_cell.0;
+        let row = |y| (field..width).map(|x| key(writer.get_pixel(x, y).unwrap())).collect();
+        let golden = [
+            "################## ×1",
+            ".................. ×2",
+            "..........+++++... ×1",
+            "++++++++..+++#+... ×1",
+            "++++++++....+#+... ×1",
+            "...++.......+#+... ×7",
+            "...++.....++##+++. ×1",
+            ".................. ×5",
+        ];
+        assert_eq!(collapsed((0..band.height).map(row).collect()), golden);
+    }
+
+    /// An 8×8 font with a box for `#`, a diagonal for `\` and no other glyphs, not even a space.
+    struct TestFont;
+
+    impl FontProvider for TestFont {
+        fn metrics(&self, _size: usize) -> Metrics {
+            Metrics { width: 8, height: 8, ascent: 7 }
+        }
+
+        fn glyph(&self, c: char, _size: usize) -> Option<FontGlyph> {
+            const BOX: [u8; 8] = [0xff, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xff];
+            const DIAGONAL: [u8; 8] = [0x80, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01];
+            match c {
+                '#' => Some(FontGlyph::from_bits(&BOX, 8, 8)),
+                '\\' => Some(FontGlyph::from_bits(&DIAGONAL, 8, 8)),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn the_writer_lays_out_and_draws_text_in_the_cells_of_its_font() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.set_font(&TestFont);
+        assert_eq!(cell_size(writer.metrics), (8, 9));
+        assert_eq!((writer.columns(), writer.rows()), (24, 13));
+        // Sizes are raster heights of the font, which only has one
+        assert!(writer.set_font_size(16).is_err());
+        writer.write_str("#\\?#\n\\").unwrap();
+        assert_eq!(writer.cell_origin(1, 1), (BORDER_PADDING + 8, BORDER_PADDING + 9));
+        assert_eq!(screen_text(&writer)[..2], ["#\\?#", "\\"]);
+
+        let key = |color| if color == Color::WHITE { '#' } else { '.' };
+        let golden = [
+            "#########...............######## ×1",
+            "#......#.#..............#......# ×1",
+            "#......#..#.............#......# ×1",
+            "#......#...#............#......# ×1",
+            "#......#....#...........#......# ×1",
+            "#......#.....#..........#......# ×1",
+            "#......#......#.........#......# ×1",
+            "########.......#........######## ×1",
+            "................................ ×1",
+        ];
+        assert_eq!(collapsed(cell_art(&writer, 0..1, 0..4, key)), golden);
+        let diagonal: Vec<_> = (0..8).map(|x| writer.get_pixel(1 + x, 10 + x)).collect();
+        assert_eq!(diagonal, [Some(Color::WHITE); 8]);
+    }
+
+    #[test]
+    fn text_regions_follow_the_text_as_it_scrolls() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        let lines: Vec<_> = (1..=8).map(|n| format!("line {n}")).collect();
+        writer.print(&lines.join("\n"));
+        let text = |writer: &FrameBufferWriter, rect| {
+            writer.get_text_region(rect).map(|row| row.to_string()).collect::<Vec<_>>()
+        };
+        let whole = Rect::new(0, 0, usize::MAX, usize::MAX);
+        assert_eq!(text(&writer, whole), lines[3..]);
+        assert_eq!(text(&writer, Rect::new(5, 1, 2, 2)), ["5", "6"]);
+        // Scrolling the text area with CSI S leaves a blank row at the bottom
+        writer.print("\x1b[S");
+        assert_eq!(text(&writer, whole), ["line 5", "line 6", "line 7", "line 8", ""]);
+        assert_eq!(text(&writer, Rect::new(5, 3, 1, 5)), ["8", ""]);
+        // While the view is scrolled back, the regions show the scrollback, which only got the
+        // lines of the overflow
+        writer.scroll_view_up(2);
+        assert_eq!(text(&writer, whole), ["line 2", "line 3", "line 5", "line 6", "line 7"]);
+        assert_eq!(text(&writer, Rect::new(0, 0, 4, 1)), ["line"]);
+        writer.view_live();
+        assert_eq!(text(&writer, Rect::new(5, 0, 1, 1)), ["5"]);
+    }
+
+    #[test]
+    fn a_hidden_cursor_leaves_no_pixels_and_comes_back_at_the_cursor() {
+        let steps = ["ab\nc", "\n1\n2\n3\n4", "\x1b[2J\x1b[2;3H", "xy"];
+        let mut never_shown = writer(PixelFormat::Rgb, 4, 0);
+        never_shown.set_cursor_visible(false);
+        let mut always_shown = writer(PixelFormat::Rgb, 4, 0);
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print(steps[0]);
+        writer.print("\x1b[?25l");
+        assert!(!writer.cursor_visible());
+        // Hiding, scrolling and clearing keep the cursor off the screen
+        for step in steps {
+            never_shown.print(step);
+            always_shown.print(step);
+            if step != steps[0] {
+                writer.print(step);
+            }
+            assert_eq!(screen_pixels(&writer), screen_pixels(&never_shown), "after {:?}", step);
+        }
+        writer.print("\x1b[?25h");
+        assert_eq!(writer.drawn_cursor, Some((4, 1)));
+        assert_eq!(screen_pixels(&writer), screen_pixels(&always_shown));
+        // The block shows the blank cell after "xy" in reverse video
+        let (x, y) = writer.cell_origin(4, 1);
+        assert_eq!(writer.get_pixel(x, y), Some(Color::WHITE));
+    }
+
+    /// Returns the wrap marker that the font of `writer` can draw.
+    fn wrap_marker(writer: &FrameBufferWriter) -> char {
+        match writer.font.glyph(WRAP_MARKER, writer.metrics.height) {
+            Some(_) => WRAP_MARKER,
+            None => WRAP_MARKER_FALLBACK,
+        }
+    }
+
+    #[test]
+    fn rows_that_soft_wrap_end_in_a_dim_marker_in_the_reserved_column() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_wrap_marker(true, false);
+        assert_eq!(writer.columns(), 17);
+        writer.print("abcdefghijklmnopqrst\nshort\n");
+        let wrapped = format!("abcdefghijklmnopq{}", wrap_marker(&writer));
+        assert_eq!(screen_text(&writer)[..3], [&wrapped[..], "rst", "short"]);
+        let cell = writer.grid.get(17, 0).unwrap();
+        assert!(cell.style.dim);
+        assert_eq!(writer.stats().soft_wraps, 1);
+        // A line that exactly fills the columns doesn't wrap
+        writer.print("\x1b[2J\x1b[H0123456789abcdefg\nx");
+        assert_eq!(screen_text(&writer)[..2], ["0123456789abcdefg", "x"]);
+    }
+
+    #[test]
+    fn wrap_markers_can_indent_the_continuation_rows() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_wrap_marker(true, true);
+        writer.print("> ");
+        writer.indent_wrapped_lines();
+        writer.print("abcdefghijklmnopqrstuvwxyz0123456\nend");
+        // The continuations start after the indent and one more cell
+        let marker = wrap_marker(&writer);
+        let rows = [
+            format!("> abcdefghijklmno{}", marker),
+            format!("   pqrstuvwxyz012{}", marker),
+            "   3456".into(),
+            "end".into(),
+        ];
+        assert_eq!(screen_text(&writer)[..4], rows);
+    }
+
+    #[test]
+    fn sgr_256_color_foregrounds_draw_the_glyphs() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        // The full block covers its whole cell in the foreground color
+        writer.print("\x1b[38;5;196m█\x1b[38;5;244m█\x1b[39m█");
+        let colors = [Color::new(255, 0, 0), Color::new(128, 128, 128), Color::WHITE];
+        for (column, color) in colors.into_iter().enumerate() {
+            assert!(cell_colors(&writer, column).iter().all(|&pixel| pixel == color), "{}", column);
+        }
+    }
+
+    #[test]
+    fn sgr_resets_and_clears_use_the_new_default_colors() {
+        let (gray, navy) = (Color::new(170, 170, 170), Color::new(0, 0, 128));
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.print("\x1b[31mr\x1b[0mk");
+        writer.set_default_colors(gray, navy);
+        // Shown text keeps its colors, but the style of the next text switches
+        writer.print("n");
+        let background = |writer: &FrameBufferWriter, column| {
+            let (x, y) = writer.cell_origin(column, 0);
+            writer.get_pixel(x, y).unwrap()
+        };
+        let backgrounds = [0, 1, 2].map(|column| background(&writer, column));
+        assert_eq!(backgrounds, [Color::BLACK, Color::BLACK, navy]);
+
+        // A clear fills the whole text area, the padding around the grid included
+        writer.clear();
+        assert!(screen_pixels(&writer).iter().all(|&pixel| pixel == Some(navy)));
+        // SGR 0, 39 and 49 return to the new defaults, while other colors stay explicit
+        writer.print("\x1b[H\x1b[30;47m \x1b[0m \x1b[31;42m \x1b[39;49m ");
+        let cells = [Color::ANSI_PALETTE[7], navy, Color::GREEN, navy];
+        for (column, color) in cells.into_iter().enumerate() {
+            assert!(cell_colors(&writer, column).iter().all(|&pixel| pixel == color), "{}", column);
+        }
+        writer.print("\x1b[0m█");
+        assert!(cell_colors(&writer, 4).iter().all(|&pixel| pixel == gray));
+    }
+
+    #[test]
+    fn restoring_a_region_puts_back_its_exact_bytes_and_cells() {
+        for (pixel_format, bytes_per_pixel) in FORMATS {
+            for padding in [0, 3] {
+                let mut writer = writer(pixel_format, bytes_per_pixel, padding);
+                // Bytes that no color encodes to, e.g. in the unused byte of 4-byte pixels
+                for (index, byte) in writer.framebuffer.iter_mut().enumerate() {
+                    *byte = (index * 7 + index / 5) as u8;
+                }
+                writer.print("saved \x1b[31mtext\x1b[0m\nline 2");
+                let (before, text) = (writer.framebuffer.to_vec(), screen_text(&writer));
+                // Cuts through cells, and holds the cells 1 to 4 of row 1 completely
+                let rect = Rect::new(5, 10, 60, 40);
+                let mut buffer = vec![0; writer.region_bytes(rect)];
+                let snapshot = writer.save_region(rect, &mut buffer).unwrap();
+                writer.fill_rect(5, 10, 60, 40, Color::GREEN);
+                writer.print_at(1, 1, "pop!");
+                assert_ne!(screen_text(&writer), text);
+                writer.restore_region(&snapshot).unwrap();
+                let context = format!("{:?} padding {}", pixel_format, padding);
+                assert!(*writer.framebuffer == *before, "{}", context);
+                assert_eq!(screen_text(&writer), text, "{}", context);
+            }
+        }
+    }
+
+    #[test]
+    fn a_hidpi_scale_draws_every_pixel_as_a_square_of_framebuffer_pixels() {
+        let mut expected = writer(PixelFormat::Rgb, 4, 0);
+        // The last framebuffer column and row are left over at scale 2
+        let mut scaled = sized_writer(PixelFormat::Rgb, 4, (401, 241), 3);
+        scaled.set_hidpi_scale(2);
+        assert_eq!((scaled.width(), scaled.height()), (200, 120));
+        assert_eq!((scaled.columns(), scaled.rows()), (expected.columns(), expected.rows()));
+        for writer in [&mut expected, &mut scaled] {
+            writer.print("Hi \x1b[1;41mthere\x1b[0m\n\x1b[4mok");
+            writer.fill_rect(150, 100, 7, 5, Color::GREEN);
+        }
+        let stride = scaled.info.stride;
+        let physical = |x: usize, y: usize| {
+            let start = (y * stride + x) * 4;
+            scaled.decode_color(&scaled.framebuffer[start..start + 4])
+        };
+        for y in 0..241 {
+            for x in 0..401 {
+                let logical = expected.get_pixel(x / 2, y / 2).unwrap_or(Color::BLACK);
+                assert_eq!(physical(x, y), logical, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn blinking_text_toggles_every_interval_until_it_is_overwritten() {
+        let mut hidden = writer(PixelFormat::Rgb, 4, 0);
+        let mut shown = writer(PixelFormat::Rgb, 4, 0);
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        for writer in [&mut hidden, &mut shown, &mut writer] {
+            writer.set_cursor_visible(false);
+        }
+        hidden.print("one\n      steady");
+        shown.print("one\n\x1b[1mblink\x1b[0m steady");
+        writer.print("one\n\x1b[5;1mblink\x1b[25;22m steady");
+        let (hidden, shown) = (screen_pixels(&hidden), screen_pixels(&shown));
+        assert!(screen_pixels(&writer) == shown);
+        for now in (0..=2000).step_by(250) {
+            writer.tick(now);
+            let expected = if now / BLINK_INTERVAL_MS % 2 == 1 { &hidden } else { &shown };
+            assert!(screen_pixels(&writer) == *expected, "at {} ms", now);
+        }
+        // Blinking text moves along with its row
+        writer.print("\n\n\n\n");
+        assert_eq!(writer.grid.blinking_rows().collect::<Vec<_>>(), [0]);
+        // Overwritten while hidden, the cells stay as they are written
+        writer.tick(2500);
+        writer.print("\x1b[H\x1b[1mblink");
+        let overwritten = screen_pixels(&writer);
+        for now in [3000, 3500, 4000] {
+            writer.tick(now);
+            assert!(screen_pixels(&writer) == overwritten, "at {} ms", now);
+        }
+        assert_eq!(writer.grid.blinking_rows().count(), 0);
+    }
+
+    #[test]
+    fn a_highlight_reverses_the_shown_cells_and_clearing_it_restores_their_styles() {
+        let texts = [
+            "\x1b[31mr\x1b[7me\x1b[39mxyte\x1b[27mxt\ns\x1b[7mecond\x1b[27m line\nthird",
+            "\x1b[31mre\x1b[39mxytext\nsecond line\n\x1b[7mthi\x1b[27mrd",
+            "\x1b[31mre\x1b[39mxytext\nsecond line\nthird",
+        ];
+        let expected = texts.map(|text| {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            writer.set_cursor_visible(false);
+            writer.print(text);
+            screen_pixels(&writer)
+        });
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.print("\x1b[31mred\x1b[0m text\nsecond line\nthird");
+        writer.highlight_region(Rect::new(1, 0, 5, 2));
+        // Text printed into the rectangle is highlighted as well
+        writer.print("\x1b[1;3Hxy");
+        assert!(screen_pixels(&writer) == expected[0]);
+        // The grid keeps the styles that the text was written with
+        let rows = (0..3).flat_map(|row| writer.grid.row(row).unwrap());
+        assert!(rows.clone().all(|cell| !cell.style.reverse));
+        assert_eq!(rows.filter(|cell| cell.style.foreground == Color::RED).count(), 2);
+        // A new highlight replaces the old one
+        writer.highlight_region(Rect::new(0, 2, 3, 1));
+        assert_eq!(writer.highlight(), Some(Rect::new(0, 2, 3, 1)));
+        assert!(screen_pixels(&writer) == expected[1]);
+        writer.clear_highlight();
+        assert_eq!(writer.highlight(), None);
+        assert!(screen_pixels(&writer) == expected[2]);
+        assert_eq!(screen_text(&writer)[..3], ["rexytext", "second line", "third"]);
+    }
+
+    #[test]
+    fn a_soft_reset_leaves_a_writer_that_acts_like_a_fresh_one() {
+        // The partial escape sequence doesn't survive raw mode, so they are mangled separately
+        for raw_mode in [false, true] {
+            let mut fresh = writer(PixelFormat::Rgb, 4, 0);
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            fresh.print("before ");
+            writer.print("before ");
+            writer.print("\x1b[1;4;5;7;31;42m\x1b[#{\x1b[2m\x1b[4h\x1b[?25l");
+            writer.print("\x1b[2;3H\x1b[s\x1b[1;8H");
+            writer.highlight_region(Rect::new(0, 0, 3, 2));
+            match raw_mode {
+                true => writer.set_raw_mode(true),
+                false => writer.print("\x1b[3"),
+            }
+            // The start of a 'é'
+            writer.write_bytes(&[0xc3]);
+            writer.soft_reset();
+            assert!(!writer.insert_mode && !writer.raw_mode() && writer.cursor_visible());
+            assert_eq!((writer.style, writer.highlight()), (Style::DEFAULT, None));
+            assert_eq!(writer.saved_cursor, None);
+            // An 'm' would end a kept partial sequence, and a kept first byte would make the
+            // 'é' invalid. Then the empty style stack is popped and the missing saved cursor
+            // restored.
+            for writer in [&mut fresh, &mut writer] {
+                writer.write_bytes(b"m\x1b[#}\x1b[u\xc3\xa9\x1b[1mok");
+            }
+            assert_eq!(screen_text(&writer), screen_text(&fresh), "raw mode {}", raw_mode);
+            assert_eq!(writer.cursor_position(), fresh.cursor_position());
+            assert_eq!(writer.style, fresh.style);
+            assert!(screen_pixels(&writer) == screen_pixels(&fresh), "raw mode {}", raw_mode);
+        }
+    }
+
+    #[test]
+    fn a_saved_cursor_brings_back_its_cell_and_style() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print("ab\x1b[31m\x1b[s\ncd\x1b[0m\x1b[u!");
+        assert_eq!(screen_text(&writer)[..2], ["ab!", "cd"]);
+        assert_eq!((writer.cursor_position(), writer.style.foreground), ((3, 0), Color::RED));
+        // Without a saved cursor, the top left in the default style
+        writer.soft_reset();
+        writer.print("\x1b[1m\x1b[2;4H\x1b[u?");
+        assert_eq!(screen_text(&writer)[0], "?b!");
+        assert_eq!(writer.style, Style::DEFAULT);
+    }
+
+    #[test]
+    fn a_dismissed_toast_leaves_the_screen_as_it_was() {
+        // With the saved pixels, and redrawn from the grid without a buffer for them
+        for saved in [true, false] {
+            let mut expected = writer(PixelFormat::Rgb, 4, 0);
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            if !saved {
+                writer.toast_buffer = None;
+            }
+            writer.set_cursor_visible(false);
+            // The box covers the columns 5 to 13 of the rows 1 to 3
+            writer.print("\x1b[44mblue\x1b[0m\nbelow \x1b[1;31mthe\x1b[0m toast\nend");
+            let before = writer.framebuffer.to_vec();
+            writer.toast("note", 1000, 0);
+            assert!(writer.has_toast() && *writer.framebuffer != *before);
+            writer.tick(999);
+            assert!(writer.has_toast());
+            writer.tick(1000);
+            assert!(!writer.has_toast());
+            assert!(*writer.framebuffer == *before, "saved {}", saved);
+
+            writer.toast("note", 1000, 2000);
+            writer.dismiss_toast();
+            assert!(*writer.framebuffer == *before, "saved {}", saved);
+            // Text printed into the box dismisses it first
+            writer.toast("note", 1000, 3000);
+            writer.print("\x1b[2;7Hx");
+            assert!(!writer.has_toast());
+            expected.set_cursor_visible(false);
+            expected.print("\x1b[44mblue\x1b[0m\nbelow x\x1b[1;31mhe\x1b[0m toast\nend");
+            assert!(*writer.framebuffer == *expected.framebuffer, "saved {}", saved);
+        }
+    }
+
+    #[test]
+    fn cursor_movements_stop_at_the_edges_and_erasures_blank_their_cells() {
+        const FULL: &str = "abcdefghijklmnopqr";
+        // Each case starts at the top left of a screen full of text
+        let cases: [(&str, (usize, usize), [&str; 5]); 18] = [
+            ("\x1b[3;5H", (4, 2), [FULL; 5]),
+            ("\x1b[3;5H\x1b[2A", (4, 0), [FULL; 5]),
+            ("\x1b[3;5H\x1b[9A", (4, 0), [FULL; 5]),
+            ("\x1b[3;5H\x1b[B", (4, 3), [FULL; 5]),
+            ("\x1b[3;5H\x1b[9B", (4, 4), [FULL; 5]),
+            ("\x1b[3;5H\x1b[3C", (7, 2), [FULL; 5]),
+            ("\x1b[3;5H\x1b[99C", (17, 2), [FULL; 5]),
+            ("\x1b[3;5H\x1b[2D", (2, 2), [FULL; 5]),
+            ("\x1b[3;5H\x1b[9D", (0, 2), [FULL; 5]),
+            ("\x1b[3;5H\x1b[10G", (9, 2), [FULL; 5]),
+            ("\x1b[3;5H\x1b[G", (0, 2), [FULL; 5]),
+            ("\x1b[99;99H", (17, 4), [FULL; 5]),
+            ("\x1b[3;5H\x1b[K", (4, 2), [FULL, FULL, "abcd", FULL, FULL]),
+            ("\x1b[3;5H\x1b[1K", (4, 2), [FULL, FULL, "     fghijklmnopqr", FULL, FULL]),
+            ("\x1b[3;5H\x1b[2K", (4, 2), [FULL, FULL, "", FULL, FULL]),
+            ("\x1b[3;5H\x1b[J", (4, 2), [FULL, FULL, "abcd", "", ""]),
+            ("\x1b[3;5H\x1b[1J", (4, 2), ["", "", "     fghijklmnopqr", FULL, FULL]),
+            ("\x1b[3;5H\x1b[2J", (4, 2), [""; 5]),
+        ];
+        for (sequence, cursor, rows) in cases {
+            let mut writer = writer(PixelFormat::Rgb, 4, 0);
+            for row in 0..5 {
+                writer.print_at(0, row, FULL);
+            }
+            writer.print(sequence);
+            assert_eq!(writer.cursor_position(), cursor, "{:?}", sequence);
+            assert_eq!(screen_text(&writer), rows, "{:?}", sequence);
+        }
+
+        // The erased cells take the background of the current style
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.set_cursor_visible(false);
+        writer.print_at(0, 0, FULL);
+        writer.print("\x1b[1;3H\x1b[44m\x1b[K\x1b[0m");
+        let backgrounds: Vec<_> = (0..18).map(|column| cell_colors(&writer, column)).collect();
+        let blue = Color::new(0, 0, 170);
+        let is_blank = |colors: &Vec<Color>| colors.iter().all(|&color| color == blue);
+        assert!(backgrounds[2..].iter().all(is_blank));
+        assert!(!backgrounds[..2].iter().any(is_blank));
+    }
+
+    #[test]
+    fn extended_color_parameters_select_24_bit_and_palette_colors() {
+        let default = Style::DEFAULT;
+        let (foreground, background) = (default.foreground, default.background);
+        let cases = [
+            ("38;2;10;20;30", Color::new(10, 20, 30), background),
+            ("48;2;200;100;0", foreground, Color::new(200, 100, 0)),
+            // Channels above 255 are clamped
+            ("38;2;300;0;999", Color::new(255, 0, 255), background),
+            ("38;5;1", Color::ANSI_PALETTE[1], background),
+            ("48;5;9", foreground, Color::ANSI_PALETTE[9]),
+            ("38;5;16", Color::new(0, 0, 0), background),
+            ("38;5;21", Color::new(0, 0, 255), background),
+            ("48;5;196", foreground, Color::new(255, 0, 0)),
+            ("38;5;231", Color::new(255, 255, 255), background),
+            ("38;5;232", Color::new(8, 8, 8), background),
+            ("48;5;255", foreground, Color::new(238, 238, 238)),
+            ("38;2;1;2;3;48;5;21", Color::new(1, 2, 3), Color::new(0, 0, 255)),
+            // Missing or out of range parameters select no color
+            ("38;5;256", foreground, background),
+            ("38;2;1;2", foreground, background),
+            ("48;5", foreground, background),
+        ];
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        for (params, foreground, background) in cases {
+            writer.print(&format!("\x1b[0m\x1b[{}m", params));
+            let colors = (writer.style.foreground, writer.style.background);
+            assert_eq!(colors, (foreground, background), "{}", params);
+        }
+        // The parameters after a color still apply
+        writer.print("\x1b[0m\x1b[1;38;2;1;2;3;4m");
+        let style = writer.style;
+        assert!(style.bold && style.underline && style.foreground == Color::new(1, 2, 3));
+    }
+
+    #[test]
+    fn scrolling_by_redrawing_shows_the_same_pixels_as_moving_them() {
+        let steps: [&dyn Fn(&mut FrameBufferWriter); 7] = [
+            &|writer| writer.print("same\nsame\nsame\n\x1b[31mred\x1b[0m\nsame\nsame\n"),
+            &|writer| writer.print("\x1b[44mblue rows\n\n\x1b[0mplain\n"),
+            &|writer| writer.print("wide 漢字\x1b[7mrev\x1b[0m\n\tab\n"),
+            &|writer| {
+                writer.highlight_region(Rect::new(1, 2, 4, 2));
+                writer.print("highlighted\nmore\n")
+            },
+            &|writer| {
+                writer.clear_highlight();
+                writer.set_text_scale(2);
+                writer.print("big\n");
+                writer.set_text_scale(1);
+                writer.print("small\n\n\n\n");
+            },
+            &|writer| writer.print("\x1b[4munder\x1b[0m\x1b[3S"),
+            &|writer| writer.print(&"x\n".repeat(12)),
+        ];
+        let mut moved = writer(PixelFormat::Rgb, 4, 0);
+        let mut redrawn = writer(PixelFormat::Rgb, 4, 0);
+        moved.set_scroll_redraw(false);
+        for (index, step) in steps.iter().enumerate() {
+            step(&mut moved);
+            step(&mut redrawn);
+            assert_eq!(screen_text(&redrawn), screen_text(&moved), "step {}", index);
+            assert!(*redrawn.framebuffer == *moved.framebuffer, "step {}", index);
+        }
+        assert_eq!(moved.stats().redrawn_scrolls, 0);
+        // Every scroll but the one of CSI S was redrawn
+        let stats = redrawn.stats();
+        assert_eq!((stats.scrolls, stats.redrawn_scrolls), (27, 26));
+    }
+
+    #[test]
+    fn a_shorter_scrollback_returns_the_view_to_the_live_text_and_keeps_its_last_lines() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        let lines: Vec<_> = (1..=12).map(|n| n.to_string()).collect();
+        writer.print(&lines.join("\n"));
+        let shown = |first: usize| (first..first + 5).map(|n| n.to_string()).collect::<Vec<_>>();
+        writer.scroll_view_up(3);
+        assert_eq!(shown_text(&writer), shown(5));
+
+        writer.set_scrollback_lines(4);
+        assert!(!writer.is_viewing_scrollback());
+        assert_eq!(shown_text(&writer), shown(8));
+        assert_eq!(writer.scrollback_size(), (0, 4));
+        for n in 13..=18 {
+            writer.print(&format!("\n{}", n));
+        }
+        // Of the 13 lines above the rows, only the last 4 are kept
+        assert_eq!(writer.scrollback_size(), (4, 4));
+        writer.view_oldest();
+        assert_eq!(shown_text(&writer), shown(10));
+        // Lines that are dropped while they are viewed move the view to the oldest kept one
+        writer.print("\n19");
+        assert_eq!(shown_text(&writer), shown(11));
+        writer.view_live();
+        assert_eq!(shown_text(&writer), shown(15));
+    }
+
+    #[test]
+    fn cursor_style_sequences_select_the_shape_and_whether_it_blinks() {
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print("ab");
+        let cases = [
+            ("\x1b[3 q", CursorShape::Underline, true),
+            ("\x1b[ q", CursorShape::Block, false),
+            ("\x1b[4 q", CursorShape::Underline, false),
+            ("\x1b[1 q", CursorShape::Block, true),
+            // Bars aren't supported, so they leave the cursor as it was
+            ("\x1b[5 q", CursorShape::Block, true),
+            ("\x1b[6 q", CursorShape::Block, true),
+            ("\x1b[2 q", CursorShape::Block, false),
+        ];
+        let (foreground, background) = writer.style.colors();
+        for (sequence, shape, blink) in cases {
+            writer.print(sequence);
+            let selected = (writer.cursor_shape(), writer.cursor_blink());
+            assert_eq!(selected, (shape, blink), "{:?}", sequence);
+            // The block fills the blank cell after "ab", the underline only its bottom rows
+            let line = writer.line_height() - UNDERLINE_CURSOR_HEIGHT;
+            let colors = cell_colors(&writer, 2);
+            for (i, color) in colors.iter().enumerate() {
+                let covered = shape == CursorShape::Block || i / writer.cell_width() >= line;
+                let expected = if covered { foreground } else { background };
+                assert_eq!(*color, expected, "pixel {} after {:?}", i, sequence);
+            }
+        }
+    }
+
+    #[test]
+    fn a_blinking_cursor_toggles_every_interval_of_idle_ticks() {
+        let mut hidden = writer(PixelFormat::Rgb, 4, 0);
+        hidden.set_cursor_visible(false);
+        hidden.print("ab");
+        let mut steady = writer(PixelFormat::Rgb, 4, 0);
+        steady.print("ab");
+        let mut writer = writer(PixelFormat::Rgb, 4, 0);
+        writer.print("\x1b[1 qab");
+        assert_eq!(screen_pixels(&writer), screen_pixels(&steady));
+
+        // The first tick records the output as the last activity
+        for now in (1000..=3000).step_by(250) {
+            writer.tick(now);
+            let on = ((now - 1000) / BLINK_INTERVAL_MS).is_multiple_of(2);
+            let expected = if on { &steady } else { &hidden };
+            assert_eq!(screen_pixels(&writer), screen_pixels(expected), "at {} ms", now);
+        }
+        // Output in the hidden half shows the cursor at once, and the blink starts over
+        writer.tick(3500);
+        assert_eq!(screen_pixels(&writer), screen_pixels(&hidden));
+        writer.print("c");
+        steady.print("c");
+        hidden.print("c");
+        assert_eq!(screen_pixels(&writer), screen_pixels(&steady));
+        writer.tick(3600);
+        writer.tick(4000);
+        assert_eq!(screen_pixels(&writer), screen_pixels(&steady));
+        writer.tick(4100);
+        assert_eq!(screen_pixels(&writer), screen_pixels(&hidden));
+        // A steady cursor stays through the ticks
+        writer.print("\x1b[2 q");
+        writer.tick(5000);
+        writer.tick(5700);
+        assert_eq!(screen_pixels(&writer), screen_pixels(&steady));
+    }
+}
@@ -0,0 +1,277 @@
+/// Escape character that introduces a control sequence.
+pub const ESC: char = '\x1b';
+
+/// Maximum number of numeric parameters kept for a single control sequence. Further parameters
+/// are ignored.
+const MAX_PARAMS: usize = 16;
+
+/// Maximum number of bytes kept of the string of an operating system command. Further
+/// characters are dropped.
+const MAX_OSC_LEN: usize = 128;
+
+/// Bell character, which terminates an operating system command.
+const BEL: char = '\x07';
+
+/// A parsed control sequence (`ESC [ params intermediates final`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsiSequence {
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    /// Private marker at the start of the parameters, e.g. `?` in `ESC[?25h`.
+    pub private: Option<char>,
+    /// Intermediate character before the final character, e.g. `#` in `ESC[#{`.
+    pub intermediate: Option<char>,
+    /// The final character that selects the function.
+    pub final_char: char,
+}
+
+impl CsiSequence {
+    const EMPTY: CsiSequence = CsiSequence {
+        params: [0; MAX_PARAMS],
+        param_count: 0,
+        private: None,
+        intermediate: None,
+        final_char: '\0',
+    };
+
+    /// Returns the parameters. Omitted parameters are 0.
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.param_count]
+    }
+
+    /// Returns the parameter at `index`, or `default` if it was omitted or is 0.
+    pub fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+/// A reply to a query sequence, e.g. the cursor position report `ESC[row;colR`.
+pub struct Response {
+    bytes: [u8; 32],
+    len: usize,
+}
+
+impl Response {
+    /// Formats a response, cutting it off at 32 bytes.
+    pub fn new(args: core::fmt::Arguments) -> Self {
+        let mut response = Self { bytes: [0; 32], len: 0 };
+        let _ = core::fmt::Write::write_fmt(&mut response, args);
+        response
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+}
+
+impl core::fmt::Write for Response {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let len = s.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Result of feeding a character to the [Parser].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The character is regular text or a control character and should be written.
+    Print(char),
+    /// A complete control sequence was parsed.
+    Csi(CsiSequence),
+    /// A complete operating system command (`ESC ] string BEL` or `ESC ] string ESC \\`) was
+    /// parsed. Its string is available through [Parser::osc_string].
+    Osc,
+    /// A control sequence was aborted by an unexpected character, which was consumed.
+    Malformed,
+    /// The character was consumed by an escape sequence that isn't complete yet or is not
+    /// supported.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    CsiParams,
+    CsiIntermediate,
+    OscString,
+    /// An escape inside an operating system command, which may start its terminator.
+    OscEscape,
+}
+
+/// Incremental parser for ANSI escape sequences.
+///
+/// It keeps its state between calls, so sequences may be split across several writes.
+#[derive(Debug, Clone)]
+pub struct Parser {
+    state: State,
+    sequence: CsiSequence,
+    osc: [u8; MAX_OSC_LEN],
+    osc_len: usize,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            sequence: CsiSequence::EMPTY,
+            osc: [0; MAX_OSC_LEN],
+            osc_len: 0,
+        }
+    }
+
+    /// Returns the string of the last operating system command, without the terminator. It is
+    /// cut off after [MAX_OSC_LEN] bytes at a char boundary.
+    pub fn osc_string(&self) -> &str {
+        // Only whole chars are ever added
+        core::str::from_utf8(&self.osc[..self.osc_len]).unwrap_or_default()
+    }
+
+    /// Feeds the next character to the parser.
+    pub fn advance(&mut self, c: char) -> Action {
+        match self.state {
+            State::Ground if c == ESC => {
+                self.state = State::Escape;
+                Action::None
+            }
+            State::Ground => Action::Print(c),
+            State::Escape => {
+                self.state = match c {
+                    '[' => {
+                        self.sequence = CsiSequence::EMPTY;
+                        State::CsiParams
+                    }
+                    ']' => {
+                        self.osc_len = 0;
+                        State::OscString
+                    }
+                    ESC => State::Escape,
+                    _ => State::Ground,
+                };
+                Action::None
+            }
+            State::CsiParams | State::CsiIntermediate => self.advance_csi(c),
+            State::OscString | State::OscEscape => self.advance_osc(c),
+        }
+    }
+
+    fn advance_osc(&mut self, c: char) -> Action {
+        match (self.state, c) {
+            (State::OscString, BEL) | (State::OscEscape, '\\') => {
+                self.state = State::Ground;
+                Action::Osc
+            }
+            (State::OscString, ESC) => {
+                self.state = State::OscEscape;
+                Action::None
+            }
+            // Any other escape aborts the string and starts a new sequence.
+            (State::OscEscape, c) => {
+                self.state = State::Escape;
+                self.advance(c);
+                Action::Malformed
+            }
+            // Other control characters abort the string, so that an unterminated one can't
+            // swallow the following output, and are executed.
+            (_, c) if c.is_control() => {
+                self.state = State::Ground;
+                Action::Print(c)
+            }
+            (_, c) => {
+                let mut bytes = [0; 4];
+                let encoded = c.encode_utf8(&mut bytes).as_bytes();
+                if let Some(free) = self.osc.get_mut(self.osc_len..self.osc_len + encoded.len()) {
+                    free.copy_from_slice(encoded);
+                    self.osc_len += encoded.len();
+                }
+                Action::None
+            }
+        }
+    }
+
+    fn advance_csi(&mut self, c: char) -> Action {
+        let sequence = &mut self.sequence;
+        match c {
+            // An escape aborts the current sequence and starts a new one.
+            ESC => {
+                self.state = State::Escape;
+                return Action::Malformed;
+            }
+            // Control characters are executed in the middle of a sequence.
+            c if c.is_control() => return Action::Print(c),
+            '0'..='9' if self.state == State::CsiParams => {
+                if sequence.param_count == 0 {
+                    sequence.param_count = 1;
+                }
+                if let Some(param) = sequence.params.get_mut(sequence.param_count - 1) {
+                    let digit = c as u16 - '0' as u16;
+                    *param = param.saturating_mul(10).saturating_add(digit);
+                }
+            }
+            ';' if self.state == State::CsiParams => {
+                sequence.param_count = (sequence.param_count.max(1) + 1).min(MAX_PARAMS + 1);
+            }
+            '<'..='?' if self.state == State::CsiParams && sequence.param_count == 0 => {
+                sequence.private = Some(c);
+            }
+            ' '..='/' => {
+                sequence.intermediate = Some(c);
+                self.state = State::CsiIntermediate;
+            }
+            '@'..='~' => {
+                sequence.final_char = c;
+                sequence.param_count = sequence.param_count.min(MAX_PARAMS);
+                self.state = State::Ground;
+                return Action::Csi(*sequence);
+            }
+            // Anything else makes the sequence malformed; drop it.
+            _ => {
+                self.state = State::Ground;
+                return Action::Malformed;
+            }
+        }
+        Action::None
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `text` to `parser` and returns the actions that aren't [Action::None].
+    fn feed(parser: &mut Parser, text: &str) -> Vec<Action> {
+        text.chars().map(|c| parser.advance(c)).filter(|action| *action != Action::None).collect()
+    }
+
+    #[test]
+    fn operating_system_commands_end_at_a_bell_or_a_string_terminator() {
+        let mut parser = Parser::new();
+        assert_eq!(feed(&mut parser, "\x1b]0;bell\x07a"), [Action::Osc, Action::Print('a')]);
+        assert_eq!(parser.osc_string(), "0;bell");
+        assert_eq!(feed(&mut parser, "\x1b]2;st\x1b\\b"), [Action::Osc, Action::Print('b')]);
+        assert_eq!(parser.osc_string(), "2;st");
+    }
+
+    #[test]
+    fn long_operating_system_commands_are_cut_off_at_a_char_boundary() {
+        let mut parser = Parser::new();
+        let long = format!("0;{}", "x".repeat(MAX_OSC_LEN));
+        assert_eq!(feed(&mut parser, &format!("\x1b]{}\x07", long)), [Action::Osc]);
+        assert_eq!(parser.osc_string(), &long[..MAX_OSC_LEN]);
+        // The two bytes of the last é don't fit into the one byte left
+        let long = format!("0;{}é", "x".repeat(MAX_OSC_LEN - 3));
+        assert_eq!(feed(&mut parser, &format!("\x1b]{}\x07", long)), [Action::Osc]);
+        assert_eq!(parser.osc_string(), &long[..MAX_OSC_LEN - 1]);
+    }
+}
@@ -0,0 +1,131 @@
+/// A 24-bit color. Channels are always stored in RGB order; the conversion to the
+/// framebuffer's pixel format happens when a pixel is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const RED: Color = Color::new(170, 0, 0);
+    pub const GREEN: Color = Color::new(0, 170, 0);
+    pub const YELLOW: Color = Color::new(170, 85, 0);
+    pub const BLUE: Color = Color::new(0, 0, 255);
+    pub const MAGENTA: Color = Color::new(170, 0, 170);
+    pub const CYAN: Color = Color::new(0, 170, 170);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+
+    /// The 16 colors selected by the SGR color parameters: black, red, green, yellow, blue,
+    /// magenta, cyan and white, followed by their bright variants.
+    pub const ANSI_PALETTE: [Color; 16] = [
+        Color::BLACK,
+        Color::RED,
+        Color::GREEN,
+        Color::YELLOW,
+        Color::new(0, 0, 170),
+        Color::MAGENTA,
+        Color::CYAN,
+        Color::new(170, 170, 170),
+        Color::new(85, 85, 85),
+        Color::new(255, 85, 85),
+        Color::new(85, 255, 85),
+        Color::new(255, 255, 85),
+        Color::new(85, 85, 255),
+        Color::new(255, 85, 255),
+        Color::new(85, 255, 255),
+        Color::WHITE,
+    ];
+
+    /// Creates a color from its red, green and blue channels.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Returns the color of an index of the xterm 256-color palette, which SGR 38;5 and 48;5
+    /// select: the [ANSI_PALETTE](Self::ANSI_PALETTE) up to 15, then a 6×6×6 color cube, and
+    /// a ramp of 24 grays from 232.
+    pub const fn indexed(index: u8) -> Self {
+        const fn cube_level(level: u8) -> u8 {
+            match level {
+                0 => 0,
+                level => 55 + level * 40,
+            }
+        }
+        match index {
+            0..=15 => Self::ANSI_PALETTE[index as usize],
+            16..=231 => {
+                let index = index - 16;
+                Self::new(cube_level(index / 36), cube_level(index / 6 % 6), cube_level(index % 6))
+            }
+            _ => {
+                let gray = 8 + (index - 232) * 10;
+                Self::new(gray, gray, gray)
+            }
+        }
+    }
+
+    /// Parses a color in the X11 notation of OSC 10 and 11: `rgb:r/g/b` with 1 to 4 hex digits
+    /// per channel, which are scaled to 8 bits, or `#rrggbb`.
+    pub fn from_spec(spec: &str) -> Option<Self> {
+        if let Some(hex) = spec.strip_prefix('#') {
+            let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            if hex.len() != 6 {
+                return None;
+            }
+            return Some(Self::new(channel(0)?, channel(2)?, channel(4)?));
+        }
+        let mut channels = spec.strip_prefix("rgb:")?.split('/').map(|hex| {
+            let value = u32::from_str_radix(hex, 16).ok().filter(|_| (1..=4).contains(&hex.len()))?;
+            let max = (1 << (4 * hex.len())) - 1;
+            Some(((value * 255 + max / 2) / max) as u8)
+        });
+        let color = Self::new(channels.next()??, channels.next()??, channels.next()??);
+        channels.next().is_none().then_some(color)
+    }
+
+    /// Interpolates between this color (the background) and `foreground` by `coverage`, where
+    /// 255 results in the foreground and 0 leaves the background unchanged.
+    pub const fn blend(self, foreground: Color, coverage: u8) -> Self {
+        const fn mix(background: u8, foreground: u8, coverage: u8) -> u8 {
+            let coverage = coverage as u16;
+            ((background as u16 * (255 - coverage) + foreground as u16 * coverage + 127) / 255) as u8
+        }
+        Self::new(
+            mix(self.r, foreground.r, coverage),
+            mix(self.g, foreground.g, coverage),
+            mix(self.b, foreground.b, coverage),
+        )
+    }
+
+    /// Returns the complementary color (255 - value per channel).
+    pub const fn inverted(self) -> Self {
+        Self::new(255 - self.r, 255 - self.g, 255 - self.b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexed_colors_match_the_xterm_palette() {
+        let expected = [
+            (1, Color::RED),
+            (12, Color::new(85, 85, 255)),
+            (16, Color::BLACK),
+            (21, Color::new(0, 0, 255)),
+            (46, Color::new(0, 255, 0)),
+            (196, Color::new(255, 0, 0)),
+            (208, Color::new(255, 135, 0)),
+            (231, Color::WHITE),
+            (232, Color::new(8, 8, 8)),
+            (244, Color::new(128, 128, 128)),
+            (255, Color::new(238, 238, 238)),
+        ];
+        for (index, color) in expected {
+            assert_eq!(Color::indexed(index), color, "index {}", index);
+        }
+    }
+}
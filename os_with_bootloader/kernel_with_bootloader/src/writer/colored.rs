@@ -0,0 +1,78 @@
+use core::fmt;
+
+use super::color::Color;
+use super::style::Style;
+
+/// Displays the wrapped value in the given foreground color, e.g.
+/// `println!("status: {}", green("OK"))`.
+///
+/// The color is applied through escape sequences, so it works anywhere text ends up in the
+/// writer, including inside `write!` and `format_args!`. The surrounding style is saved before
+/// and restored after the value, so nesting colored values inside other colored text keeps the
+/// outer color. Width and padding specifiers apply to the wrapped value.
+#[derive(Debug, Clone, Copy)]
+pub struct Colored<T: fmt::Display>(pub T, pub Color);
+
+impl<T: fmt::Display> fmt::Display for Colored<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Color { r, g, b } = self.1;
+        write!(f, "\x1b[#{{\x1b[38;2;{r};{g};{b}m")?;
+        self.0.fmt(f)?;
+        f.write_str("\x1b[#}")
+    }
+}
+
+/// Displays the wrapped value in the given style, like [Colored]. Text effects can't be
+/// expressed as escape sequences, so they are dropped.
+#[derive(Debug, Clone, Copy)]
+pub struct Styled<T: fmt::Display>(pub T, pub Style);
+
+impl<T: fmt::Display> fmt::Display for Styled<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let style = self.1;
+        f.write_str("\x1b[#{\x1b[0")?;
+        for (enabled, param) in
+            [(style.bold, ";1"), (style.dim, ";2"), (style.underline, ";4"), (style.reverse, ";7")]
+        {
+            if enabled {
+                f.write_str(param)?;
+            }
+        }
+        let (Color { r, g, b }, background) = (style.foreground, style.background);
+        write!(f, ";38;2;{r};{g};{b}")?;
+        let Color { r, g, b } = background;
+        write!(f, ";48;2;{r};{g};{b}m")?;
+        self.0.fmt(f)?;
+        f.write_str("\x1b[#}")
+    }
+}
+
+/// Displays `value` in red.
+pub fn red<T: fmt::Display>(value: T) -> Colored<T> {
+    Colored(value, Color::RED)
+}
+
+/// Displays `value` in green.
+pub fn green<T: fmt::Display>(value: T) -> Colored<T> {
+    Colored(value, Color::GREEN)
+}
+
+/// Displays `value` in yellow.
+pub fn yellow<T: fmt::Display>(value: T) -> Colored<T> {
+    Colored(value, Color::YELLOW)
+}
+
+/// Displays `value` in blue.
+pub fn blue<T: fmt::Display>(value: T) -> Colored<T> {
+    Colored(value, Color::BLUE)
+}
+
+/// Displays `value` in magenta.
+pub fn magenta<T: fmt::Display>(value: T) -> Colored<T> {
+    Colored(value, Color::MAGENTA)
+}
+
+/// Displays `value` in cyan.
+pub fn cyan<T: fmt::Display>(value: T) -> Colored<T> {
+    Colored(value, Color::CYAN)
+}
@@ -6,12 +6,58 @@ pub mod font_constants {
     use super::*;
     /// Height of each char raster. The font size is ~0.84% of this. Thus, this is the line height that
     /// enables multiple characters to be side-by-side and appear optically in one line in a natural way.
-    pub const CHAR_RASTER_HEIGHT: RasterHeight = RasterHeight::Size16;
+    ///
+    /// The sizes that are compiled in are selected with the `font-*` features. The text uses
+    /// 20 pixels if available, and otherwise the smallest size.
+    #[cfg(feature = "font-20")]
+    pub const CHAR_RASTER_HEIGHT: RasterHeight = RasterHeight::Size20;
+    #[cfg(not(feature = "font-20"))]
+    pub const CHAR_RASTER_HEIGHT: RasterHeight = SMALLEST_RASTER_HEIGHT;
+    /// Height of the chars in the header band: the smallest size, to take less space.
+    pub const HEADER_RASTER_HEIGHT: RasterHeight = SMALLEST_RASTER_HEIGHT;
+    #[cfg(feature = "font-16")]
+    const SMALLEST_RASTER_HEIGHT: RasterHeight = RasterHeight::Size16;
+    #[cfg(all(feature = "font-20", not(feature = "font-16")))]
+    const SMALLEST_RASTER_HEIGHT: RasterHeight = RasterHeight::Size20;
+    #[cfg(all(feature = "font-24", not(any(feature = "font-16", feature = "font-20"))))]
+    const SMALLEST_RASTER_HEIGHT: RasterHeight = RasterHeight::Size24;
+    #[cfg(not(any(feature = "font-16", feature = "font-20", feature = "font-24")))]
+    const SMALLEST_RASTER_HEIGHT: RasterHeight = RasterHeight::Size32;
     /// The width of each single symbol of the mono space font.
-    pub const CHAR_RASTER_WIDTH: usize = get_raster_width(FontWeight::Regular, CHAR_RASTER_HEIGHT);
+    pub const CHAR_RASTER_WIDTH: usize = get_raster_width(FONT_WEIGHT, CHAR_RASTER_HEIGHT);
     /// Backup character if a desired symbol is not available by the font.
     /// The '	' character requires the feature "unicode-specials".
     pub const BACKUP_CHAR: char = ' ';
-    pub const FONT_WEIGHT: FontWeight = FontWeight::Regular; 
+    /// Weight of the font: bold with the `font-bold` feature, regular with `font-regular`.
+    #[cfg(feature = "font-bold")]
+    pub const FONT_WEIGHT: FontWeight = FontWeight::Bold;
+    #[cfg(not(feature = "font-bold"))]
+    pub const FONT_WEIGHT: FontWeight = FontWeight::Regular;
     pub const BACKSPACE: char='\u{0008}';
-}
\ No newline at end of file
+
+    /// Returns the raster height of the given size in pixels, if it is compiled in.
+    pub fn raster_height(pixels: usize) -> Option<RasterHeight> {
+        match pixels {
+            #[cfg(feature = "font-16")]
+            16 => Some(RasterHeight::Size16),
+            #[cfg(feature = "font-20")]
+            20 => Some(RasterHeight::Size20),
+            #[cfg(feature = "font-24")]
+            24 => Some(RasterHeight::Size24),
+            #[cfg(feature = "font-32")]
+            32 => Some(RasterHeight::Size32),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(any(
+    feature = "font-16",
+    feature = "font-20",
+    feature = "font-24",
+    feature = "font-32"
+)))]
+compile_error!("at least one of the features font-16, font-20, font-24 and font-32 is required");
+
+#[cfg(not(any(feature = "font-regular", feature = "font-bold")))]
+compile_error!("at least one of the features font-regular and font-bold is required");
@@ -0,0 +1,77 @@
+use core::ops::Range;
+use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Size of the back buffer: enough for 1920×1080 pixels of 4 bytes. Larger framebuffers can't
+/// be double-buffered.
+pub const BACK_BUFFER_BYTES: usize = 1920 * 1080 * 4;
+
+/// Backing storage of the back buffer. It is handed out once, to the first writer that asks.
+static mut BYTES: [u8; BACK_BUFFER_BYTES] = [0; BACK_BUFFER_BYTES];
+static BYTES_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns the back buffer, which is empty for all but the first caller.
+pub(super) fn take_buffer() -> &'static mut [u8] {
+    if BYTES_TAKEN.swap(true, Ordering::AcqRel) {
+        return super::spare_storage(0, BACK_BUFFER_BYTES);
+    }
+    // SAFETY: the storage is handed out only once, guarded by BYTES_TAKEN.
+    unsafe { slice::from_raw_parts_mut((&raw mut BYTES).cast::<u8>(), BACK_BUFFER_BYTES) }
+}
+
+/// The framebuffer on the screen while the writer draws into the back buffer, see
+/// [super::FrameBufferWriter::set_double_buffering].
+pub(super) struct DoubleBuffer {
+    pub front: &'static mut [u8],
+    /// The rest of the back buffer, beyond the size of the framebuffer.
+    pub spare: &'static mut [u8],
+    /// The bytes that changed since they were last presented, empty if none did.
+    dirty: Range<usize>,
+}
+
+impl DoubleBuffer {
+    /// Splits `buffer` into the part that the writer draws into, which is returned, and the
+    /// spare rest, and copies the screen into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is smaller than `front`.
+    pub fn new(front: &'static mut [u8], buffer: &'static mut [u8]) -> (Self, &'static mut [u8]) {
+        let (back, spare) = buffer.split_at_mut(front.len());
+        back.copy_from_slice(front);
+        (Self { front, spare, dirty: 0..0 }, back)
+    }
+
+    /// Records that the given bytes of the back buffer changed.
+    pub fn mark(&mut self, bytes: Range<usize>) {
+        self.dirty = match self.dirty.is_empty() {
+            true => bytes,
+            false => self.dirty.start.min(bytes.start)..self.dirty.end.max(bytes.end),
+        };
+    }
+
+    /// Copies the bytes of `back` that changed to the screen, in one pass. Returns their number.
+    pub fn present(&mut self, back: &[u8]) -> usize {
+        let dirty = core::mem::replace(&mut self.dirty, 0..0);
+        let dirty = dirty.start.min(back.len())..dirty.end.min(back.len());
+        self.front[dirty.clone()].copy_from_slice(&back[dirty.clone()]);
+        dirty.len()
+    }
+
+    /// Gives back the framebuffer on the screen and the whole back buffer.
+    ///
+    /// # Safety
+    ///
+    /// `back` must be the part of the back buffer that [DoubleBuffer::new] returned.
+    pub unsafe fn into_parts(
+        self,
+        back: &'static mut [u8],
+    ) -> (&'static mut [u8], &'static mut [u8]) {
+        let len = back.len() + self.spare.len();
+        debug_assert_eq!(back.as_ptr_range().end, self.spare.as_ptr());
+        // SAFETY: `back` and `spare` were split from one buffer and are adjacent, so together
+        // they are that buffer again.
+        let buffer = unsafe { slice::from_raw_parts_mut(back.as_mut_ptr(), len) };
+        (self.front, buffer)
+    }
+}
@@ -0,0 +1,236 @@
+use super::rect::Rect;
+use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+use core::fmt;
+
+/// Reasons why a framebuffer can't be used by a [super::FrameBufferWriter].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBufferError {
+    /// `bytes_per_pixel` is 0 or larger than the 4 bytes a pixel is encoded into.
+    InvalidBytesPerPixel(usize),
+    /// A row is shorter than the visible width.
+    StrideTooSmall { stride: usize, width: usize },
+    /// The buffer can't hold `stride * height` pixels.
+    BufferTooSmall { required: usize, len: usize },
+    /// The buffer length differs from the one reported in the info.
+    LengthMismatch { expected: usize, len: usize },
+    /// The pixel format needs more bytes than a pixel has.
+    PixelFormatMismatch { pixel_format: PixelFormat, bytes_per_pixel: usize },
+}
+
+impl FrameBufferError {
+    /// Checks that a buffer of `len` bytes can be written safely according to `info`.
+    pub fn check(len: usize, info: &FrameBufferInfo) -> Result<(), Self> {
+        let bytes_per_pixel = info.bytes_per_pixel;
+        if !(1..=4).contains(&bytes_per_pixel) {
+            return Err(Self::InvalidBytesPerPixel(bytes_per_pixel));
+        }
+        if info.stride < info.width {
+            return Err(Self::StrideTooSmall { stride: info.stride, width: info.width });
+        }
+        if len != info.byte_len {
+            return Err(Self::LengthMismatch { expected: info.byte_len, len });
+        }
+        let required = info
+            .stride
+            .checked_mul(info.height)
+            .and_then(|pixels| pixels.checked_mul(bytes_per_pixel))
+            .unwrap_or(usize::MAX);
+        if len < required {
+            return Err(Self::BufferTooSmall { required, len });
+        }
+        let required_bits = match info.pixel_format {
+            PixelFormat::Rgb | PixelFormat::Bgr => 24,
+            PixelFormat::U8 => 8,
+            PixelFormat::Unknown { red_position, green_position, blue_position } => {
+                red_position.max(green_position).max(blue_position) as usize + 8
+            }
+            _ => 0,
+        };
+        if required_bits > bytes_per_pixel * 8 {
+            let pixel_format = info.pixel_format;
+            return Err(Self::PixelFormatMismatch { pixel_format, bytes_per_pixel });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FrameBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidBytesPerPixel(bytes) => write!(f, "invalid bytes per pixel: {}", bytes),
+            Self::StrideTooSmall { stride, width } => {
+                write!(f, "stride {} is smaller than the width {}", stride, width)
+            }
+            Self::BufferTooSmall { required, len } => {
+                write!(f, "buffer of {} bytes is smaller than the required {}", len, required)
+            }
+            Self::LengthMismatch { expected, len } => {
+                write!(f, "buffer of {} bytes doesn't match the reported {}", len, expected)
+            }
+            Self::PixelFormatMismatch { pixel_format, bytes_per_pixel } => write!(
+                f,
+                "pixel format {:?} doesn't fit into {} bytes per pixel",
+                pixel_format, bytes_per_pixel
+            ),
+        }
+    }
+}
+
+/// A font size in pixels that the font lacks, e.g. because it isn't compiled in, see the
+/// `font-*` features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedFontSize(pub usize);
+
+impl fmt::Display for UnsupportedFontSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the font has no size of {} pixels", self.0)
+    }
+}
+
+/// The back buffer is smaller than the framebuffer, or was taken by another writer, see
+/// [super::FrameBufferWriter::set_double_buffering].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackBufferTooSmall {
+    pub required: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for BackBufferTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Self { required, len } = self;
+        write!(f, "back buffer of {} bytes is smaller than the framebuffer of {}", len, required)
+    }
+}
+
+/// Reasons why a region of the screen can't be saved or restored, see
+/// [super::FrameBufferWriter::save_region].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The buffer can't hold the pixels of the region.
+    BufferTooSmall { required: usize, len: usize },
+    /// The resolution, pixel format, rotation or text layout changed since the snapshot was
+    /// taken, so it doesn't fit anymore.
+    GeometryChanged,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BufferTooSmall { required, len } => {
+                write!(f, "buffer of {} bytes is smaller than the required {}", len, required)
+            }
+            Self::GeometryChanged => write!(f, "the screen changed since the snapshot was taken"),
+        }
+    }
+}
+
+/// A broken invariant of the writer's state, see
+/// [super::FrameBufferWriter::check_consistency].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// The cursor is outside of the text area.
+    CursorOutOfBounds { x: usize, y: usize, area: Rect },
+    /// The cell grid doesn't fit the text area, which has `expected` columns and rows.
+    GridSize { columns: usize, rows: usize, expected: (usize, usize) },
+    /// The text scale is 0.
+    ZeroTextScale,
+    /// The view is scrolled back further than the scrollback reaches.
+    ViewOffset { offset: usize, len: usize },
+    /// The highlighted cells aren't all inside of the grid.
+    HighlightOutOfBounds(Rect),
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CursorOutOfBounds { x, y, area } => {
+                write!(f, "cursor at ({}, {}) is outside of the text area {:?}", x, y, area)
+            }
+            Self::GridSize { columns, rows, expected } => write!(
+                f,
+                "grid of {}x{} cells doesn't fit the text area of {}x{}",
+                columns, rows, expected.0, expected.1
+            ),
+            Self::ZeroTextScale => write!(f, "text scale is 0"),
+            Self::ViewOffset { offset, len } => {
+                write!(f, "view offset {} is past the scrollback of {} lines", offset, len)
+            }
+            Self::HighlightOutOfBounds(rect) => {
+                write!(f, "highlight {:?} is outside of the grid", rect)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A valid layout: 640×480 pixels of 4 bytes with 16 pixels of padding per row.
+    const INFO: FrameBufferInfo = FrameBufferInfo {
+        byte_len: 656 * 480 * 4,
+        width: 640,
+        height: 480,
+        pixel_format: PixelFormat::Bgr,
+        bytes_per_pixel: 4,
+        stride: 656,
+    };
+
+    #[test]
+    fn accepts_a_consistent_layout() {
+        assert_eq!(FrameBufferError::check(INFO.byte_len, &INFO), Ok(()));
+        let (pixel_format, byte_len) = (PixelFormat::U8, 656 * 480);
+        let info = FrameBufferInfo { byte_len, pixel_format, bytes_per_pixel: 1, ..INFO };
+        assert_eq!(FrameBufferError::check(byte_len, &info), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_bytes_per_pixel() {
+        for bytes_per_pixel in [0, 5, usize::MAX] {
+            let info = FrameBufferInfo { bytes_per_pixel, ..INFO };
+            let error = FrameBufferError::InvalidBytesPerPixel(bytes_per_pixel);
+            assert_eq!(FrameBufferError::check(INFO.byte_len, &info), Err(error));
+        }
+    }
+
+    #[test]
+    fn rejects_a_stride_below_the_width() {
+        let info = FrameBufferInfo { stride: 639, ..INFO };
+        let error = FrameBufferError::StrideTooSmall { stride: 639, width: 640 };
+        assert_eq!(FrameBufferError::check(INFO.byte_len, &info), Err(error));
+    }
+
+    #[test]
+    fn rejects_a_length_that_differs_from_the_info() {
+        let len = INFO.byte_len - 1;
+        let error = FrameBufferError::LengthMismatch { expected: INFO.byte_len, len };
+        assert_eq!(FrameBufferError::check(len, &INFO), Err(error));
+    }
+
+    #[test]
+    fn rejects_a_buffer_too_short_for_the_rows() {
+        let byte_len = 656 * 479 * 4;
+        let info = FrameBufferInfo { byte_len, ..INFO };
+        let error = FrameBufferError::BufferTooSmall { required: INFO.byte_len, len: byte_len };
+        assert_eq!(FrameBufferError::check(byte_len, &info), Err(error));
+        // A size that overflows can't be satisfied by any buffer
+        let info = FrameBufferInfo { height: usize::MAX, ..info };
+        let error = FrameBufferError::BufferTooSmall { required: usize::MAX, len: byte_len };
+        assert_eq!(FrameBufferError::check(byte_len, &info), Err(error));
+    }
+
+    #[test]
+    fn rejects_a_pixel_format_wider_than_a_pixel() {
+        let cases = [
+            (PixelFormat::Rgb, 2),
+            (PixelFormat::Bgr, 1),
+            (PixelFormat::Unknown { red_position: 0, green_position: 8, blue_position: 24 }, 3),
+        ];
+        for (pixel_format, bytes_per_pixel) in cases {
+            let byte_len = 656 * 480 * bytes_per_pixel;
+            let info = FrameBufferInfo { byte_len, pixel_format, bytes_per_pixel, ..INFO };
+            let error = FrameBufferError::PixelFormatMismatch { pixel_format, bytes_per_pixel };
+            assert_eq!(FrameBufferError::check(byte_len, &info), Err(error));
+        }
+    }
+}
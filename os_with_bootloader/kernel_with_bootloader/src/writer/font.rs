@@ -0,0 +1,124 @@
+use noto_sans_mono_bitmap::{get_raster, get_raster_width, RasterHeight};
+
+use super::constants::font_constants::{self, CHAR_RASTER_HEIGHT, FONT_WEIGHT};
+
+/// Dimensions of a font at one raster height, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+    /// Width of every glyph, as the font is monospaced.
+    pub width: usize,
+    /// Height of every glyph's raster.
+    pub height: usize,
+    /// Distance from the top of a raster to the baseline.
+    pub ascent: usize,
+}
+
+/// Maximum width of a glyph whose coverage is stored as bits; wider ones are cut off.
+pub const MAX_BITS_WIDTH: usize = 32;
+
+/// How a [FontGlyph] stores its coverage.
+#[derive(Debug, Clone, Copy)]
+enum Coverage {
+    /// Rows of one byte per pixel, from 0 (background) to 255 (foreground).
+    Bytes(&'static [&'static [u8]]),
+    /// One bit per pixel, the most significant bit first, with each row padded to whole bytes,
+    /// as in PSF fonts. Rows start every `stride` bytes.
+    Bits { bits: &'static [u8], stride: usize },
+}
+
+/// The coverage of a char in a font.
+#[derive(Debug, Clone, Copy)]
+pub struct FontGlyph {
+    coverage: Coverage,
+    width: usize,
+    height: usize,
+}
+
+impl FontGlyph {
+    /// Creates a glyph from its rows of one byte per pixel, which are all `width` bytes long.
+    pub const fn new(rows: &'static [&'static [u8]], width: usize) -> Self {
+        Self { coverage: Coverage::Bytes(rows), width, height: rows.len() }
+    }
+
+    /// Creates a glyph from a bitmap of one bit per pixel, row by row, with each row padded
+    /// to whole bytes. It is cut off at [MAX_BITS_WIDTH] and the end of the bitmap.
+    pub const fn from_bits(bits: &'static [u8], width: usize, height: usize) -> Self {
+        let stride = width.div_ceil(8);
+        let rows = match bits.len().checked_div(stride) {
+            Some(rows) => rows,
+            None => 0,
+        };
+        let width = if width < MAX_BITS_WIDTH { width } else { MAX_BITS_WIDTH };
+        let height = if height < rows { height } else { rows };
+        Self { coverage: Coverage::Bits { bits, stride }, width, height }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the coverage of the pixels in row `y`, one byte per pixel. Bits are expanded
+    /// into `buffer`.
+    pub fn row<'a>(&'a self, y: usize, buffer: &'a mut [u8; MAX_BITS_WIDTH]) -> &'a [u8] {
+        match self.coverage {
+            Coverage::Bytes(rows) => rows[y],
+            Coverage::Bits { bits, stride } => {
+                let row = &bits[y * stride..];
+                for (x, coverage) in buffer[..self.width].iter_mut().enumerate() {
+                    let set = row[x / 8] & (0x80 >> (x % 8)) != 0;
+                    *coverage = if set { 255 } else { 0 };
+                }
+                &buffer[..self.width]
+            }
+        }
+    }
+}
+
+/// A monospaced bitmap font that a [super::FrameBufferWriter] draws text with, see
+/// [super::FrameBufferWriter::set_font].
+///
+/// Sizes are raster heights in pixels. A font that lacks the requested size uses one that it
+/// has instead, so a font with a single size ignores it.
+pub trait FontProvider: Sync {
+    /// Returns the metrics of the size closest to `size`.
+    fn metrics(&self, size: usize) -> Metrics;
+
+    /// Returns the glyph of `c` in the size closest to `size`, or `None` if the font lacks it.
+    fn glyph(&self, c: char, size: usize) -> Option<FontGlyph>;
+}
+
+/// Noto Sans Mono of the [noto_sans_mono_bitmap] crate, in the sizes and the weight selected
+/// with the `font-*` features. Sizes that aren't compiled in fall back to [CHAR_RASTER_HEIGHT].
+pub struct NotoSansMono;
+
+/// The font a writer starts with.
+pub static NOTO_SANS_MONO: NotoSansMono = NotoSansMono;
+
+impl NotoSansMono {
+    fn raster_height(size: usize) -> RasterHeight {
+        font_constants::raster_height(size).unwrap_or(CHAR_RASTER_HEIGHT)
+    }
+}
+
+impl FontProvider for NotoSansMono {
+    /// The ascent is measured as the bottom of the coverage of `H`.
+    fn metrics(&self, size: usize) -> Metrics {
+        let raster_height = Self::raster_height(size);
+        let height = raster_height.val();
+        let rows = get_raster('H', FONT_WEIGHT, raster_height).map(|raster| raster.raster());
+        let last_covered = rows.and_then(|rows| {
+            rows.iter().rposition(|row| row.iter().any(|&coverage| coverage > 0))
+        });
+        let width = get_raster_width(FONT_WEIGHT, raster_height);
+        Metrics { width, height, ascent: last_covered.map_or(height, |row| row + 1) }
+    }
+
+    fn glyph(&self, c: char, size: usize) -> Option<FontGlyph> {
+        let raster = get_raster(c, FONT_WEIGHT, Self::raster_height(size))?;
+        Some(FontGlyph::new(raster.raster(), raster.width()))
+    }
+}
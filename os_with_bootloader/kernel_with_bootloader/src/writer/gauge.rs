@@ -0,0 +1,103 @@
+use core::fmt;
+
+/// Partially filled cells, from one eighth to seven eighths.
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// Displays a horizontal bar of `width` cells that is filled to `value / max`, e.g.
+/// `println!("{}", Gauge::new(used, size, 20))`.
+///
+/// The bar is drawn with the eighth blocks, so it has eight fill levels per cell, and the empty
+/// part with the light shade.
+#[derive(Debug, Clone, Copy)]
+pub struct Gauge {
+    value: usize,
+    max: usize,
+    width: usize,
+}
+
+impl Gauge {
+    /// Creates a gauge; values above `max` fill the bar, and a `max` of 0 leaves it empty.
+    pub fn new(value: usize, max: usize, width: usize) -> Self {
+        Self { value: value.min(max), max, width }
+    }
+
+    /// Returns the number of filled eighths of a cell, rounded to the nearest one.
+    pub fn eighths(&self) -> usize {
+        let eighths = self.width as u128 * 8;
+        match self.max {
+            0 => 0,
+            max => ((self.value as u128 * eighths + max as u128 / 2) / max as u128) as usize,
+        }
+    }
+}
+
+impl fmt::Display for Gauge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let eighths = self.eighths();
+        let (full, partial) = (eighths / 8, eighths % 8);
+        for _ in 0..full {
+            f.write_str("█")?;
+        }
+        let mut drawn = full;
+        if partial > 0 {
+            write!(f, "{}", EIGHTHS[partial - 1])?;
+            drawn += 1;
+        }
+        for _ in drawn..self.width {
+            f.write_str("░")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    /// Returns the eighths that the cells of a rendered gauge show.
+    fn filled(bar: &str) -> usize {
+        let level = |c| match c {
+            '█' => 8,
+            '░' => 0,
+            c => EIGHTHS.iter().position(|&eighth| eighth == c).unwrap() + 1,
+        };
+        bar.chars().map(level).sum()
+    }
+
+    #[test]
+    fn the_bar_fills_monotonically_from_empty_to_full() {
+        let (max, width) = (37, 5);
+        let mut last = 0;
+        for value in 0..=max + 3 {
+            let bar = Gauge::new(value, max, width).to_string();
+            assert_eq!(bar.chars().count(), width, "{:?}", bar);
+            // Only the cell after the full ones is partial, and the rest is empty
+            let partial = bar.trim_start_matches('█').trim_end_matches('░');
+            assert!(partial.chars().count() <= 1, "{:?}", bar);
+            let eighths = filled(&bar);
+            assert_eq!(eighths, Gauge::new(value, max, width).eighths());
+            // 40 eighths over 37 values step by one or two
+            assert!((last..=last + 2).contains(&eighths), "{} after {}", eighths, last);
+            last = eighths;
+        }
+        assert_eq!(last, width * 8);
+    }
+
+    #[test]
+    fn eighths_round_to_the_nearest_one() {
+        let cases = [
+            ((0, 10, 4), "░░░░"),
+            ((5, 10, 4), "██░░"),
+            ((1, 16, 2), "▏░"),
+            ((1, 64, 2), "░░"),
+            ((3, 64, 2), "▏░"),
+            ((10, 10, 4), "████"),
+            ((99, 10, 2), "██"),
+            ((5, 0, 3), "░░░"),
+        ];
+        for ((value, max, width), bar) in cases {
+            assert_eq!(Gauge::new(value, max, width).to_string(), bar, "{}/{}", value, max);
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use core::{fmt, ops::Range};
+
+use spin::Mutex;
+
+use super::font::{FontGlyph, MAX_BITS_WIDTH};
+use super::{CELL_HEIGHT, CELL_WIDTH};
+
+/// Coverage of a synthesized glyph, one byte per pixel of a cell at text scale 1.
+pub type Bitmap = [[u8; CELL_WIDTH]; CELL_HEIGHT];
+
+/// The coverage of a character: a raster of the font, or a bitmap that covers a cell.
+// Glyphs live on the stack only while they are drawn, so the size of a bitmap doesn't matter
+#[allow(clippy::large_enum_variant)]
+pub enum Glyph {
+    Font(FontGlyph),
+    /// A glyph registered with [register_glyph], or synthesized for a box drawing or block
+    /// element character that the font lacks.
+    Synthesized(Bitmap),
+}
+
+impl Glyph {
+    pub fn width(&self) -> usize {
+        match self {
+            Glyph::Font(raster) => raster.width(),
+            Glyph::Synthesized(_) => CELL_WIDTH,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self {
+            Glyph::Font(raster) => raster.height(),
+            Glyph::Synthesized(_) => CELL_HEIGHT,
+        }
+    }
+
+    /// Returns the coverage of the pixels in row `y`, expanding the bits of a font's glyph into
+    /// `buffer` if needed.
+    pub fn row<'a>(&'a self, y: usize, buffer: &'a mut [u8; MAX_BITS_WIDTH]) -> &'a [u8] {
+        match self {
+            Glyph::Font(raster) => raster.row(y, buffer),
+            Glyph::Synthesized(bitmap) => &bitmap[y],
+        }
+    }
+}
+
+/// Maximum number of glyphs that can be registered with [register_glyph].
+pub const MAX_REGISTERED: usize = 16;
+
+/// Reasons why [register_glyph] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlyphError {
+    /// [MAX_REGISTERED] glyphs are registered already.
+    Full,
+    /// The width or height is 0, or the coverage doesn't have `width * height` bytes.
+    InvalidDimensions { width: usize, height: usize, len: usize },
+}
+
+impl fmt::Display for GlyphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "at most {} glyphs can be registered", MAX_REGISTERED),
+            Self::InvalidDimensions { width, height, len } => {
+                write!(f, "{} bytes of coverage don't make a {}x{} glyph", len, width, height)
+            }
+        }
+    }
+}
+
+struct Registry {
+    glyphs: [(char, Bitmap); MAX_REGISTERED],
+    len: usize,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    glyphs: [('\0', [[0; CELL_WIDTH]; CELL_HEIGHT]); MAX_REGISTERED],
+    len: 0,
+});
+
+/// Registers a glyph that is drawn for `c` instead of the font's, e.g. for icons in the private
+/// use area. `coverage` holds `width * height` bytes, row by row, from 0 (background) to 255
+/// (foreground). Registering `c` again replaces its glyph.
+///
+/// A glyph of a different size than a cell is scaled with nearest neighbor sampling to the
+/// largest size that fits into the cell, keeping its aspect ratio, and centered in it.
+pub fn register_glyph(
+    c: char,
+    width: usize,
+    height: usize,
+    coverage: &'static [u8],
+) -> Result<(), GlyphError> {
+    if width == 0 || height == 0 || width.checked_mul(height) != Some(coverage.len()) {
+        return Err(GlyphError::InvalidDimensions { width, height, len: coverage.len() });
+    }
+    let mut registry = REGISTRY.lock();
+    let Registry { glyphs, len } = &mut *registry;
+    let index = match glyphs[..*len].iter().position(|&(registered, _)| registered == c) {
+        Some(index) => index,
+        None if *len < MAX_REGISTERED => {
+            *len += 1;
+            *len - 1
+        }
+        None => return Err(GlyphError::Full),
+    };
+    glyphs[index] = (c, fit_to_cell(width, height, coverage));
+    Ok(())
+}
+
+/// Returns the glyph registered for `c`, if any. Gives up instead of waiting if the registry
+/// is locked, so that a panic during registration can still be printed.
+pub fn registered(c: char) -> Option<Glyph> {
+    let registry = REGISTRY.try_lock()?;
+    let glyphs = &registry.glyphs[..registry.len];
+    let &(_, bitmap) = glyphs.iter().find(|&&(registered, _)| registered == c)?;
+    Some(Glyph::Synthesized(bitmap))
+}
+
+/// Scales a glyph of the given size to the largest size that fits into a cell, keeping its
+/// aspect ratio, and centers it.
+fn fit_to_cell(width: usize, height: usize, coverage: &[u8]) -> Bitmap {
+    let (scaled_width, scaled_height) = match CELL_WIDTH * height <= CELL_HEIGHT * width {
+        true => (CELL_WIDTH, (height * CELL_WIDTH / width).max(1)),
+        false => ((width * CELL_HEIGHT / height).max(1), CELL_HEIGHT),
+    };
+    let (left, top) = ((CELL_WIDTH - scaled_width) / 2, (CELL_HEIGHT - scaled_height) / 2);
+    let mut bitmap = [[0; CELL_WIDTH]; CELL_HEIGHT];
+    for y in 0..scaled_height {
+        let source = &coverage[y * height / scaled_height * width..][..width];
+        for x in 0..scaled_width {
+            bitmap[top + y][left + x] = source[x * width / scaled_width];
+        }
+    }
+    bitmap
+}
+
+/// The characters that can be synthesized: the Box Drawing and Block Elements blocks.
+const FIRST: char = '\u{2500}';
+const COUNT: usize = 0xA0;
+
+/// Synthesized glyphs, generated on first use.
+struct Cache {
+    bitmaps: [Bitmap; COUNT],
+    generated: [bool; COUNT],
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache {
+    bitmaps: [[[0; CELL_WIDTH]; CELL_HEIGHT]; COUNT],
+    generated: [false; COUNT],
+});
+
+/// Returns the synthesized glyph of a box drawing or block element character, or `None` for
+/// other characters.
+///
+/// The glyph covers exactly one cell, including the leading, so that lines and blocks in
+/// adjacent cells connect.
+pub fn synthesize(c: char) -> Option<Glyph> {
+    let index = (c as usize).checked_sub(FIRST as usize).filter(|&index| index < COUNT)?;
+    let mut cache = CACHE.lock();
+    if !cache.generated[index] {
+        cache.bitmaps[index] = generate(c);
+        cache.generated[index] = true;
+    }
+    Some(Glyph::Synthesized(cache.bitmaps[index]))
+}
+
+fn generate(c: char) -> Bitmap {
+    let mut bitmap = [[0; CELL_WIDTH]; CELL_HEIGHT];
+    match c {
+        '\u{2571}'..='\u{2573}' => draw_diagonals(&mut bitmap, c),
+        '\u{2500}'..='\u{257F}' => {
+            draw_lines(&mut bitmap, BOX_ARMS[c as usize - FIRST as usize]);
+            cut_dashes(&mut bitmap, c);
+        }
+        _ => draw_block(&mut bitmap, c),
+    }
+    bitmap
+}
+
+fn fill(bitmap: &mut Bitmap, x: Range<usize>, y: Range<usize>, coverage: u8) {
+    for row in &mut bitmap[y] {
+        row[x.clone()].fill(coverage);
+    }
+}
+
+/// Width of a light line in pixels. Heavy lines and pairs of double lines are three times as
+/// wide.
+const LIGHT: usize = if CELL_WIDTH / 8 > 1 { CELL_WIDTH / 8 } else { 1 };
+
+/// Weight of the line that extends from the center of a cell to one of its edges.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Weight {
+    None,
+    Light,
+    Heavy,
+    Double,
+}
+
+const N: Weight = Weight::None;
+const L: Weight = Weight::Light;
+const H: Weight = Weight::Heavy;
+const D: Weight = Weight::Double;
+
+/// The lines of a box drawing character, from the center to each edge.
+#[derive(Clone, Copy)]
+struct Arms {
+    up: Weight,
+    right: Weight,
+    down: Weight,
+    left: Weight,
+}
+
+const fn arms(up: Weight, right: Weight, down: Weight, left: Weight) -> Arms {
+    Arms { up, right, down, left }
+}
+
+/// The arms of U+2500..=U+257F. Dashed lines, arcs and diagonals are listed with the arms of
+/// the solid lines, the corners and no arms respectively.
+const BOX_ARMS: [Arms; 0x80] = [
+    arms(N, L, N, L), arms(N, H, N, H), arms(L, N, L, N), arms(H, N, H, N), // ─━│┃
+    arms(N, L, N, L), arms(N, H, N, H), arms(L, N, L, N), arms(H, N, H, N), // ┄┅┆┇
+    arms(N, L, N, L), arms(N, H, N, H), arms(L, N, L, N), arms(H, N, H, N), // ┈┉┊┋
+    arms(N, L, L, N), arms(N, H, L, N), arms(N, L, H, N), arms(N, H, H, N), // ┌┍┎┏
+    arms(N, N, L, L), arms(N, N, L, H), arms(N, N, H, L), arms(N, N, H, H), // ┐┑┒┓
+    arms(L, L, N, N), arms(L, H, N, N), arms(H, L, N, N), arms(H, H, N, N), // └┕┖┗
+    arms(L, N, N, L), arms(L, N, N, H), arms(H, N, N, L), arms(H, N, N, H), // ┘┙┚┛
+    arms(L, L, L, N), arms(L, H, L, N), arms(H, L, L, N), arms(L, L, H, N), // ├┝┞┟
+    arms(H, L, H, N), arms(H, H, L, N), arms(L, H, H, N), arms(H, H, H, N), // ┠┡┢┣
+    arms(L, N, L, L), arms(L, N, L, H), arms(H, N, L, L), arms(L, N, H, L), // ┤┥┦┧
+    arms(H, N, H, L), arms(H, N, L, H), arms(L, N, H, H), arms(H, N, H, H), // ┨┩┪┫
+    arms(N, L, L, L), arms(N, L, L, H), arms(N, H, L, L), arms(N, H, L, H), // ┬┭┮┯
+    arms(N, L, H, L), arms(N, L, H, H), arms(N, H, H, L), arms(N, H, H, H), // ┰┱┲┳
+    arms(L, L, N, L), arms(L, L, N, H), arms(L, H, N, L), arms(L, H, N, H), // ┴┵┶┷
+    arms(H, L, N, L), arms(H, L, N, H), arms(H, H, N, L), arms(H, H, N, H), // ┸┹┺┻
+    arms(L, L, L, L), arms(L, L, L, H), arms(L, H, L, L), arms(L, H, L, H), // ┼┽┾┿
+    arms(H, L, L, L), arms(L, L, H, L), arms(H, L, H, L), arms(H, L, L, H), // ╀╁╂╃
+    arms(H, H, L, L), arms(L, L, H, H), arms(L, H, H, L), arms(H, H, L, H), // ╄╅╆╇
+    arms(L, H, H, H), arms(H, L, H, H), arms(H, H, H, L), arms(H, H, H, H), // ╈╉╊╋
+    arms(N, L, N, L), arms(N, H, N, H), arms(L, N, L, N), arms(H, N, H, N), // ╌╍╎╏
+    arms(N, D, N, D), arms(D, N, D, N), arms(N, D, L, N), arms(N, L, D, N), // ═║╒╓
+    arms(N, D, D, N), arms(N, N, L, D), arms(N, N, D, L), arms(N, N, D, D), // ╔╕╖╗
+    arms(L, D, N, N), arms(D, L, N, N), arms(D, D, N, N), arms(L, N, N, D), // ╘╙╚╛
+    arms(D, N, N, L), arms(D, N, N, D), arms(L, D, L, N), arms(D, L, D, N), // ╜╝╞╟
+    arms(D, D, D, N), arms(L, N, L, D), arms(D, N, D, L), arms(D, N, D, D), // ╠╡╢╣
+    arms(N, D, L, D), arms(N, L, D, L), arms(N, D, D, D), arms(L, D, N, D), // ╤╥╦╧
+    arms(D, L, N, L), arms(D, D, N, D), arms(L, D, L, D), arms(D, L, D, L), // ╨╩╪╫
+    arms(D, D, D, D), arms(N, L, L, N), arms(N, N, L, L), arms(L, N, N, L), // ╬╭╮╯
+    arms(L, L, N, N), arms(N, N, N, N), arms(N, N, N, N), arms(N, N, N, N), // ╰╱╲╳
+    arms(N, N, N, L), arms(L, N, N, N), arms(N, L, N, N), arms(N, N, L, N), // ╴╵╶╷
+    arms(N, N, N, H), arms(H, N, N, N), arms(N, H, N, N), arms(N, N, H, N), // ╸╹╺╻
+    arms(N, H, N, L), arms(L, N, H, N), arms(N, L, N, H), arms(H, N, L, N), // ╼╽╾╿
+];
+
+/// Where a line of an arm ends near the center: at the near or the far one of a pair of double
+/// lines across it, or in the center.
+#[derive(Clone, Copy)]
+enum Stop {
+    Near,
+    Center,
+    Far,
+}
+
+/// Returns the part of an axis of length `len`, whose center line starts at `center`, that a
+/// line starting at the low or high edge and ending at `stop` covers.
+fn extent(stop: Stop, from_low: bool, center: usize, len: usize) -> Range<usize> {
+    match (from_low, stop) {
+        (true, Stop::Near) => 0..center,
+        (true, Stop::Center) => 0..center + LIGHT,
+        (true, Stop::Far) => 0..center + 2 * LIGHT,
+        (false, Stop::Near) => center + LIGHT..len,
+        (false, Stop::Center) => center..len,
+        (false, Stop::Far) => center - LIGHT..len,
+    }
+}
+
+/// Returns the lines of an arm as their range across it and where they stop, given the weights
+/// of the arm on the other side of the center and of the arms across it on its low and high
+/// side.
+///
+/// Lines stop where they meet the lines across them, continue through the center to the arm on
+/// the other side, and pairs of double lines form inner and outer corners.
+fn lines(
+    arm: Weight,
+    opposite: Weight,
+    low: Weight,
+    high: Weight,
+    center: usize,
+) -> impl Iterator<Item = (Range<usize>, Stop)> {
+    let single = match (low, high) {
+        (D, _) | (_, D) if opposite != N => Stop::Center,
+        (D, D) => Stop::Near,
+        (D, _) | (_, D) => Stop::Far,
+        (H, _) | (_, H) => Stop::Far,
+        _ => Stop::Center,
+    };
+    let double = |side: Weight, other: Weight| match (side, other) {
+        (D, _) => Stop::Near,
+        (H, _) | (N, D) => Stop::Far,
+        _ => Stop::Center,
+    };
+    let lines = match arm {
+        Weight::None => [None, None],
+        Weight::Light => [Some((center..center + LIGHT, single)), None],
+        Weight::Heavy => [Some((center - LIGHT..center + 2 * LIGHT, single)), None],
+        Weight::Double => [
+            Some((center - LIGHT..center, double(low, high))),
+            Some((center + LIGHT..center + 2 * LIGHT, double(high, low))),
+        ],
+    };
+    lines.into_iter().flatten()
+}
+
+fn draw_lines(bitmap: &mut Bitmap, arms: Arms) {
+    let (cx, cy) = ((CELL_WIDTH - LIGHT) / 2, (CELL_HEIGHT - LIGHT) / 2);
+    for (arm, opposite, from_low) in [(arms.up, arms.down, true), (arms.down, arms.up, false)] {
+        for (x, stop) in lines(arm, opposite, arms.left, arms.right, cx) {
+            fill(bitmap, x, extent(stop, from_low, cy, CELL_HEIGHT), 255);
+        }
+    }
+    for (arm, opposite, from_low) in [(arms.left, arms.right, true), (arms.right, arms.left, false)] {
+        for (y, stop) in lines(arm, opposite, arms.up, arms.down, cy) {
+            fill(bitmap, extent(stop, from_low, cx, CELL_WIDTH), y, 255);
+        }
+    }
+}
+
+/// Cuts the gaps into the lines of the dashed box drawing characters.
+fn cut_dashes(bitmap: &mut Bitmap, c: char) {
+    let (dashes, horizontal) = match c {
+        '\u{2504}' | '\u{2505}' => (3, true),
+        '\u{2506}' | '\u{2507}' => (3, false),
+        '\u{2508}' | '\u{2509}' => (4, true),
+        '\u{250A}' | '\u{250B}' => (4, false),
+        '\u{254C}' | '\u{254D}' => (2, true),
+        '\u{254E}' | '\u{254F}' => (2, false),
+        _ => return,
+    };
+    let len = if horizontal { CELL_WIDTH } else { CELL_HEIGHT };
+    for i in 0..len {
+        // Each dash is followed by a gap of a third of its length
+        let dash = i * dashes / len;
+        let (start, end) = ((dash * len).div_ceil(dashes), ((dash + 1) * len).div_ceil(dashes));
+        if i - start < end - start - (end - start + 1) / 3 {
+            continue;
+        }
+        match horizontal {
+            true => fill(bitmap, i..i + 1, 0..CELL_HEIGHT, 0),
+            false => fill(bitmap, 0..CELL_WIDTH, i..i + 1, 0),
+        }
+    }
+}
+
+fn draw_diagonals(bitmap: &mut Bitmap, c: char) {
+    for y in 0..CELL_HEIGHT {
+        let x = y * CELL_WIDTH / CELL_HEIGHT;
+        // U+2571 goes from the upper right to the lower left, U+2572 the other way
+        if c != '\u{2572}' {
+            let x = CELL_WIDTH - 1 - x;
+            fill(bitmap, x.saturating_sub(LIGHT - 1)..x + 1, y..y + 1, 255);
+        }
+        if c != '\u{2571}' {
+            fill(bitmap, x..(x + LIGHT).min(CELL_WIDTH), y..y + 1, 255);
+        }
+    }
+}
+
+/// Returns whether `c` is one of the block elements U+2580..=U+259F, which have to fill exact
+/// fractions of the cell to line up in gauges.
+pub fn is_block_element(c: char) -> bool {
+    matches!(c, '\u{2580}'..='\u{259F}')
+}
+
+/// Threshold map of a 4×4 ordered dither: a pixel is covered by a shade of `n` sixteenths if its
+/// entry is below `n`.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Quadrants filled by U+2596..=U+259F: 1 is the upper left, 2 the upper right, 4 the lower
+/// left and 8 the lower right one.
+const QUADRANTS: [u8; 10] = [4, 8, 1, 1 | 4 | 8, 1 | 8, 1 | 2 | 4, 1 | 2 | 8, 2, 2 | 4, 2 | 4 | 8];
+
+/// Draws one of the block elements U+2580..=U+259F: partial blocks, shades and quadrants.
+fn draw_block(bitmap: &mut Bitmap, c: char) {
+    let (w, h) = (CELL_WIDTH, CELL_HEIGHT);
+    let eighths = |len: usize, n: usize| (len * n + 4) / 8;
+    let code = c as usize;
+    match c {
+        '\u{2580}' => fill(bitmap, 0..w, 0..h / 2, 255),
+        // Lower one eighth to full block
+        '\u{2581}'..='\u{2588}' => fill(bitmap, 0..w, h - eighths(h, code - 0x2580)..h, 255),
+        // Left seven eighths to left one eighth
+        '\u{2589}'..='\u{258F}' => fill(bitmap, 0..eighths(w, 0x2590 - code), 0..h, 255),
+        '\u{2590}' => fill(bitmap, w / 2..w, 0..h, 255),
+        // Light, medium and dark shade, dithered so that they stay crisp at any color
+        '\u{2591}'..='\u{2593}' => {
+            let sixteenths = 4 * (code - 0x2590) as u8;
+            for (y, row) in bitmap.iter_mut().enumerate() {
+                for (x, coverage) in row.iter_mut().enumerate() {
+                    *coverage = if BAYER[y % 4][x % 4] < sixteenths { 255 } else { 0 };
+                }
+            }
+        }
+        '\u{2594}' => fill(bitmap, 0..w, 0..eighths(h, 1), 255),
+        '\u{2595}' => fill(bitmap, w - eighths(w, 1)..w, 0..h, 255),
+        '\u{2596}'..='\u{259F}' => {
+            let mask = QUADRANTS[code - 0x2596];
+            let quadrants = [
+                (1, 0..w / 2, 0..h / 2),
+                (2, w / 2..w, 0..h / 2),
+                (4, 0..w / 2, h / 2..h),
+                (8, w / 2..w, h / 2..h),
+            ];
+            for (bit, x, y) in quadrants {
+                if mask & bit != 0 {
+                    fill(bitmap, x, y, 255);
+                }
+            }
+        }
+        _ => {}
+    }
+}
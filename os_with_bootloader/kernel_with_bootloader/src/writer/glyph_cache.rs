@@ -0,0 +1,157 @@
+use super::font::{FontGlyph, FontProvider};
+
+/// The printable ASCII chars, whose glyphs in the size of the text are looked up in advance.
+const ASCII: core::ops::RangeInclusive<char> = ' '..='~';
+const ASCII_LEN: usize = 95;
+
+/// Number of glyphs of other chars, or of other sizes, that the cache keeps. The least
+/// recently used one makes room for a new one.
+pub const LRU_ENTRIES: usize = 64;
+
+/// A glyph that the cache keeps, with the time it was last used.
+#[derive(Clone, Copy)]
+struct Entry {
+    c: char,
+    size: usize,
+    glyph: Option<FontGlyph>,
+    used: u64,
+}
+
+/// The glyphs of a font that were looked up before, so that printing the same chars again
+/// skips the font's lookup. The weight is fixed at compile time, so chars and sizes are the
+/// key. The cache must be [loaded](GlyphCache::load) again when the font changes.
+pub(super) struct GlyphCache {
+    /// The size of the text, which the ASCII glyphs are in.
+    size: usize,
+    ascii: [Option<FontGlyph>; ASCII_LEN],
+    entries: [Option<Entry>; LRU_ENTRIES],
+    /// Number of lookups so far, which orders the entries by their last use.
+    clock: u64,
+}
+
+impl GlyphCache {
+    pub const fn new() -> Self {
+        Self { size: 0, ascii: [None; ASCII_LEN], entries: [None; LRU_ENTRIES], clock: 0 }
+    }
+
+    /// Forgets all glyphs and looks up the ASCII ones of `font` in the text size `size`.
+    pub fn load(&mut self, font: &dyn FontProvider, size: usize) {
+        self.size = size;
+        for (glyph, c) in self.ascii.iter_mut().zip(ASCII) {
+            *glyph = font.glyph(c, size);
+        }
+        self.entries = [None; LRU_ENTRIES];
+    }
+
+    /// Returns the glyph of `c` in the size closest to `size`, like [FontProvider::glyph],
+    /// from the cache if it is there and from `font` otherwise.
+    pub fn glyph(&mut self, font: &dyn FontProvider, c: char, size: usize) -> Option<FontGlyph> {
+        if size == self.size && ASCII.contains(&c) {
+            return self.ascii[c as usize - ' ' as usize];
+        }
+        self.clock += 1;
+        let mut entries = self.entries.iter_mut().flatten();
+        if let Some(entry) = entries.find(|entry| (entry.c, entry.size) == (c, size)) {
+            entry.used = self.clock;
+            return entry.glyph;
+        }
+        let glyph = font.glyph(c, size);
+        let last_use = |entry: &&mut Option<Entry>| entry.map_or(0, |entry| entry.used);
+        if let Some(oldest) = self.entries.iter_mut().min_by_key(last_use) {
+            *oldest = Some(Entry { c, size, glyph, used: self.clock });
+        }
+        glyph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::font::Metrics;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A font that counts its lookups. The width of a glyph tells its char and size apart, and
+    /// it lacks the glyph of `?`.
+    #[derive(Default)]
+    struct CountingFont {
+        lookups: AtomicUsize,
+    }
+
+    impl CountingFont {
+        /// Returns the number of lookups since the last call.
+        fn take(&self) -> usize {
+            self.lookups.swap(0, Ordering::Relaxed)
+        }
+    }
+
+    impl FontProvider for CountingFont {
+        fn metrics(&self, size: usize) -> Metrics {
+            Metrics { width: size / 2, height: size, ascent: size }
+        }
+
+        fn glyph(&self, c: char, size: usize) -> Option<FontGlyph> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            (c != '?').then(|| FontGlyph::new(&[], c as usize * 100 + size))
+        }
+    }
+
+    /// Returns the width of the glyph that `cache` returns, which identifies it.
+    fn lookup(cache: &mut GlyphCache, font: &CountingFont, c: char, size: usize) -> Option<usize> {
+        cache.glyph(font, c, size).map(|glyph| glyph.width())
+    }
+
+    #[test]
+    fn glyphs_are_looked_up_in_the_font_once() {
+        let (font, mut cache) = (CountingFont::default(), GlyphCache::new());
+        cache.load(&font, 16);
+        assert_eq!(font.take(), ASCII_LEN);
+        for _ in 0..3 {
+            assert_eq!(lookup(&mut cache, &font, 'A', 16), Some('A' as usize * 100 + 16));
+            assert_eq!(lookup(&mut cache, &font, '?', 16), None);
+            assert_eq!(lookup(&mut cache, &font, 'é', 16), Some('é' as usize * 100 + 16));
+            assert_eq!(lookup(&mut cache, &font, 'A', 24), Some('A' as usize * 100 + 24));
+        }
+        // Only the glyphs besides the ASCII ones in the text size were looked up, once each
+        assert_eq!(font.take(), 2);
+    }
+
+    #[test]
+    fn the_least_recently_used_glyph_makes_room_for_a_new_one() {
+        let (font, mut cache) = (CountingFont::default(), GlyphCache::new());
+        cache.load(&font, 16);
+        let chars: Vec<char> = ('α'..).take(LRU_ENTRIES + 1).collect();
+        for &c in &chars[..LRU_ENTRIES] {
+            lookup(&mut cache, &font, c, 16);
+        }
+        font.take();
+        // Using the oldest glyph again makes the second one the least recently used
+        lookup(&mut cache, &font, chars[0], 16);
+        lookup(&mut cache, &font, chars[LRU_ENTRIES], 16);
+        assert_eq!(font.take(), 1);
+        for &c in [chars[0]].iter().chain(&chars[2..]) {
+            assert_eq!(lookup(&mut cache, &font, c, 16), Some(c as usize * 100 + 16));
+        }
+        assert_eq!(font.take(), 0);
+        assert_eq!(lookup(&mut cache, &font, chars[1], 16), Some(chars[1] as usize * 100 + 16));
+        assert_eq!(font.take(), 1);
+    }
+
+    #[test]
+    fn loading_forgets_the_glyphs_of_the_font_before() {
+        let (font, mut cache) = (CountingFont::default(), GlyphCache::new());
+        cache.load(&font, 16);
+        lookup(&mut cache, &font, 'é', 16);
+        lookup(&mut cache, &font, 'A', 24);
+        font.take();
+
+        let other = CountingFont::default();
+        cache.load(&other, 24);
+        assert_eq!(other.take(), ASCII_LEN);
+        assert_eq!(lookup(&mut cache, &other, 'A', 24), Some('A' as usize * 100 + 24));
+        assert_eq!(lookup(&mut cache, &other, 'é', 16), Some('é' as usize * 100 + 16));
+        assert_eq!(lookup(&mut cache, &other, 'A', 16), Some('A' as usize * 100 + 16));
+        // The ASCII glyphs are in the new text size, the others come from the new font
+        assert_eq!(other.take(), 2);
+        assert_eq!(font.take(), 0);
+    }
+}
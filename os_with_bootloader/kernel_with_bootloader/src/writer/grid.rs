@@ -0,0 +1,358 @@
+use core::{
+    fmt::{self, Write},
+    mem::MaybeUninit,
+    slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::scrollback::Scrollback;
+use super::style::Style;
+
+/// Maximum number of cells the grid can hold. Enough for 1080p with the default font.
+const CAPACITY: usize = 32 * 1024;
+
+/// Number of rows from the top whose blinking cells are tracked; blinking cells further down
+/// don't blink.
+pub const BLINK_ROWS: usize = 256;
+
+/// Backing storage of the grid. It is handed out once, to the first writer that asks for it.
+static mut CELLS: MaybeUninit<[Cell; CAPACITY]> = MaybeUninit::uninit();
+static CELLS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A character cell of the text grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// The displayed character, or [Cell::CONTINUATION] if the cell is covered by a larger or
+    /// wide glyph that starts in a cell to the left or above.
+    pub c: char,
+    /// Style the character was written with.
+    pub style: Style,
+    /// Text scale of the character; a glyph of scale `n` covers `n`×`n` cells, or `2n`×`n` if it
+    /// is a wide character.
+    pub scale: u8,
+    /// Pixels the glyph is drawn below the top of the cell, so that its baseline lines up with
+    /// larger text on the same line.
+    pub shift: u8,
+}
+
+impl Cell {
+    /// Marker for cells that are covered by a glyph starting in another cell.
+    pub const CONTINUATION: char = '\0';
+
+    /// An empty cell in the default style.
+    pub const BLANK: Cell = Cell::blank(Style::DEFAULT);
+
+    /// An empty cell with the given style.
+    pub const fn blank(style: Style) -> Cell {
+        Cell::new(' ', style)
+    }
+
+    /// A cell with the given character at scale 1.
+    pub const fn new(c: char, style: Style) -> Cell {
+        Cell { c, style, scale: 1, shift: 0 }
+    }
+}
+
+/// The text of a run of cells in a row, see [super::FrameBufferWriter::get_text_region]. It
+/// compares equal to the `str` it shows, and displays as that.
+#[derive(Debug, Clone, Copy)]
+pub struct TextRow<'a> {
+    cells: &'a [Cell],
+}
+
+impl<'a> TextRow<'a> {
+    /// Creates the text of the cells, without their trailing blanks.
+    pub fn new(cells: &'a [Cell]) -> Self {
+        let len = cells.iter().rposition(|cell| !matches!(cell.c, ' ' | Cell::CONTINUATION));
+        Self { cells: &cells[..len.map_or(0, |last| last + 1)] }
+    }
+
+    /// Returns the cells that start a character, with their styles. Cells covered by wide or
+    /// scaled characters are skipped.
+    pub fn cells(&self) -> impl Iterator<Item = &'a Cell> + 'a {
+        self.cells.iter().filter(|cell| cell.c != Cell::CONTINUATION)
+    }
+
+    /// Returns the characters of the text.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        self.cells().map(|cell| cell.c)
+    }
+}
+
+impl fmt::Display for TextRow<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chars().try_for_each(|c| f.write_char(c))
+    }
+}
+
+impl PartialEq<str> for TextRow<'_> {
+    fn eq(&self, other: &str) -> bool {
+        self.chars().eq(other.chars())
+    }
+}
+
+impl PartialEq<&str> for TextRow<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+/// Rows saved at the end of the grid storage by [CellGrid::stash], so that they survive a
+/// resize.
+#[derive(Debug, Clone, Copy)]
+pub struct Stash {
+    start: usize,
+    columns: usize,
+    rows: usize,
+}
+
+impl Stash {
+    /// Returns the number of saved rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the width of the saved rows.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+}
+
+/// The characters currently displayed in the text area, one [Cell] per character cell.
+///
+/// This is the source of truth for redrawing parts of the screen, e.g. when shifting the rest of
+/// a line in insert mode.
+pub struct CellGrid {
+    cells: &'static mut [Cell],
+    columns: usize,
+    rows: usize,
+    /// One bit per row that may contain blinking cells. Rows are marked when a blinking cell is
+    /// stored or their cells are handed out mutably, and unmarked by the caller of
+    /// [CellGrid::blinking_rows] once they turn out to have none.
+    blinking: [u64; BLINK_ROWS / 64],
+}
+
+impl CellGrid {
+    /// Creates a grid backed by the static cell storage. Only the first grid gets the storage;
+    /// any later grid has a capacity of zero cells and records nothing.
+    pub fn new() -> Self {
+        let cells: &'static mut [Cell] = if CELLS_TAKEN.swap(true, Ordering::AcqRel) {
+            super::spare_storage(Cell::BLANK, CAPACITY)
+        } else {
+            let start = (&raw mut CELLS).cast::<Cell>();
+            // SAFETY: the storage is handed out only once, guarded by CELLS_TAKEN, and every cell
+            // is initialized before the slice is created.
+            unsafe {
+                for i in 0..CAPACITY {
+                    start.add(i).write(Cell::BLANK);
+                }
+                slice::from_raw_parts_mut(start, CAPACITY)
+            }
+        };
+        Self { cells, columns: 0, rows: 0, blinking: [0; BLINK_ROWS / 64] }
+    }
+
+    /// Changes the dimensions of the grid and blanks all cells. Rows that don't fit into the
+    /// storage are dropped.
+    pub fn resize(&mut self, columns: usize, rows: usize, style: Style) {
+        self.columns = columns;
+        self.rows = rows.min(self.cells.len().checked_div(columns).unwrap_or(0));
+        self.clear(style);
+    }
+
+    /// Saves the last lines of `scrollback` followed by the first `rows` rows of the grid at the
+    /// end of the storage, keeping the first `reserved` cells free for the resized grid. As
+    /// many of the latest rows as fit are saved; they stay intact until the next stash.
+    pub fn stash(&mut self, scrollback: &Scrollback, rows: usize, reserved: usize) -> Stash {
+        let columns = self.columns;
+        let room = self.cells.len().saturating_sub(reserved).checked_div(columns).unwrap_or(0);
+        let rows = rows.min(self.rows);
+        let grid_rows = rows.min(room);
+        let lines = scrollback.len().min(room - grid_rows);
+        let start = self.cells.len() - (lines + grid_rows) * columns;
+        // The grid rows may overlap the target, so they are moved before the lines are copied
+        let first = rows - grid_rows;
+        let target = start + lines * columns;
+        self.cells.copy_within(first * columns..rows * columns, target);
+        for i in 0..lines {
+            let Some(line) = scrollback.line(scrollback.len() - lines + i) else { continue };
+            let target = &mut self.cells[start + i * columns..][..columns];
+            let copied = line.len().min(columns);
+            target[..copied].copy_from_slice(&line[..copied]);
+            target[copied..].fill(Cell::BLANK);
+        }
+        Stash { start, columns, rows: lines + grid_rows }
+    }
+
+    /// Returns a row saved by the [CellGrid::stash] that returned `stash`.
+    pub fn stashed_row(&self, stash: &Stash, row: usize) -> &[Cell] {
+        &self.cells[stash.start + row * stash.columns..][..stash.columns]
+    }
+
+    /// Returns the number of columns.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Returns the number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Blanks all cells.
+    pub fn clear(&mut self, style: Style) {
+        let len = self.columns * self.rows;
+        self.cells[..len].fill(Cell::blank(style));
+        self.blinking = [0; BLINK_ROWS / 64];
+    }
+
+    /// Returns the cell at the given position, if it is inside of the grid.
+    pub fn get(&self, column: usize, row: usize) -> Option<&Cell> {
+        self.row(row)?.get(column)
+    }
+
+    /// Stores `cell` at the given position. Positions outside of the grid are ignored.
+    pub fn set(&mut self, column: usize, row: usize, cell: Cell) {
+        if column >= self.columns || row >= self.rows {
+            return;
+        }
+        self.cells[row * self.columns + column] = cell;
+        if cell.style.blink {
+            self.set_blinking(row, true);
+        }
+    }
+
+    /// Returns the cells of a row.
+    pub fn row(&self, row: usize) -> Option<&[Cell]> {
+        let start = row.checked_mul(self.columns).filter(|_| row < self.rows)?;
+        Some(&self.cells[start..start + self.columns])
+    }
+
+    /// Returns the cells of a row mutably. The row counts as possibly blinking afterwards.
+    pub fn row_mut(&mut self, row: usize) -> Option<&mut [Cell]> {
+        let start = row.checked_mul(self.columns).filter(|_| row < self.rows)?;
+        self.set_blinking(row, true);
+        Some(&mut self.cells[start..start + self.columns])
+    }
+
+    /// Returns the rows that may contain blinking cells, top to bottom.
+    pub fn blinking_rows(&self) -> impl Iterator<Item = usize> + use<> {
+        let blinking = self.blinking;
+        let rows = self.rows.min(BLINK_ROWS);
+        (0..rows).filter(move |&row| blinking[row / 64] & (1 << (row % 64)) != 0)
+    }
+
+    /// Marks a row as possibly containing blinking cells, or as containing none.
+    pub fn set_blinking(&mut self, row: usize, blinking: bool) {
+        if let Some(word) = self.blinking.get_mut(row / 64) {
+            let bit = 1 << (row % 64);
+            match blinking {
+                true => *word |= bit,
+                false => *word &= !bit,
+            }
+        }
+    }
+
+    /// Moves the marks of [CellGrid::blinking_rows] along with rows that move from `src` to
+    /// `dest`, clearing the marks of the rows that are opened.
+    fn move_blinking(&mut self, src: usize, dest: usize) {
+        let marked = self.blinking;
+        self.blinking = [0; BLINK_ROWS / 64];
+        for row in 0..self.rows.min(BLINK_ROWS) {
+            let from = (row + src).checked_sub(dest).filter(|&from| from < BLINK_ROWS);
+            if from.is_some_and(|from| marked[from / 64] & (1 << (from % 64)) != 0) {
+                self.set_blinking(row, true);
+            }
+        }
+    }
+
+    /// Shifts the cells of `row` starting at `column` to the right by `count` cells. The cells
+    /// pushed past the end of the row are dropped and the opened cells are blanked.
+    pub fn insert_cells(&mut self, column: usize, row: usize, count: usize, style: Style) {
+        if let Some(row) = self.row_mut(row) {
+            let column = column.min(row.len());
+            let tail = &mut row[column..];
+            let count = count.min(tail.len());
+            tail.copy_within(..tail.len() - count, count);
+            tail[..count].fill(Cell::blank(style));
+        }
+    }
+
+    /// Moves all rows up by `count` rows and blanks the rows opened at the bottom.
+    pub fn scroll_up(&mut self, count: usize, style: Style) {
+        let len = self.columns * self.rows;
+        let count = count.min(self.rows);
+        self.cells[..len].copy_within(count * self.columns.., 0);
+        self.cells[len - count * self.columns..len].fill(Cell::blank(style));
+        self.move_blinking(count, 0);
+    }
+
+    /// Moves all rows down by `count` rows and blanks the rows opened at the top.
+    pub fn scroll_down(&mut self, count: usize, style: Style) {
+        let len = self.columns * self.rows;
+        let count = count.min(self.rows);
+        self.cells[..len].copy_within(..len - count * self.columns, count * self.columns);
+        self.cells[..count * self.columns].fill(Cell::blank(style));
+        self.move_blinking(0, count);
+    }
+}
+
+impl Default for CellGrid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::color::Color;
+
+    /// Returns a grid of 3 columns and 5 rows that shows `a` to `e` in its rows.
+    fn lettered() -> CellGrid {
+        let mut grid = CellGrid::new();
+        grid.resize(3, 5, Style::DEFAULT);
+        for (row, c) in ('a'..='e').enumerate() {
+            grid.row_mut(row).unwrap().fill(Cell::new(c, Style::DEFAULT));
+            grid.set_blinking(row, false);
+        }
+        grid
+    }
+
+    /// Returns the text of every row.
+    fn text(grid: &CellGrid) -> Vec<String> {
+        (0..grid.rows()).map(|row| TextRow::new(grid.row(row).unwrap()).to_string()).collect()
+    }
+
+    #[test]
+    fn scrolling_up_shifts_the_rows_and_blanks_the_bottom() {
+        let mut grid = lettered();
+        let opened = Style { background: Color::BLUE, ..Style::DEFAULT };
+        grid.scroll_up(2, opened);
+        assert_eq!(text(&grid), ["ccc", "ddd", "eee", "", ""]);
+        assert!(grid.row(4).unwrap().iter().all(|&cell| cell == Cell::blank(opened)));
+        grid.scroll_up(9, Style::DEFAULT);
+        assert_eq!(text(&grid), ["", "", "", "", ""]);
+    }
+
+    #[test]
+    fn scrolling_down_shifts_the_rows_and_blanks_the_top() {
+        let mut grid = lettered();
+        let opened = Style { background: Color::BLUE, ..Style::DEFAULT };
+        grid.scroll_down(1, opened);
+        assert_eq!(text(&grid), ["", "aaa", "bbb", "ccc", "ddd"]);
+        assert!(grid.row(0).unwrap().iter().all(|&cell| cell == Cell::blank(opened)));
+        grid.scroll_down(0, Style::DEFAULT);
+        assert_eq!(text(&grid), ["", "aaa", "bbb", "ccc", "ddd"]);
+    }
+
+    #[test]
+    fn blinking_rows_move_with_their_cells() {
+        let mut grid = lettered();
+        grid.set(0, 3, Cell::new('x', Style { blink: true, ..Style::DEFAULT }));
+        grid.scroll_up(2, Style::DEFAULT);
+        assert_eq!(grid.blinking_rows().collect::<Vec<_>>(), [1]);
+        grid.scroll_down(3, Style::DEFAULT);
+        assert_eq!(grid.blinking_rows().collect::<Vec<_>>(), [4]);
+    }
+}
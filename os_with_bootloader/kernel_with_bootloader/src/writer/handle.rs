@@ -0,0 +1,90 @@
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use spin::{Mutex, MutexGuard};
+use x86_64::instructions::interrupts;
+
+use super::FrameBufferWriter;
+
+/// The way to share a [FrameBufferWriter]: a lock around an optional writer that also keeps
+/// interrupts disabled while it is held, so that a handler can't deadlock on the lock held by
+/// the code it interrupted, or draw in the middle of its output.
+pub struct ConsoleHandle {
+    writer: Mutex<Option<FrameBufferWriter>>,
+}
+
+impl ConsoleHandle {
+    /// Creates a handle without a writer.
+    pub const fn new() -> Self {
+        Self { writer: Mutex::new(None) }
+    }
+
+    /// Disables interrupts and waits for the lock.
+    pub fn lock(&self) -> ConsoleGuard<'_> {
+        let enabled = disable_interrupts();
+        ConsoleGuard { guard: ManuallyDrop::new(self.writer.lock()), enabled }
+    }
+
+    /// Like [ConsoleHandle::lock], but returns `None` instead of waiting if the lock is held.
+    pub fn try_lock(&self) -> Option<ConsoleGuard<'_>> {
+        let enabled = disable_interrupts();
+        match self.writer.try_lock() {
+            Some(guard) => Some(ConsoleGuard { guard: ManuallyDrop::new(guard), enabled }),
+            None => {
+                if enabled {
+                    interrupts::enable();
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Disables interrupts and returns whether they were enabled. Host tests run in user mode,
+/// which may not touch the interrupt flag, and have no interrupts to begin with.
+fn disable_interrupts() -> bool {
+    #[cfg(test)]
+    return false;
+    #[cfg(not(test))]
+    {
+        let enabled = interrupts::are_enabled();
+        interrupts::disable();
+        enabled
+    }
+}
+
+impl Default for ConsoleHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Access to the writer of a [ConsoleHandle]. Dropping it releases the lock and enables
+/// interrupts again if they were enabled before.
+pub struct ConsoleGuard<'a> {
+    guard: ManuallyDrop<MutexGuard<'a, Option<FrameBufferWriter>>>,
+    enabled: bool,
+}
+
+impl Deref for ConsoleGuard<'_> {
+    type Target = Option<FrameBufferWriter>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl DerefMut for ConsoleGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl Drop for ConsoleGuard<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the guard is dropped exactly once, here, and never used afterwards.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        if self.enabled {
+            interrupts::enable();
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use core::fmt::{self, Write};
+
+use super::stats::{self, Stats};
+use super::WRITER;
+
+/// Width of the throughput overlay in cells.
+pub const WIDTH: usize = 24;
+
+/// Milliseconds between updates of the overlay.
+const INTERVAL_MS: u64 = 1000;
+
+/// State of the throughput overlay: the text it shows, and the counters at the start of the
+/// current measurement interval.
+pub struct ThroughputOverlay {
+    text: [u8; WIDTH],
+    len: usize,
+    sample_time: u64,
+    sample: Stats,
+}
+
+impl ThroughputOverlay {
+    pub fn new(now: u64, stats: Stats) -> Self {
+        let mut overlay = Self { text: [0; WIDTH], len: 0, sample_time: now, sample: stats };
+        overlay.set_text(format_args!("measuring..."));
+        overlay
+    }
+
+    /// Returns the text that is shown.
+    pub fn text(&self) -> &str {
+        core::str::from_utf8(&self.text[..self.len]).unwrap_or_default()
+    }
+
+    /// Recomputes the rates if an interval has passed since the last update. Returns whether
+    /// the text changed.
+    pub fn update(&mut self, now: u64, stats: Stats) -> bool {
+        let elapsed = now.saturating_sub(self.sample_time);
+        if elapsed < INTERVAL_MS {
+            return false;
+        }
+        let since = |now: u64, then: u64| stats::rate(now.saturating_sub(then), elapsed);
+        let chars = since(stats.chars_written, self.sample.chars_written);
+        let scrolls = since(stats.scrolls, self.sample.scrolls);
+        self.set_text(format_args!("{:>7} ch/s {:>5} sc/s", chars, scrolls));
+        (self.sample_time, self.sample) = (now, stats);
+        true
+    }
+
+    /// Starts the current interval over with the given counters, e.g. after they were reset.
+    pub fn restart(&mut self, stats: Stats) {
+        self.sample = stats;
+    }
+
+    fn set_text(&mut self, args: fmt::Arguments) {
+        self.len = 0;
+        let _ = self.write_fmt(args);
+    }
+}
+
+impl Write for ThroughputOverlay {
+    /// Appends ASCII text, dropping anything past [WIDTH].
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes().filter(u8::is_ascii) {
+            if let Some(target) = self.text.get_mut(self.len) {
+                *target = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Shows the mouse pointer of the global [WRITER] with its tip at `(x, y)`, or moves it there.
+pub fn show_cursor(x: usize, y: usize) {
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.show_pointer(x, y);
+    }
+}
+
+/// Removes the mouse pointer of the global [WRITER].
+pub fn hide_cursor() {
+    if let Some(writer) = WRITER.lock().as_mut() {
+        writer.hide_pointer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns counters with the given number of chars written and scrolls.
+    fn stats(chars_written: u64, scrolls: u64) -> Stats {
+        Stats { chars_written, scrolls, ..Stats::default() }
+    }
+
+    #[test]
+    fn rates_are_computed_over_the_time_since_the_last_update() {
+        let mut overlay = ThroughputOverlay::new(10_000, Stats::default());
+        assert_eq!(overlay.text(), "measuring...");
+        assert!(!overlay.update(10_999, stats(500, 5)));
+        assert_eq!(overlay.text(), "measuring...");
+        // A late update measures over all of the time that passed
+        assert!(overlay.update(12_000, stats(5000, 3)));
+        assert_eq!(overlay.text(), "   2500 ch/s     1 sc/s");
+        assert!(overlay.update(13_000, stats(5120, 13)));
+        assert_eq!(overlay.text(), "    120 ch/s    10 sc/s");
+        // After a reset of the counters, they count from 0 again
+        overlay.restart(Stats::default());
+        assert!(overlay.update(14_500, stats(300, 0)));
+        assert_eq!(overlay.text(), "    200 ch/s     0 sc/s");
+    }
+
+    #[test]
+    fn the_text_is_cut_to_the_width_of_the_overlay() {
+        let mut overlay = ThroughputOverlay::new(0, Stats::default());
+        assert!(overlay.update(1000, stats(u64::MAX, u64::MAX)));
+        assert_eq!(overlay.text(), "18446744073709551 ch/s 1");
+    }
+}
@@ -0,0 +1,103 @@
+use core::fmt;
+
+use super::gauge::Gauge;
+
+/// Cells of a sparkline, from one eighth to the full height.
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Width of the bars of a [BarChart] in cells.
+const BAR_WIDTH: usize = 24;
+
+/// Displays values as a row of vertical eighth blocks scaled to the largest value, see
+/// [sparkline].
+#[derive(Debug, Clone, Copy)]
+pub struct Sparkline<'a> {
+    values: &'a [u32],
+    width: usize,
+}
+
+/// Returns a sparkline of at most `width` cells. With more values than cells, each cell shows
+/// the largest of the values that fall into it. Zeros are blank, other values fill at least
+/// one eighth.
+pub fn sparkline(values: &[u32], width: usize) -> Sparkline<'_> {
+    Sparkline { values, width }
+}
+
+impl Sparkline<'_> {
+    /// Returns the largest of the values that fall into the given cell of `cells`.
+    fn cell(&self, cell: usize, cells: usize) -> u32 {
+        let len = self.values.len();
+        let (start, end) = (cell * len / cells, ((cell + 1) * len).div_ceil(cells));
+        self.values[start..end.max(start + 1)].iter().copied().max().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for Sparkline<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cells = self.values.len().min(self.width);
+        let max = u64::from(self.values.iter().copied().max().unwrap_or(0));
+        for cell in 0..cells {
+            let value = u64::from(self.cell(cell, cells));
+            match (value * LEVELS.len() as u64).div_ceil(max.max(1)) as usize {
+                0 => f.write_str(" ")?,
+                level => write!(f, "{}", LEVELS[level - 1])?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Displays labeled horizontal bars scaled to the largest value, one line per value with the
+/// value at its end, see [bar_chart].
+#[derive(Debug, Clone, Copy)]
+pub struct BarChart<'a> {
+    labels: &'a [&'a str],
+    values: &'a [u32],
+}
+
+/// Returns a bar chart of the values with their labels. Values without a label get none, and
+/// labels without a value are dropped. Without any labels, the lines start with the bars.
+pub fn bar_chart<'a>(labels: &'a [&'a str], values: &'a [u32]) -> BarChart<'a> {
+    BarChart { labels, values }
+}
+
+impl fmt::Display for BarChart<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = |index: usize| self.labels.get(index).copied().unwrap_or_default();
+        let labels = 0..self.values.len();
+        let label_width = labels.map(|index| label(index).chars().count()).max().unwrap_or(0);
+        let max = self.values.iter().copied().max().unwrap_or(0) as usize;
+        for (index, &value) in self.values.iter().enumerate() {
+            if label_width > 0 {
+                write!(f, "{:<width$} ", label(index), width = label_width)?;
+            }
+            writeln!(f, "{} {}", Gauge::new(value as usize, max, BAR_WIDTH), value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn sparklines_scale_to_the_largest_value() {
+        assert_eq!(sparkline(&[0, 1, 2, 3, 4, 5, 6, 7, 8], 9).to_string(), " ▁▂▃▄▅▆▇█");
+        // Small values fill at least one eighth, and the levels round up
+        assert_eq!(sparkline(&[1, 100, 13, 50, 0], 5).to_string(), "▁█▂▄ ");
+        assert_eq!(sparkline(&[0, 0, 0], 5).to_string(), "   ");
+        assert_eq!(sparkline(&[], 5).to_string(), "");
+    }
+
+    #[test]
+    fn sparklines_with_more_values_than_cells_show_the_largest_of_each_cell() {
+        let values = [8, 1, 1, 2, 0, 0, 4, 3];
+        assert_eq!(sparkline(&values, 4).to_string(), "█▂ ▄");
+        // Cells that don't divide the values evenly share the values at their borders
+        assert_eq!(sparkline(&values, 3).to_string(), "█▂▄");
+        assert_eq!(sparkline(&values, 8).to_string(), "█▁▁▂  ▄▃");
+        assert_eq!(sparkline(&values, 1).to_string(), "█");
+    }
+}
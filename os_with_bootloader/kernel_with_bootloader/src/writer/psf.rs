@@ -0,0 +1,332 @@
+use core::fmt;
+
+use spin::Once;
+
+use super::font::{FontGlyph, FontProvider, Metrics, MAX_BITS_WIDTH};
+
+/// Magic number at the start of a PSF2 file.
+const MAGIC: u32 = 0x864a_b572;
+
+/// Length of the fixed part of the header, which `headersize` may extend.
+const HEADER_LEN: usize = 32;
+
+/// Bit of the header flags that says the glyphs are followed by a Unicode table.
+const HAS_UNICODE_TABLE: u32 = 1;
+
+/// Bytes of the Unicode table that end the entry of a glyph and start a sequence of chars
+/// drawn with a single glyph, which are skipped.
+const ENTRY_END: u8 = 0xff;
+const SEQUENCE_START: u8 = 0xfe;
+
+/// Chars below this are looked up in a table; others scan the Unicode table of the file.
+const DIRECT_CHARS: usize = 0x800;
+
+/// Entry of the lookup table for chars that have no glyph.
+const NO_GLYPH: u16 = u16::MAX;
+
+/// Reasons why [Psf2Font::parse] rejected a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsfError {
+    /// The file is shorter than the header or than the glyphs it announces.
+    Truncated { required: usize, len: usize },
+    /// The file doesn't start with the PSF2 magic number.
+    BadMagic(u32),
+    /// The header has a version other than 0, or a size smaller than the fixed part.
+    UnsupportedHeader { version: u32, header_len: usize },
+    /// The glyphs are empty, wider than [MAX_BITS_WIDTH], or don't fit into their size.
+    InvalidGlyphSize { width: usize, height: usize, bytes_per_glyph: usize },
+    /// There are no glyphs, or more than the lookup table can index.
+    InvalidGlyphCount(usize),
+    /// An entry of the Unicode table isn't UTF-8.
+    InvalidUnicodeTable { glyph: usize },
+}
+
+impl fmt::Display for PsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Truncated { required, len } => {
+                write!(f, "file of {} bytes is shorter than the required {}", len, required)
+            }
+            Self::BadMagic(magic) => write!(f, "bad magic number {:#x}", magic),
+            Self::UnsupportedHeader { version, header_len } => {
+                write!(f, "unsupported header of version {} and {} bytes", version, header_len)
+            }
+            Self::InvalidGlyphSize { width, height, bytes_per_glyph } => write!(
+                f,
+                "{}x{} glyphs don't fit into {} bytes or are not supported",
+                width, height, bytes_per_glyph
+            ),
+            Self::InvalidGlyphCount(count) => write!(f, "unsupported number of glyphs {}", count),
+            Self::InvalidUnicodeTable { glyph } => {
+                write!(f, "Unicode table entry of glyph {} is not UTF-8", glyph)
+            }
+        }
+    }
+}
+
+/// A font in the PSF2 format of the Linux console, read in place from memory that lives as long
+/// as the kernel, e.g. the ramdisk.
+pub struct Psf2Font {
+    glyphs: &'static [u8],
+    count: usize,
+    bytes_per_glyph: usize,
+    width: usize,
+    height: usize,
+    ascent: usize,
+    /// The Unicode table after the glyphs, if the file has one. Without it, the glyph of a char
+    /// is the one at the index of its code point.
+    unicode: Option<&'static [u8]>,
+    /// Glyph index of every char below [DIRECT_CHARS].
+    direct: [u16; DIRECT_CHARS],
+}
+
+/// Reads the little-endian u32 at `offset`, which must be in bounds.
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let bytes = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
+    u32::from_le_bytes(bytes)
+}
+
+/// Calls `f` with every char of the Unicode table and the index of its glyph, stopping when it
+/// returns true, and fails on entries that aren't UTF-8. Chars that are part of a sequence are
+/// skipped, and so are entries beyond the last glyph.
+fn for_each_mapping(
+    table: &[u8],
+    count: usize,
+    mut f: impl FnMut(char, usize) -> bool,
+) -> Result<(), PsfError> {
+    for (glyph, entry) in table.split(|&byte| byte == ENTRY_END).take(count).enumerate() {
+        let chars = entry.split(|&byte| byte == SEQUENCE_START).next().unwrap_or_default();
+        let chars =
+            core::str::from_utf8(chars).map_err(|_| PsfError::InvalidUnicodeTable { glyph })?;
+        for c in chars.chars() {
+            if f(c, glyph) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Psf2Font {
+    /// Checks the header and the Unicode table of a PSF2 file and builds the lookup table of its
+    /// glyphs, which are read from `data` when they are drawn.
+    pub fn parse(data: &'static [u8]) -> Result<Self, PsfError> {
+        if data.len() < HEADER_LEN {
+            return Err(PsfError::Truncated { required: HEADER_LEN, len: data.len() });
+        }
+        let field = |index: usize| read_u32(data, index * 4);
+        if field(0) != MAGIC {
+            return Err(PsfError::BadMagic(field(0)));
+        }
+        let (version, header_len) = (field(1), field(2) as usize);
+        if version != 0 || header_len < HEADER_LEN {
+            return Err(PsfError::UnsupportedHeader { version, header_len });
+        }
+        let (flags, count, bytes_per_glyph) = (field(3), field(4) as usize, field(5) as usize);
+        let (height, width) = (field(6) as usize, field(7) as usize);
+        let bitmap_len = width.div_ceil(8) * height;
+        if width == 0 || height == 0 || width > MAX_BITS_WIDTH || bitmap_len > bytes_per_glyph {
+            return Err(PsfError::InvalidGlyphSize { width, height, bytes_per_glyph });
+        }
+        if count == 0 || count >= usize::from(NO_GLYPH) {
+            return Err(PsfError::InvalidGlyphCount(count));
+        }
+        let end = bytes_per_glyph
+            .checked_mul(count)
+            .and_then(|len| len.checked_add(header_len))
+            .unwrap_or(usize::MAX);
+        if data.len() < end {
+            return Err(PsfError::Truncated { required: end, len: data.len() });
+        }
+
+        let unicode = (flags & HAS_UNICODE_TABLE != 0).then(|| &data[end..]);
+        let mut direct = [NO_GLYPH; DIRECT_CHARS];
+        match unicode {
+            Some(table) => for_each_mapping(table, count, |c, glyph| {
+                if let Some(entry) = direct.get_mut(c as usize) {
+                    *entry = glyph as u16;
+                }
+                false
+            })?,
+            None => {
+                for (c, entry) in direct.iter_mut().take(count).enumerate() {
+                    *entry = c as u16;
+                }
+            }
+        }
+
+        let glyphs = &data[header_len..end];
+        let mut font =
+            Self { glyphs, count, bytes_per_glyph, width, height, ascent: height, unicode, direct };
+        if let Some(glyph) = font.glyph('H', height) {
+            let mut buffer = [0; MAX_BITS_WIDTH];
+            let covered =
+                |y: &usize| glyph.row(*y, &mut buffer).iter().any(|&coverage| coverage > 0);
+            font.ascent = (0..glyph.height()).rev().find(covered).map_or(height, |row| row + 1);
+        }
+        Ok(font)
+    }
+
+    /// Returns the index of the glyph of `c`.
+    fn find(&self, c: char) -> Option<usize> {
+        if let Some(&glyph) = self.direct.get(c as usize) {
+            return (glyph != NO_GLYPH).then_some(usize::from(glyph));
+        }
+        let Some(table) = self.unicode else {
+            return ((c as usize) < self.count).then_some(c as usize);
+        };
+        let mut found = None;
+        // The table was checked when the font was parsed
+        let _ = for_each_mapping(table, self.count, |mapped, glyph| {
+            found = (mapped == c).then_some(glyph);
+            found.is_some()
+        });
+        found
+    }
+}
+
+impl FontProvider for Psf2Font {
+    /// The font has a single size. The ascent is measured as the bottom of the coverage of `H`.
+    fn metrics(&self, _size: usize) -> Metrics {
+        Metrics { width: self.width, height: self.height, ascent: self.ascent }
+    }
+
+    fn glyph(&self, c: char, _size: usize) -> Option<FontGlyph> {
+        let start = self.find(c)? * self.bytes_per_glyph;
+        let bits = &self.glyphs[start..start + self.bytes_per_glyph];
+        Some(FontGlyph::from_bits(bits, self.width, self.height))
+    }
+}
+
+static FONT: Once<Psf2Font> = Once::new();
+
+/// Parses a PSF2 file, see [Psf2Font::parse], and keeps the font for the kernel's lifetime, so
+/// that writers can draw with it. Only the first font that loads is kept, later calls return
+/// it as well.
+pub fn load(data: &'static [u8]) -> Result<&'static Psf2Font, PsfError> {
+    if let Some(font) = FONT.get() {
+        return Ok(font);
+    }
+    let font = Psf2Font::parse(data)?;
+    Ok(FONT.call_once(|| font))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Returns a PSF2 file with `width`×`height` glyphs of the given bits, which are padded to the
+    /// size of a glyph, followed by `unicode` if it is set.
+    pub(in crate::writer) fn fixture(
+        width: usize,
+        height: usize,
+        glyphs: &[&[u8]],
+        unicode: Option<&[u8]>,
+    ) -> &'static [u8] {
+        let bytes_per_glyph = width.div_ceil(8) * height;
+        let flags = u32::from(unicode.is_some()) * HAS_UNICODE_TABLE;
+        let header = [MAGIC, 0, HEADER_LEN as u32, flags, glyphs.len() as u32];
+        let header = header.into_iter().chain([bytes_per_glyph, height, width].map(|n| n as u32));
+        let mut file: Vec<u8> = header.flat_map(u32::to_le_bytes).collect();
+        for glyph in glyphs {
+            let start = file.len();
+            file.extend_from_slice(glyph);
+            file.resize(start + bytes_per_glyph, 0);
+        }
+        file.extend_from_slice(unicode.unwrap_or_default());
+        Vec::leak(file)
+    }
+
+    /// An 8×8 `H`, which leaves the bottom two rows empty.
+    pub(in crate::writer) const H: [u8; 8] = [0x42, 0x42, 0x42, 0x7e, 0x42, 0x42, 0, 0];
+
+    /// A font of a blank, an `H` and a box, with a Unicode table that maps chars on both sides
+    /// of [DIRECT_CHARS] and a sequence.
+    pub(in crate::writer) fn mapped_font() -> &'static [u8] {
+        // The chars of every glyph, then its sequences
+        let entries: [&[&str]; 3] = [&[" \u{a0}"], &["H", "H\u{30a}"], &["\u{25a1}\u{2610}"]];
+        let mut unicode = Vec::new();
+        for entry in entries {
+            for (index, chars) in entry.iter().enumerate() {
+                if index > 0 {
+                    unicode.push(SEQUENCE_START);
+                }
+                unicode.extend_from_slice(chars.as_bytes());
+            }
+            unicode.push(ENTRY_END);
+        }
+        let boxed = [0xff, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xff];
+        fixture(8, 8, &[&[], &H, &boxed], Some(Vec::leak(unicode)))
+    }
+
+    /// Returns the coverage of `glyph` as rows of `#` and `.`.
+    fn rows(glyph: FontGlyph) -> Vec<String> {
+        let mut buffer = [0; MAX_BITS_WIDTH];
+        let row = |y| {
+            let coverage = glyph.row(y, &mut buffer);
+            coverage.iter().map(|&coverage| if coverage == 255 { '#' } else { '.' }).collect()
+        };
+        (0..glyph.height()).map(row).collect()
+    }
+
+    #[test]
+    fn maps_chars_through_the_unicode_table() {
+        let font = Psf2Font::parse(mapped_font()).unwrap();
+        assert_eq!(font.metrics(16), Metrics { width: 8, height: 8, ascent: 6 });
+        let glyph = |c| font.glyph(c, 8).map(rows);
+        assert_eq!(glyph(' ').unwrap(), ["........"; 8]);
+        assert_eq!(glyph(' '), glyph('\u{a0}'));
+        assert_eq!(glyph('H').unwrap()[..4], [".#....#.", ".#....#.", ".#....#.", ".######."]);
+        // Beyond the lookup table, and in the entry of the same glyph
+        assert_eq!(glyph('\u{2610}'), glyph('\u{25a1}'));
+        assert_eq!(glyph('\u{25a1}').unwrap()[1], "#......#");
+        // A char of a sequence has no glyph of its own
+        assert_eq!(glyph('\u{30a}'), None);
+        assert_eq!(glyph('x'), None);
+    }
+
+    #[test]
+    fn indexes_glyphs_by_code_point_without_a_unicode_table() {
+        // 12 pixels wide, so that every row is padded to two bytes
+        let bar = [0xff, 0xf0, 0x80, 0x10, 0x80, 0x10, 0xff, 0xf0];
+        let font = Psf2Font::parse(fixture(12, 4, &[&[], &bar], None)).unwrap();
+        assert_eq!(font.metrics(8), Metrics { width: 12, height: 4, ascent: 4 });
+        let bar = font.glyph('\u{1}', 8).map(rows).unwrap();
+        assert_eq!(bar, ["############", "#..........#", "#..........#", "############"]);
+        assert!(font.glyph('\u{0}', 8).is_some());
+        assert!(font.glyph('\u{2}', 8).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let error = |file: &'static [u8]| Psf2Font::parse(file).err();
+        let patched = |index: usize, value: u32| {
+            let mut file = mapped_font().to_vec();
+            file[index * 4..index * 4 + 4].copy_from_slice(&value.to_le_bytes());
+            error(Vec::leak(file))
+        };
+        let len = mapped_font().len();
+        let truncated = |required, len| Some(PsfError::Truncated { required, len });
+        assert_eq!(error(&mapped_font()[..31]), truncated(32, 31));
+        // The header announces three glyphs of 8 bytes
+        assert_eq!(error(&mapped_font()[..55]), truncated(56, 55));
+        assert_eq!(patched(0, 0x0436), Some(PsfError::BadMagic(0x0436)));
+        let header =
+            |version, header_len| Some(PsfError::UnsupportedHeader { version, header_len });
+        assert_eq!(patched(1, 1), header(1, 32));
+        assert_eq!(patched(2, 16), header(0, 16));
+        let size = |width, height, bytes_per_glyph| {
+            Some(PsfError::InvalidGlyphSize { width, height, bytes_per_glyph })
+        };
+        assert_eq!(patched(7, 0), size(0, 8, 8));
+        assert_eq!(patched(7, 33), size(33, 8, 8));
+        assert_eq!(patched(7, 9), size(9, 8, 8));
+        assert_eq!(patched(6, 0), size(8, 0, 8));
+        assert_eq!(patched(4, 0), Some(PsfError::InvalidGlyphCount(0)));
+        assert_eq!(patched(4, 0x1_0000), Some(PsfError::InvalidGlyphCount(0x1_0000)));
+        assert_eq!(patched(4, 0x8000), truncated(32 + 8 * 0x8000, len));
+        let mut file = mapped_font().to_vec();
+        file[32 + 24] = 0xc3;
+        assert_eq!(error(Vec::leak(file)), Some(PsfError::InvalidUnicodeTable { glyph: 0 }));
+    }
+}
@@ -0,0 +1,38 @@
+use super::rect::Rect;
+use bootloader_api::info::FrameBufferInfo;
+
+/// Mutable access to the pixels of a rectangle of the framebuffer, handed out by
+/// [super::FrameBufferWriter::with_raw_region]. Pixels outside of the rectangle can't be
+/// reached through it.
+pub struct RawRegion<'a> {
+    framebuffer: &'a mut [u8],
+    info: FrameBufferInfo,
+    rect: Rect,
+}
+
+impl<'a> RawRegion<'a> {
+    pub(super) fn new(framebuffer: &'a mut [u8], info: FrameBufferInfo, rect: Rect) -> Self {
+        Self { framebuffer, info, rect }
+    }
+
+    /// Returns the accessible rectangle in screen coordinates.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Returns the layout of the framebuffer, which describes the encoding of the pixels.
+    pub fn info(&self) -> &FrameBufferInfo {
+        &self.info
+    }
+
+    /// Returns the bytes of row `y` of the rectangle, counted from its top edge, or `None` if
+    /// the row is outside of it.
+    pub fn row_mut(&mut self, y: usize) -> Option<&mut [u8]> {
+        if y >= self.rect.height {
+            return None;
+        }
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let start = ((self.rect.y + y) * self.info.stride + self.rect.x) * bytes_per_pixel;
+        self.framebuffer.get_mut(start..start + self.rect.width * bytes_per_pixel)
+    }
+}
@@ -0,0 +1,44 @@
+/// An axis-aligned rectangle in pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// Creates a rectangle from its top left corner and its size.
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Returns the x coordinate just past the right edge, or `usize::MAX` if it is beyond.
+    pub const fn right(&self) -> usize {
+        self.x.saturating_add(self.width)
+    }
+
+    /// Returns the y coordinate just past the bottom edge, or `usize::MAX` if it is beyond.
+    pub const fn bottom(&self) -> usize {
+        self.y.saturating_add(self.height)
+    }
+
+    /// Returns whether the rectangle covers no pixels.
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns whether the point `(x, y)` lies inside of the rectangle.
+    pub const fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Returns the area covered by both rectangles. The result is empty if they don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right()).max(x);
+        let bottom = self.bottom().min(other.bottom()).max(y);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+}
@@ -0,0 +1,51 @@
+use super::rect::Rect;
+
+/// How far the output is rotated clockwise on the physical framebuffer, to make up for a panel
+/// that is mounted sideways or upside down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    /// Parses the angle in degrees: `0`, `90`, `180` or `270`.
+    pub fn from_degrees(degrees: &str) -> Option<Self> {
+        match degrees {
+            "0" => Some(Self::None),
+            "90" => Some(Self::Cw90),
+            "180" => Some(Self::Cw180),
+            "270" => Some(Self::Cw270),
+            _ => None,
+        }
+    }
+
+    /// Returns whether logical rows run along physical columns, which swaps width and height.
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Self::Cw90 | Self::Cw270)
+    }
+
+    /// Maps the logical position `(x, y)` to the physical framebuffer of the given size.
+    pub fn to_physical(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Self::None => (x, y),
+            Self::Cw90 => (width - 1 - y, x),
+            Self::Cw180 => (width - 1 - x, height - 1 - y),
+            Self::Cw270 => (y, height - 1 - x),
+        }
+    }
+
+    /// Maps a non-empty logical rectangle to the physical rectangle that holds its pixels.
+    pub fn rect_to_physical(self, rect: Rect, width: usize, height: usize) -> Rect {
+        if rect.width == 0 || rect.height == 0 {
+            return Rect::new(0, 0, 0, 0);
+        }
+        let (x0, y0) = self.to_physical(rect.x, rect.y, width, height);
+        let (x1, y1) = self.to_physical(rect.right() - 1, rect.bottom() - 1, width, height);
+        let (left, top) = (x0.min(x1), y0.min(y1));
+        Rect::new(left, top, x0.max(x1) - left + 1, y0.max(y1) - top + 1)
+    }
+}
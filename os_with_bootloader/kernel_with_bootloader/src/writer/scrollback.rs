@@ -0,0 +1,180 @@
+use core::{
+    mem::MaybeUninit,
+    slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use super::grid::Cell;
+
+/// Maximum number of cells the scrollback can hold. About 130 lines at 1080p with the default
+/// font.
+const CAPACITY: usize = 32 * 1024;
+
+/// Backing storage of the scrollback. It is handed out once, to the first writer that asks for
+/// it.
+static mut LINES: MaybeUninit<[Cell; CAPACITY]> = MaybeUninit::uninit();
+static LINES_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// The lines that scrolled off the top of the text area, oldest first. When full, the oldest
+/// line is overwritten.
+pub struct Scrollback {
+    cells: &'static mut [Cell],
+    columns: usize,
+    /// Number of lines that are kept: as many as fit into the storage, up to `max_lines`.
+    capacity: usize,
+    max_lines: usize,
+    /// Index of the oldest line in the storage.
+    start: usize,
+    len: usize,
+}
+
+impl Scrollback {
+    /// Creates a scrollback backed by the static storage. Only the first scrollback gets the
+    /// storage; any later one has a capacity of zero lines.
+    pub fn new() -> Self {
+        let cells: &'static mut [Cell] = if LINES_TAKEN.swap(true, Ordering::AcqRel) {
+            super::spare_storage(Cell::BLANK, CAPACITY)
+        } else {
+            let start = (&raw mut LINES).cast::<Cell>();
+            // SAFETY: the storage is handed out only once, guarded by LINES_TAKEN, and every cell
+            // is initialized before the slice is created.
+            unsafe {
+                for i in 0..CAPACITY {
+                    start.add(i).write(Cell::BLANK);
+                }
+                slice::from_raw_parts_mut(start, CAPACITY)
+            }
+        };
+        Self { cells, columns: 0, capacity: 0, max_lines: usize::MAX, start: 0, len: 0 }
+    }
+
+    /// Changes the width of the lines and drops all of them.
+    pub fn resize(&mut self, columns: usize) {
+        self.columns = columns;
+        let fitting = self.cells.len().checked_div(columns).unwrap_or(0);
+        self.capacity = fitting.min(self.max_lines);
+        self.start = 0;
+        self.len = 0;
+    }
+
+    /// Limits the number of kept lines, which is unlimited by default, and drops all of them.
+    /// More lines than fit into the storage are never kept.
+    pub fn set_max_lines(&mut self, lines: usize) {
+        self.max_lines = lines;
+        self.resize(self.columns);
+    }
+
+    /// Returns the number of lines that are kept before the oldest one is overwritten.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of stored lines.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends a line, which is cut off or padded with blanks to the width of the scrollback.
+    pub fn push(&mut self, line: &[Cell]) {
+        if self.capacity == 0 {
+            return;
+        }
+        let index = (self.start + self.len) % self.capacity;
+        if self.len == self.capacity {
+            self.start = (self.start + 1) % self.capacity;
+        } else {
+            self.len += 1;
+        }
+        let target = &mut self.cells[index * self.columns..(index + 1) * self.columns];
+        let copied = line.len().min(target.len());
+        target[..copied].copy_from_slice(&line[..copied]);
+        target[copied..].fill(Cell::BLANK);
+    }
+
+    /// Returns the line with the given index, counted from the oldest stored line.
+    pub fn line(&self, index: usize) -> Option<&[Cell]> {
+        if index >= self.len {
+            return None;
+        }
+        let index = (self.start + index) % self.capacity;
+        Some(&self.cells[index * self.columns..(index + 1) * self.columns])
+    }
+
+    /// Returns the line with the given index mutably, see [Scrollback::line].
+    pub fn line_mut(&mut self, index: usize) -> Option<&mut [Cell]> {
+        if index >= self.len {
+            return None;
+        }
+        let index = (self.start + index) % self.capacity;
+        Some(&mut self.cells[index * self.columns..(index + 1) * self.columns])
+    }
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::grid::TextRow;
+    use crate::writer::style::Style;
+
+    /// Returns the text of every stored line, oldest first.
+    fn text(scrollback: &Scrollback) -> Vec<String> {
+        let lines = (0..scrollback.len()).map_while(|line| scrollback.line(line));
+        lines.map(|cells| TextRow::new(cells).to_string()).collect()
+    }
+
+    /// Returns a line of a char per cell.
+    fn line(text: &str) -> Vec<Cell> {
+        text.chars().map(|c| Cell::new(c, Style::DEFAULT)).collect()
+    }
+
+    #[test]
+    fn the_oldest_lines_make_room_beyond_the_limit() {
+        let mut scrollback = Scrollback::new();
+        scrollback.resize(3);
+        scrollback.set_max_lines(4);
+        assert_eq!(scrollback.capacity(), 4);
+        for text in ["a", "bb", "ccc", "dddd", "e", "f"] {
+            scrollback.push(&line(text));
+        }
+        // Lines are cut off at the width, and the first two were overwritten
+        assert_eq!(text(&scrollback), ["ccc", "ddd", "e", "f"]);
+        assert_eq!(scrollback.line(4), None);
+
+        // Changing the limit drops the lines
+        scrollback.set_max_lines(2);
+        assert!(scrollback.is_empty());
+        for text in ["x", "y", "z"] {
+            scrollback.push(&line(text));
+        }
+        assert_eq!(text(&scrollback), ["y", "z"]);
+        scrollback.set_max_lines(0);
+        scrollback.push(&line("x"));
+        assert!(scrollback.is_empty());
+    }
+
+    #[test]
+    fn the_storage_caps_the_limit_at_every_width() {
+        let mut scrollback = Scrollback::new();
+        scrollback.resize(1000);
+        assert_eq!(scrollback.capacity(), CAPACITY / 1000);
+        scrollback.set_max_lines(10);
+        assert_eq!(scrollback.capacity(), 10);
+        scrollback.set_max_lines(100);
+        assert_eq!(scrollback.capacity(), CAPACITY / 1000);
+        scrollback.push(&line("x"));
+        // Resizing drops the lines but keeps the limit
+        scrollback.resize(CAPACITY / 8);
+        assert!(scrollback.is_empty());
+        assert_eq!(scrollback.capacity(), 8);
+    }
+}
@@ -0,0 +1,82 @@
+use super::grid::Cell;
+use super::rect::Rect;
+use super::rotation::Rotation;
+use bootloader_api::info::{FrameBufferInfo, PixelFormat};
+
+/// Largest number of grid cells that a [RegionSnapshot] keeps. Of larger regions, only the
+/// pixels are saved.
+pub const MAX_CELLS: usize = 256;
+
+/// The layout of the framebuffer that a snapshot was taken with. Its pixels only fit into a
+/// framebuffer with the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Geometry {
+    width: usize,
+    height: usize,
+    stride: usize,
+    bytes_per_pixel: usize,
+    pixel_format: PixelFormat,
+    rotation: Rotation,
+    hidpi_scale: usize,
+}
+
+impl Geometry {
+    pub(super) fn new(info: &FrameBufferInfo, rotation: Rotation, hidpi_scale: usize) -> Self {
+        let (width, height, stride) = (info.width, info.height, info.stride);
+        let (bytes_per_pixel, pixel_format) = (info.bytes_per_pixel, info.pixel_format);
+        Self { width, height, stride, bytes_per_pixel, pixel_format, rotation, hidpi_scale }
+    }
+}
+
+/// Where the grid cells are on the screen. Saved cells only fit into a grid with the same one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct CellLayout {
+    /// Pixel position of the top left corner of the first cell.
+    pub origin: (usize, usize),
+    /// Width and height of a cell in pixels.
+    pub cell_size: (usize, usize),
+    /// Number of columns and rows of the grid.
+    pub grid_size: (usize, usize),
+}
+
+/// The grid cells that lie completely inside of a saved region.
+pub(super) struct SavedCells {
+    pub layout: CellLayout,
+    /// The saved cells, in columns and rows.
+    pub area: Rect,
+    /// The cells of `area`, row by row.
+    pub cells: [Cell; MAX_CELLS],
+}
+
+/// A rectangle of the screen saved by [super::FrameBufferWriter::save_region], which
+/// [super::FrameBufferWriter::restore_region] puts back. The pixels are kept in a buffer that
+/// the caller provides; the grid cells inside of the rectangle are kept in the snapshot itself
+/// if there are at most [MAX_CELLS].
+pub struct RegionSnapshot<'a> {
+    pub(super) rect: Rect,
+    /// The framebuffer pixels that `rect` covers, which are neither rotated nor scaled.
+    pub(super) physical: Rect,
+    pub(super) geometry: Geometry,
+    /// The buffer whose first bytes are the framebuffer bytes of the rows of `physical`, back
+    /// to back.
+    pub(super) pixels: &'a mut [u8],
+    pub(super) cells: Option<SavedCells>,
+}
+
+impl<'a> RegionSnapshot<'a> {
+    /// Returns the saved rectangle in screen coordinates, which is cut to the screen.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Returns whether the grid cells inside of the rectangle were saved along with the pixels.
+    pub fn has_cells(&self) -> bool {
+        self.cells.is_some()
+    }
+
+    /// Returns the buffer that was passed to [super::FrameBufferWriter::save_region], e.g. to
+    /// save another region once this one is restored.
+    pub fn into_buffer(self) -> &'a mut [u8] {
+        self.pixels
+    }
+}
@@ -0,0 +1,70 @@
+use super::color::Color;
+use super::rect::Rect;
+
+/// Width of the pointer sprite in pixels.
+pub const WIDTH: usize = 11;
+
+/// Height of the pointer sprite in pixels.
+pub const HEIGHT: usize = 16;
+
+/// The arrow of the mouse pointer: `#` is the outline, `.` the fill, and spaces are transparent.
+const ARROW: [&[u8; WIDTH]; HEIGHT] = [
+    b"#          ",
+    b"##         ",
+    b"#.#        ",
+    b"#..#       ",
+    b"#...#      ",
+    b"#....#     ",
+    b"#.....#    ",
+    b"#......#   ",
+    b"#.......#  ",
+    b"#........# ",
+    b"#.....#####",
+    b"#..#..#    ",
+    b"#.# #..#   ",
+    b"##  #..#   ",
+    b"#    #..#  ",
+    b"      ##   ",
+];
+
+/// The mouse pointer drawn over the screen, with the pixels it covers.
+pub struct Sprite {
+    x: usize,
+    y: usize,
+    /// Framebuffer bytes of the covered pixels, row by row, or `None` for pixels outside of
+    /// the screen.
+    saved: [Option<[u8; 4]>; WIDTH * HEIGHT],
+}
+
+impl Sprite {
+    /// Creates the sprite with its tip at `(x, y)`, without any saved pixels yet.
+    pub fn new(x: usize, y: usize) -> Self {
+        Self { x, y, saved: [None; WIDTH * HEIGHT] }
+    }
+
+    /// Returns the position of the tip.
+    pub fn position(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    /// Returns the area the sprite covers.
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, WIDTH, HEIGHT)
+    }
+
+    /// Returns the color of the sprite at the screen position `(x, y)`, or `None` if it is
+    /// transparent there or outside of the sprite.
+    pub fn color_at(&self, x: usize, y: usize) -> Option<Color> {
+        let row = ARROW.get(y.checked_sub(self.y)?)?;
+        match row.get(x.checked_sub(self.x)?)? {
+            b'#' => Some(Color::BLACK),
+            b'.' => Some(Color::WHITE),
+            _ => None,
+        }
+    }
+
+    /// Returns the saved bytes of the pixel at the screen position `(x, y)` inside the sprite.
+    pub fn saved_mut(&mut self, x: usize, y: usize) -> &mut Option<[u8; 4]> {
+        &mut self.saved[(y - self.y) * WIDTH + (x - self.x)]
+    }
+}
@@ -0,0 +1,37 @@
+/// Counters of the work done by a [super::FrameBufferWriter], e.g. for measuring its
+/// throughput or checking that an operation didn't scroll. All counters saturate instead of
+/// wrapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Printable characters written at the cursor.
+    pub chars_written: u64,
+    /// Explicit newlines.
+    pub lines: u64,
+    /// Line breaks inserted because text reached the right edge.
+    pub soft_wraps: u64,
+    /// Times the text area was scrolled.
+    pub scrolls: u64,
+    /// Bytes of pixel data moved by scrolling.
+    pub scrolled_bytes: u64,
+    /// Scrolls that redrew the rows that changed instead of moving pixels.
+    pub redrawn_scrolls: u64,
+    /// Bytes copied from the back buffer to the screen while double buffering.
+    pub presented_bytes: u64,
+    /// Times the text area was cleared.
+    pub clears: u64,
+    /// Complete control sequences, whether supported or not.
+    pub escape_sequences: u64,
+    /// Control sequences that were aborted by an unexpected character.
+    pub malformed_sequences: u64,
+}
+
+/// Adds one to a counter, saturating at the maximum.
+pub(super) fn bump(counter: &mut u64) {
+    *counter = counter.saturating_add(1);
+}
+
+/// Returns the number of events per second, given how many happened in `elapsed_ms`
+/// milliseconds.
+pub fn rate(count: u64, elapsed_ms: u64) -> u64 {
+    count.saturating_mul(1000).checked_div(elapsed_ms).unwrap_or(0)
+}
@@ -0,0 +1,211 @@
+use super::grid::Cell;
+
+/// Number of fields the header band can show, see [super::FrameBufferWriter::set_status_field].
+pub const MAX_FIELDS: usize = 4;
+
+/// Maximum width of a field in cells; longer fields are cut off.
+pub const FIELD_WIDTH: usize = 32;
+
+/// Maximum length of the text of a ticker field in cells; longer text is cut off.
+pub const MAX_TICKER_LEN: usize = 128;
+
+/// How a ticker field scrolls its text, see
+/// [super::FrameBufferWriter::set_status_ticker].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ticker {
+    /// Width of the field in cells, at most [FIELD_WIDTH]. Text that fits is shown as is.
+    pub width: usize,
+    /// Number of blank cells between the end of the text and its next repetition.
+    pub gap: usize,
+    /// Number of ticks per step of one cell to the left.
+    pub divider: u32,
+    /// Number of ticks that the text stays in place whenever its start is at the start of the
+    /// field.
+    pub pause: u32,
+}
+
+impl Ticker {
+    pub const DEFAULT: Self = Self { width: 24, gap: 4, divider: 4, pause: 40 };
+}
+
+impl Default for Ticker {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The text of a ticker field and how far it has scrolled.
+#[derive(Clone, Copy)]
+struct Scroll {
+    ticker: Ticker,
+    text: [Cell; MAX_TICKER_LEN],
+    len: usize,
+    /// Index in the text, counting the gap after it, that the field starts at.
+    offset: usize,
+    /// Number of ticks until the next step.
+    countdown: u32,
+}
+
+impl Scroll {
+    /// Returns the cell shown at `column` of the field. The gap is blank in the style of the
+    /// text's last cell.
+    fn cell(&self, column: usize) -> Cell {
+        let index = (self.offset + column) % (self.len + self.ticker.gap);
+        match self.text[..self.len].get(index) {
+            Some(&cell) => cell,
+            None => Cell::blank(self.text[self.len - 1].style),
+        }
+    }
+}
+
+/// Fields shown at the right end of the header band, e.g. indicators that are updated by
+/// other modules. Field 0 is the rightmost one.
+pub struct StatusFields {
+    cells: [[Cell; FIELD_WIDTH]; MAX_FIELDS],
+    lens: [usize; MAX_FIELDS],
+    /// The fields whose text scrolls, see [StatusFields::set_ticker].
+    scrolls: [Option<Scroll>; MAX_FIELDS],
+}
+
+impl StatusFields {
+    pub const fn new() -> Self {
+        Self {
+            cells: [[Cell::BLANK; FIELD_WIDTH]; MAX_FIELDS],
+            lens: [0; MAX_FIELDS],
+            scrolls: [None; MAX_FIELDS],
+        }
+    }
+
+    /// Replaces the content of a field, which stops it from scrolling. Returns whether it
+    /// changed; fields beyond [MAX_FIELDS] never do.
+    pub fn set(&mut self, index: usize, cells: &[Cell]) -> bool {
+        let Some(field) = self.cells.get_mut(index) else { return false };
+        let stopped = self.scrolls[index].take().is_some();
+        let cells = &cells[..cells.len().min(FIELD_WIDTH)];
+        if field[..self.lens[index]] == *cells {
+            return stopped;
+        }
+        field[..cells.len()].copy_from_slice(cells);
+        self.lens[index] = cells.len();
+        true
+    }
+
+    /// Replaces the content of a field with text that scrolls through it as the field is
+    /// [ticked](StatusFields::tick), starting with a pause, or that is shown as is if it fits.
+    /// Setting the text that is already scrolling with the same [Ticker] keeps it going.
+    /// Returns whether the field changed, like [StatusFields::set].
+    pub fn set_ticker(&mut self, index: usize, cells: &[Cell], ticker: Ticker) -> bool {
+        let ticker = Ticker { width: ticker.width.min(FIELD_WIDTH), ..ticker };
+        if index >= MAX_FIELDS || cells.len() <= ticker.width {
+            return self.set(index, cells);
+        }
+        let len = cells.len().min(MAX_TICKER_LEN);
+        let cells = &cells[..len];
+        let same = |scroll: &Scroll| scroll.ticker == ticker && scroll.text[..scroll.len] == *cells;
+        if self.scrolls[index].as_ref().is_some_and(same) {
+            return false;
+        }
+        let mut text = [Cell::BLANK; MAX_TICKER_LEN];
+        text[..len].copy_from_slice(cells);
+        let scroll = Scroll { ticker, text, len, offset: 0, countdown: ticker.pause.max(1) };
+        self.scrolls[index] = Some(scroll);
+        self.lens[index] = ticker.width;
+        self.show(index);
+        true
+    }
+
+    /// Advances the ticker fields by one tick. Returns a bit per field, with field 0 in the
+    /// lowest one, that is set if the field scrolled by a cell.
+    pub fn tick(&mut self) -> u8 {
+        let mut stepped = 0;
+        for index in 0..MAX_FIELDS {
+            let Some(scroll) = self.scrolls[index].as_mut() else { continue };
+            scroll.countdown = scroll.countdown.saturating_sub(1);
+            if scroll.countdown > 0 {
+                continue;
+            }
+            scroll.offset = (scroll.offset + 1) % (scroll.len + scroll.ticker.gap);
+            scroll.countdown = match scroll.offset {
+                0 => scroll.ticker.pause,
+                _ => scroll.ticker.divider,
+            }
+            .max(1);
+            self.show(index);
+            stepped |= 1 << index;
+        }
+        stepped
+    }
+
+    /// Copies the visible part of the text of a ticker field into its cells.
+    fn show(&mut self, index: usize) {
+        let Some(scroll) = self.scrolls[index].as_ref() else { return };
+        for (column, cell) in self.cells[index][..scroll.ticker.width].iter_mut().enumerate() {
+            *cell = scroll.cell(column);
+        }
+    }
+
+    /// Returns the cells of a field; an unused field is empty.
+    pub fn get(&self, index: usize) -> &[Cell] {
+        &self.cells[index][..self.lens[index]]
+    }
+}
+
+impl Default for StatusFields {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::style::Style;
+
+    fn cells(text: &str) -> Vec<Cell> {
+        text.chars().map(|c| Cell::new(c, Style::DEFAULT)).collect()
+    }
+
+    fn text(fields: &StatusFields, index: usize) -> String {
+        fields.get(index).iter().map(|cell| cell.c).collect()
+    }
+
+    #[test]
+    fn a_ticker_pauses_at_the_start_and_steps_through_the_text_and_the_gap() {
+        let mut fields = StatusFields::new();
+        let ticker = Ticker { width: 4, gap: 2, divider: 2, pause: 3 };
+        assert!(fields.set_ticker(1, &cells("abcdef"), ticker));
+        assert_eq!(text(&fields, 1), "abcd");
+        let shown: Vec<_> = (1..=20).map(|_| (fields.tick(), text(&fields, 1))).collect();
+        let windows = [
+            "abcd", "abcd", "bcde", "bcde", "cdef", "cdef", "def ", "def ", "ef  ", "ef  ",
+            "f  a", "f  a", "  ab", "  ab", " abc", " abc", "abcd", "abcd", "abcd", "bcde",
+        ];
+        // The field scrolls every divider ticks, and waits for the pause when the text starts
+        // it again
+        let stepped = |tick: usize| ((3..=17).contains(&tick) && tick % 2 == 1) || tick == 20;
+        let expected: Vec<_> = (1..=20)
+            .zip(windows)
+            .map(|(tick, window)| (if stepped(tick) { 0b10 } else { 0 }, window.to_owned()))
+            .collect();
+        assert_eq!(shown, expected);
+
+        // Setting the same text again keeps the position
+        assert!(!fields.set_ticker(1, &cells("abcdef"), ticker));
+        assert_eq!(text(&fields, 1), "bcde");
+        assert!(fields.set_ticker(1, &cells("abcdeg"), ticker));
+        assert_eq!(text(&fields, 1), "abcd");
+    }
+
+    #[test]
+    fn text_that_fits_the_ticker_field_stays_in_place() {
+        let mut fields = StatusFields::new();
+        let ticker = Ticker { width: 4, gap: 2, divider: 1, pause: 1 };
+        assert!(fields.set_ticker(0, &cells("abcd"), ticker));
+        assert!((0..10).all(|_| fields.tick() == 0));
+        assert_eq!(text(&fields, 0), "abcd");
+        // The width is capped to the field
+        let long = "x".repeat(FIELD_WIDTH + 8);
+        fields.set_ticker(0, &cells(&long), Ticker { width: FIELD_WIDTH + 4, ..ticker });
+        assert_eq!(fields.get(0).len(), FIELD_WIDTH);
+    }
+}
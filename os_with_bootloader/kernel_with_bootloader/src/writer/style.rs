@@ -0,0 +1,179 @@
+use super::color::Color;
+
+/// Coverage with which the foreground is blended over the background for dim text.
+const DIM_INTENSITY: u8 = 140;
+
+/// Optional decoration drawn behind each glyph so that text stays readable on top of
+/// graphics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEffect {
+    /// Plain glyphs.
+    None,
+    /// The glyph is drawn offset by one pixel to the bottom right in the effect color first.
+    Shadow,
+    /// The glyph is dilated by one pixel in all directions in the effect color first.
+    Outline,
+}
+
+/// How the text cursor is drawn, see [super::FrameBufferWriter::set_cursor_shape].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorShape {
+    /// The cell at the cursor in reverse video.
+    #[default]
+    Block,
+    /// A line in the foreground color along the bottom of the cell at the cursor.
+    Underline,
+}
+
+/// Attributes used to render text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    /// Color of the glyphs.
+    pub foreground: Color,
+    /// Color the glyphs are drawn onto. Antialiased glyph edges are blended with it.
+    pub background: Color,
+    /// Whether the glyphs are drawn with reduced intensity.
+    pub dim: bool,
+    /// Whether the glyphs are drawn thicker.
+    pub bold: bool,
+    /// Whether a line is drawn below the glyphs.
+    pub underline: bool,
+    /// Whether foreground and background are swapped.
+    pub reverse: bool,
+    /// Whether the glyphs are hidden and shown again periodically, see
+    /// [super::FrameBufferWriter::tick].
+    pub blink: bool,
+    /// Decoration drawn behind the glyphs.
+    pub effect: TextEffect,
+    /// Color of the shadow or outline.
+    pub effect_color: Color,
+}
+
+impl Style {
+    /// White text on black without any decoration.
+    pub const DEFAULT: Style = Style {
+        foreground: Color::WHITE,
+        background: Color::BLACK,
+        dim: false,
+        bold: false,
+        underline: false,
+        reverse: false,
+        blink: false,
+        effect: TextEffect::None,
+        effect_color: Color::BLACK,
+    };
+}
+
+impl Style {
+    /// Returns the colors the glyphs and the cell background are actually drawn in, taking
+    /// reverse video and dim text into account.
+    pub fn colors(&self) -> (Color, Color) {
+        let (foreground, background) = match self.reverse {
+            true => (self.background, self.foreground),
+            false => (self.foreground, self.background),
+        };
+        match self.dim {
+            true => (background.blend(foreground, DIM_INTENSITY), background),
+            false => (foreground, background),
+        }
+    }
+
+    /// Returns the style that blinking text is drawn in while it is hidden: everything in the
+    /// color of the cell background.
+    pub fn hidden(&self) -> Style {
+        let (_, background) = self.colors();
+        Style {
+            foreground: background,
+            background,
+            effect_color: background,
+            dim: false,
+            reverse: false,
+            ..*self
+        }
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Maximum number of styles kept by a [StyleStack].
+const STYLE_STACK_DEPTH: usize = 8;
+
+/// A small fixed-depth stack of saved styles, used for the push/pop escape pair.
+#[derive(Debug, Clone)]
+pub struct StyleStack {
+    styles: [Style; STYLE_STACK_DEPTH],
+    len: usize,
+}
+
+impl StyleStack {
+    pub const fn new() -> Self {
+        Self { styles: [Style::DEFAULT; STYLE_STACK_DEPTH], len: 0 }
+    }
+
+    /// Saves `style`. If the stack is full, the oldest entry is dropped.
+    pub fn push(&mut self, style: Style) {
+        if self.len == STYLE_STACK_DEPTH {
+            self.styles.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.styles[self.len] = style;
+        self.len += 1;
+    }
+
+    /// Returns the most recently saved style, or `None` if the stack is empty.
+    pub fn pop(&mut self) -> Option<Style> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.styles[self.len])
+    }
+}
+
+impl Default for StyleStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a style that differs from the others in its foreground.
+    fn style(r: u8) -> Style {
+        Style { foreground: Color::new(r, 0, 0), ..Style::DEFAULT }
+    }
+
+    #[test]
+    fn styles_are_popped_in_reverse_order() {
+        let mut stack = StyleStack::new();
+        for r in 1..=3 {
+            stack.push(style(r));
+        }
+        assert_eq!([stack.pop(), stack.pop(), stack.pop()], [3, 2, 1].map(|r| Some(style(r))));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn popping_an_empty_stack_returns_nothing() {
+        let mut stack = StyleStack::new();
+        assert_eq!(stack.pop(), None);
+        // An empty pop doesn't underflow into the next push
+        stack.push(style(1));
+        assert_eq!([stack.pop(), stack.pop()], [Some(style(1)), None]);
+    }
+
+    #[test]
+    fn a_full_stack_drops_the_oldest_style() {
+        let mut stack = StyleStack::new();
+        for r in 0..=STYLE_STACK_DEPTH as u8 {
+            stack.push(style(r));
+        }
+        for r in (1..=STYLE_STACK_DEPTH as u8).rev() {
+            assert_eq!(stack.pop(), Some(style(r)));
+        }
+        assert_eq!(stack.pop(), None);
+    }
+}
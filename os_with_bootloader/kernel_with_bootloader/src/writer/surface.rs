@@ -0,0 +1,50 @@
+use bootloader_api::info::{FrameBuffer, FrameBufferInfo};
+
+/// Pixels that a [super::FrameBufferWriter] draws into, laid out as described by a
+/// [FrameBufferInfo] and living as long as the kernel: the framebuffer of the bootloader, or
+/// any memory wrapped in a [MemorySurface], such as memory of the host in tests.
+pub trait PixelSurface {
+    /// Returns the layout of the pixels.
+    fn info(&self) -> FrameBufferInfo;
+
+    /// Gives up the surface for the bytes of its pixels.
+    fn into_bytes(self) -> &'static mut [u8];
+}
+
+impl PixelSurface for &'static mut FrameBuffer {
+    fn info(&self) -> FrameBufferInfo {
+        FrameBuffer::info(self)
+    }
+
+    fn into_bytes(self) -> &'static mut [u8] {
+        self.buffer_mut()
+    }
+}
+
+/// Pixels in memory with the given layout, which a writer checks before it draws into them.
+pub struct MemorySurface {
+    bytes: &'static mut [u8],
+    info: FrameBufferInfo,
+}
+
+impl MemorySurface {
+    pub fn new(bytes: &'static mut [u8], info: FrameBufferInfo) -> Self {
+        Self { bytes, info }
+    }
+
+    /// Returns a surface of `info.byte_len` zeroed bytes of the host, which are leaked.
+    #[cfg(test)]
+    pub fn zeroed(info: FrameBufferInfo) -> Self {
+        Self::new(Vec::leak(vec![0; info.byte_len]), info)
+    }
+}
+
+impl PixelSurface for MemorySurface {
+    fn info(&self) -> FrameBufferInfo {
+        self.info
+    }
+
+    fn into_bytes(self) -> &'static mut [u8] {
+        self.bytes
+    }
+}
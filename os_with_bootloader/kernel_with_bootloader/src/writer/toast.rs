@@ -0,0 +1,37 @@
+use super::rect::Rect;
+use super::snapshot::RegionSnapshot;
+use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Longest message of a toast; longer ones are cut off, as are those wider than the screen.
+pub const MAX_TOAST_LEN: usize = 64;
+
+/// Size of the buffer that the pixels below a toast are saved in. Enough for a toast of
+/// [MAX_TOAST_LEN] chars at 1080p with the default font; below larger ones, the text is
+/// redrawn from the grid instead.
+const PIXEL_BYTES: usize = 512 * 1024;
+
+/// Backing storage of the saved pixels. It is handed out once, to the first writer that asks.
+static mut PIXELS: [u8; PIXEL_BYTES] = [0; PIXEL_BYTES];
+static PIXELS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns the buffer for the pixels below toasts, which is empty for all but the first caller.
+pub(super) fn take_buffer() -> &'static mut [u8] {
+    if PIXELS_TAKEN.swap(true, Ordering::AcqRel) {
+        return super::spare_storage(0, PIXEL_BYTES);
+    }
+    // SAFETY: the storage is handed out only once, guarded by PIXELS_TAKEN.
+    unsafe { slice::from_raw_parts_mut((&raw mut PIXELS).cast::<u8>(), PIXEL_BYTES) }
+}
+
+/// A toast that is shown, see [super::FrameBufferWriter::toast].
+pub(super) struct Toast {
+    /// The covered pixels.
+    pub rect: Rect,
+    /// The covered grid cells, in columns and rows.
+    pub cells: Rect,
+    /// Time in milliseconds at which the toast is dismissed.
+    pub until: u64,
+    /// The covered pixels, if they fit into the buffer.
+    pub snapshot: Option<RegionSnapshot<'static>>,
+}
@@ -0,0 +1,69 @@
+//! Small tables of Unicode properties that affect the layout of text in the cell grid. They
+//! cover the commonly used ranges only, not the full Unicode database.
+use core::ops::RangeInclusive;
+
+/// Combining marks, which are drawn over the preceding character.
+const COMBINING: &[RangeInclusive<char>] = &[
+    '\u{0300}'..='\u{036F}', // Combining Diacritical Marks
+    '\u{0483}'..='\u{0489}', // Cyrillic
+    '\u{0591}'..='\u{05BD}', // Hebrew
+    '\u{0610}'..='\u{061A}', // Arabic
+    '\u{064B}'..='\u{065F}',
+    '\u{1AB0}'..='\u{1AFF}', // Combining Diacritical Marks Extended
+    '\u{1DC0}'..='\u{1DFF}', // Combining Diacritical Marks Supplement
+    '\u{20D0}'..='\u{20FF}', // Combining Diacritical Marks for Symbols
+    '\u{FE20}'..='\u{FE2F}', // Combining Half Marks
+];
+
+/// Characters that take no space and have no glyph of their own.
+const ZERO_WIDTH: &[RangeInclusive<char>] = &[
+    '\u{00AD}'..='\u{00AD}', // Soft hyphen
+    '\u{200B}'..='\u{200F}', // Zero width space, (non-)joiner, direction marks
+    '\u{2060}'..='\u{2064}', // Word joiner, invisible operators
+    '\u{FE00}'..='\u{FE0F}', // Variation selectors
+    '\u{FEFF}'..='\u{FEFF}', // Zero width no-break space (byte order mark)
+    '\u{E0100}'..='\u{E01EF}', // Variation selectors supplement
+];
+
+/// East Asian wide and fullwidth characters, which take two cells.
+const WIDE: &[RangeInclusive<char>] = &[
+    '\u{1100}'..='\u{115F}', // Hangul Jamo initials
+    '\u{2E80}'..='\u{303E}', // CJK radicals, Kangxi radicals, CJK symbols and punctuation
+    '\u{3041}'..='\u{33FF}', // Hiragana, Katakana, Bopomofo, CJK compatibility
+    '\u{3400}'..='\u{4DBF}', // CJK Unified Ideographs Extension A
+    '\u{4E00}'..='\u{9FFF}', // CJK Unified Ideographs
+    '\u{A000}'..='\u{A4CF}', // Yi
+    '\u{AC00}'..='\u{D7A3}', // Hangul syllables
+    '\u{F900}'..='\u{FAFF}', // CJK Compatibility Ideographs
+    '\u{FE30}'..='\u{FE4F}', // CJK Compatibility Forms
+    '\u{FF00}'..='\u{FF60}', // Fullwidth forms
+    '\u{FFE0}'..='\u{FFE6}',
+    '\u{1F300}'..='\u{1F64F}', // Pictographs and emoticons
+    '\u{1F900}'..='\u{1F9FF}', // Supplemental symbols and pictographs
+    '\u{20000}'..='\u{2FFFD}', // CJK Unified Ideographs Extension B and later
+    '\u{30000}'..='\u{3FFFD}',
+];
+
+fn in_table(table: &[RangeInclusive<char>], c: char) -> bool {
+    table.iter().any(|range| range.contains(&c))
+}
+
+/// Returns whether `c` is a combining mark.
+pub fn is_combining(c: char) -> bool {
+    in_table(COMBINING, c)
+}
+
+/// Returns whether `c` doesn't advance the cursor: combining marks and invisible formatting
+/// characters like the zero width joiner.
+pub fn is_zero_width(c: char) -> bool {
+    !c.is_ascii() && (is_combining(c) || in_table(ZERO_WIDTH, c))
+}
+
+/// Returns the number of cells that `c` takes at text scale 1: two for wide characters, one
+/// otherwise. Characters without width are handled separately, see [is_zero_width].
+pub fn columns(c: char) -> usize {
+    match !c.is_ascii() && in_table(WIDE, c) {
+        true => 2,
+        false => 1,
+    }
+}
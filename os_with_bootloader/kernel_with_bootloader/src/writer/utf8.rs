@@ -0,0 +1,93 @@
+/// Incremental, lossy UTF-8 decoder for text that arrives as bytes in arbitrary chunks.
+///
+/// Bytes of an incomplete sequence are kept until the sequence is complete, so a char may be
+/// split across calls. Every invalid byte becomes [char::REPLACEMENT_CHARACTER].
+#[derive(Debug, Clone, Copy)]
+pub struct Utf8Decoder {
+    buffer: [u8; 4],
+    len: usize,
+}
+
+impl Utf8Decoder {
+    pub const fn new() -> Self {
+        Self { buffer: [0; 4], len: 0 }
+    }
+
+    /// Feeds one byte, passing the chars it completes to `emit`: none while a sequence is
+    /// incomplete, and up to two if it ends an invalid sequence and starts a new one.
+    pub fn push(&mut self, byte: u8, emit: &mut impl FnMut(char)) {
+        self.buffer[self.len] = byte;
+        self.len += 1;
+        match core::str::from_utf8(&self.buffer[..self.len]) {
+            Ok(text) => {
+                self.len = 0;
+                text.chars().for_each(&mut *emit);
+            }
+            // The sequence may still be completed by the next bytes
+            Err(error) if error.error_len().is_none() => {}
+            Err(_) => {
+                // The bytes before were a valid prefix, so only the last one is at fault. It
+                // may start a new sequence.
+                let retry = self.len > 1;
+                self.len = 0;
+                emit(char::REPLACEMENT_CHARACTER);
+                if retry {
+                    self.push(byte, emit);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Utf8Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `bytes` to a fresh decoder one at a time and returns the chars it emits.
+    fn decode(bytes: &[u8]) -> String {
+        let mut decoder = Utf8Decoder::new();
+        let mut text = String::new();
+        for &byte in bytes {
+            decoder.push(byte, &mut |c| text.push(c));
+        }
+        text
+    }
+
+    #[test]
+    fn chars_split_into_single_bytes_are_decoded_whole() {
+        let text = "aé€😀\x1b[1m";
+        assert_eq!(decode(text.as_bytes()), text);
+    }
+
+    #[test]
+    fn invalid_bytes_become_replacement_chars_like_lossy_decoding() {
+        let inputs: [&[u8]; 6] = [
+            b"\xff",
+            b"a\x80b",
+            b"\xe2\x82a",
+            b"\xf0\x9f\x98\xc3\xa9",
+            b"\xc0\xaf",
+            b"\xed\xa0\x80x",
+        ];
+        for bytes in inputs {
+            assert_eq!(decode(bytes), String::from_utf8_lossy(bytes), "{:x?}", bytes);
+        }
+    }
+
+    #[test]
+    fn an_incomplete_char_is_kept_until_its_last_byte() {
+        let mut decoder = Utf8Decoder::new();
+        let mut text = String::new();
+        for &byte in "€".as_bytes() {
+            assert_eq!(text, "");
+            decoder.push(byte, &mut |c| text.push(c));
+        }
+        assert_eq!(text, "€");
+    }
+}
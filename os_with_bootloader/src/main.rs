@@ -14,6 +14,15 @@ fn main() {
         cmd.arg("-drive").arg(format!("format=raw,file={}", bios_path));
     }
 
+    // Pass the kernel command line, e.g. CMDLINE="console.theme=light", through the firmware
+    // configuration, where commas are doubled
+    if let Ok(cmdline) = std::env::var("CMDLINE") {
+        cmd.arg("-fw_cfg").arg(format!("name=opt/cmdline,string={}", cmdline.replace(',', ",,")));
+    }
+
+    // Connect COM1 to the terminal, so the kernel console can be used without the window
+    cmd.arg("-serial").arg("stdio");
+
     let mut child = cmd.spawn().unwrap();
     child.wait().unwrap();
 }
\ No newline at end of file
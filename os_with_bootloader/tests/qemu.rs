@@ -0,0 +1,190 @@
+//! Boots the kernel in QEMU without a window and drives it over the serial port, like a user
+//! of `cargo run` would. These tests need `qemu-system-x86_64` and take a few seconds each, so
+//! they only run with `cargo test -- --ignored`.
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// Time that the kernel gets to print what a test waits for.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Prompt of the QEMU monitor, printed when it's ready for the next command.
+const MONITOR_PROMPT: &str = "(qemu) ";
+
+/// Number of machines started so far, which tell their files apart.
+static MACHINES: AtomicUsize = AtomicUsize::new(0);
+
+/// A machine running the kernel, with COM1 connected to the test and the monitor on a socket.
+struct Qemu {
+    child: Child,
+    stdin: ChildStdin,
+    /// Output of the serial port, read by a thread so that waiting for it can time out.
+    serial: Receiver<Vec<u8>>,
+    /// The output of the serial port received so far.
+    output: Vec<u8>,
+    monitor: PathBuf,
+    screen: PathBuf,
+}
+
+impl Qemu {
+    /// Boots the BIOS image that `cargo run` starts as well.
+    fn boot() -> Self {
+        let machine = MACHINES.fetch_add(1, Ordering::Relaxed);
+        let file = |extension| {
+            let name = format!("kernel-test-{}-{}.{}", std::process::id(), machine, extension);
+            std::env::temp_dir().join(name)
+        };
+        let (monitor, screen) = (file("sock"), file("ppm"));
+        let mut child = Command::new("qemu-system-x86_64")
+            .arg("-drive")
+            .arg(format!("format=raw,file={}", env!("BIOS_PATH")))
+            .args(["-display", "none", "-serial", "stdio", "-no-reboot"])
+            .arg("-monitor")
+            .arg(format!("unix:{},server=on,wait=off", monitor.display()))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("qemu-system-x86_64 should start");
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let (sender, serial) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(len @ 1..) = stdout.read(&mut buffer) {
+                if sender.send(buffer[..len].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { child, stdin, serial, output: Vec::new(), monitor, screen }
+    }
+
+    /// Waits until the serial output contains `text` after everything that the previous waits
+    /// consumed, and consumes the output up to its end.
+    fn wait_for(&mut self, text: &str) {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let mut windows = self.output.windows(text.len());
+            if let Some(start) = windows.position(|window| window == text.as_bytes()) {
+                self.output.drain(..start + text.len());
+                return;
+            }
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.serial.recv_timeout(timeout) {
+                Ok(bytes) => self.output.extend(bytes),
+                Err(_) => panic!(
+                    "timed out waiting for {:?}, got {:?}",
+                    text,
+                    String::from_utf8_lossy(&self.output)
+                ),
+            }
+        }
+    }
+
+    /// Sends `text` to the serial port, as if it was typed into a terminal.
+    fn send(&mut self, text: &str) {
+        self.stdin.write_all(text.as_bytes()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    /// Takes a screenshot with the monitor's `screendump` command.
+    fn screendump(&self) -> Screen {
+        let mut monitor = UnixStream::connect(&self.monitor).expect("the monitor should listen");
+        monitor.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let command = format!("screendump {}\n", self.screen.display());
+        monitor.write_all(command.as_bytes()).unwrap();
+        // The prompt is printed once on connecting and again once the command is done
+        let mut reply = Vec::new();
+        while String::from_utf8_lossy(&reply).matches(MONITOR_PROMPT).count() < 2 {
+            let mut buffer = [0; 1024];
+            let len = monitor.read(&mut buffer).expect("the monitor should reply");
+            assert!(len > 0, "the monitor hung up: {:?}", String::from_utf8_lossy(&reply));
+            reply.extend_from_slice(&buffer[..len]);
+        }
+        Screen::parse(&std::fs::read(&self.screen).expect("screendump should write the file"))
+    }
+
+    /// Takes screenshots until `done` accepts one, and returns that one.
+    fn wait_for_screen(&self, done: impl Fn(&Screen) -> bool) -> Screen {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let screen = self.screendump();
+            if done(&screen) {
+                return screen;
+            }
+            assert!(Instant::now() < deadline, "timed out waiting for the screen");
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.monitor);
+        let _ = std::fs::remove_file(&self.screen);
+    }
+}
+
+/// A screenshot in the binary PPM format that `screendump` writes.
+struct Screen {
+    width: usize,
+    height: usize,
+    /// Red, green and blue of every pixel, row by row.
+    pixels: Vec<u8>,
+}
+
+impl Screen {
+    fn parse(ppm: &[u8]) -> Self {
+        // The header is "P6", the width, the height and the maximum value, each followed by a
+        // single whitespace char
+        let mut fields = ppm.splitn(5, |byte| byte.is_ascii_whitespace());
+        let mut field = || std::str::from_utf8(fields.next().unwrap()).unwrap().to_owned();
+        assert_eq!(field(), "P6");
+        let (width, height) = (field().parse().unwrap(), field().parse().unwrap());
+        assert_eq!(field(), "255");
+        let pixels = fields.next().unwrap().to_vec();
+        assert_eq!(pixels.len(), width * height * 3);
+        Self { width, height, pixels }
+    }
+
+    /// Returns the share of the pixels that have the given color.
+    fn share_of(&self, rgb: [u8; 3]) -> f64 {
+        let matching = self.pixels.chunks_exact(3).filter(|pixel| *pixel == rgb).count();
+        matching as f64 / (self.width * self.height) as f64
+    }
+}
+
+#[test]
+#[ignore = "boots the kernel in QEMU"]
+fn a_panic_while_the_console_is_locked_still_shows_the_panic_screen() {
+    let mut qemu = Qemu::boot();
+    qemu.wait_for("framebuffer console ready");
+    qemu.wait_for("> ");
+    qemu.send("panic locked\n");
+    qemu.wait_for("=== PANIC ===");
+    qemu.wait_for("panic while printing");
+    qemu.wait_for("=== END PANIC ===");
+    // The report reaches the serial port before the screen is drawn. The panic screen is red
+    // (the RED of the console) apart from the text and the QR code.
+    let red = [170, 0, 0];
+    qemu.wait_for_screen(|screen| screen.share_of(red) > 0.5);
+}
+
+#[test]
+#[ignore = "boots the kernel in QEMU"]
+fn the_shell_runs_commands_typed_over_the_serial_port() {
+    let mut qemu = Qemu::boot();
+    qemu.wait_for("framebuffer console ready");
+    qemu.wait_for("> ");
+    qemu.send("echo ping\n");
+    // The output of the command follows the echo of the typed line on a line of its own, with
+    // the LF turned into CRLF by the serial port
+    qemu.wait_for("\r\nping\r\n");
+    qemu.wait_for("> ");
+}